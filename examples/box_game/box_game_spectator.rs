@@ -1,7 +1,7 @@
 use std::net::SocketAddr;
 
 use bevy::prelude::*;
-use bevy_ggrs::{GGRSPlugin, Session};
+use bevy_ggrs::{GGRSPlugin, GgrsLogPlugin, Session};
 use ggrs::{SessionBuilder, UdpNonBlockingSocket};
 use structopt::StructOpt;
 
@@ -22,9 +22,6 @@ struct Opt {
     host: SocketAddr,
 }
 
-#[derive(Resource)]
-struct NetworkStatsTimer(Timer);
-
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // read cmd line arguments
     let opt = Opt::from_args();
@@ -67,45 +64,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .insert_resource(Session::SpectatorSession(sess))
         // register a resource that will be rolled back
         .insert_resource(FrameCount { frame: 0 })
-        //print some network stats - not part of the rollback schedule as it does not need to be rolled back
-        .insert_resource(NetworkStatsTimer(Timer::from_seconds(
-            2.0,
-            TimerMode::Repeating,
-        )))
-        .add_system(print_network_stats_system)
-        .add_system(print_events_system)
+        // log events, network stats and rollbacks - not part of the rollback schedule as it does not need to be rolled back
+        .add_plugin(GgrsLogPlugin::<GGRSConfig>::new())
         .run();
 
     Ok(())
 }
-
-fn print_events_system(mut session: ResMut<Session<GGRSConfig>>) {
-    match session.as_mut() {
-        Session::SpectatorSession(s) => {
-            for event in s.events() {
-                println!("GGRS Event: {:?}", event);
-            }
-        }
-        _ => panic!("This example focuses on spectators."),
-    }
-}
-
-fn print_network_stats_system(
-    time: Res<Time>,
-    mut timer: ResMut<NetworkStatsTimer>,
-    p2p_session: Option<Res<Session<GGRSConfig>>>,
-) {
-    // print only when timer runs out
-    if timer.0.tick(time.delta()).just_finished() {
-        if let Some(sess) = p2p_session {
-            match sess.as_ref() {
-                Session::SpectatorSession(s) => {
-                    if let Ok(stats) = s.network_stats() {
-                        println!("NetworkStats : {:?}", stats);
-                    }
-                }
-                _ => panic!("This example focuses on spectators."),
-            }
-        }
-    }
-}
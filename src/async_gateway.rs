@@ -0,0 +1,166 @@
+use bevy::{
+    prelude::*,
+    reflect::FromReflect,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use futures_lite::future;
+use std::{collections::BTreeMap, future::Future};
+
+use crate::ggrs_stage::StageEvent;
+
+struct PendingTask<T> {
+    id: u64,
+    deliver_at: i32,
+    task: Task<T>,
+}
+
+/// Runs long computations (procedural generation, AI planning, ...) in the background and hands their results to
+/// the rollback schedule at an agreed upon frame. Register the result type with
+/// `GGRSPlugin::register_async_gateway::<T>()`.
+///
+/// Every peer has to spawn the same task with the same `deliver_at` frame, for example as a reaction to a
+/// deterministic event inside the simulation. No matter how fast the computation finishes on each machine, the
+/// result is only visible to the rollback schedule when simulating `deliver_at`, through the `AsyncResults<T>`
+/// resource. If the task has not finished by then, the GGRS stage blocks until it has. Results are kept until their
+/// delivery frame is confirmed, so resimulating the delivery frame after a rollback yields the same result again, and
+/// resimulating the frame that spawned the task doesn't start it again.
+///
+/// A task whose delivery frame has already been simulated when it is spawned is dropped with a warning, on every
+/// peer alike.
+///
+/// Note that blocking on the task is not possible on single-threaded targets like wasm; schedule the
+/// delivery frame far enough in the future there.
+#[derive(Resource)]
+pub struct AsyncGateway<T: FromReflect> {
+    pending: Vec<PendingTask<T>>,
+    /// finished results by delivery frame, until that frame is confirmed
+    finished: BTreeMap<i32, Vec<(u64, T)>>,
+}
+
+impl<T: FromReflect> Default for AsyncGateway<T> {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+            finished: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: FromReflect + Clone> AsyncGateway<T> {
+    /// Creates an empty gateway.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Starts `future` on the `AsyncComputeTaskPool`. Its result is delivered when simulating frame `deliver_at`,
+    /// tagged with `id`. Returns false without starting it if a task with the same id and delivery frame has been
+    /// spawned before, for example when the spawning frame is resimulated after a rollback.
+    pub fn spawn(
+        &mut self,
+        id: u64,
+        deliver_at: i32,
+        future: impl Future<Output = T> + Send + 'static,
+    ) -> bool {
+        let spawned = self
+            .pending
+            .iter()
+            .any(|pending| pending.id == id && pending.deliver_at == deliver_at)
+            || self.finished.get(&deliver_at).map_or(false, |results| {
+                results.iter().any(|(other, _)| *other == id)
+            });
+        if spawned {
+            return false;
+        }
+        let task = AsyncComputeTaskPool::get().spawn(future);
+        self.pending.push(PendingTask {
+            id,
+            deliver_at,
+            task,
+        });
+        true
+    }
+
+    /// Returns the number of tasks that have not been delivered yet.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Finishes the tasks due at `frame`, blocking if necessary, and returns the results of `frame` ordered by
+    /// their id.
+    fn deliver(&mut self, frame: i32) -> Vec<(u64, T)> {
+        let mut i = 0;
+        while i < self.pending.len() {
+            let deliver_at = self.pending[i].deliver_at;
+            if deliver_at > frame {
+                i += 1;
+                continue;
+            }
+            let pending = self.pending.remove(i);
+            if deliver_at < frame {
+                // delivering it now would hand the result to the simulation at a later frame than agreed
+                warn!(
+                    "AsyncGateway: dropped task {}, which was spawned after its delivery frame {deliver_at}",
+                    pending.id
+                );
+                continue;
+            }
+            let result = future::block_on(pending.task);
+            let results = self.finished.entry(deliver_at).or_default();
+            results.push((pending.id, result));
+            results.sort_by_key(|(id, _)| *id);
+        }
+        self.finished.get(&frame).cloned().unwrap_or_default()
+    }
+
+    pub(crate) fn on_stage_event(&mut self, world: &mut World, event: StageEvent) {
+        match event {
+            StageEvent::Advancing { frame } => {
+                let results = self.deliver(frame);
+                world.insert_resource(AsyncResults {
+                    ids: results.iter().map(|(id, _)| *id).collect(),
+                    values: results.into_iter().map(|(_, value)| value).collect(),
+                });
+            }
+            // results of confirmed frames can't be resimulated anymore
+            StageEvent::Confirmed { frame } => {
+                self.finished = self.finished.split_off(&(frame + 1))
+            }
+            StageEvent::Reset => {
+                self.pending.clear();
+                self.finished.clear();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The results an `AsyncGateway<T>` delivers at the frame being simulated, ordered by their id. Part of the
+/// snapshots, and set again for every simulation of a frame, resimulations included.
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct AsyncResults<T: FromReflect> {
+    ids: Vec<u64>,
+    values: Vec<T>,
+}
+
+impl<T: FromReflect> Default for AsyncResults<T> {
+    fn default() -> Self {
+        Self {
+            ids: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<T: FromReflect> AsyncResults<T> {
+    /// Iterates over the id and result of every task delivered at the frame being simulated.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &T)> + '_ {
+        self.ids.iter().copied().zip(self.values.iter())
+    }
+
+    /// Returns the result of the task with the given id, if it is delivered at the frame being simulated.
+    pub fn get(&self, id: u64) -> Option<&T> {
+        let index = self.ids.iter().position(|other| *other == id)?;
+        self.values.get(index)
+    }
+}
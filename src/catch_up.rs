@@ -0,0 +1,65 @@
+use instant::Duration;
+
+/// Number of frames `CatchUp::dilate()` catches up on slowly. GGRS predicts up to 8 frames by default, so remote
+/// peers don't have to wait for us.
+const DEFAULT_MAX_BACKLOG: u32 = 8;
+
+/// How the GGRS stage catches up after it fell behind, for example after a hitch or while the window was dragged.
+/// Set it with `GGRSPlugin::with_catch_up()`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CatchUp {
+    /// Simulates all missed frames in the next update. Catches up right away, but moving objects visibly jump.
+    #[default]
+    Burst,
+    /// Runs the simulation `rate` times as fast as the update frequency until it caught up, for example at 62 Hz
+    /// with a rate of `62. / 60.`. Presentation stays smooth, which suits rhythm games, but catching up takes a
+    /// while: at 62 Hz, every frame behind takes half a second. Anything beyond `max_backlog` frames behind is still
+    /// simulated in a burst.
+    ///
+    /// In a `P2PSession`, remote peers stall once we are more frames behind than they can predict, so keep
+    /// `max_backlog` within the prediction window.
+    Dilate { rate: f64, max_backlog: u32 },
+}
+
+impl CatchUp {
+    /// Runs the simulation `rate` times as fast until it caught up on up to 8 frames.
+    pub fn dilate(rate: f64) -> Self {
+        Self::Dilate {
+            rate,
+            max_backlog: DEFAULT_MAX_BACKLOG,
+        }
+    }
+
+    /// Moves the time the stage is behind between the accumulator, which is simulated in this update, and the
+    /// backlog, which is simulated over the following ones. `delta` is the time since the last update.
+    pub(crate) fn apply(
+        self,
+        delta: Duration,
+        frame: Duration,
+        accumulator: &mut Duration,
+        backlog: &mut Duration,
+    ) {
+        let CatchUp::Dilate { rate, max_backlog } = self else {
+            *accumulator = accumulator.saturating_add(std::mem::take(backlog));
+            return;
+        };
+
+        // up to two frames are regular jitter, anything beyond that means we fell behind
+        let ahead = frame * 2;
+        if *accumulator > ahead {
+            *backlog += *accumulator - ahead;
+            *accumulator = ahead;
+        }
+
+        // catch up on the backlog a bit faster than real time, but not on the time of the hitch itself
+        let extra = delta.min(frame).mul_f64((rate - 1.).max(0.)).min(*backlog);
+        *backlog -= extra;
+        *accumulator += extra;
+
+        let limit = frame * max_backlog;
+        if *backlog > limit {
+            *accumulator += *backlog - limit;
+            *backlog = limit;
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use bevy::{prelude::*, reflect::TypeRegistry};
+use ggrs::{Config, PlayerHandle, PlayerType, SessionBuilder};
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    ggrs_stage::{StageEvent, StepPerUpdate},
+    world_snapshot::WorldSnapshot,
+    DatagramSocket, GGRSPlugin, MultiplexSocket, PlayerInputs, Rollback, RollbackFrame,
+    RollbackStats, Session,
+};
+
+/// GGRS predicts this many frames by default, which limits the latency of the loopback.
+const MAX_PREDICTION: usize = 8;
+const DAMAGE: u32 = 25;
+const START_HEALTH: u32 = 100;
+
+/// The GGRS config of the peers of a `ConformanceScenario`. Each input is a `ScriptedAction`.
+pub struct ConformanceConfig;
+impl Config for ConformanceConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// What a player does on a frame of a `ConformanceScenario`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptedAction {
+    /// Spawns an actor owned by the player, with full health.
+    Spawn,
+    /// Damages all actors of the other player. Actors without health left are despawned.
+    Damage,
+    /// Despawns the oldest actor of the player.
+    Despawn,
+}
+
+impl ScriptedAction {
+    fn to_input(action: Option<Self>) -> u8 {
+        match action {
+            None => 0,
+            Some(ScriptedAction::Spawn) => 1,
+            Some(ScriptedAction::Damage) => 2,
+            Some(ScriptedAction::Despawn) => 3,
+        }
+    }
+
+    fn from_input(input: u8) -> Option<Self> {
+        match input {
+            1 => Some(ScriptedAction::Spawn),
+            2 => Some(ScriptedAction::Damage),
+            3 => Some(ScriptedAction::Despawn),
+            _ => None,
+        }
+    }
+}
+
+/// An entity spawned by a `ScriptedAction`.
+#[derive(Component, Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[reflect(Component, Hash, PartialEq)]
+pub struct Actor {
+    pub owner: usize,
+}
+
+/// The health of an `Actor`.
+#[derive(Component, Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[reflect(Component, Hash, PartialEq)]
+pub struct Health(pub u32);
+
+/// What a finished `ConformanceScenario` went through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConformanceReport {
+    /// Frames confirmed and compared on both peers.
+    pub frames_compared: usize,
+    /// Rollbacks of both peers together.
+    pub rollbacks: usize,
+    /// The deepest rollback of either peer.
+    pub max_rollback_depth: i32,
+}
+
+type PluginSetup = Box<dyn Fn(GGRSPlugin<ConformanceConfig>) -> GGRSPlugin<ConformanceConfig>>;
+
+/// A conformance test for the snapshot handling: two peers connected over an in-memory loopback with latency
+/// follow a script of spawning, damaging and despawning actors. Every change of input is mispredicted by the other
+/// peer, so they roll back across spawns and despawns all the time. After every update, the reflected state of all
+/// registered rollback components and resources at the end of all newly confirmed frames is compared between the
+/// peers, and `run()` panics at the first difference.
+///
+/// The peers simulate one frame per update instead of following the clock, and the latency is counted in updates,
+/// so a scenario runs as fast as the simulation allows.
+///
+/// Enabled with the `test-utils` feature. To test your own way of saving and restoring the world, change how the
+/// `GGRSPlugin` of the peers is set up with `with_plugin_setup()`.
+pub struct ConformanceScenario {
+    frames: i32,
+    latency: usize,
+    script: BTreeMap<(i32, PlayerHandle), ScriptedAction>,
+    setup: PluginSetup,
+}
+
+impl ConformanceScenario {
+    /// Creates a scenario running for the given number of frames, without any actions.
+    pub fn new(frames: i32) -> Self {
+        Self {
+            frames,
+            latency: 4,
+            script: BTreeMap::new(),
+            setup: Box::new(|plugin| {
+                plugin
+                    .register_rollback_component::<Actor>()
+                    .register_rollback_component::<Health>()
+            }),
+        }
+    }
+
+    /// Creates a scenario of 240 frames in which both players repeatedly spawn actors, kill the actors of the other
+    /// player and despawn their own.
+    pub fn standard() -> Self {
+        let mut scenario = Self::new(240);
+        for round in 0..4 {
+            let start = round * 60;
+            scenario = scenario
+                .with_action(start + 2, 0, ScriptedAction::Spawn)
+                .with_action(start + 3, 1, ScriptedAction::Spawn)
+                .with_action(start + 4, 0, ScriptedAction::Spawn)
+                .with_action(start + 20, 0, ScriptedAction::Damage)
+                .with_action(start + 30, 1, ScriptedAction::Despawn)
+                .with_action(start + 50, 0, ScriptedAction::Despawn);
+            for hit in 0..4 {
+                scenario = scenario.with_action(start + 10 + hit * 8, 1, ScriptedAction::Damage);
+            }
+        }
+        scenario
+    }
+
+    /// Lets the given player do something on the given frame.
+    pub fn with_action(mut self, frame: i32, handle: PlayerHandle, action: ScriptedAction) -> Self {
+        self.script.insert((frame, handle), action);
+        self
+    }
+
+    /// Changes the number of updates a datagram takes to the other peer, which is about the depth of the
+    /// rollbacks. Defaults to 4, at most 7.
+    pub fn with_latency(mut self, updates: usize) -> Self {
+        self.latency = updates.min(MAX_PREDICTION - 1);
+        self
+    }
+
+    /// Changes the setup of the `GGRSPlugin` of both peers. The plugin comes with the input system and the rollback
+    /// schedule of the scenario; the default setup registers `Actor` and `Health` for rollback.
+    pub fn with_plugin_setup(
+        mut self,
+        setup: impl Fn(GGRSPlugin<ConformanceConfig>) -> GGRSPlugin<ConformanceConfig> + 'static,
+    ) -> Self {
+        self.setup = Box::new(setup);
+        self
+    }
+
+    /// Runs the scenario. Panics if the peers disagree about the state of any confirmed frame.
+    pub fn run(&self) -> ConformanceReport {
+        let clock = Arc::new(AtomicUsize::new(0));
+        let (a, b) = (Queue::default(), Queue::default());
+        let link = |addr, inbox, outbox| LoopbackLink {
+            addr,
+            clock: clock.clone(),
+            latency: self.latency,
+            inbox,
+            outbox,
+        };
+        let mut peers = [
+            self.peer(MultiplexSocket::new(link(0, a.clone(), b.clone())), 0),
+            self.peer(MultiplexSocket::new(link(1, b, a)), 1),
+        ];
+
+        let mut compared = -1;
+        // a generous limit for synchronizing, waiting for the other peer and confirming the last frames
+        for _ in 0..self.frames * 4 + 300 {
+            clock.fetch_add(1, Ordering::Relaxed);
+            for peer in peers.iter_mut() {
+                peer.update();
+            }
+
+            // frames confirmed by the session may not have been resimulated with the confirmed inputs yet
+            let confirmed = peers
+                .iter()
+                .map(|peer| peer.world.resource::<FrameSnapshots>().confirmed)
+                .min()
+                .unwrap_or(-1)
+                .min(self.frames - 1);
+            for frame in compared + 1..=confirmed {
+                let [a, b] = &peers;
+                let a = a.world.resource::<FrameSnapshots>();
+                let b = b.world.resource::<FrameSnapshots>();
+                let (Some(snapshot), Some(other)) = (a.frames.get(&frame), b.frames.get(&frame))
+                else {
+                    panic!("frame {frame} was not simulated by both peers");
+                };
+                let differences = snapshot.diff(other, &a.type_registry);
+                assert!(
+                    differences.is_empty(),
+                    "the peers disagree about the state at the end of frame {frame}:\n{}",
+                    differences.join("\n")
+                );
+            }
+            compared = compared.max(confirmed);
+            for peer in peers.iter_mut() {
+                let mut snapshots = peer.world.resource_mut::<FrameSnapshots>();
+                snapshots.frames = snapshots.frames.split_off(&(compared + 1));
+            }
+            if compared == self.frames - 1 {
+                break;
+            }
+        }
+        assert_eq!(
+            compared,
+            self.frames - 1,
+            "the peers didn't confirm all frames in time"
+        );
+
+        let stats: Vec<_> = peers
+            .iter()
+            .map(|peer| peer.world.resource::<RollbackStats>().clone())
+            .collect();
+        ConformanceReport {
+            frames_compared: self.frames as usize,
+            rollbacks: stats.iter().map(|stats| stats.rollbacks).sum(),
+            max_rollback_depth: stats.iter().map(|stats| stats.max_depth).max().unwrap_or(0),
+        }
+    }
+
+    fn peer(&self, socket: MultiplexSocket<usize>, local: PlayerHandle) -> App {
+        let session = SessionBuilder::<ConformanceConfig>::new()
+            .with_num_players(2)
+            .add_player(PlayerType::Local, local)
+            .expect("the local player should be valid")
+            .add_player(PlayerType::Remote(1 - local), 1 - local)
+            .expect("the remote player should be valid")
+            .start_p2p_session(socket.clone())
+            .expect("the session should start");
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(Session::P2PSession(session))
+            .insert_resource(socket)
+            .insert_resource(Script(self.script.clone()))
+            .insert_resource(StepPerUpdate);
+
+        let mut plugin = GGRSPlugin::<ConformanceConfig>::new()
+            .with_update_frequency(60)
+            .with_input_system(script_input_system)
+            .with_rollback_schedule(Schedule::default().with_stage(
+                "conformance_actions",
+                SystemStage::single_threaded().with_system(apply_actions_system),
+            ));
+        // the setup may register more types later on, which end up in the same registry
+        app.insert_resource(FrameSnapshots::new(plugin.type_registry.clone()));
+        plugin
+            .hooks
+            .push(Box::new(|world: &mut World, event| match event {
+                StageEvent::Advanced { frame } => {
+                    let type_registry = world.resource::<FrameSnapshots>().type_registry.clone();
+                    let snapshot = WorldSnapshot::from_world(world, &type_registry);
+                    world
+                        .resource_mut::<FrameSnapshots>()
+                        .frames
+                        .insert(frame, snapshot);
+                }
+                StageEvent::Confirmed { frame } => {
+                    world.resource_mut::<FrameSnapshots>().confirmed = frame;
+                }
+                _ => {}
+            }));
+        (self.setup)(plugin).build(&mut app);
+        app
+    }
+}
+
+type Queue = Arc<Mutex<Vec<(usize, usize, Vec<u8>)>>>;
+
+/// One end of the in-memory connection, delivering datagrams `latency` updates after they were sent.
+struct LoopbackLink {
+    addr: usize,
+    clock: Arc<AtomicUsize>,
+    latency: usize,
+    inbox: Queue,
+    outbox: Queue,
+}
+
+impl DatagramSocket<usize> for LoopbackLink {
+    fn send_datagram(&mut self, data: &[u8], _: &usize) {
+        let due = self.clock.load(Ordering::Relaxed) + self.latency;
+        self.outbox.lock().push((due, self.addr, data.to_vec()));
+    }
+
+    fn receive_datagrams(&mut self) -> Vec<(usize, Vec<u8>)> {
+        let now = self.clock.load(Ordering::Relaxed);
+        let mut inbox = self.inbox.lock();
+        let (arrived, pending) = inbox.drain(..).partition(|(due, _, _)| *due <= now);
+        *inbox = pending;
+        arrived
+            .into_iter()
+            .map(|(_, addr, data)| (addr, data))
+            .collect()
+    }
+}
+
+#[derive(Resource)]
+struct Script(BTreeMap<(i32, PlayerHandle), ScriptedAction>);
+
+/// The rollback state at the end of every frame that hasn't been compared yet, overwritten when the frame is
+/// resimulated. Not part of the snapshots itself.
+#[derive(Resource)]
+struct FrameSnapshots {
+    type_registry: TypeRegistry,
+    frames: BTreeMap<i32, WorldSnapshot>,
+    /// the latest frame the stage reported as confirmed
+    confirmed: i32,
+}
+
+impl FrameSnapshots {
+    fn new(type_registry: TypeRegistry) -> Self {
+        Self {
+            type_registry,
+            frames: BTreeMap::new(),
+            confirmed: -1,
+        }
+    }
+}
+
+fn script_input_system(
+    In(handle): In<PlayerHandle>,
+    script: Res<Script>,
+    frame: Option<Res<RollbackFrame>>,
+) -> u8 {
+    // the input is collected right before the frame after the last simulated one
+    let frame = frame.map_or(0, |frame| **frame + 1);
+    ScriptedAction::to_input(script.0.get(&(frame, handle)).copied())
+}
+
+fn apply_actions_system(
+    mut commands: Commands,
+    frame: Res<RollbackFrame>,
+    inputs: Res<PlayerInputs<ConformanceConfig>>,
+    mut actors: Query<(Entity, &Rollback, &Actor, &mut Health)>,
+) {
+    let mut actors: Vec<_> = actors.iter_mut().collect();
+    actors.sort_by_key(|(_, rollback, _, _)| **rollback);
+
+    for (handle, (input, _)) in inputs.iter().enumerate() {
+        match ScriptedAction::from_input(*input) {
+            Some(ScriptedAction::Spawn) => {
+                let parent = Rollback::from_name("conformance");
+                let index = **frame as u32 * inputs.len() as u32 + handle as u32;
+                commands.spawn((
+                    Rollback::derived(&parent, index),
+                    Actor { owner: handle },
+                    Health(START_HEALTH),
+                ));
+            }
+            Some(ScriptedAction::Damage) => {
+                for (entity, _, actor, health) in actors.iter_mut() {
+                    if actor.owner != handle && health.0 > 0 {
+                        health.0 = health.0.saturating_sub(DAMAGE);
+                        if health.0 == 0 {
+                            commands.entity(*entity).despawn();
+                        }
+                    }
+                }
+            }
+            Some(ScriptedAction::Despawn) => {
+                let oldest = actors
+                    .iter_mut()
+                    .find(|(_, _, actor, health)| actor.owner == handle && health.0 > 0);
+                if let Some((entity, _, _, health)) = oldest {
+                    // keeps a damage later in the frame from despawning it again
+                    health.0 = 0;
+                    commands.entity(*entity).despawn();
+                }
+            }
+            None => {}
+        }
+    }
+}
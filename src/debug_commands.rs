@@ -0,0 +1,298 @@
+use bevy::prelude::*;
+use ggrs::PlayerHandle;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    ggrs_stage::StageEvent,
+    socket::{channel, MultiplexSocket},
+};
+
+/// Default number of frames between issuing a command and executing it.
+const DEFAULT_DELAY: i32 = 10;
+
+/// (frame, issuer, id of the command on the issuer)
+type CommandKey = (i32, PlayerHandle, u32);
+
+/// Ids of commands issued outside of the rollback schedule. The others are numbered within the frame they were
+/// issued in, so resimulating that frame issues them with the same id again.
+const UNSCHEDULED_ID: u32 = 1 << 31;
+
+/// "These are all my commands issued up to and including frame `declared`"
+#[derive(Serialize, Deserialize)]
+struct Packet {
+    /// the number of rematches before, so packets of the previous match are ignored
+    matches: u32,
+    declared: i32,
+    commands: Vec<(i32, PlayerHandle, u32, Vec<u8>)>,
+}
+
+/// Debug console commands (spawn an enemy, give an item, ...) executed by all peers at the same frame, so using
+/// them in an online session doesn't desync it. Register the command type with
+/// `GGRSPlugin::register_debug_commands::<C>()` and insert the resource on all peers.
+///
+/// `issue()` schedules a command a few frames into the future and sends it to the other peers, which keep it until
+/// that frame is simulated. Systems in the rollback schedule read the commands of the current frame with
+/// `current()` and apply them, just like inputs. Every command is tagged with the handle of the player that issued
+/// it. Commands issued in the rollback schedule are only sent once the frame they were issued in is confirmed, and
+/// issuing the same commands again while resimulating that frame doesn't duplicate them.
+///
+/// Peers regularly tell each other up to which frame they have sent all their commands. A peer that would simulate
+/// a frame the commands of another peer may not have arrived for yet stops until they do, so a lost packet can't
+/// desync the session. Pick a delay above the round trip time to keep these stalls rare.
+#[derive(Resource)]
+pub struct DebugCommands<C, A> {
+    socket: MultiplexSocket<A>,
+    peers: Vec<A>,
+    local: PlayerHandle,
+    /// players allowed to issue commands, everyone if `None`
+    authorities: Option<Vec<PlayerHandle>>,
+    delay: i32,
+    /// the frame being simulated, or the next one between frames
+    frame: i32,
+    /// true while the rollback schedule runs
+    simulating: bool,
+    confirmed: i32,
+    /// commands issued in the frame being simulated, and outside of the rollback schedule
+    issued_in_frame: u32,
+    next_unscheduled_id: u32,
+    scheduled: BTreeMap<CommandKey, C>,
+    /// for each peer, the frame up to which we have all of its commands
+    declared: Vec<i32>,
+    /// frames between repeated sends, the frame we last sent our commands at
+    send_interval: i32,
+    last_sent: i32,
+    /// true if a command was issued outside of the rollback schedule since we last sent our commands
+    unsent: bool,
+    /// the number of times the stage started over with a new session
+    matches: u32,
+}
+
+impl<C, A> DebugCommands<C, A>
+where
+    C: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    A: Clone + PartialEq + Send + Sync + 'static,
+{
+    /// Creates the resource for the local player with the given handle, exchanging commands with the given peers.
+    pub fn new(socket: MultiplexSocket<A>, local: PlayerHandle, peers: Vec<A>) -> Self {
+        Self {
+            declared: vec![-1; peers.len()],
+            socket,
+            peers,
+            local,
+            authorities: None,
+            delay: DEFAULT_DELAY,
+            frame: 0,
+            simulating: false,
+            confirmed: -1,
+            issued_in_frame: 0,
+            next_unscheduled_id: UNSCHEDULED_ID,
+            scheduled: BTreeMap::new(),
+            send_interval: 1,
+            last_sent: -1,
+            unsent: false,
+            matches: 0,
+        }
+    }
+
+    /// Only accepts commands issued by the given players. Commands of other players are dropped by every peer.
+    /// All peers must use the same list.
+    pub fn with_authorities(mut self, authorities: Vec<PlayerHandle>) -> Self {
+        self.authorities = Some(authorities);
+        self
+    }
+
+    /// Changes the number of frames between issuing a command and executing it. Defaults to 10.
+    pub fn with_delay(mut self, frames: i32) -> Self {
+        self.delay = frames.max(1);
+        self
+    }
+
+    /// Returns true if the given player may issue commands.
+    pub fn is_authority(&self, handle: PlayerHandle) -> bool {
+        self.authorities
+            .as_ref()
+            .map_or(true, |authorities| authorities.contains(&handle))
+    }
+
+    /// Schedules a command for all peers and returns the frame it will be executed at. Returns `None` if the local
+    /// player is not allowed to issue commands.
+    ///
+    /// In the rollback schedule, the n-th command issued while simulating a frame replaces the n-th command of the
+    /// earlier simulations of that frame, so commands are not duplicated by rollbacks.
+    pub fn issue(&mut self, command: C) -> Option<i32> {
+        if !self.is_authority(self.local) {
+            warn!("player {} may not issue debug commands", self.local);
+            return None;
+        }
+        let frame = self.frame + self.delay;
+        let id = if self.simulating {
+            self.issued_in_frame += 1;
+            self.issued_in_frame - 1
+        } else {
+            self.next_unscheduled_id += 1;
+            self.unsent = true;
+            self.next_unscheduled_id - 1
+        };
+        self.scheduled.insert((frame, self.local, id), command);
+        if !self.simulating {
+            self.send_pending();
+        }
+        Some(frame)
+    }
+
+    /// Returns the commands scheduled for the frame being simulated, together with the player that issued them.
+    /// The order is the same on all peers.
+    pub fn current(&self) -> impl Iterator<Item = (PlayerHandle, &C)> {
+        self.scheduled
+            .range((self.frame, 0, 0)..(self.frame + 1, 0, 0))
+            .map(|((_, issuer, _), command)| (*issuer, command))
+    }
+
+    /// Returns true if a peer may still send commands for `frame`, so it must not be simulated yet.
+    pub(crate) fn holds(&self, frame: i32) -> bool {
+        self.declared
+            .iter()
+            .any(|declared| declared + self.delay < frame)
+    }
+
+    /// Returns the frame a local command was issued in, `None` if it was issued outside of the rollback schedule.
+    fn issued_at(&self, (frame, _, id): &CommandKey) -> Option<i32> {
+        (*id < UNSCHEDULED_ID).then_some(frame - self.delay)
+    }
+
+    /// Sends all commands issued locally up to the confirmed frame, which can't be rolled back anymore, and which
+    /// have not been executed yet. Datagrams may get lost, so commands are sent again every frame, or every few frames
+    /// with `NetworkProfile::LowBandwidth`, until their frame is confirmed.
+    fn send_pending(&mut self) {
+        let commands = self
+            .scheduled
+            .iter()
+            .filter(|(key, _)| {
+                key.1 == self.local
+                    && self
+                        .issued_at(key)
+                        .map_or(true, |issued| issued <= self.confirmed)
+            })
+            .map(|((frame, issuer, id), command)| {
+                let command = bincode::serialize(command).expect("debug commands should serialize");
+                (*frame, *issuer, *id, command)
+            })
+            .collect();
+        let packet = Packet {
+            matches: self.matches,
+            declared: self.confirmed,
+            commands,
+        };
+        let packet = bincode::serialize(&packet).expect("should serialize");
+        for peer in &self.peers {
+            self.socket.send_on(channel::DEBUG_COMMANDS, &packet, peer);
+        }
+        self.last_sent = self.confirmed;
+        self.unsent = false;
+    }
+
+    pub(crate) fn set_send_interval(&mut self, frames: i32) {
+        self.send_interval = frames.max(1);
+    }
+
+    pub(crate) fn on_stage_event(&mut self, event: StageEvent) {
+        match event {
+            StageEvent::Advancing { frame } => {
+                self.frame = frame;
+                self.simulating = true;
+                self.issued_in_frame = 0;
+            }
+            StageEvent::Advanced { frame } => {
+                self.frame = frame + 1;
+                self.simulating = false;
+            }
+            StageEvent::Loaded { frame } => {
+                // the resimulated frames issue their commands again
+                let local = self.local;
+                let delay = self.delay;
+                self.scheduled.retain(|(at, issuer, id), _| {
+                    *issuer != local || *id >= UNSCHEDULED_ID || at - delay < frame
+                });
+            }
+            StageEvent::Confirmed { frame } => {
+                let previous = self.confirmed;
+                self.confirmed = frame;
+                self.scheduled = self.scheduled.split_off(&(frame + 1, 0, 0));
+                let issued = self.scheduled.keys().any(|key| {
+                    key.1 == self.local
+                        && self
+                            .issued_at(key)
+                            .map_or(false, |issued| issued > previous && issued <= frame)
+                });
+                // others wait for our confirmed frame, so it can't lag behind by more than the delay
+                let interval = self.send_interval.min(self.delay / 2).max(1);
+                if issued || self.unsent || frame - self.last_sent >= interval {
+                    self.send_pending();
+                }
+            }
+            StageEvent::Reset => {
+                self.frame = 0;
+                self.simulating = false;
+                self.confirmed = -1;
+                self.scheduled.clear();
+                self.declared.iter_mut().for_each(|declared| *declared = -1);
+                self.last_sent = -1;
+                self.unsent = false;
+                self.matches += 1;
+            }
+            StageEvent::Saved { .. } => {}
+        }
+    }
+
+    /// Collects commands sent by the other peers.
+    pub(crate) fn receive(&mut self) {
+        for (addr, packet) in self.socket.receive_on(channel::DEBUG_COMMANDS) {
+            if !self.peers.contains(&addr) {
+                continue;
+            }
+            let Ok(packet) = bincode::deserialize::<Packet>(&packet) else {
+                debug!("received a malformed debug command packet");
+                continue;
+            };
+            if packet.matches != self.matches {
+                continue;
+            }
+            for (frame, issuer, id, command) in packet.commands {
+                let key = (frame, issuer, id);
+                if frame <= self.confirmed || self.scheduled.contains_key(&key) {
+                    continue;
+                }
+                if !self.is_authority(issuer) {
+                    warn!(
+                        "dropping a debug command of player {issuer}, who may not issue commands"
+                    );
+                    continue;
+                }
+                if frame < self.frame {
+                    warn!("a debug command of player {issuer} for frame {frame} arrived too late, the session will desync");
+                    continue;
+                }
+                let Ok(command) = bincode::deserialize(&command) else {
+                    debug!("received a malformed debug command");
+                    continue;
+                };
+                self.scheduled.insert(key, command);
+            }
+            if let Some(index) = self.peers.iter().position(|peer| *peer == addr) {
+                let declared = &mut self.declared[index];
+                *declared = (*declared).max(packet.declared);
+            }
+        }
+    }
+}
+
+pub(crate) fn receive_debug_commands_system<C, A>(commands: Option<ResMut<DebugCommands<C, A>>>)
+where
+    C: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    A: Clone + PartialEq + Send + Sync + 'static,
+{
+    if let Some(mut commands) = commands {
+        commands.receive();
+    }
+}
@@ -0,0 +1,265 @@
+use bevy::prelude::*;
+use std::collections::BTreeMap;
+
+use crate::{Rollback, RollbackFrame};
+
+/// Number of frames of shapes kept by default.
+const DEFAULT_HISTORY: usize = 16;
+/// Number of line segments per circle of a sphere.
+const CIRCLE_SEGMENTS: usize = 16;
+
+/// The outline of a collision volume, relative to the `Transform` of its entity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    /// An axis aligned box in the space of the entity.
+    Box { center: Vec3, half_extents: Vec3 },
+    /// A sphere, or a circle for 2D games looking along the z axis.
+    Sphere { center: Vec3, radius: f32 },
+}
+
+/// Implement this for collision components (hitboxes, hurtboxes, ...) and register them with
+/// `GGRSPlugin::register_debug_shape::<C>()` to record their shapes into `DebugShapes`.
+pub trait DebugShape {
+    /// Returns the shape of the component.
+    fn debug_shape(&self) -> Shape;
+}
+
+/// A recorded shape of a rollback entity at a specific frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapeInstance {
+    /// The rollback id of the entity.
+    pub rollback_id: u32,
+    /// The name of the component type the shape belongs to.
+    pub type_name: &'static str,
+    pub shape: Shape,
+    /// The `Transform` of the entity. Only the transform of the entity itself is used, so entities with shapes
+    /// should not have a parent.
+    pub transform: Transform,
+}
+
+impl ShapeInstance {
+    /// Returns the outline of the shape in world space as line segments, to be drawn with any line renderer.
+    pub fn lines(&self) -> Vec<(Vec3, Vec3)> {
+        let point = |p: Vec3| self.transform.transform_point(p);
+        match self.shape {
+            Shape::Box {
+                center,
+                half_extents,
+            } => {
+                let corner = |i: usize| {
+                    let sign = |bit: usize| if i & bit == 0 { -1. } else { 1. };
+                    point(center + half_extents * Vec3::new(sign(1), sign(2), sign(4)))
+                };
+                // every pair of corners that differs in exactly one axis is an edge
+                let mut lines = Vec::with_capacity(12);
+                for i in 0..8 {
+                    for bit in [1, 2, 4] {
+                        if i & bit == 0 {
+                            lines.push((corner(i), corner(i | bit)));
+                        }
+                    }
+                }
+                lines
+            }
+            Shape::Sphere { center, radius } => {
+                let mut lines = Vec::with_capacity(3 * CIRCLE_SEGMENTS);
+                let axes = [(Vec3::X, Vec3::Y), (Vec3::X, Vec3::Z), (Vec3::Y, Vec3::Z)];
+                for (u, v) in axes {
+                    let on_circle = |i: usize| {
+                        let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                        point(center + (u * angle.cos() + v * angle.sin()) * radius)
+                    };
+                    for i in 0..CIRCLE_SEGMENTS {
+                        lines.push((on_circle(i), on_circle(i + 1)));
+                    }
+                }
+                lines
+            }
+        }
+    }
+}
+
+/// The shapes of all registered collision components, recorded at the end of every simulated frame. Since
+/// rollbacks record their frames again, the shapes of earlier frames show what the corrected simulation looked like
+/// at that frame. `current()` holds the shapes of the frame the world shows, `frames_ago()` the ones to overlay.
+///
+/// With the `debug-lines` feature, `GGRSPlugin::with_debug_lines()` draws them. Otherwise turn them into line
+/// segments with `ShapeInstance::lines()` and hand those to a line renderer of your choice, for example with a
+/// different color for the shapes of earlier frames.
+#[derive(Resource, Debug, Clone)]
+pub struct DebugShapes {
+    history: usize,
+    frames: BTreeMap<i32, Vec<ShapeInstance>>,
+}
+
+impl Default for DebugShapes {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY)
+    }
+}
+
+impl DebugShapes {
+    /// Keeps the shapes of the given number of frames.
+    pub fn new(history: usize) -> Self {
+        Self {
+            history: history.max(1),
+            frames: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the shapes at the latest simulated frame.
+    pub fn current(&self) -> &[ShapeInstance] {
+        self.frames_ago(0).unwrap_or_default()
+    }
+
+    /// Returns the shapes `frames` frames before the latest simulated frame, if they are still kept.
+    pub fn frames_ago(&self, frames: usize) -> Option<&[ShapeInstance]> {
+        let (latest, _) = self.frames.iter().next_back()?;
+        self.at_frame(latest - frames as i32)
+    }
+
+    /// Returns the shapes at the given frame, if they are still kept.
+    pub fn at_frame(&self, frame: i32) -> Option<&[ShapeInstance]> {
+        self.frames.get(&frame).map(Vec::as_slice)
+    }
+
+    /// Returns the memory held by the recorded shapes, in bytes.
+    pub(crate) fn memory_size(&self) -> usize {
+        self.frames
+            .values()
+            .map(|shapes| {
+                std::mem::size_of::<(i32, Vec<ShapeInstance>)>()
+                    + shapes.capacity() * std::mem::size_of::<ShapeInstance>()
+            })
+            .sum()
+    }
+
+    fn record(&mut self, frame: i32, type_name: &'static str, shapes: Vec<ShapeInstance>) {
+        // a resimulated frame replaces what was recorded before, later frames will be simulated again as well
+        self.frames.retain(|recorded, _| *recorded <= frame);
+        let recorded = self.frames.entry(frame).or_default();
+        recorded.retain(|instance| instance.type_name != type_name);
+        recorded.extend(shapes);
+        while self.frames.len() > self.history {
+            let oldest = *self
+                .frames
+                .keys()
+                .next()
+                .expect("frames should not be empty");
+            self.frames.remove(&oldest);
+        }
+    }
+}
+
+pub(crate) fn record_debug_shapes_system<C: Component + DebugShape>(
+    frame: Option<Res<RollbackFrame>>,
+    shapes: Option<ResMut<DebugShapes>>,
+    query: Query<(&Rollback, &Transform, &C)>,
+) {
+    let (Some(frame), Some(mut shapes)) = (frame, shapes) else {
+        return;
+    };
+    let mut instances: Vec<_> = query
+        .iter()
+        .map(|(rollback, transform, component)| ShapeInstance {
+            rollback_id: rollback.id(),
+            type_name: std::any::type_name::<C>(),
+            shape: component.debug_shape(),
+            transform: *transform,
+        })
+        .collect();
+    instances.sort_by_key(|instance| instance.rollback_id);
+    shapes.record(**frame, std::any::type_name::<C>(), instances);
+}
+
+/// How `GGRSPlugin::with_debug_lines()` draws the `DebugShapes`.
+#[cfg(feature = "debug-lines")]
+#[derive(Resource, Debug, Clone)]
+pub struct DebugLines {
+    /// Number of frames drawn, the current one included.
+    pub frames: usize,
+    /// Color of the shapes at the current frame.
+    pub current: Color,
+    /// Color of the shapes at the oldest frame drawn. The frames in between blend from `current` to this one.
+    pub oldest: Color,
+}
+
+#[cfg(feature = "debug-lines")]
+impl Default for DebugLines {
+    fn default() -> Self {
+        Self {
+            frames: 4,
+            current: Color::GREEN,
+            oldest: Color::rgba(1., 0., 0., 0.25),
+        }
+    }
+}
+
+/// Marks the entity with the mesh of the `DebugLines`.
+#[cfg(feature = "debug-lines")]
+#[derive(Component)]
+pub(crate) struct DebugLinesMesh;
+
+/// Builds a mesh with the lines of the shapes of every frame drawn, replacing the one of the last update.
+#[cfg(feature = "debug-lines")]
+pub(crate) fn draw_debug_lines_system(
+    mut commands: Commands,
+    shapes: Option<Res<DebugShapes>>,
+    lines: Res<DebugLines>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    query: Query<&Handle<Mesh>, With<DebugLinesMesh>>,
+) {
+    use bevy::render::{mesh::PrimitiveTopology, view::NoFrustumCulling};
+
+    let Some(shapes) = shapes else {
+        return;
+    };
+    let current = lines.current.as_linear_rgba_f32();
+    let oldest = lines.oldest.as_linear_rgba_f32();
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    for age in 0..lines.frames {
+        let Some(instances) = shapes.frames_ago(age) else {
+            break;
+        };
+        let blend = age as f32 / (lines.frames - 1).max(1) as f32;
+        let color: [f32; 4] =
+            std::array::from_fn(|i| current[i] + (oldest[i] - current[i]) * blend);
+        for (start, end) in instances.iter().flat_map(ShapeInstance::lines) {
+            positions.extend([start.to_array(), end.to_array()]);
+            colors.extend([color, color]);
+        }
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    // unlit, but the pipeline of the `StandardMaterial` expects normals
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 0., 1.]; positions.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+
+    match query
+        .get_single()
+        .ok()
+        .and_then(|handle| meshes.get_mut(handle))
+    {
+        Some(existing) => *existing = mesh,
+        None => {
+            let material = StandardMaterial {
+                base_color: Color::WHITE,
+                unlit: true,
+                alpha_mode: AlphaMode::Blend,
+                ..default()
+            };
+            commands.spawn((
+                PbrBundle {
+                    mesh: meshes.add(mesh),
+                    material: materials.add(material),
+                    ..default()
+                },
+                // the bounds change with every update
+                NoFrustumCulling,
+                DebugLinesMesh,
+            ));
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    ggrs_stage::StageEvent,
+    migration::PeerAddressChanged,
+    shutdown::PeerLeft,
+    socket::{channel, MultiplexSocket},
+};
+
+const DEFAULT_MIN_INTERVAL: u32 = 10;
+const DEFAULT_MAX_INTERVAL: u32 = 600;
+const DEFAULT_STABLE_CHECKS: u32 = 5;
+const DEFAULT_UNSTABLE_ROLLBACK: i32 = 8;
+/// Number of confirmed checksums kept to compare with the checksums of peers that are behind.
+const CHECKSUM_HISTORY: usize = 1024;
+
+#[derive(Serialize, Deserialize)]
+struct ChecksumPacket {
+    frame: i32,
+    checksum: u64,
+}
+
+/// Sent when the confirmed state of a frame differs from the one of a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesyncCheckFailed<A> {
+    pub peer: A,
+    /// The state at the start of this frame differs.
+    pub frame: i32,
+    pub local: u64,
+    pub remote: u64,
+}
+
+/// Compares the checksums of confirmed states with the peers at a cadence that follows how stable the session is,
+/// as an alternative to the fixed interval of GGRS' desync detection. Insert it as a resource on all peers of a
+/// `P2PSession`; differences are reported as `DesyncCheckFailed` events.
+///
+/// Checks start at the shortest interval. Every few matching checks, the interval doubles up to the longest one.
+/// Mismatches, rollbacks deeper than a threshold, peers that left or moved to another address, and calls to
+/// `tighten()` go back to the shortest interval. Call `tighten()` on GGRS' connection events, such as
+/// `NetworkInterrupted`, which bevy_ggrs doesn't see. The current cadence is shown by `NetcodeHud`.
+///
+/// Every peer picks its own cadence, the receiver compares with the checksum it saved for the same frame. With
+/// `NetworkProfile::LowBandwidth`, checks are at least a second apart and only compare frames that are multiples of
+/// a second, which every peer takes the checksum of, sparse saving or not.
+#[derive(Resource)]
+pub struct AdaptiveDesyncCheck<A> {
+    socket: MultiplexSocket<A>,
+    peers: Vec<A>,
+    min_interval: u32,
+    max_interval: u32,
+    /// the shortest interval allowed by the `NetworkProfile`
+    floor: u32,
+    stable_checks: u32,
+    unstable_rollback: i32,
+    interval: u32,
+    /// matching checks since the interval last changed
+    stable: u32,
+    /// the first frame whose state is sent at the next check
+    next_check: i32,
+    /// the checksums of the saved states that are not final yet
+    saved: BTreeMap<i32, u64>,
+    /// the checksums of final states
+    confirmed: BTreeMap<i32, u64>,
+    /// the latest frame whose state is final
+    final_frame: i32,
+    /// the first frame that hasn't been simulated yet
+    next_frame: i32,
+    /// checksums of peers for frames that aren't final here yet
+    pending: Vec<(A, ChecksumPacket)>,
+    checks: usize,
+    failures: Vec<DesyncCheckFailed<A>>,
+}
+
+impl<A> AdaptiveDesyncCheck<A>
+where
+    A: Clone + PartialEq + Send + Sync + 'static,
+{
+    /// Compares checksums with the given peers.
+    pub fn new(socket: MultiplexSocket<A>, peers: Vec<A>) -> Self {
+        Self {
+            socket,
+            peers,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            max_interval: DEFAULT_MAX_INTERVAL,
+            floor: 1,
+            stable_checks: DEFAULT_STABLE_CHECKS,
+            unstable_rollback: DEFAULT_UNSTABLE_ROLLBACK,
+            interval: DEFAULT_MIN_INTERVAL,
+            stable: 0,
+            next_check: 0,
+            saved: BTreeMap::new(),
+            confirmed: BTreeMap::new(),
+            final_frame: -1,
+            next_frame: 0,
+            pending: Vec::new(),
+            checks: 0,
+            failures: Vec::new(),
+        }
+    }
+
+    /// Changes the shortest and longest number of frames between checks. Defaults to 10 and 600.
+    pub fn with_interval_range(mut self, min: u32, max: u32) -> Self {
+        self.min_interval = min.max(1);
+        self.max_interval = max.max(self.min_interval);
+        self.interval = self.min_interval;
+        self
+    }
+
+    /// Changes the number of matching checks after which the interval doubles. Defaults to 5.
+    pub fn with_stable_checks(mut self, checks: u32) -> Self {
+        self.stable_checks = checks.max(1);
+        self
+    }
+
+    /// Changes the depth of rollbacks in frames that counts as instability. Defaults to 8.
+    pub fn with_unstable_rollback(mut self, frames: i32) -> Self {
+        self.unstable_rollback = frames;
+        self
+    }
+
+    /// Returns the number of frames between the checks sent to the peers.
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    /// Returns the number of checksums of peers compared so far.
+    pub fn checks(&self) -> usize {
+        self.checks
+    }
+
+    /// Goes back to the shortest interval, with the next check right away.
+    pub fn tighten(&mut self) {
+        self.interval = self.min_interval.max(self.floor);
+        self.stable = 0;
+        self.next_check = self.next_check.min(self.final_frame + 1);
+    }
+
+    pub(crate) fn set_interval_floor(&mut self, frames: u32) {
+        self.floor = frames.max(1);
+        self.interval = self.interval.max(self.floor);
+    }
+
+    /// Returns true if the state at the start of `frame` may be compared, but hasn't been saved.
+    pub(crate) fn needs_checksum(&self, frame: i32) -> bool {
+        self.floor > 1 && frame % self.floor as i32 == 0 && !self.saved.contains_key(&frame)
+    }
+
+    pub(crate) fn on_stage_event(&mut self, event: StageEvent) {
+        match event {
+            StageEvent::Saved { frame, checksum } => {
+                self.saved.insert(frame, checksum);
+            }
+            StageEvent::Loaded { frame } => {
+                // states saved after the loaded one will be saved again, if at all
+                self.saved.split_off(&(frame + 1));
+                if self.next_frame - frame >= self.unstable_rollback {
+                    self.tighten();
+                }
+            }
+            StageEvent::Advanced { frame } => self.next_frame = self.next_frame.max(frame + 1),
+            StageEvent::Confirmed { frame } => self.confirm(frame + 1),
+            StageEvent::Reset => {
+                self.saved.clear();
+                self.confirmed.clear();
+                self.pending.clear();
+                self.final_frame = -1;
+                self.next_frame = 0;
+                self.next_check = 0;
+                self.tighten();
+            }
+            StageEvent::Advancing { .. } => {}
+        }
+    }
+
+    /// All states up to the one at the start of `final_frame` can't change anymore.
+    fn confirm(&mut self, final_frame: i32) {
+        self.final_frame = final_frame;
+        let unconfirmed = self.saved.split_off(&(final_frame + 1));
+        let confirmed = std::mem::replace(&mut self.saved, unconfirmed);
+        for (frame, checksum) in confirmed {
+            if frame >= self.next_check && frame % self.floor as i32 == 0 {
+                let packet = bincode::serialize(&ChecksumPacket { frame, checksum })
+                    .expect("should serialize");
+                for peer in &self.peers {
+                    self.socket.send_on(channel::DESYNC_CHECK, &packet, peer);
+                }
+                self.next_check = frame + self.interval as i32;
+            }
+            self.confirmed.insert(frame, checksum);
+        }
+        while self.confirmed.len() > CHECKSUM_HISTORY {
+            self.confirmed.pop_first();
+        }
+        self.compare_pending();
+    }
+
+    /// Collects the checksums of the peers and compares the ones of final states.
+    pub(crate) fn poll(&mut self) {
+        for (addr, data) in self.socket.receive_on(channel::DESYNC_CHECK) {
+            if !self.peers.contains(&addr) {
+                continue;
+            }
+            match bincode::deserialize::<ChecksumPacket>(&data) {
+                Ok(packet) => self.pending.push((addr, packet)),
+                Err(_) => debug!("received a malformed desync check"),
+            }
+        }
+        self.compare_pending();
+    }
+
+    fn compare_pending(&mut self) {
+        let (due, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|(_, packet)| packet.frame <= self.final_frame);
+        self.pending = pending;
+        while self.pending.len() > CHECKSUM_HISTORY {
+            self.pending.remove(0);
+        }
+
+        for (peer, packet) in due {
+            // too old, or not saved with sparse saving
+            let Some(&local) = self.confirmed.get(&packet.frame) else {
+                continue;
+            };
+            self.checks += 1;
+            if local == packet.checksum {
+                self.stable += 1;
+                if self.stable >= self.stable_checks {
+                    self.interval = (self.interval * 2).min(self.max_interval.max(self.floor));
+                    self.stable = 0;
+                }
+            } else {
+                warn!(
+                    "desync check: the state of frame {} differs from the one of a peer",
+                    packet.frame
+                );
+                self.failures.push(DesyncCheckFailed {
+                    peer,
+                    frame: packet.frame,
+                    local,
+                    remote: packet.checksum,
+                });
+                self.tighten();
+            }
+        }
+    }
+
+    pub(crate) fn take_failures(&mut self) -> Vec<DesyncCheckFailed<A>> {
+        std::mem::take(&mut self.failures)
+    }
+}
+
+pub(crate) fn poll_desync_check_system<A>(
+    check: Option<ResMut<AdaptiveDesyncCheck<A>>>,
+    mut left: EventReader<PeerLeft<A>>,
+    mut moved: EventReader<PeerAddressChanged<A>>,
+    mut failures: EventWriter<DesyncCheckFailed<A>>,
+) where
+    A: Clone + PartialEq + Send + Sync + 'static,
+{
+    let Some(mut check) = check else {
+        return;
+    };
+    // connection events are a good time to make sure everyone is still in sync
+    if left.iter().count() + moved.iter().count() > 0 {
+        check.tighten();
+    }
+    check.poll();
+    failures.send_batch(check.take_failures());
+}
@@ -0,0 +1,374 @@
+use bevy::prelude::*;
+use ggrs::{Config, PlayerHandle};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    socket::{channel, MultiplexSocket},
+    RollbackFrame,
+};
+
+/// A device a local player reads its input from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AssignedDevice {
+    /// The keyboard, and the mouse along with it.
+    Keyboard,
+    /// A single gamepad.
+    Gamepad(Gamepad),
+}
+
+/// What happens while the gamepad of a local player is disconnected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisconnectPolicy {
+    /// The simulation stops until the gamepad is back or another device took over. With
+    /// `DeviceAssignment::with_peers()`, all peers stop at the same frame.
+    #[default]
+    Pause,
+    /// The player continues with the substitute input, which is sent to the other peers like a regular input.
+    Substitute,
+}
+
+/// "I am in pause number `pauses` and stop before `stop_at`, or it has ended"
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+struct PauseState {
+    pauses: u32,
+    stop_at: i32,
+    ended: bool,
+    disconnected: bool,
+}
+
+/// The other peers of a session, without their address type.
+trait PausePeers: Send + Sync {
+    fn len(&self) -> usize;
+    fn send(&self, state: &PauseState);
+    /// Returns the states received since the last call, with the index of the peer that sent them.
+    fn receive(&self) -> Vec<(usize, PauseState)>;
+}
+
+struct Peers<A> {
+    socket: MultiplexSocket<A>,
+    addrs: Vec<A>,
+}
+
+impl<A: Clone + PartialEq + Send + Sync + 'static> PausePeers for Peers<A> {
+    fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    fn send(&self, state: &PauseState) {
+        let packet = bincode::serialize(state).expect("should serialize");
+        for addr in &self.addrs {
+            self.socket.send_on(channel::DEVICE_PAUSE, &packet, addr);
+        }
+    }
+
+    fn receive(&self) -> Vec<(usize, PauseState)> {
+        let mut received = Vec::new();
+        for (addr, data) in self.socket.receive_on(channel::DEVICE_PAUSE) {
+            let Some(peer) = self.addrs.iter().position(|a| *a == addr) else {
+                continue;
+            };
+            match bincode::deserialize(&data) {
+                Ok(state) => received.push((peer, state)),
+                Err(_) => debug!("received a malformed device pause packet"),
+            }
+        }
+        received
+    }
+}
+
+/// Maps keyboard and gamepads to local player handles at runtime. Insert it as a resource and read the device of
+/// a handle in your input system with `device()`. Pressing the join key or button on an unassigned device assigns
+/// it to the first local handle without a device, or to one whose gamepad has been disconnected.
+///
+/// While an assigned gamepad is disconnected, the `DisconnectPolicy` decides whether the simulation pauses or the
+/// player continues with a substitute input. The substitute input takes the place of the input system and goes
+/// through GGRS like any other local input.
+///
+/// In a `P2PSession`, pass the other peers to `with_peers()` on every peer, so a pause stops all of them at the same
+/// frame. Each peer that learns about a pause proposes to stop before the next frame it would simulate and waits
+/// until it knows the proposals of everyone; all peers then simulate up to the latest proposal and stop there. The
+/// disconnected player plays these frames with the substitute input. The pause ends for everyone once no peer has a
+/// disconnected gamepad anymore.
+#[derive(Resource)]
+pub struct DeviceAssignment<T: Config> {
+    handles: Vec<PlayerHandle>,
+    devices: BTreeMap<PlayerHandle, AssignedDevice>,
+    disconnected: Vec<PlayerHandle>,
+    join_key: KeyCode,
+    join_button: GamepadButtonType,
+    policy: DisconnectPolicy,
+    substitute: T::Input,
+    peers: Option<Box<dyn PausePeers>>,
+    /// the number of pauses so far, the current one included
+    pauses: u32,
+    /// the frame we proposed to stop before in the current pause
+    pause: Option<i32>,
+    /// the latest state of each peer
+    remote: Vec<Option<PauseState>>,
+}
+
+impl<T: Config> DeviceAssignment<T> {
+    /// Creates an assignment for the given local player handles, filled in this order.
+    pub fn new(handles: Vec<PlayerHandle>) -> Self {
+        Self {
+            handles,
+            devices: BTreeMap::new(),
+            disconnected: Vec::new(),
+            join_key: KeyCode::Return,
+            join_button: GamepadButtonType::Start,
+            policy: DisconnectPolicy::default(),
+            substitute: bytemuck::Zeroable::zeroed(),
+            peers: None,
+            pauses: 0,
+            pause: None,
+            remote: Vec::new(),
+        }
+    }
+
+    /// Pauses together with the peers at the given addresses, which need a `DeviceAssignment` with us as their peer
+    /// as well. Use a clone of the `MultiplexSocket` the session runs on.
+    pub fn with_peers<A>(mut self, socket: MultiplexSocket<A>, peers: Vec<A>) -> Self
+    where
+        A: Clone + PartialEq + Send + Sync + 'static,
+    {
+        self.remote = vec![None; peers.len()];
+        self.peers = Some(Box::new(Peers {
+            socket,
+            addrs: peers,
+        }));
+        self
+    }
+
+    /// Changes the key that assigns the keyboard. Defaults to `KeyCode::Return`.
+    pub fn with_join_key(mut self, key: KeyCode) -> Self {
+        self.join_key = key;
+        self
+    }
+
+    /// Changes the button that assigns a gamepad. Defaults to `GamepadButtonType::Start`.
+    pub fn with_join_button(mut self, button: GamepadButtonType) -> Self {
+        self.join_button = button;
+        self
+    }
+
+    /// Changes what happens while a gamepad is disconnected. Defaults to `DisconnectPolicy::Pause`.
+    pub fn with_disconnect_policy(mut self, policy: DisconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Changes the input used with `DisconnectPolicy::Substitute`. Defaults to the zeroed input.
+    pub fn with_substitute_input(mut self, input: T::Input) -> Self {
+        self.substitute = input;
+        self
+    }
+
+    /// Returns the device of the given local player.
+    pub fn device(&self, handle: PlayerHandle) -> Option<AssignedDevice> {
+        self.devices.get(&handle).copied()
+    }
+
+    /// Returns the local player the device is assigned to.
+    pub fn handle(&self, device: AssignedDevice) -> Option<PlayerHandle> {
+        self.devices
+            .iter()
+            .find(|(_, d)| **d == device)
+            .map(|(handle, _)| *handle)
+    }
+
+    /// Assigns the device to the given local player, replacing the device it had and taking the device away from
+    /// the player it was assigned to before.
+    pub fn assign(&mut self, handle: PlayerHandle, device: AssignedDevice) {
+        self.devices.retain(|_, d| *d != device);
+        self.devices.insert(handle, device);
+        self.disconnected.retain(|h| *h != handle);
+    }
+
+    /// Removes the device of the given local player.
+    pub fn unassign(&mut self, handle: PlayerHandle) {
+        self.devices.remove(&handle);
+        self.disconnected.retain(|h| *h != handle);
+    }
+
+    /// Returns true if all local players have a device.
+    pub fn is_complete(&self) -> bool {
+        self.handles
+            .iter()
+            .all(|handle| self.devices.contains_key(handle))
+    }
+
+    /// Returns true while the gamepad of the given local player is disconnected.
+    pub fn is_disconnected(&self, handle: PlayerHandle) -> bool {
+        self.disconnected.contains(&handle)
+    }
+
+    /// Returns true during a pause caused by a disconnected gamepad, here or on another peer. The simulation may
+    /// still run for a few frames, up to the frame all peers agreed to stop at.
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_some()
+    }
+
+    /// Returns the input replacing the input system for the given player, if its gamepad is disconnected.
+    pub(crate) fn substitute(&self, handle: PlayerHandle) -> Option<T::Input> {
+        let substituted = (self.policy == DisconnectPolicy::Substitute || self.is_paused())
+            && self.is_disconnected(handle);
+        substituted.then_some(self.substitute)
+    }
+
+    /// Returns true if the simulation has to stop before `frame`, because of a pause whose stopping frame is
+    /// either reached or not agreed on yet.
+    pub(crate) fn holds(&self, frame: i32) -> bool {
+        let Some(proposed) = self.pause else {
+            return false;
+        };
+        let mut stop_at = proposed;
+        for state in self.remote.iter() {
+            match state {
+                Some(state) if state.pauses == self.pauses => stop_at = stop_at.max(state.stop_at),
+                // the peer has not proposed a frame yet
+                _ => return frame >= proposed,
+            }
+        }
+        frame >= stop_at
+    }
+
+    /// Starts, joins and ends pauses together with the peers. `frame` is the next frame to be simulated.
+    fn update_pause(&mut self, frame: i32) {
+        let received = self
+            .peers
+            .as_ref()
+            .map_or_else(Vec::new, |peers| peers.receive());
+        let mut missed = false;
+        for (peer, state) in received {
+            let newer = self.remote[peer].map_or(true, |known| state.pauses >= known.pauses);
+            if newer {
+                self.remote[peer] = Some(state);
+            }
+            // the peer may have missed that the pause has ended
+            missed |= state.pauses == self.pauses && !state.ended && self.pause.is_none();
+        }
+
+        let disconnected = self.policy == DisconnectPolicy::Pause && !self.disconnected.is_empty();
+        // a peer started a pause we don't know about yet, possibly while we missed the end of the previous one
+        let started = self
+            .remote
+            .iter()
+            .flatten()
+            .filter(|state| state.pauses > self.pauses && !state.ended)
+            .map(|state| state.pauses)
+            .max();
+        let latest = self.remote.iter().flatten().map(|state| state.pauses).max();
+        let mut changed = false;
+        if let Some(pauses) = started {
+            self.pauses = pauses;
+            self.pause = Some(frame);
+            changed = true;
+        } else if self.pause.is_none() {
+            // don't reuse the number of a pause that ended without us
+            self.pauses = self.pauses.max(latest.unwrap_or_default());
+            if disconnected {
+                self.pauses += 1;
+                self.pause = Some(frame);
+                changed = true;
+            }
+        } else {
+            let current =
+                |state: &Option<PauseState>| state.filter(|state| state.pauses == self.pauses);
+            let ended_elsewhere = self
+                .remote
+                .iter()
+                .any(|state| current(state).map_or(false, |state| state.ended));
+            let connected_everywhere = self
+                .remote
+                .iter()
+                .all(|state| current(state).map_or(false, |state| !state.disconnected));
+            if ended_elsewhere || (connected_everywhere && !disconnected) {
+                self.pause = None;
+                changed = true;
+            }
+        }
+
+        if changed || missed || self.pause.is_some() {
+            if let Some(peers) = self.peers.as_ref() {
+                peers.send(&PauseState {
+                    pauses: self.pauses,
+                    stop_at: self.pause.unwrap_or(frame),
+                    ended: self.pause.is_none(),
+                    disconnected,
+                });
+            }
+        }
+    }
+
+    /// Assigns a device that pressed its join key or button to the first free handle.
+    fn join(&mut self, device: AssignedDevice) {
+        if self.handle(device).is_some() {
+            return;
+        }
+        let free = self
+            .handles
+            .iter()
+            .find(|handle| !self.devices.contains_key(handle))
+            .or_else(|| self.disconnected.first())
+            .copied();
+        match free {
+            Some(handle) => {
+                info!("assigned {device:?} to local player {handle}");
+                self.assign(handle, device);
+            }
+            None => debug!("no free local player for {device:?}"),
+        }
+    }
+}
+
+/// Handles press-to-join, tracks which assigned gamepads are connected and coordinates pauses with the peers.
+pub(crate) fn assign_devices_system<T: Config>(
+    keys: Option<Res<Input<KeyCode>>>,
+    buttons: Option<Res<Input<GamepadButton>>>,
+    gamepads: Option<Res<Gamepads>>,
+    frame: Option<Res<RollbackFrame>>,
+    assignment: Option<ResMut<DeviceAssignment<T>>>,
+) {
+    let Some(mut assignment) = assignment else {
+        return;
+    };
+
+    if let Some(keys) = keys {
+        if keys.just_pressed(assignment.join_key) {
+            assignment.join(AssignedDevice::Keyboard);
+        }
+    }
+    if let Some(buttons) = buttons {
+        let join_button = assignment.join_button;
+        for button in buttons.get_just_pressed() {
+            if button.button_type == join_button {
+                assignment.join(AssignedDevice::Gamepad(button.gamepad));
+            }
+        }
+    }
+
+    let disconnected: Vec<_> = assignment
+        .devices
+        .iter()
+        .filter(|(_, device)| match device {
+            AssignedDevice::Keyboard => false,
+            // without gamepad support, there is nothing that could tell us about a disconnect
+            AssignedDevice::Gamepad(gamepad) => gamepads
+                .as_ref()
+                .map_or(false, |gamepads| !gamepads.contains(*gamepad)),
+        })
+        .map(|(handle, _)| *handle)
+        .collect();
+    if disconnected != assignment.disconnected {
+        for handle in disconnected.iter() {
+            if !assignment.disconnected.contains(handle) {
+                warn!("the gamepad of local player {handle} has been disconnected");
+            }
+        }
+        assignment.disconnected = disconnected;
+    }
+
+    let frame = frame.map_or(0, |frame| **frame + 1);
+    assignment.update_pause(frame);
+}
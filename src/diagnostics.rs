@@ -0,0 +1,230 @@
+use bevy::prelude::*;
+use ggrs::{Config, NetworkStats, PlayerHandle};
+use instant::{Duration, Instant};
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{log_plugin::RollbackStats, state_transfer, Session};
+
+const HEADER: &str = "# bevy_ggrs diagnostics v1";
+/// Number of saved snapshots whose metadata is kept.
+const SNAPSHOT_HISTORY: usize = 16;
+/// Time between two samples of the network stats.
+const NETWORK_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Number of samples of the network stats kept, of all players together.
+const NETWORK_HISTORY: usize = 600;
+
+/// What is known about a saved snapshot without its contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotMetadata {
+    pub frame: i32,
+    pub checksum: u64,
+    /// Number of rollback entities in the snapshot.
+    pub entities: usize,
+    /// Estimated size of the snapshot. Includes the saved values and their heap allocations while
+    /// `GGRSPlugin::with_snapshot_stats()` or a `MemoryBudget` is enabled, since measuring them walks every value.
+    pub bytes: usize,
+}
+
+/// Collects what a player can attach to a bug report, always available as a resource. Call
+/// `export_diagnostics_bundle()` to write everything into a single zip file:
+/// - `config.txt`: the config checksum and what it is computed from,
+/// - `requests.trace`: the request trace, if enabled with `GGRSPlugin::with_request_trace()`,
+/// - `network_stats.txt`: the network stats of every remote player, sampled once per second,
+/// - `rollbacks.txt`: the `RollbackStats`,
+/// - `snapshots.txt`: frame, checksum, entity count and size of the last snapshots saved.
+///
+/// The config checksum covers the settings of the plugin, all registered rollback types and the state loaded with
+/// `GGRSPlugin::with_initial_scene()`. Peers whose checksums differ run different builds or levels and are bound to
+/// desync, so a `MatchSetupExchange` compares them and doesn't complete on a mismatch.
+#[derive(Resource, Debug, Clone)]
+pub struct SessionDiagnostics {
+    config: String,
+    config_checksum: u64,
+    trace_path: Option<PathBuf>,
+    started: Instant,
+    last_sample: Option<Instant>,
+    network_stats: VecDeque<(Duration, PlayerHandle, NetworkStats)>,
+    rollbacks: RollbackStats,
+    snapshots: VecDeque<SnapshotMetadata>,
+}
+
+impl SessionDiagnostics {
+    pub(crate) fn new(config: String, trace_path: Option<PathBuf>) -> Self {
+        Self {
+            config_checksum: state_transfer::checksum(config.as_bytes()),
+            config,
+            trace_path,
+            started: Instant::now(),
+            last_sample: None,
+            network_stats: VecDeque::new(),
+            rollbacks: RollbackStats::default(),
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    /// Returns the checksum of the plugin settings, the registered rollback types and the initial state.
+    pub fn config_checksum(&self) -> u64 {
+        self.config_checksum
+    }
+
+    /// Returns the memory held by the collected samples, in bytes.
+    pub(crate) fn memory_size(&self) -> usize {
+        self.config.capacity()
+            + self.network_stats.capacity()
+                * std::mem::size_of::<(Duration, PlayerHandle, NetworkStats)>()
+            + self.snapshots.capacity() * std::mem::size_of::<SnapshotMetadata>()
+    }
+
+    /// Returns the metadata of the last snapshots saved, the latest one last.
+    pub fn snapshots(&self) -> impl Iterator<Item = &SnapshotMetadata> {
+        self.snapshots.iter()
+    }
+
+    /// Writes everything collected so far into a zip file at `path`, replacing an existing file.
+    pub fn export_diagnostics_bundle(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut zip = ZipWriter::new(fs::File::create(path)?);
+
+        add_entry(
+            &mut zip,
+            "config.txt",
+            format!(
+                "{HEADER}\nchecksum: {:016x}\n{}",
+                self.config_checksum, self.config
+            )
+            .as_bytes(),
+        )?;
+
+        if let Some(trace_path) = &self.trace_path {
+            match fs::read(trace_path) {
+                Ok(trace) => add_entry(&mut zip, "requests.trace", &trace)?,
+                Err(e) => warn!(
+                    "diagnostics: could not read the request trace at {}: {e}",
+                    trace_path.display()
+                ),
+            }
+        }
+
+        let mut network_stats = String::new();
+        for (time, handle, stats) in &self.network_stats {
+            let _ = writeln!(
+                network_stats,
+                "{:.1}s player {handle}: {stats:?}",
+                time.as_secs_f32()
+            );
+        }
+        add_entry(&mut zip, "network_stats.txt", network_stats.as_bytes())?;
+
+        let rollbacks = &self.rollbacks;
+        add_entry(
+            &mut zip,
+            "rollbacks.txt",
+            format!(
+                "rollbacks: {}\nresimulated frames: {}\nmax depth: {}\n",
+                rollbacks.rollbacks, rollbacks.resimulated_frames, rollbacks.max_depth
+            )
+            .as_bytes(),
+        )?;
+
+        let mut snapshots = String::new();
+        for snapshot in &self.snapshots {
+            let _ = writeln!(
+                snapshots,
+                "{} {:016x} {} entities {} bytes",
+                snapshot.frame, snapshot.checksum, snapshot.entities, snapshot.bytes
+            );
+        }
+        add_entry(&mut zip, "snapshots.txt", snapshots.as_bytes())?;
+
+        zip.finish()?.flush()
+    }
+
+    pub(crate) fn record_snapshot(&mut self, snapshot: SnapshotMetadata) {
+        self.snapshots.push_back(snapshot);
+        while self.snapshots.len() > SNAPSHOT_HISTORY {
+            self.snapshots.pop_front();
+        }
+    }
+
+    fn record_network_stats(&mut self, handle: PlayerHandle, stats: NetworkStats) {
+        self.network_stats
+            .push_back((self.started.elapsed(), handle, stats));
+        while self.network_stats.len() > NETWORK_HISTORY {
+            self.network_stats.pop_front();
+        }
+    }
+}
+
+/// Describes the settings that have to match between peers, for the config checksum.
+pub(crate) fn describe_config<T: Config>(
+    fps: usize,
+    type_names: &mut [String],
+    initial_state: Option<u64>,
+) -> String {
+    type_names.sort();
+    let mut config = format!(
+        "bevy_ggrs: {}\nfps: {fps}\ninput: {} ({} bytes)\n",
+        env!("CARGO_PKG_VERSION"),
+        std::any::type_name::<T::Input>(),
+        std::mem::size_of::<T::Input>()
+    );
+    for name in type_names.iter() {
+        let _ = writeln!(config, "rollback type: {name}");
+    }
+    if let Some(checksum) = initial_state {
+        let _ = writeln!(config, "initial state: {checksum:016x}");
+    }
+    config
+}
+
+pub(crate) fn record_diagnostics_system<T: Config>(
+    session: Option<Res<Session<T>>>,
+    stats: Res<RollbackStats>,
+    mut diagnostics: ResMut<SessionDiagnostics>,
+) {
+    if stats.is_changed() {
+        diagnostics.rollbacks = (*stats).clone();
+    }
+
+    let now = Instant::now();
+    let due = diagnostics.last_sample.map_or(true, |last| {
+        now.duration_since(last) >= NETWORK_SAMPLE_INTERVAL
+    });
+    if !due {
+        return;
+    }
+    match session.as_deref() {
+        Some(Session::P2PSession(session)) => {
+            diagnostics.last_sample = Some(now);
+            for handle in 0..session.num_players() {
+                // local players have no network stats
+                if let Ok(stats) = session.network_stats(handle) {
+                    diagnostics.record_network_stats(handle, stats);
+                }
+            }
+        }
+        Some(Session::SpectatorSession(session)) => {
+            diagnostics.last_sample = Some(now);
+            if let Ok(stats) = session.network_stats() {
+                diagnostics.record_network_stats(0, stats);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Adds an uncompressed entry, which is all a diagnostics bundle needs. Entries of 4 GiB and more are written in
+/// the zip64 format.
+fn add_entry(zip: &mut ZipWriter<fs::File>, name: &str, contents: &[u8]) -> io::Result<()> {
+    let options = FileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(contents.len() as u64 >= u32::MAX as u64);
+    zip.start_file(name, options)?;
+    zip.write_all(contents)
+}
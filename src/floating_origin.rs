@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+
+use crate::Rollback;
+
+/// The offset of the simulated space from the true world origin, in whole cells. Large worlds shift everything back
+/// towards the origin to keep `f32` positions precise. Set up with `GGRSPlugin::with_floating_origin()`, which
+/// registers this resource for rollback and runs the shift at the end of every frame of the rollback schedule, so
+/// it happens at the same frame on every peer and is saved and restored with the snapshots.
+#[derive(Resource, Reflect, Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[reflect(Resource, Hash, PartialEq)]
+pub struct FloatingOrigin {
+    cell_size: u32,
+    x: i64,
+    y: i64,
+    z: i64,
+}
+
+impl FloatingOrigin {
+    pub(crate) fn new(cell_size: u32) -> Self {
+        Self {
+            cell_size: cell_size.max(1),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the size of a cell. The origin moves in steps of whole cells, once the focus is more than half a
+    /// cell away from it.
+    pub fn cell_size(&self) -> u32 {
+        self.cell_size
+    }
+
+    /// Returns the cell the simulated space is centered on.
+    pub fn cell(&self) -> [i64; 3] {
+        [self.x, self.y, self.z]
+    }
+
+    /// Converts a position in the simulated space to a position relative to the true world origin.
+    pub fn to_world(&self, translation: Vec3) -> [f64; 3] {
+        let size = self.cell_size as f64;
+        [
+            self.x as f64 * size + translation.x as f64,
+            self.y as f64 * size + translation.y as f64,
+            self.z as f64 * size + translation.z as f64,
+        ]
+    }
+
+    /// Converts a position relative to the true world origin to a position in the simulated space.
+    pub fn to_local(&self, position: [f64; 3]) -> Vec3 {
+        let size = self.cell_size as f64;
+        Vec3::new(
+            (position[0] - self.x as f64 * size) as f32,
+            (position[1] - self.y as f64 * size) as f32,
+            (position[2] - self.z as f64 * size) as f32,
+        )
+    }
+
+    /// Returns the distance between two origins in the simulated space.
+    fn offset_to(&self, other: &Self) -> Vec3 {
+        let size = self.cell_size as f32;
+        Vec3::new(
+            (other.x - self.x) as f32 * size,
+            (other.y - self.y) as f32 * size,
+            (other.z - self.z) as f32 * size,
+        )
+    }
+}
+
+/// Marks the rollback entity the origin follows, usually the player or the camera target. All peers must put it on
+/// the same entity, which must not have a parent. If several entities are marked, the one with the lowest rollback
+/// id is followed.
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+#[reflect(Component)]
+pub struct OriginFocus;
+
+/// Moves the origin to the cell of the focus and shifts all root rollback entities accordingly.
+pub(crate) fn shift_origin_system(
+    mut origin: ResMut<FloatingOrigin>,
+    mut roots: Query<(&Rollback, &mut Transform, Option<&OriginFocus>), Without<Parent>>,
+) {
+    // the query order may differ between peers, the rollback id doesn't
+    let Some((_, focus, _)) = roots
+        .iter()
+        .filter(|(_, _, focus)| focus.is_some())
+        .min_by_key(|(rollback, _, _)| rollback.id())
+    else {
+        return;
+    };
+    let size = origin.cell_size as f32;
+    let cell = (focus.translation / size).round();
+    if cell == Vec3::ZERO {
+        return;
+    }
+    let shift = cell * size;
+    for (_, mut transform, _) in roots.iter_mut() {
+        transform.translation -= shift;
+    }
+    origin.x += cell.x as i64;
+    origin.y += cell.y as i64;
+    origin.z += cell.z as i64;
+    debug!("moved the floating origin to {:?}", origin.cell());
+}
+
+/// Shifts root entities outside of the rollback (cameras, effects, ...) whenever the origin moved, including moves
+/// that were undone by a rollback.
+pub(crate) fn shift_presentation_system(
+    origin: Option<Res<FloatingOrigin>>,
+    mut applied: Local<Option<FloatingOrigin>>,
+    mut roots: Query<&mut Transform, (Without<Rollback>, Without<Parent>)>,
+) {
+    let Some(origin) = origin else {
+        return;
+    };
+    let previous = applied.replace(origin.clone());
+    let Some(previous) = previous else {
+        return;
+    };
+    let shift = previous.offset_to(&origin);
+    if shift == Vec3::ZERO {
+        return;
+    }
+    for mut transform in roots.iter_mut() {
+        transform.translation -= shift;
+    }
+}
@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+
+/// A timer counting simulation frames instead of wall-clock time, to replace `bevy::time::Timer` in rollback
+/// systems. Ticking a `Timer` with `Time::delta()` makes its state depend on the frame rate of each machine, while a
+/// `FrameTimer` ticked once per frame behaves the same on all peers and after every rollback.
+///
+/// The type is registered for reflection by `GGRSPlugin`, so rollback components and resources can contain it.
+#[derive(Reflect, FromReflect, Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[reflect(Hash, PartialEq)]
+pub struct FrameTimer {
+    duration: u32,
+    elapsed: u32,
+    repeating: bool,
+    paused: bool,
+    finished: bool,
+    times_finished_this_tick: u32,
+}
+
+impl FrameTimer {
+    /// Creates a timer that finishes after the given number of frames.
+    pub fn new(frames: u32, mode: TimerMode) -> Self {
+        Self {
+            duration: frames,
+            repeating: mode == TimerMode::Repeating,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a timer lasting `seconds` at the given update frequency, rounded to the nearest frame.
+    pub fn from_seconds(seconds: f32, fps: usize, mode: TimerMode) -> Self {
+        Self::new((seconds * fps as f32).round() as u32, mode)
+    }
+
+    /// Advances the timer by one frame. Call it exactly once per frame from a system in the rollback schedule.
+    pub fn tick(&mut self) -> &mut Self {
+        self.tick_frames(1)
+    }
+
+    /// Advances the timer by the given number of frames.
+    pub fn tick_frames(&mut self, frames: u32) -> &mut Self {
+        self.times_finished_this_tick = 0;
+        if self.paused || (self.finished && !self.repeating) {
+            return self;
+        }
+        self.elapsed = self.elapsed.saturating_add(frames);
+        if self.elapsed >= self.duration {
+            self.finished = true;
+            if self.repeating && self.duration > 0 {
+                self.times_finished_this_tick = self.elapsed / self.duration;
+                self.elapsed %= self.duration;
+            } else {
+                self.times_finished_this_tick = 1;
+                self.elapsed = self.duration;
+            }
+        } else if self.repeating {
+            self.finished = false;
+        }
+        self
+    }
+
+    /// Returns true if the timer has reached its duration. Repeating timers are only finished in the tick they
+    /// wrapped around in.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns true if the timer finished during the last tick.
+    pub fn just_finished(&self) -> bool {
+        self.times_finished_this_tick > 0
+    }
+
+    /// Returns how often a repeating timer wrapped around during the last tick.
+    pub fn times_finished_this_tick(&self) -> u32 {
+        self.times_finished_this_tick
+    }
+
+    /// Number of frames elapsed since the timer started or last wrapped around.
+    pub fn elapsed(&self) -> u32 {
+        self.elapsed
+    }
+
+    /// Number of frames left until the timer finishes.
+    pub fn remaining(&self) -> u32 {
+        self.duration - self.elapsed
+    }
+
+    /// Number of frames after which the timer finishes.
+    pub fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    /// Changes the number of frames after which the timer finishes, without resetting it.
+    pub fn set_duration(&mut self, frames: u32) {
+        self.duration = frames;
+        self.elapsed = self.elapsed.min(frames);
+    }
+
+    /// Fraction of the duration that has elapsed, between 0 and 1.
+    pub fn percent(&self) -> f32 {
+        match self.duration {
+            0 => 1.,
+            duration => self.elapsed as f32 / duration as f32,
+        }
+    }
+
+    /// Returns the mode the timer was created with.
+    pub fn mode(&self) -> TimerMode {
+        if self.repeating {
+            TimerMode::Repeating
+        } else {
+            TimerMode::Once
+        }
+    }
+
+    /// Stops the timer from advancing when ticked.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Lets the timer advance again when ticked.
+    pub fn unpause(&mut self) {
+        self.paused = false;
+    }
+
+    /// Returns true if the timer is paused.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Restarts the timer from zero.
+    pub fn reset(&mut self) {
+        self.elapsed = 0;
+        self.finished = false;
+        self.times_finished_this_tick = 0;
+    }
+}
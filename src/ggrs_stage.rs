@@ -1,9 +1,97 @@
-use crate::{world_snapshot::WorldSnapshot, PlayerInputs, Session};
-use bevy::{prelude::*, reflect::TypeRegistry};
+use crate::{
+    catch_up::CatchUp,
+    debug_shapes::DebugShapes,
+    device_assignment::DeviceAssignment,
+    diagnostics::{SessionDiagnostics, SnapshotMetadata},
+    input_injection::InjectedInputs,
+    interpolation::FrameAlpha,
+    late_join::{self, LateJoin},
+    level_barrier::LevelBarrier,
+    memory_budget::{MemoryBudget, MemoryBudgetExceeded, MemoryDegradation, MemoryUsage},
+    panic_dump::PanicDump,
+    playback::PlaybackSpeed,
+    prefab,
+    prewarm::SnapshotCapacity,
+    replay_branch::ReplayBranch,
+    request_trace::{self, RequestTrace},
+    resync::DesyncRecovery,
+    rewind::Rewind,
+    rollback_toggles::{self, RollbackToggles},
+    schedule_lint::{self, ScheduleLint},
+    snapshot_stats::SnapshotStats,
+    socket::MultiplexSocket,
+    standby::StandbySession,
+    state_transfer::StateTransfer,
+    world_snapshot::{Measurements, WorldSnapshot},
+    PlayerInputs, RollbackFrame, Session,
+};
+use bevy::{ecs::schedule::StageLabelId, prelude::*, reflect::TypeRegistry, utils::HashMap};
 use ggrs::{
     Config, GGRSError, GGRSRequest, GameStateCell, InputStatus, PlayerHandle, SessionState,
 };
 use instant::{Duration, Instant};
+use std::{
+    any::Any,
+    collections::VecDeque,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Number of frames of inputs kept around while desync recovery is enabled. A follower can only adopt states
+/// that are at most this many frames old.
+const RECOVERY_INPUT_HISTORY: usize = 128;
+
+/// Something the GGRS stage did, passed to all stage hooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StageEvent {
+    /// The world state at the start of `frame` has been saved.
+    Saved { frame: i32, checksum: u64 },
+    /// The world has been restored to the state at the start of `frame`. Every frame from there on will be
+    /// simulated again.
+    Loaded { frame: i32 },
+    /// The rollback schedule is about to simulate `frame`.
+    Advancing { frame: i32 },
+    /// The rollback schedule has simulated `frame`.
+    Advanced { frame: i32 },
+    /// The simulation of all frames up to and including `frame` is final and won't be rolled back.
+    Confirmed { frame: i32 },
+    /// A new session replaced the current one and the stage starts over at frame 0. The frames counted so far
+    /// belong to the previous match.
+    Reset,
+}
+
+/// The result of the last desync bisection enabled with `GGRSPlugin::with_sync_test_bisection()`. Inserted as a
+/// resource once a bisection has finished.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct DesyncBisection {
+    frame: i32,
+    findings: Vec<String>,
+}
+
+impl DesyncBisection {
+    /// Returns the frame the sync test reported the mismatch in.
+    pub fn frame(&self) -> i32 {
+        self.frame
+    }
+
+    /// Iterates over the descriptions of the stages and differences found, as logged.
+    pub fn findings(&self) -> impl Iterator<Item = &str> {
+        self.findings.iter().map(String::as_str)
+    }
+}
+
+/// Lets other parts of the plugin follow what the stage does, with access to the world.
+pub(crate) type StageHook = Box<dyn FnMut(&mut World, StageEvent) + Send + Sync>;
+
+/// Lets other parts of the plugin stop the stage before it simulates the given frame.
+pub(crate) type StageHold = Box<dyn Fn(&World, i32) -> bool + Send + Sync>;
+
+/// Makes the stage simulate one frame per update, however much time has passed, so a `ConformanceScenario` runs
+/// as fast as it can.
+#[cfg(feature = "test-utils")]
+#[derive(Resource)]
+pub(crate) struct StepPerUpdate;
 
 /// The GGRSStage handles updating, saving and loading the game state.
 pub(crate) struct GGRSStage<T>
@@ -14,10 +102,22 @@ where
     schedule: Schedule,
     /// Used to register all types considered when loading and saving
     pub(crate) type_registry: TypeRegistry,
+    /// all registered types, including the ones excluded with `RollbackToggles`
+    registered: TypeRegistry,
+    /// the exclusions `type_registry` was filtered with
+    toggles: RollbackToggles,
+    /// the checksums GGRS got for the frames saved before the exclusions last changed, reported again for them
+    toggled_checksums: HashMap<i32, u64>,
     /// This system is used to get an encoded representation of the input that GGRS can handle
     pub(crate) input_system: Box<dyn System<In = PlayerHandle, Out = T::Input>>,
     /// Instead of using GGRS's internal storage for encoded save states, we save the world here, avoiding serialization into `Vec<u8>`.
     snapshots: Vec<WorldSnapshot>,
+    /// the frame and GGRS cell each snapshot was saved for, so resimulated snapshots can be saved again
+    cells: Vec<Option<(i32, GameStateCell<T::State>)>>,
+    /// the state of frame 0, saved when switching to a standby session and used once GGRS asks for frame 0
+    presaved: Option<WorldSnapshot>,
+    /// the types each snapshot was saved with
+    saved_with: Vec<TypeRegistry>,
     /// fixed FPS our logic is running with
     update_frequency: usize,
     /// counts the number of frames that have been executed
@@ -26,20 +126,84 @@ where
     last_update: Instant,
     /// accumulated time. once enough time has been accumulated, an update is executed
     accumulator: Duration,
+    /// time we are behind and catch up on over the next updates, with `CatchUp::Dilate`
+    backlog: Duration,
+    /// how to catch up after falling behind
+    catch_up: CatchUp,
     /// boolean to see if we should run slow to let remote clients catch up
     run_slow: bool,
+    /// if true, sync test mismatches are investigated by resimulating the recorded frames
+    bisect_desyncs: bool,
+    /// if true, the time spent on each type is measured when saving and loading
+    snapshot_stats: bool,
+    /// if true, the rollback schedule is checked for sources of nondeterminism after it first ran
+    lint_pending: bool,
+    /// true while a `DesyncRecovery` resource exists
+    recovery: bool,
+    /// inputs of the most recent frames, recorded when bisecting or recovering from desyncs
+    input_history: VecDeque<(i32, Vec<(T::Input, InputStatus)>)>,
+    /// the last frame reported as confirmed to the hooks
+    confirmed_frame: i32,
+    /// called for everything the stage does
+    hooks: Vec<StageHook>,
+    /// asked before each frame is simulated
+    holds: Vec<StageHold>,
+    /// if set, a panic in the rollback schedule writes a dump of the current frame to this file
+    panic_dump: Option<PathBuf>,
+    /// number of frames kept for `Rewind`, none if 0
+    rewind_capacity: usize,
+    /// the state at the start of every frame up to `history_frame`, the latest one last, with the frame it was saved
+    /// in and the types it was saved with
+    rewind_history: VecDeque<(i32, WorldSnapshot, TypeRegistry)>,
+    history_frame: i32,
+    /// number of frames inputs are recorded for while we are the host of a `LateJoin`
+    late_join_limit: Option<usize>,
+    /// the inputs of every frame from the start of the session, recorded for late joiners
+    join_inputs: Vec<Vec<(T::Input, InputStatus)>>,
+    /// how far we cut back to stay within the `MemoryBudget`
+    degradation: MemoryDegradation,
 }
 
-impl<T: Config + Send + Sync> Stage for GGRSStage<T> {
+impl<T: Config + Send + Sync> Stage for GGRSStage<T>
+where
+    T::Address: Send + Sync + 'static,
+{
     fn run(&mut self, world: &mut World) {
+        self.process_standby(world);
+
         // get delta time from last run() call and accumulate it
-        let delta = Instant::now().duration_since(self.last_update);
+        let mut delta = Instant::now().duration_since(self.last_update);
+        if let (Some(speed), Some(Session::SpectatorSession(_) | Session::SyncTestSession(_))) = (
+            world.get_resource::<PlaybackSpeed>(),
+            world.get_resource::<Session<T>>(),
+        ) {
+            delta = speed.scale(delta);
+        }
         let mut fps_delta = 1. / self.update_frequency as f64;
         if self.run_slow {
             fps_delta *= 1.1;
         }
         self.accumulator = self.accumulator.saturating_add(delta);
+        self.catch_up.apply(
+            delta,
+            Duration::from_secs_f64(fps_delta),
+            &mut self.accumulator,
+            &mut self.backlog,
+        );
+        #[cfg(feature = "test-utils")]
+        if world.contains_resource::<StepPerUpdate>() {
+            self.accumulator = Duration::from_secs_f64(fps_delta * 1.5);
+            self.backlog = Duration::ZERO;
+        }
         self.last_update = Instant::now();
+        self.recovery = world.contains_resource::<DesyncRecovery<T::Address>>();
+        let late_join = world.get_resource::<LateJoin<T::Address>>();
+        self.late_join_limit = late_join.and_then(|late_join| late_join.recording_limit());
+        let late_join = late_join.is_some();
+        self.degradation = world
+            .get_resource::<MemoryBudget>()
+            .map_or(MemoryDegradation::None, |budget| budget.degradation());
+        self.apply_toggles(world);
 
         // no matter what, poll remotes and send responses
         if let Some(mut session) = world.get_resource_mut::<Session<T>>() {
@@ -54,8 +218,27 @@ impl<T: Config + Send + Sync> Stage for GGRSStage<T> {
             }
         }
 
+        if self.rewind_capacity > 0 {
+            self.process_rewind(world);
+        }
+
         // if we accumulated enough time, do steps
         while self.accumulator.as_secs_f64() > fps_delta {
+            if self.held_by_barrier(world)
+                || self.held_by_late_join(world)
+                || self.held_by_devices(world)
+                || self.holds.iter().any(|hold| hold(world, self.frame))
+            {
+                // continue right away once everyone has loaded, instead of catching up
+                self.accumulator = Duration::ZERO;
+                self.backlog = Duration::ZERO;
+                self.confirm_frames(world);
+                if late_join {
+                    self.process_late_join(world);
+                }
+                break;
+            }
+
             // decrease accumulator
             self.accumulator = self
                 .accumulator
@@ -69,7 +252,24 @@ impl<T: Config + Send + Sync> Stage for GGRSStage<T> {
                 Some(&Session::SpectatorSession(_)) => self.run_spectator(world),
                 _ => self.reset(), // No session has been started yet
             }
+            self.confirm_frames(world);
+            if self.recovery {
+                self.process_resync(world);
+            }
+            if late_join {
+                self.process_late_join(world);
+            }
+            if let Some(mut trace) = world.get_resource_mut::<RequestTrace>() {
+                trace.flush();
+            }
+        }
+
+        if world.contains_resource::<MemoryBudget>() {
+            self.apply_memory_budget(world);
         }
+
+        let alpha = self.accumulator.as_secs_f64() / fps_delta;
+        world.insert_resource(FrameAlpha(alpha.clamp(0., 1.) as f32));
     }
 }
 
@@ -78,22 +278,83 @@ impl<T: Config> GGRSStage<T> {
         Self {
             schedule: Schedule::default(),
             type_registry: TypeRegistry::default(),
+            registered: TypeRegistry::default(),
+            toggles: RollbackToggles::default(),
+            toggled_checksums: HashMap::new(),
             input_system,
             snapshots: Vec::new(),
+            cells: Vec::new(),
+            presaved: None,
+            saved_with: Vec::new(),
             frame: 0,
             update_frequency: 60,
             last_update: Instant::now(),
             accumulator: Duration::ZERO,
+            backlog: Duration::ZERO,
+            catch_up: CatchUp::Burst,
             run_slow: false,
+            bisect_desyncs: false,
+            snapshot_stats: false,
+            lint_pending: false,
+            recovery: false,
+            input_history: VecDeque::new(),
+            confirmed_frame: -1,
+            hooks: Vec::new(),
+            holds: Vec::new(),
+            panic_dump: None,
+            rewind_capacity: 0,
+            rewind_history: VecDeque::new(),
+            history_frame: -1,
+            late_join_limit: None,
+            join_inputs: Vec::new(),
+            degradation: MemoryDegradation::None,
+        }
+    }
+
+    /// Leaves the types excluded with `RollbackToggles` out of the snapshots from now on.
+    fn apply_toggles(&mut self, world: &mut World) {
+        if let Some(mut toggles) = world.get_resource_mut::<RollbackToggles>() {
+            if toggles.needs_registered() {
+                toggles.set_registered(&self.registered);
+            }
         }
+        let default = RollbackToggles::default();
+        let toggles = world.get_resource::<RollbackToggles>().unwrap_or(&default);
+        if toggles.excluded().eq(self.toggles.excluded()) {
+            return;
+        }
+        self.type_registry = toggles.apply(&self.registered);
+        self.toggles = toggles.clone();
+        // GGRS compares the checksums of frames saved again with the ones it got first
+        self.toggled_checksums = self
+            .cells
+            .iter()
+            .zip(self.snapshots.iter())
+            .filter_map(|(cell, snapshot)| Some((cell.as_ref()?.0, snapshot.checksum)))
+            .collect();
+        let excluded: Vec<&str> = self.toggles.excluded().collect();
+        info!(
+            "rollback toggles: excluding [{}] from the snapshots",
+            excluded.join(", ")
+        );
     }
 
     pub(crate) fn reset(&mut self) {
         self.last_update = Instant::now();
         self.accumulator = Duration::ZERO;
+        self.backlog = Duration::ZERO;
         self.frame = 0;
+        self.toggled_checksums.clear();
         self.run_slow = false;
         self.snapshots = Vec::new();
+        self.cells = Vec::new();
+        self.presaved = None;
+        self.saved_with = Vec::new();
+        self.input_history.clear();
+        self.confirmed_frame = -1;
+        self.rewind_history.clear();
+        self.history_frame = -1;
+        self.join_inputs.clear();
     }
 
     pub(crate) fn run_synctest(&mut self, world: &mut World) {
@@ -113,7 +374,17 @@ impl<T: Config> GGRSStage<T> {
         // get inputs for all players
         let mut inputs = Vec::new();
         for handle in 0..sess.num_players() {
-            inputs.push(self.input_system.run(handle, world));
+            let replayed = world
+                .get_resource::<ReplayBranch<T>>()
+                .and_then(|replay| replay.input(handle));
+            let input = match replayed {
+                Some(input) => self.injected_input(handle, world).unwrap_or(input),
+                None => self.local_input(handle, world),
+            };
+            inputs.push(input);
+        }
+        if let Some(mut replay) = world.get_resource_mut::<ReplayBranch<T>>() {
+            replay.advance();
         }
 
         let mut sess = world.get_resource_mut::<Session<T>>();
@@ -126,6 +397,10 @@ impl<T: Config> GGRSStage<T> {
         }
         match sess.advance_frame() {
             Ok(requests) => self.handle_requests(requests, world),
+            Err(e @ GGRSError::MismatchedChecksum { .. }) if self.bisect_desyncs => {
+                warn!("{}", e);
+                self.bisect_desync(world);
+            }
             Err(e) => warn!("{}", e),
         }
     }
@@ -170,11 +445,17 @@ impl<T: Config> GGRSStage<T> {
 
         // get local player handles
         let local_handles = sess.local_player_handles();
+        // injected inputs are only used up once they can be added to the session
+        let running = sess.current_state() == SessionState::Running;
 
         // get local player inputs
         let mut local_inputs = Vec::new();
         for &local_handle in &local_handles {
-            let input = self.input_system.run(local_handle, world);
+            let input = if running {
+                self.local_input(local_handle, world)
+            } else {
+                self.input_system.run(local_handle, world)
+            };
             local_inputs.push(input);
         }
 
@@ -199,6 +480,30 @@ impl<T: Config> GGRSStage<T> {
         }
     }
 
+    /// Returns the input injected for the given player at the current frame, or runs the input system.
+    fn local_input(&mut self, handle: PlayerHandle, world: &mut World) -> T::Input {
+        let injected = self.injected_input(handle, world).or_else(|| {
+            world
+                .get_resource::<DeviceAssignment<T>>()
+                .and_then(|assignment| assignment.substitute(handle))
+        });
+        injected.unwrap_or_else(|| self.input_system.run(handle, world))
+    }
+
+    /// Returns true while a disconnected gamepad pauses the simulation.
+    fn held_by_devices(&self, world: &World) -> bool {
+        world
+            .get_resource::<DeviceAssignment<T>>()
+            .map_or(false, |assignment| assignment.holds(self.frame))
+    }
+
+    /// Returns the input injected for the given player at the current frame.
+    fn injected_input(&self, handle: PlayerHandle, world: &mut World) -> Option<T::Input> {
+        world
+            .get_resource_mut::<InjectedInputs<T>>()
+            .and_then(|mut injected| injected.take(handle, self.frame))
+    }
+
     pub(crate) fn handle_requests(&mut self, requests: Vec<GGRSRequest<T>>, world: &mut World) {
         for request in requests {
             match request {
@@ -219,26 +524,187 @@ impl<T: Config> GGRSStage<T> {
         assert_eq!(self.frame, frame);
 
         // we make a snapshot of our world
-        let snapshot = WorldSnapshot::from_world(world, &self.type_registry);
+        let mut capacity = world
+            .get_resource::<SnapshotCapacity>()
+            .copied()
+            .unwrap_or_default();
+        if let Some((_, previous)) = self.latest_snapshot() {
+            let previous = previous.capacity();
+            capacity.entities = capacity.entities.max(previous.entities);
+            capacity.resources = capacity.resources.max(previous.resources);
+        }
+        let mut measurements = self.snapshot_stats.then(Measurements::default);
+        let snapshot = match self.presaved.take() {
+            Some(snapshot) if frame == 0 => snapshot,
+            _ => WorldSnapshot::from_world_timed(
+                world,
+                &self.type_registry,
+                capacity,
+                self.measures_sizes(world),
+                measurements.as_mut(),
+            ),
+        };
+        if let Some(measurements) = measurements {
+            let previous = self.saved_snapshot(frame - 1);
+            if let Some(mut stats) = world.get_resource_mut::<SnapshotStats>() {
+                stats.record_save(&snapshot, previous, measurements);
+            }
+        }
+        let checksum = snapshot.checksum;
+        if let Some(mut diagnostics) = world.get_resource_mut::<SessionDiagnostics>() {
+            diagnostics.record_snapshot(SnapshotMetadata {
+                frame,
+                checksum,
+                entities: snapshot.entity_count(),
+                bytes: snapshot.size(),
+            });
+        }
 
         // we don't really use the buffer provided by GGRS
-        cell.save(self.frame, None, Some(snapshot.checksum as u128));
+        let reported = self
+            .toggled_checksums
+            .get(&frame)
+            .copied()
+            .unwrap_or(checksum);
+        cell.save(self.frame, None, Some(reported as u128));
 
         // store the snapshot ourselves (since the snapshots don't implement clone)
         let pos = frame as usize % self.snapshots.len();
         self.snapshots[pos] = snapshot;
+        self.cells.resize_with(self.snapshots.len(), || None);
+        self.cells[pos] = Some((frame, cell));
+        self.saved_with
+            .resize_with(self.snapshots.len(), TypeRegistry::default);
+        self.saved_with[pos] = self.type_registry.clone();
+        if self.rewind_capacity > 0 {
+            self.record_history(frame, world);
+        }
+        if let Some(mut trace) = world.get_resource_mut::<RequestTrace>() {
+            trace.save(frame, checksum);
+        }
+        self.notify(world, StageEvent::Saved { frame, checksum });
+    }
+
+    /// Keeps the state at the start of `frame` for rewinding. Frames saved again after a rollback replace the
+    /// states recorded for them before.
+    fn record_history(&mut self, frame: i32, world: &mut World) {
+        if frame <= self.history_frame {
+            let outdated = (self.history_frame - frame + 1) as usize;
+            let kept = self.rewind_history.len().saturating_sub(outdated);
+            self.rewind_history.truncate(kept);
+        }
+        let snapshot = WorldSnapshot::from_world_timed(
+            world,
+            &self.type_registry,
+            SnapshotCapacity::default(),
+            self.measures_sizes(world),
+            None,
+        );
+        self.rewind_history
+            .push_back((frame, snapshot, self.type_registry.clone()));
+        while self.rewind_history.len() > self.history_capacity() {
+            self.rewind_history.pop_front();
+        }
+        self.history_frame = frame;
+        if let Some(mut rewind) = world.get_resource_mut::<Rewind>() {
+            rewind.set_available(self.rewind_history.len());
+        }
+    }
+
+    /// Restores the state requested with `Rewind`. The frame count of the session keeps going, only the state of
+    /// the world goes back.
+    fn process_rewind(&mut self, world: &mut World) {
+        let Some(frames) = world
+            .get_resource_mut::<Rewind>()
+            .and_then(|mut rewind| rewind.take_request())
+        else {
+            return;
+        };
+        match world.get_resource::<Session<T>>() {
+            Some(Session::SyncTestSession(session)) if session.check_distance() == 0 => {}
+            Some(Session::SyncTestSession(_)) => {
+                warn!("can't rewind a SyncTestSession with a check distance greater than 0");
+                return;
+            }
+            _ => {
+                warn!("rewinding is only possible in a SyncTestSession");
+                return;
+            }
+        }
+
+        // the latest entry is the state at the start of the previous frame
+        let frames = frames.min(self.rewind_history.len());
+        if frames == 0 {
+            return;
+        }
+        let kept = self.rewind_history.len() - frames + 1;
+        self.rewind_history.truncate(kept);
+        let (saved_frame, snapshot, saved_with) = self
+            .rewind_history
+            .pop_back()
+            .expect("history should not be empty");
+        self.restore_snapshot(&snapshot, &saved_with, saved_frame, world, None);
+        // the restored state becomes the state at the start of the current frame
+        self.history_frame = self.frame - 1;
+        if let Some(mut rewind) = world.get_resource_mut::<Rewind>() {
+            rewind.set_available(self.rewind_history.len());
+        }
+        if let Some(mut replay) = world.get_resource_mut::<ReplayBranch<T>>() {
+            replay.rewind(frames);
+        }
+        info!("rewound {frames} frames at frame {}", self.frame);
+        self.notify(world, StageEvent::Loaded { frame: self.frame });
     }
 
     pub(crate) fn load_world(&mut self, frame: i32, world: &mut World) {
         debug!("restoring snapshot for frame {frame}");
         self.frame = frame;
+        if let Some(mut trace) = world.get_resource_mut::<RequestTrace>() {
+            trace.load(frame);
+        }
 
         // we get the correct snapshot
         let pos = frame as usize % self.snapshots.len();
         let snapshot_to_load = &self.snapshots[pos];
+        let saved_with = self.saved_with.get(pos).unwrap_or(&self.type_registry);
 
         // load the entities
-        snapshot_to_load.write_to_world(world, &self.type_registry);
+        if self.snapshot_stats {
+            let mut measurements = Measurements::default();
+            self.restore_snapshot(
+                snapshot_to_load,
+                saved_with,
+                frame,
+                world,
+                Some(&mut measurements),
+            );
+            if let Some(mut stats) = world.get_resource_mut::<SnapshotStats>() {
+                stats.record_load(measurements);
+            }
+        } else {
+            self.restore_snapshot(snapshot_to_load, saved_with, frame, world, None);
+        }
+        self.notify(world, StageEvent::Loaded { frame });
+    }
+
+    /// Restores `snapshot`, saved with the types of `saved_with`, as the state at the start of `frame`. Every
+    /// snapshot is restored through here: prefabs are spawned again before the snapshot overwrites their rollback
+    /// components, and only the types that were saved and haven't been excluded since are restored.
+    fn restore_snapshot(
+        &self,
+        snapshot: &WorldSnapshot,
+        saved_with: &TypeRegistry,
+        frame: i32,
+        world: &mut World,
+        measurements: Option<&mut Measurements>,
+    ) {
+        prefab::restore_prefabs(world, frame);
+        let type_registry = if Arc::ptr_eq(&saved_with.internal, &self.type_registry.internal) {
+            self.type_registry.clone()
+        } else {
+            rollback_toggles::intersect(saved_with, &self.type_registry)
+        };
+        snapshot.write_to_world(world, &type_registry, measurements);
     }
 
     pub(crate) fn advance_frame(
@@ -247,22 +713,683 @@ impl<T: Config> GGRSStage<T> {
         world: &mut World,
     ) {
         debug!("advancing to frame: {}", self.frame + 1);
+        if self.bisect_desyncs || self.recovery {
+            self.record_inputs(&inputs);
+        }
+        match self.late_join_limit {
+            Some(limit) if self.degradation < MemoryDegradation::NoReplayCapture => {
+                self.record_join_inputs(limit, &inputs);
+            }
+            _ => {}
+        }
+        if let Some(mut trace) = world.get_resource_mut::<RequestTrace>() {
+            trace.advance(self.frame, &inputs);
+        }
         world.insert_resource(PlayerInputs::<T>(inputs));
-        self.schedule.run_once(world);
+        world.insert_resource(RollbackFrame(self.frame));
+        self.notify(world, StageEvent::Advancing { frame: self.frame });
+        if let Some(path) = &self.panic_dump {
+            // write a dump before a panic continues to unwind
+            let schedule = &mut self.schedule;
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| schedule.run_once(world)));
+            if let Err(payload) = result {
+                self.write_panic_dump(world, path, payload.as_ref());
+                std::panic::resume_unwind(payload);
+            }
+        } else {
+            self.schedule.run_once(world);
+        }
+        if self.lint_pending {
+            // systems are only initialized and ordered once the schedule has run
+            self.lint_pending = false;
+            let findings = schedule_lint::lint_schedule(&self.schedule, world, &self.type_registry);
+            for warning in findings.iter() {
+                warn!("rollback schedule: {warning}");
+            }
+            world.insert_resource(ScheduleLint { findings });
+        }
+        self.notify(world, StageEvent::Advanced { frame: self.frame });
         world.remove_resource::<PlayerInputs<T>>();
         self.frame += 1;
         debug!("frame {} completed", self.frame);
     }
 
+    /// Writes a dump of the current frame for a panic in the rollback schedule.
+    fn write_panic_dump(&self, world: &World, path: &Path, payload: &(dyn Any + Send)) {
+        let inputs = world
+            .get_resource::<PlayerInputs<T>>()
+            .map(|inputs| request_trace::format_inputs(&inputs.0))
+            .unwrap_or_default();
+        let snapshot = self.latest_snapshot().map(|(frame, snapshot)| {
+            let contents = snapshot.to_text(&self.type_registry);
+            (frame, snapshot.checksum, contents)
+        });
+        let dump = PanicDump {
+            frame: self.frame,
+            inputs,
+            snapshot,
+            payload,
+        };
+        dump.write(path);
+    }
+
+    /// Remembers the inputs of the frame about to be simulated.
+    fn record_inputs(&mut self, inputs: &[(T::Input, InputStatus)]) {
+        // after a rollback, the inputs of resimulated frames replace the previous ones
+        while matches!(self.input_history.back(), Some((frame, _)) if *frame >= self.frame) {
+            self.input_history.pop_back();
+        }
+        self.input_history.push_back((self.frame, inputs.to_vec()));
+
+        let capacity = if self.recovery && self.degradation < MemoryDegradation::ReducedHistory {
+            RECOVERY_INPUT_HISTORY.max(self.snapshots.len())
+        } else {
+            self.snapshots.len()
+        };
+        while self.input_history.len() > capacity {
+            self.input_history.pop_front();
+        }
+    }
+
+    /// Remembers the inputs of the frame about to be simulated for late joiners, up to `limit` frames.
+    fn record_join_inputs(&mut self, limit: usize, inputs: &[(T::Input, InputStatus)]) {
+        let frame = self.frame as usize;
+        // after a rollback, the inputs of resimulated frames replace the previous ones
+        self.join_inputs.truncate(frame);
+        if frame < limit && self.join_inputs.len() == frame {
+            self.join_inputs.push(inputs.to_vec());
+        }
+    }
+
+    /// Returns the snapshot saved for `frame`, if it is still stored.
+    fn saved_snapshot(&self, frame: i32) -> Option<&WorldSnapshot> {
+        if frame < 0 || self.snapshots.is_empty() {
+            return None;
+        }
+        let pos = frame as usize % self.snapshots.len();
+        match self.cells.get(pos) {
+            Some(Some((saved_frame, _))) if *saved_frame == frame => Some(&self.snapshots[pos]),
+            _ => None,
+        }
+    }
+
+    /// Returns the most recent snapshot saved up to the current frame.
+    fn latest_snapshot(&self) -> Option<(i32, &WorldSnapshot)> {
+        self.latest_stored(self.frame)
+            .map(|(frame, pos)| (frame, &self.snapshots[pos]))
+    }
+
+    /// Returns the most recent frame up to `frame` whose snapshot is stored, and the position of that snapshot. With
+    /// sparse saving, GGRS doesn't save every frame.
+    fn latest_stored(&self, frame: i32) -> Option<(i32, usize)> {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, cell)| cell.as_ref().map(|(saved, _)| (*saved, pos)))
+            .filter(|(saved, _)| *saved <= frame)
+            .max_by_key(|(saved, _)| *saved)
+    }
+
+    /// Tells the hooks about frames that became final since the last step.
+    pub(crate) fn confirm_frames(&mut self, world: &mut World) {
+        let confirmed = match world.get_resource::<Session<T>>() {
+            Some(Session::P2PSession(sess)) => sess.confirmed_frame(),
+            // a sync test rolls back at most as many frames as we have snapshots
+            Some(Session::SyncTestSession(_)) => self.frame - self.snapshots.len() as i32 - 1,
+            // spectators only ever simulate confirmed inputs
+            Some(Session::SpectatorSession(_)) => self.frame - 1,
+            None => return,
+        };
+        if confirmed > self.confirmed_frame {
+            self.confirmed_frame = confirmed;
+            self.notify(world, StageEvent::Confirmed { frame: confirmed });
+            if self.degradation >= MemoryDegradation::NoConfirmedSnapshots {
+                self.release_confirmed_snapshots();
+            }
+        }
+    }
+
+    /// Drops the snapshots of confirmed frames, except for the latest final state. That is the state at the start
+    /// of the frame after the confirmed one, kept for desync recovery and late joiners, or with sparse saving the
+    /// confirmed state GGRS rolls back to.
+    fn release_confirmed_snapshots(&mut self) {
+        let kept = self
+            .latest_stored(self.confirmed_frame + 1)
+            .map(|(frame, _)| frame);
+        for (pos, cell) in self.cells.iter_mut().enumerate() {
+            if matches!(cell, Some((frame, _)) if *frame <= self.confirmed_frame && Some(*frame) != kept)
+            {
+                *cell = None;
+                self.snapshots[pos] = WorldSnapshot::default();
+            }
+        }
+    }
+
+    /// Returns true if the size of the saved values is needed, by `SnapshotStats` or a `MemoryBudget`. Estimating
+    /// it walks every saved value.
+    fn measures_sizes(&self, world: &World) -> bool {
+        self.snapshot_stats || world.contains_resource::<MemoryBudget>()
+    }
+
+    /// Returns the number of frames kept for `Rewind`.
+    fn history_capacity(&self) -> usize {
+        match self.degradation {
+            MemoryDegradation::None => self.rewind_capacity,
+            _ => self.rewind_capacity / 4,
+        }
+    }
+
+    pub(crate) fn notify(&mut self, world: &mut World, event: StageEvent) {
+        for hook in self.hooks.iter_mut() {
+            hook(world, event);
+        }
+    }
+
+    /// Called after a sync test reported mismatching checksums. Resimulates every recorded frame twice from the
+    /// same snapshot, taking a snapshot after each stage of the rollback schedule. The first stage whose results
+    /// differ between the two runs is reported together with the differing components. Afterwards, the world is
+    /// restored to the state it had before. The hooks see every run as a regular load and advance, and the final
+    /// restore as a load of the current frame.
+    pub(crate) fn bisect_desync(&mut self, world: &mut World) {
+        let backup = WorldSnapshot::from_world(world, &self.type_registry);
+        let oldest_snapshot = (self.frame - self.snapshots.len() as i32 + 1).max(0);
+        let frames: Vec<_> = self
+            .input_history
+            .iter()
+            .filter(|(frame, _)| *frame >= oldest_snapshot && *frame < self.frame)
+            .cloned()
+            .collect();
+        let labels: Vec<StageLabelId> = self
+            .schedule
+            .iter_stages()
+            .map(|(label, _)| label.as_label())
+            .collect();
+
+        let mut findings = Vec::new();
+        for (frame, inputs) in frames {
+            let first_run = self.instrumented_run(frame, &inputs, &labels, world);
+            let second_run = self.instrumented_run(frame, &inputs, &labels, world);
+
+            for (i, label) in labels.iter().enumerate() {
+                let differences = first_run[i].diff(&second_run[i], &self.type_registry);
+                if first_run[i].checksum != second_run[i].checksum || !differences.is_empty() {
+                    findings.push(format!(
+                        "desync bisection: stage {:?} produced different results when simulating frame {} twice from the same state. Systems in this stage: [{}]. Differences: [{}]",
+                        label,
+                        frame,
+                        self.stage_systems(*label).join(", "),
+                        differences.join("; ")
+                    ));
+                    break;
+                }
+            }
+            if !findings.is_empty() {
+                break;
+            }
+
+            // both runs agree, but they might still disagree with what was saved when the frame was simulated before
+            if let Some(result) = first_run.last() {
+                let pos = (frame + 1) as usize % self.snapshots.len();
+                let saved = &self.snapshots[pos];
+                if saved.checksum != result.checksum {
+                    findings.push(format!(
+                        "desync bisection: simulating frame {} is repeatable now, but differs from the originally saved state. The frame likely depends on state that is not rolled back. Differences: [{}]",
+                        frame,
+                        saved.diff(result, &self.type_registry).join("; ")
+                    ));
+                    break;
+                }
+            }
+        }
+
+        if findings.is_empty() {
+            findings.push(
+                "desync bisection: could not reproduce the mismatch within the recorded frames."
+                    .to_string(),
+            );
+        }
+        for finding in findings.iter() {
+            warn!("{finding}");
+        }
+        self.restore_snapshot(&backup, &self.type_registry, self.frame, world, None);
+        self.notify(world, StageEvent::Loaded { frame: self.frame });
+        world.insert_resource(DesyncBisection {
+            frame: self.frame,
+            findings,
+        });
+    }
+
+    /// Loads the snapshot of `frame` and runs the rollback schedule stage by stage, returning a snapshot after each.
+    fn instrumented_run(
+        &mut self,
+        frame: i32,
+        inputs: &[(T::Input, InputStatus)],
+        labels: &[StageLabelId],
+        world: &mut World,
+    ) -> Vec<WorldSnapshot> {
+        let pos = frame as usize % self.snapshots.len();
+        let saved_with = self.saved_with.get(pos).unwrap_or(&self.type_registry);
+        self.restore_snapshot(&self.snapshots[pos], saved_with, frame, world, None);
+        self.notify(world, StageEvent::Loaded { frame });
+        world.insert_resource(PlayerInputs::<T>(inputs.to_vec()));
+        world.insert_resource(RollbackFrame(frame));
+        self.notify(world, StageEvent::Advancing { frame });
+
+        let mut results = Vec::new();
+        for label in labels {
+            match self.schedule.get_stage_mut::<SystemStage>(*label) {
+                Some(stage) => stage.run(world),
+                None => warn!(
+                    "desync bisection: stage {:?} is not a SystemStage and is skipped",
+                    label
+                ),
+            }
+            results.push(WorldSnapshot::from_world(world, &self.type_registry));
+        }
+
+        self.notify(world, StageEvent::Advanced { frame });
+        world.remove_resource::<PlayerInputs<T>>();
+        results
+    }
+
+    /// Returns the names of all systems in the given stage of the rollback schedule.
+    fn stage_systems(&self, label: StageLabelId) -> Vec<String> {
+        self.schedule
+            .get_stage::<SystemStage>(label)
+            .map(|stage| {
+                stage
+                    .parallel_systems()
+                    .iter()
+                    .map(|system| system.name().to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn add_hook(&mut self, hook: StageHook) {
+        self.hooks.push(hook);
+    }
+
+    pub(crate) fn add_hold(&mut self, hold: StageHold) {
+        self.holds.push(hold);
+    }
+
+    pub(crate) fn set_rewind_history(&mut self, frames: usize) {
+        self.rewind_capacity = frames;
+    }
+
+    pub(crate) fn set_panic_dump(&mut self, path: PathBuf) {
+        self.panic_dump = Some(path);
+    }
+
+    pub(crate) fn set_snapshot_stats(&mut self, enabled: bool) {
+        self.snapshot_stats = enabled;
+    }
+
+    pub(crate) fn set_schedule_lint(&mut self, enabled: bool) {
+        self.lint_pending = enabled;
+    }
+
+    pub(crate) fn set_desync_bisection(&mut self, enabled: bool) {
+        self.bisect_desyncs = enabled;
+    }
+
     pub(crate) fn set_update_frequency(&mut self, update_frequency: usize) {
         self.update_frequency = update_frequency
     }
 
+    pub(crate) fn set_catch_up(&mut self, catch_up: CatchUp) {
+        self.catch_up = catch_up
+    }
+
     pub(crate) fn set_schedule(&mut self, schedule: Schedule) {
         self.schedule = schedule;
     }
 
     pub(crate) fn set_type_registry(&mut self, type_registry: TypeRegistry) {
+        self.registered = type_registry.clone();
         self.type_registry = type_registry;
     }
 }
+
+impl<T: Config> GGRSStage<T>
+where
+    T::Address: Send + Sync + 'static,
+{
+    /// Estimates the memory held by the stage and the netcode resources, see `MemoryUsage`.
+    fn memory_usage(&self, world: &World) -> MemoryUsage {
+        let input_size = std::mem::size_of::<(T::Input, InputStatus)>();
+        let snapshots = self.snapshots.iter().map(WorldSnapshot::size).sum();
+        let inputs: usize = self
+            .input_history
+            .iter()
+            .map(|(_, inputs)| {
+                std::mem::size_of::<(i32, Vec<(T::Input, InputStatus)>)>()
+                    + inputs.capacity() * input_size
+            })
+            .sum();
+        let history = self
+            .rewind_history
+            .iter()
+            .map(|(_, snapshot, _)| snapshot.size())
+            .sum::<usize>()
+            + inputs;
+        let replay = self
+            .join_inputs
+            .iter()
+            .map(|inputs| {
+                std::mem::size_of::<Vec<(T::Input, InputStatus)>>() + inputs.capacity() * input_size
+            })
+            .sum();
+        let diagnostics = world
+            .get_resource::<RequestTrace>()
+            .map_or(0, RequestTrace::memory_size)
+            + world
+                .get_resource::<SessionDiagnostics>()
+                .map_or(0, SessionDiagnostics::memory_size)
+            + world
+                .get_resource::<DebugShapes>()
+                .map_or(0, DebugShapes::memory_size);
+        let transfers = world
+            .get_resource::<StateTransfer<T::Address>>()
+            .map_or(0, StateTransfer::memory_size)
+            + world
+                .get_resource::<DesyncRecovery<T::Address>>()
+                .map_or(0, DesyncRecovery::memory_size);
+        MemoryUsage {
+            snapshots,
+            history,
+            replay,
+            diagnostics,
+            transfers,
+        }
+    }
+
+    /// Measures the memory in use and cuts back further if it exceeds the `MemoryBudget`.
+    fn apply_memory_budget(&mut self, world: &mut World) {
+        let usage = self.memory_usage(world);
+        let Some(exceeded) = world.resource_mut::<MemoryBudget>().update(usage) else {
+            return;
+        };
+        self.degradation = exceeded.degradation;
+        match self.degradation {
+            MemoryDegradation::None => {}
+            MemoryDegradation::ReducedHistory => {
+                while self.rewind_history.len() > self.history_capacity() {
+                    self.rewind_history.pop_front();
+                }
+                if let Some(mut rewind) = world.get_resource_mut::<Rewind>() {
+                    rewind.set_available(self.rewind_history.len());
+                }
+                while self.input_history.len() > self.snapshots.len() {
+                    self.input_history.pop_front();
+                }
+            }
+            MemoryDegradation::NoConfirmedSnapshots => self.release_confirmed_snapshots(),
+            MemoryDegradation::NoReplayCapture => self.join_inputs = Vec::new(),
+        }
+        warn!(
+            "memory budget of {} bytes exceeded with {} bytes, degrading to {:?}",
+            exceeded.limit,
+            usage.total(),
+            exceeded.degradation
+        );
+        if let Some(mut events) = world.get_resource_mut::<Events<MemoryBudgetExceeded>>() {
+            events.send(exceeded);
+        }
+    }
+
+    /// Replaces the session with the standby session, once it is ready and a rematch has been requested.
+    fn process_standby(&mut self, world: &mut World) {
+        let due = world
+            .get_resource::<StandbySession<T>>()
+            .map_or(false, |standby| standby.is_due());
+        if !due {
+            return;
+        }
+        let standby = world
+            .remove_resource::<StandbySession<T>>()
+            .expect("standby session should exist");
+        let previous_channel = standby.previous_channel();
+        world.insert_resource(Session::P2PSession(standby.into_session()));
+        // peers still on the old session may send a few more messages
+        if let Some(socket) = world.get_resource::<MultiplexSocket<T::Address>>() {
+            socket.receive_on(previous_channel);
+        }
+        self.reset();
+        self.notify(world, StageEvent::Reset);
+        // the world as it is now is the start of the match, even if the first frame has to wait
+        self.presaved = Some(WorldSnapshot::from_world_timed(
+            world,
+            &self.type_registry,
+            SnapshotCapacity::default(),
+            self.measures_sizes(world),
+            None,
+        ));
+        info!("rematch: switched to the standby session");
+    }
+
+    /// Returns true if a `LevelBarrier` doesn't let us simulate the next frame yet.
+    fn held_by_barrier(&mut self, world: &mut World) -> bool {
+        // only the requests of a P2PSession can still be rolled back after being simulated
+        let confirmed = match world.get_resource::<Session<T>>() {
+            Some(Session::P2PSession(_)) => self.confirmed_frame,
+            _ => i32::MAX,
+        };
+        let frame = self.frame;
+        world
+            .get_resource_mut::<LevelBarrier<T::Address>>()
+            .map_or(false, |mut barrier| barrier.holds(frame, confirmed))
+    }
+
+    /// Returns true if everyone has stopped for a `LateJoin`.
+    fn held_by_late_join(&self, world: &World) -> bool {
+        world
+            .get_resource::<LateJoin<T::Address>>()
+            .map_or(false, |late_join| late_join.ready_frame().is_some())
+    }
+
+    /// Sends the recorded inputs to late joiners and stops at the handoff frame. Without a session, the inputs
+    /// that arrived from the host are replayed instead.
+    fn process_late_join(&mut self, world: &mut World) {
+        let final_frame = match world.get_resource::<Session<T>>() {
+            // all inputs up to the confirmed frame are final, so the state at the start of the next frame is final too
+            Some(Session::P2PSession(_)) => self.confirmed_frame + 1,
+            // a sync test has checked the state it is at
+            Some(Session::SyncTestSession(_)) => self.frame,
+            Some(Session::SpectatorSession(_)) => return,
+            None => -1,
+        };
+
+        let (handoff, replay) = {
+            let Some(mut late_join) = world.get_resource_mut::<LateJoin<T::Address>>() else {
+                return;
+            };
+            late_join.poll();
+            if late_join.ready_frame().is_some() {
+                return;
+            }
+            if final_frame < 0 {
+                let max_frames = late_join.max_frames();
+                let replay = late_join
+                    .take_inputs()
+                    .map(|(frame, inputs)| (frame, inputs, max_frames));
+                (None, replay)
+            } else {
+                if late_join.needs_history() {
+                    let inputs = (final_frame as usize <= self.join_inputs.len()).then(|| {
+                        late_join::encode_inputs(&self.join_inputs[..final_frame as usize])
+                    });
+                    late_join.start_handoff(final_frame, inputs);
+                }
+                (late_join.pending_handoff(), None)
+            }
+        };
+        if let Some((frame, inputs, max_frames)) = replay {
+            self.fast_forward(frame, &inputs, max_frames, world);
+            return;
+        }
+        let Some(handoff) = handoff else {
+            return;
+        };
+
+        // keep simulating until the handoff frame can't be rolled back anymore
+        if final_frame < handoff {
+            return;
+        }
+        if handoff != self.frame {
+            // with sparse saving, the handoff frame may not have been saved; the frames after the latest saved one
+            // are simulated again with the confirmed inputs recorded for the joiner
+            let stored = self.latest_stored(handoff).filter(|(frame, _)| {
+                *frame == handoff || handoff as usize <= self.join_inputs.len()
+            });
+            let Some((frame, pos)) = stored else {
+                warn!("late join: the state of frame {handoff} is no longer stored");
+                return;
+            };
+            self.restore_snapshot(
+                &self.snapshots[pos],
+                &self.saved_with[pos],
+                frame,
+                world,
+                None,
+            );
+            self.frame = frame;
+            self.notify(world, StageEvent::Loaded { frame });
+            let inputs = self
+                .join_inputs
+                .get(frame as usize..handoff as usize)
+                .unwrap_or_default()
+                .to_vec();
+            for inputs in inputs {
+                self.resave(world);
+                self.advance_frame(inputs, world);
+            }
+        }
+        world
+            .resource_mut::<LateJoin<T::Address>>()
+            .set_ready(handoff);
+        info!("late join: stopped at frame {handoff}");
+    }
+
+    /// Replays the inputs of every frame before `frame`, starting from the initial state.
+    fn fast_forward(&mut self, frame: i32, inputs: &[u8], max_frames: usize, world: &mut World) {
+        let inputs = match late_join::decode_inputs::<T::Input>(inputs) {
+            Ok(inputs) => inputs,
+            Err(e) => {
+                warn!("late join: {e}");
+                return;
+            }
+        };
+        if inputs.len() != frame as usize || inputs.len() > max_frames {
+            warn!(
+                "late join: can't catch up to frame {frame} with {} frames of inputs",
+                inputs.len()
+            );
+            return;
+        }
+        self.reset();
+        for inputs in inputs {
+            self.advance_frame(inputs, world);
+        }
+        world
+            .resource_mut::<LateJoin<T::Address>>()
+            .set_ready(frame);
+        info!("late join: caught up to frame {frame}");
+    }
+
+    /// Sends our confirmed state to the peers that asked for it and adopts a state that arrived from the authority.
+    pub(crate) fn process_resync(&mut self, world: &mut World) {
+        if !matches!(
+            world.get_resource::<Session<T>>(),
+            Some(Session::P2PSession(_))
+        ) {
+            return;
+        }
+        // all inputs up to the confirmed frame are final, so the state at the start of the next frame is final too
+        let final_frame = self.confirmed_frame + 1;
+        // with sparse saving, the latest final state GGRS saved may be older
+        let available = self.latest_stored(final_frame);
+
+        let (requesters, received) = {
+            let Some(mut recovery) = world.get_resource_mut::<DesyncRecovery<T::Address>>() else {
+                return;
+            };
+            recovery.poll();
+            let requesters = if available.is_some() {
+                recovery.take_requesters()
+            } else {
+                Vec::new()
+            };
+            // adopting a state we haven't confirmed ourselves yet could be undone by a rollback
+            (requesters, recovery.take_state(final_frame))
+        };
+
+        if !requesters.is_empty() {
+            let (frame, pos) = available.expect("snapshot should be available");
+            match self.snapshots[pos].to_bytes(&self.type_registry) {
+                Ok(state) => {
+                    world
+                        .resource_mut::<DesyncRecovery<T::Address>>()
+                        .send_state(&requesters, frame, &state);
+                    info!(
+                        "desync recovery: sent the state of frame {frame} to {} peer(s)",
+                        requesters.len()
+                    );
+                }
+                Err(e) => warn!("desync recovery: {e}"),
+            }
+        }
+
+        if let Some((frame, state)) = received {
+            self.adopt_state(frame, &state, world);
+        }
+    }
+
+    /// Loads the state of the authority at the start of `frame` and resimulates up to the current frame.
+    fn adopt_state(&mut self, frame: i32, state: &[u8], world: &mut World) {
+        let current_frame = self.frame;
+        let inputs: Vec<_> = self
+            .input_history
+            .iter()
+            .filter(|(f, _)| *f >= frame)
+            .cloned()
+            .collect();
+        if frame > current_frame || inputs.len() != (current_frame - frame) as usize {
+            warn!("desync recovery: can't adopt the state of frame {frame} at frame {current_frame}, the inputs in between are not recorded");
+            return;
+        }
+        let snapshot = match WorldSnapshot::from_bytes(state, &self.type_registry) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("desync recovery: received an invalid state: {e}");
+                return;
+            }
+        };
+
+        // the state was serialized with the types we restore
+        self.restore_snapshot(&snapshot, &self.type_registry, frame, world, None);
+        self.frame = frame;
+        self.notify(world, StageEvent::Loaded { frame });
+        for (_, inputs) in inputs {
+            self.resave(world);
+            self.advance_frame(inputs, world);
+        }
+        debug_assert_eq!(self.frame, current_frame);
+
+        world.resource_mut::<DesyncRecovery<T::Address>>().finish();
+        info!("desync recovery: adopted the state of frame {frame}");
+    }
+
+    /// Saves the current state again, if GGRS has saved this frame before and the snapshot is still stored.
+    fn resave(&mut self, world: &mut World) {
+        let frame = self.frame;
+        let pos = frame as usize % self.snapshots.len();
+        if let Some(Some((saved_frame, cell))) = self.cells.get(pos) {
+            if *saved_frame == frame {
+                let cell = cell.clone();
+                self.save_world(cell, frame, world);
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use bevy::{
+    ecs::{entity::EntityMap, reflect::ReflectMapEntities},
+    prelude::*,
+    reflect::TypeRegistry,
+    utils::HashSet,
+};
+
+use crate::{world_snapshot::WorldSnapshot, Rollback};
+
+/// The entities of the initial scene derive their rollback ids from this name and their entity id in the scene.
+const INITIAL_SCENE: &str = "bevy_ggrs::initial_scene";
+
+/// Spawns the entities of `scene` as rollback entities and returns the checksum of the rollback world afterwards.
+///
+/// Components are looked up among the registered rollback types first, then in the `AppTypeRegistry`. Components of
+/// unknown types are skipped with a warning, and so are `Rollback` components, since the ids are derived from the
+/// entity ids of the scene.
+pub(crate) fn spawn_initial_scene(
+    world: &mut World,
+    scene: &DynamicScene,
+    type_registry: &TypeRegistry,
+) -> u64 {
+    let app_registry = world
+        .get_resource::<AppTypeRegistry>()
+        .map(|registry| (**registry).clone())
+        .unwrap_or_default();
+    let scene_root = Rollback::from_name(INITIAL_SCENE);
+    let mut entity_map = EntityMap::default();
+
+    {
+        let rollback_types = type_registry.read();
+        let app_types = app_registry.read();
+        for scene_entity in scene.entities.iter() {
+            let entity = world
+                .spawn(Rollback::derived(&scene_root, scene_entity.entity))
+                .id();
+            entity_map.insert(Entity::from_raw(scene_entity.entity), entity);
+
+            for component in scene_entity.components.iter() {
+                let type_name = component.type_name();
+                if type_name == std::any::type_name::<Rollback>() {
+                    continue;
+                }
+                let reflect_component = rollback_types
+                    .get_with_name(type_name)
+                    .or_else(|| app_types.get_with_name(type_name))
+                    .and_then(|registration| registration.data::<ReflectComponent>());
+                match reflect_component {
+                    Some(reflect_component) => {
+                        reflect_component.insert(world, entity, &**component)
+                    }
+                    None => warn!(
+                        "initial scene: {type_name} is not a registered component, skipping it"
+                    ),
+                }
+            }
+        }
+
+        // parents and other references to entities of the scene
+        let mut mapped = HashSet::new();
+        for registration in rollback_types.iter().chain(app_types.iter()) {
+            let Some(map_entities) = registration.data::<ReflectMapEntities>() else {
+                continue;
+            };
+            if mapped.insert(registration.type_id()) {
+                if let Err(e) = map_entities.map_entities(world, &entity_map) {
+                    warn!("initial scene: could not map the entities of the scene: {e}");
+                }
+            }
+        }
+    }
+
+    let snapshot = WorldSnapshot::from_world(world, type_registry);
+    snapshot.content_checksum(type_registry)
+}
@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+use ggrs::{Config, PlayerHandle};
+use std::collections::BTreeMap;
+
+/// Inputs injected for specific players and frames, bypassing the input system. Use it to drive players from TAS
+/// tools, QA bots or tutorial scripts. Injected inputs are added to the session like regular local inputs, so
+/// they go through the normal GGRS pipeline and reach remote peers as well.
+///
+/// The frame is the frame at which the input is handed to GGRS, which is the current frame of the session. With
+/// input delay, the input is simulated the according number of frames later. In a `P2PSession`, inputs can only
+/// be injected for local players.
+#[derive(Resource)]
+pub struct InjectedInputs<T: Config> {
+    inputs: BTreeMap<(PlayerHandle, i32), T::Input>,
+}
+
+impl<T: Config> Default for InjectedInputs<T> {
+    fn default() -> Self {
+        Self {
+            inputs: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: Config> InjectedInputs<T> {
+    /// Uses `input` for the given player at the given frame, instead of calling the input system.
+    pub fn inject(&mut self, handle: PlayerHandle, frame: i32, input: T::Input) {
+        self.inputs.insert((handle, frame), input);
+    }
+
+    /// Uses the given inputs for the given player, one per frame, starting at `start_frame`.
+    pub fn inject_sequence(
+        &mut self,
+        handle: PlayerHandle,
+        start_frame: i32,
+        inputs: impl IntoIterator<Item = T::Input>,
+    ) {
+        for (frame, input) in (start_frame..).zip(inputs) {
+            self.inject(handle, frame, input);
+        }
+    }
+
+    /// Returns true if there are inputs waiting to be used for the given player.
+    pub fn is_pending(&self, handle: PlayerHandle) -> bool {
+        self.inputs.keys().any(|(h, _)| *h == handle)
+    }
+
+    /// Removes all inputs that have not been used yet.
+    pub fn clear(&mut self) {
+        self.inputs.clear();
+    }
+
+    /// Removes and returns the input injected for `handle` at `frame`. Inputs for earlier frames can't be used
+    /// anymore and are dropped.
+    pub(crate) fn take(&mut self, handle: PlayerHandle, frame: i32) -> Option<T::Input> {
+        let mut later = self.inputs.split_off(&(handle, frame));
+        let skipped = self.inputs.keys().filter(|(h, _)| *h == handle).count();
+        if skipped > 0 {
+            warn!("dropping {skipped} injected inputs of player {handle} for frames that have passed already");
+            self.inputs.retain(|(h, _), _| *h != handle);
+        }
+        let input = later.remove(&(handle, frame));
+        self.inputs.append(&mut later);
+        input
+    }
+}
@@ -0,0 +1,173 @@
+use bevy::prelude::*;
+
+/// Writes values bit by bit into a growing byte buffer, starting at the least significant bit.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitWriter {
+    /// Creates an empty writer.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Writes the lowest `bits` bits of `value`. `bits` may be at most 32.
+    pub fn write_bits(&mut self, value: u32, bits: u32) {
+        assert!(bits <= 32, "BitWriter: can write at most 32 bits at once");
+        for i in 0..bits {
+            if self.len % 8 == 0 {
+                self.bytes.push(0);
+            }
+            if value >> i & 1 == 1 {
+                self.bytes[self.len / 8] |= 1 << (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+
+    /// Writes a single bit.
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_bits(value as u32, 1);
+    }
+
+    /// Writes a signed value in two's complement, using `bits` bits.
+    pub fn write_signed(&mut self, value: i32, bits: u32) {
+        self.write_bits(value as u32, bits);
+    }
+
+    /// Clamps `value` to `min..=max` and writes it with a precision of `bits` bits.
+    pub fn write_quantized(&mut self, value: f32, min: f32, max: f32, bits: u32) {
+        let steps = max_value(bits) as f32;
+        let normalized = ((value - min) / (max - min)).clamp(0., 1.);
+        self.write_bits((normalized * steps).round() as u32, bits);
+    }
+
+    /// Returns the number of bits written so far.
+    pub fn bit_len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the written bytes. Unused bits of the last byte are zero.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Reads values written by a `BitWriter`. Reading past the end yields zero bits.
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    /// Creates a reader starting at the first bit of `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    /// Reads `bits` bits into the lowest bits of the result. `bits` may be at most 32.
+    pub fn read_bits(&mut self, bits: u32) -> u32 {
+        assert!(bits <= 32, "BitReader: can read at most 32 bits at once");
+        let mut value = 0;
+        for i in 0..bits {
+            let bit = self
+                .bytes
+                .get(self.pos / 8)
+                .map_or(0, |byte| byte >> (self.pos % 8) & 1);
+            value |= (bit as u32) << i;
+            self.pos += 1;
+        }
+        value
+    }
+
+    /// Reads a single bit.
+    pub fn read_bool(&mut self) -> bool {
+        self.read_bits(1) == 1
+    }
+
+    /// Reads a signed value written by `BitWriter::write_signed()`.
+    pub fn read_signed(&mut self, bits: u32) -> i32 {
+        let value = self.read_bits(bits);
+        if bits == 0 || bits == 32 {
+            return value as i32;
+        }
+        // sign extension
+        let shift = 32 - bits;
+        ((value << shift) as i32) >> shift
+    }
+
+    /// Reads a value written by `BitWriter::write_quantized()` with the same parameters.
+    pub fn read_quantized(&mut self, min: f32, max: f32, bits: u32) -> f32 {
+        let steps = max_value(bits) as f32;
+        min + self.read_bits(bits) as f32 / steps * (max - min)
+    }
+}
+
+fn max_value(bits: u32) -> u32 {
+    if bits >= 32 {
+        u32::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+/// Implement this for your input struct to send it as a small byte array (`[u8; N]` as `Config::Input`) instead of
+/// a hand-written bit layout. Pack buttons as single bits and quantize analog sticks to as few bits as you need.
+///
+/// ```
+/// use bevy_ggrs::{BitReader, BitWriter, PackedInput};
+///
+/// struct MyInput {
+///     jump: bool,
+///     stick_x: f32,
+/// }
+///
+/// impl PackedInput for MyInput {
+///     fn pack(&self, writer: &mut BitWriter) {
+///         writer.write_bool(self.jump);
+///         writer.write_quantized(self.stick_x, -1., 1., 7);
+///     }
+///
+///     fn unpack(reader: &mut BitReader) -> Self {
+///         Self {
+///             jump: reader.read_bool(),
+///             stick_x: reader.read_quantized(-1., 1., 7),
+///         }
+///     }
+/// }
+/// ```
+pub trait PackedInput: Sized {
+    /// Writes the input into `writer`.
+    fn pack(&self, writer: &mut BitWriter);
+    /// Reads the input written by `pack()`.
+    fn unpack(reader: &mut BitReader) -> Self;
+}
+
+/// Packs an input into a byte array of size `N`. Panics if the packed input does not fit.
+pub fn pack_input<I: PackedInput, const N: usize>(input: &I) -> [u8; N] {
+    let mut writer = BitWriter::new();
+    input.pack(&mut writer);
+    let bytes = writer.into_bytes();
+    assert!(
+        bytes.len() <= N,
+        "packed input needs {} bytes, but the input type only has {N}",
+        bytes.len()
+    );
+    let mut packed = [0; N];
+    packed[..bytes.len()].copy_from_slice(&bytes);
+    packed
+}
+
+/// Unpacks an input packed by `pack_input()`.
+pub fn unpack_input<I: PackedInput>(bytes: &[u8]) -> I {
+    I::unpack(&mut BitReader::new(bytes))
+}
+
+/// Pipe your input system into this system to pack its output:
+/// `GGRSPlugin::new().with_input_system(my_input.pipe(pack_input_system::<MyInput, 2>))`.
+pub fn pack_input_system<I: PackedInput, const N: usize>(In(input): In<I>) -> [u8; N] {
+    pack_input(&input)
+}
@@ -0,0 +1,180 @@
+use bevy::prelude::*;
+use std::marker::PhantomData;
+
+use crate::RollbackFrame;
+
+/// Linear interpolation between two values of a type. Implement it for your own components to smooth them between
+/// simulation frames with `GGRSPlugin::register_interpolated_component::<C>()`.
+pub trait Lerp {
+    /// Returns the value `t` of the way from `self` to `other`, with `t` between 0 and 1.
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Vec2 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Vec2::lerp(*self, *other, t)
+    }
+}
+
+impl Lerp for Vec3 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Vec3::lerp(*self, *other, t)
+    }
+}
+
+impl Lerp for Quat {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self.slerp(*other, t)
+    }
+}
+
+impl Lerp for Color {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        let [r, g, b, a] = self.as_rgba_f32();
+        let [other_r, other_g, other_b, other_a] = other.as_rgba_f32();
+        Color::rgba(
+            Lerp::lerp(&r, &other_r, t),
+            Lerp::lerp(&g, &other_g, t),
+            Lerp::lerp(&b, &other_b, t),
+            Lerp::lerp(&a, &other_a, t),
+        )
+    }
+}
+
+impl Lerp for Transform {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Transform {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+/// How far the time accumulated by the GGRS stage has progressed from the last simulated frame towards the next
+/// one, between 0 and 1. Updated every time the GGRS stage runs.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, PartialOrd, Deref)]
+pub struct FrameAlpha(pub(crate) f32);
+
+/// A smoothed copy of the component `C` of the same entity, for presentation. Added automatically to all entities
+/// with a `C` once the type is registered with `GGRSPlugin::register_interpolated_component::<C>()`.
+///
+/// The value moves from the state of the previous frame to the state of the latest frame as `FrameAlpha` advances,
+/// so it lags up to one frame behind the simulation. Corrections by rollbacks are blended in instead of popping.
+#[derive(Component, Debug, Clone)]
+pub struct Interpolated<C> {
+    previous: C,
+    current: C,
+    value: C,
+    frame: i32,
+}
+
+impl<C: Lerp + Clone> Interpolated<C> {
+    fn new(component: &C, frame: i32) -> Self {
+        Self {
+            previous: component.clone(),
+            current: component.clone(),
+            value: component.clone(),
+            frame,
+        }
+    }
+
+    /// Returns the interpolated value.
+    pub fn value(&self) -> &C {
+        &self.value
+    }
+
+    /// Returns the value of the latest simulated frame.
+    pub fn latest(&self) -> &C {
+        &self.current
+    }
+}
+
+/// Makes all interpolated copies of an entity jump to the state of a frame instead of blending into it, for
+/// teleports and respawns. Insert it from within the rollback schedule on the frame of the jump; it only applies to
+/// that frame, so it can stay on the entity until the next jump replaces it. A flag left behind by a rollback that
+/// undid the jump only costs the smoothing of a single frame.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snap {
+    frame: i32,
+}
+
+impl Snap {
+    /// Snaps the entity to the state at the end of the given frame.
+    pub fn on(frame: &RollbackFrame) -> Self {
+        Self { frame: **frame }
+    }
+}
+
+/// Like `Snap`, but only makes the `Interpolated<C>` of the entity jump, while its other components keep blending.
+#[derive(Component)]
+pub struct SnapComponent<C: Component> {
+    frame: i32,
+    _marker: PhantomData<fn() -> C>,
+}
+
+impl<C: Component> SnapComponent<C> {
+    /// Snaps the component to its state at the end of the given frame.
+    pub fn on(frame: &RollbackFrame) -> Self {
+        Self {
+            frame: **frame,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<C: Component> Clone for SnapComponent<C> {
+    fn clone(&self) -> Self {
+        Self {
+            frame: self.frame,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<C: Component> Copy for SnapComponent<C> {}
+
+pub(crate) fn interpolate_system<C: Component + Lerp + Clone>(
+    mut commands: Commands,
+    frame: Option<Res<RollbackFrame>>,
+    alpha: Option<Res<FrameAlpha>>,
+    new_query: Query<(Entity, &C), Without<Interpolated<C>>>,
+    mut query: Query<(
+        &C,
+        &mut Interpolated<C>,
+        Option<&Snap>,
+        Option<&SnapComponent<C>>,
+    )>,
+) {
+    let frame = frame.map_or(0, |frame| **frame);
+    let alpha = alpha.map_or(1., |alpha| **alpha);
+
+    for (entity, component) in new_query.iter() {
+        commands
+            .entity(entity)
+            .insert(Interpolated::new(component, frame));
+    }
+
+    for (component, mut interpolated, snap, snap_component) in query.iter_mut() {
+        // the frame of the jump may have been simulated along with later ones
+        let jumped = |snapped: i32| snapped > interpolated.frame && snapped <= frame;
+        let snapped = snap.map_or(false, |snap| jumped(snap.frame))
+            || snap_component.map_or(false, |snap| jumped(snap.frame));
+        if snapped {
+            *interpolated = Interpolated::new(component, frame);
+        } else if interpolated.frame != frame {
+            // start from what is displayed right now, so corrections don't pop
+            interpolated.previous = interpolated.value.clone();
+            interpolated.current = component.clone();
+            interpolated.frame = frame;
+        }
+        let value = interpolated.previous.lerp(&interpolated.current, alpha);
+        interpolated.value = value;
+    }
+}
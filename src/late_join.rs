@@ -0,0 +1,418 @@
+use bevy::prelude::*;
+use ggrs::InputStatus;
+use instant::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::socket::{channel, MultiplexSocket};
+
+const DEFAULT_RESEND_INTERVAL: Duration = Duration::from_millis(250);
+/// Ten seconds at 60 fps. Fast-forwarding replays every frame in a single update, so this has to stay small.
+const DEFAULT_MAX_FRAMES: usize = 600;
+/// Keeps every input datagram well below the receive buffer of the socket.
+const CHUNK_SIZE: usize = 1024;
+/// Larger histories are not accepted, so a malformed packet can't make us allocate arbitrary amounts of memory.
+const MAX_CHUNKS: u32 = 4 * 1024;
+
+#[derive(Serialize, Deserialize)]
+enum LateJoinPacket {
+    /// Asks the host for the inputs of the match so far.
+    Request,
+    /// The host doesn't accept the joiner, because the match is already too long.
+    Refused,
+    /// One piece of the confirmed inputs of all frames before `frame`.
+    Inputs {
+        frame: i32,
+        index: u32,
+        count: u32,
+        data: Vec<u8>,
+    },
+    /// Tells the other players to stop at the start of `frame`, where the joiner will catch up.
+    Handoff { frame: i32 },
+    /// Sent in response to `Handoff`.
+    Ack,
+}
+
+enum Role<A> {
+    Host {
+        peers: Vec<A>,
+        /// the addresses allowed to join
+        allowed: Vec<A>,
+        /// joiners waiting for the inputs
+        joiners: Vec<A>,
+        /// the handoff frame and the encoded inputs of all frames before it
+        history: Option<(i32, Vec<u8>)>,
+        /// peers that have acknowledged the handoff
+        acked: Vec<A>,
+        last_send: Option<Instant>,
+    },
+    Peer {
+        host: A,
+    },
+    Joiner {
+        host: A,
+        last_request: Option<Instant>,
+        refused: bool,
+        /// the chunks of the inputs currently arriving
+        chunks: Option<(i32, Vec<Option<Vec<u8>>>)>,
+        /// complete inputs that have not been replayed yet
+        received: Option<(i32, Vec<u8>)>,
+    },
+}
+
+/// Lets a player join a short match a few seconds late, without transferring the world state. The joiner starts
+/// from the same initial state as everyone else, receives the confirmed inputs of every frame so far and replays
+/// them at once. Meanwhile the other players stop at the frame the joiner catches up to. Once `ready_frame()`
+/// returns `Some` on a peer, its world holds the state at the start of that frame; remove the running session and
+/// this resource and start a new session with all players, joiner included.
+///
+/// Insert it as a resource using a clone of the `MultiplexSocket` the session runs on: `host()` on one of the
+/// players, `peer()` on all other players and `joiner()` on the late player, whose app runs without a session
+/// until it has caught up. The host only answers the joiners it was told about, for example by the matchmaking
+/// server or the match setup. Unlike `DesyncRecovery`, nothing needs to be serializable, but the match can only be
+/// joined for as many frames as `with_max_frames()` allows.
+#[derive(Resource)]
+pub struct LateJoin<A> {
+    socket: MultiplexSocket<A>,
+    role: Role<A>,
+    max_frames: usize,
+    resend_interval: Duration,
+    /// the frame everyone stops at, once known
+    handoff: Option<i32>,
+    /// the frame our world has reached for the new session
+    ready: Option<i32>,
+}
+
+impl<A: Clone + PartialEq + Send + Sync + 'static> LateJoin<A> {
+    /// Creates the late join for the player that records the inputs and sends them to joiners. The other players
+    /// are at `peers`, requests from addresses other than `joiners` are ignored.
+    pub fn host(socket: MultiplexSocket<A>, peers: Vec<A>, joiners: Vec<A>) -> Self {
+        Self::with_role(
+            socket,
+            Role::Host {
+                peers,
+                allowed: joiners,
+                joiners: Vec::new(),
+                history: None,
+                acked: Vec::new(),
+                last_send: None,
+            },
+        )
+    }
+
+    /// Creates the late join for a player that stops wherever the host at `host` tells it to.
+    pub fn peer(socket: MultiplexSocket<A>, host: A) -> Self {
+        Self::with_role(socket, Role::Peer { host })
+    }
+
+    /// Creates the late join for the late player, which asks the host at `host` for the inputs right away.
+    pub fn joiner(socket: MultiplexSocket<A>, host: A) -> Self {
+        Self::with_role(
+            socket,
+            Role::Joiner {
+                host,
+                last_request: None,
+                refused: false,
+                chunks: None,
+                received: None,
+            },
+        )
+    }
+
+    fn with_role(socket: MultiplexSocket<A>, role: Role<A>) -> Self {
+        Self {
+            socket,
+            role,
+            max_frames: DEFAULT_MAX_FRAMES,
+            resend_interval: DEFAULT_RESEND_INTERVAL,
+            handoff: None,
+            ready: None,
+        }
+    }
+
+    /// Changes the number of frames after which joining is refused. Defaults to 600. Set the same value on every
+    /// player and the joiner.
+    pub fn with_max_frames(mut self, frames: usize) -> Self {
+        self.max_frames = frames;
+        self
+    }
+
+    /// Changes how often requests and handoffs are repeated until they have been answered.
+    pub fn with_resend_interval(mut self, interval: Duration) -> Self {
+        self.resend_interval = interval;
+        self
+    }
+
+    /// Returns the frame the new session starts at, once our world has reached it. The simulation of the running
+    /// session stays stopped from then on.
+    pub fn ready_frame(&self) -> Option<i32> {
+        self.ready
+    }
+
+    /// Returns true if the host refused the joiner, because the match had already gone on for too long.
+    pub fn is_refused(&self) -> bool {
+        matches!(self.role, Role::Joiner { refused: true, .. })
+    }
+
+    pub(crate) fn max_frames(&self) -> usize {
+        self.max_frames
+    }
+
+    /// Returns the number of frames inputs have to be recorded for, unless this is the joiner. The host sends them,
+    /// the other players simulate the frames before the handoff again with them if that state wasn't saved.
+    pub(crate) fn recording_limit(&self) -> Option<usize> {
+        (!matches!(self.role, Role::Joiner { .. })).then_some(self.max_frames)
+    }
+
+    /// Returns the frame everyone stops at, while our world hasn't reached it yet.
+    pub(crate) fn pending_handoff(&self) -> Option<i32> {
+        match self.ready {
+            Some(_) => None,
+            None => self.handoff,
+        }
+    }
+
+    /// Returns true if the host has a joiner waiting, but hasn't picked the handoff frame yet.
+    pub(crate) fn needs_history(&self) -> bool {
+        matches!(&self.role, Role::Host { joiners, history: None, .. } if !joiners.is_empty())
+    }
+
+    /// Marks our world as being at the start of `frame`.
+    pub(crate) fn set_ready(&mut self, frame: i32) {
+        self.ready = Some(frame);
+    }
+
+    /// Sends and answers requests and handoffs, and collects arriving inputs.
+    pub(crate) fn poll(&mut self) {
+        let now = Instant::now();
+
+        for (addr, data) in self.socket.receive_on(channel::LATE_JOIN) {
+            let Ok(packet) = bincode::deserialize(&data) else {
+                debug!("received a malformed late join packet");
+                continue;
+            };
+            match (&mut self.role, packet) {
+                (Role::Host { allowed, .. }, LateJoinPacket::Request)
+                    if !allowed.contains(&addr) =>
+                {
+                    debug!("ignoring a late join request from an unknown address");
+                }
+                (
+                    Role::Host {
+                        joiners, history, ..
+                    },
+                    LateJoinPacket::Request,
+                ) => match history {
+                    // the joiner is still missing some of the inputs
+                    Some((frame, inputs)) => {
+                        send_inputs(&self.socket, &addr, *frame, inputs);
+                    }
+                    None if !joiners.contains(&addr) => joiners.push(addr),
+                    None => {}
+                },
+                (Role::Host { peers, acked, .. }, LateJoinPacket::Ack) => {
+                    if peers.contains(&addr) && !acked.contains(&addr) {
+                        acked.push(addr);
+                    }
+                }
+                (Role::Peer { host }, LateJoinPacket::Handoff { frame }) if *host == addr => {
+                    self.handoff.get_or_insert(frame);
+                    let ack = bincode::serialize(&LateJoinPacket::Ack).expect("should serialize");
+                    self.socket.send_on(channel::LATE_JOIN, &ack, host);
+                }
+                (Role::Joiner { host, refused, .. }, LateJoinPacket::Refused) if *host == addr => {
+                    *refused = true;
+                }
+                (
+                    Role::Joiner {
+                        host,
+                        chunks,
+                        received,
+                        ..
+                    },
+                    LateJoinPacket::Inputs {
+                        frame,
+                        index,
+                        count,
+                        data,
+                    },
+                ) if *host == addr && count > 0 && count <= MAX_CHUNKS => {
+                    if self.handoff.is_some() || received.is_some() {
+                        continue;
+                    }
+                    if chunks.as_ref().map_or(true, |(f, parts)| {
+                        *f != frame || parts.len() != count as usize
+                    }) {
+                        *chunks = Some((frame, vec![None; count as usize]));
+                    }
+                    let Some((_, parts)) = chunks.as_mut() else {
+                        continue;
+                    };
+                    if let Some(part) = parts.get_mut(index as usize) {
+                        *part = Some(data);
+                    }
+                    if parts.iter().all(|part| part.is_some()) {
+                        let inputs = parts.drain(..).flatten().flatten().collect();
+                        *received = Some((frame, inputs));
+                        *chunks = None;
+                    }
+                }
+                _ => debug!("ignoring an unexpected late join packet"),
+            }
+        }
+
+        let interval = self.resend_interval;
+        let due = |last: &mut Option<Instant>| {
+            let due = last.map_or(true, |last| now.duration_since(last) >= interval);
+            if due {
+                *last = Some(now);
+            }
+            due
+        };
+        match &mut self.role {
+            Role::Host {
+                peers,
+                history: Some((frame, _)),
+                acked,
+                last_send,
+                ..
+            } => {
+                if peers.iter().any(|peer| !acked.contains(peer)) && due(last_send) {
+                    let handoff = LateJoinPacket::Handoff { frame: *frame };
+                    let packet = bincode::serialize(&handoff).expect("should serialize");
+                    for peer in peers.iter().filter(|peer| !acked.contains(peer)) {
+                        self.socket.send_on(channel::LATE_JOIN, &packet, peer);
+                    }
+                }
+            }
+            Role::Joiner {
+                host,
+                last_request,
+                refused: false,
+                received: None,
+                ..
+            } => {
+                if self.handoff.is_none() && due(last_request) {
+                    let request =
+                        bincode::serialize(&LateJoinPacket::Request).expect("should serialize");
+                    self.socket.send_on(channel::LATE_JOIN, &request, host);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Lets everyone stop at the start of `frame` and sends the inputs of all frames before it to the waiting
+    /// joiners. Joiners are refused if the match is longer than the configured limit.
+    pub(crate) fn start_handoff(&mut self, frame: i32, inputs: Option<Vec<u8>>) {
+        let Role::Host {
+            joiners, history, ..
+        } = &mut self.role
+        else {
+            return;
+        };
+        let joiners = std::mem::take(joiners);
+        let Some(inputs) = inputs.filter(|_| frame as usize <= self.max_frames) else {
+            let refused = bincode::serialize(&LateJoinPacket::Refused).expect("should serialize");
+            for joiner in &joiners {
+                self.socket.send_on(channel::LATE_JOIN, &refused, joiner);
+            }
+            warn!(
+                "late join: refused {} joiner(s) at frame {frame}",
+                joiners.len()
+            );
+            return;
+        };
+        for joiner in &joiners {
+            send_inputs(&self.socket, joiner, frame, &inputs);
+        }
+        *history = Some((frame, inputs));
+        self.handoff = Some(frame);
+        info!("late join: everyone stops at frame {frame}");
+    }
+
+    /// Refuses the joiners that are still waiting for the inputs, because the host leaves.
+    pub(crate) fn refuse_waiting(&mut self) {
+        let Role::Host { joiners, .. } = &mut self.role else {
+            return;
+        };
+        let refused = bincode::serialize(&LateJoinPacket::Refused).expect("should serialize");
+        for joiner in joiners.drain(..) {
+            self.socket.send_on(channel::LATE_JOIN, &refused, &joiner);
+        }
+    }
+
+    /// Returns the received inputs, if they haven't been replayed yet.
+    pub(crate) fn take_inputs(&mut self) -> Option<(i32, Vec<u8>)> {
+        let Role::Joiner { received, .. } = &mut self.role else {
+            return None;
+        };
+        let (frame, inputs) = received.take()?;
+        self.handoff = Some(frame);
+        Some((frame, inputs))
+    }
+}
+
+fn send_inputs<A: Clone + PartialEq + Send + 'static>(
+    socket: &MultiplexSocket<A>,
+    addr: &A,
+    frame: i32,
+    inputs: &[u8],
+) {
+    let count = inputs.chunks(CHUNK_SIZE).count().max(1) as u32;
+    for index in 0..count {
+        let start = index as usize * CHUNK_SIZE;
+        let end = (start + CHUNK_SIZE).min(inputs.len());
+        let packet = LateJoinPacket::Inputs {
+            frame,
+            index,
+            count,
+            data: inputs[start..end].to_vec(),
+        };
+        let packet = bincode::serialize(&packet).expect("should serialize");
+        socket.send_on(channel::LATE_JOIN, &packet, addr);
+    }
+}
+
+/// Encodes the inputs of consecutive frames, each with the input of every player and whether the player was
+/// disconnected.
+pub(crate) fn encode_inputs<I: bytemuck::Pod>(frames: &[Vec<(I, InputStatus)>]) -> Vec<u8> {
+    let frames: Vec<Vec<(Vec<u8>, bool)>> = frames
+        .iter()
+        .map(|inputs| {
+            inputs
+                .iter()
+                .map(|(input, status)| {
+                    let disconnected = matches!(status, InputStatus::Disconnected);
+                    (bytemuck::bytes_of(input).to_vec(), disconnected)
+                })
+                .collect()
+        })
+        .collect();
+    bincode::serialize(&frames).expect("inputs should serialize")
+}
+
+/// Decodes inputs encoded with `encode_inputs()`. All inputs are confirmed.
+pub(crate) fn decode_inputs<I: bytemuck::Pod>(
+    data: &[u8],
+) -> Result<Vec<Vec<(I, InputStatus)>>, String> {
+    let frames: Vec<Vec<(Vec<u8>, bool)>> =
+        bincode::deserialize(data).map_err(|e| format!("invalid inputs: {e}"))?;
+    frames
+        .into_iter()
+        .map(|inputs| {
+            inputs
+                .into_iter()
+                .map(|(bytes, disconnected)| {
+                    let input = bytemuck::try_pod_read_unaligned(&bytes)
+                        .map_err(|e| format!("invalid input: {e}"))?;
+                    let status = if disconnected {
+                        InputStatus::Disconnected
+                    } else {
+                        InputStatus::Confirmed
+                    };
+                    Ok((input, status))
+                })
+                .collect()
+        })
+        .collect()
+}
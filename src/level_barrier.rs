@@ -0,0 +1,229 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    ggrs_stage::StageEvent,
+    socket::{channel, MultiplexSocket},
+};
+
+/// Default number of frames between requesting a level and entering it. Has to be larger than the prediction
+/// window, so the request is confirmed by the time the barrier is reached.
+const DEFAULT_DELAY: i32 = 16;
+
+/// "I have loaded the level entered at this frame"
+#[derive(Serialize, Deserialize)]
+struct Ready {
+    frame: i32,
+}
+
+/// Progress of a level transition, sent as an event for loading screens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LevelTransition {
+    /// All peers stopped at `frame` and should load `level` now. Call `LevelBarrier::ready()` when done.
+    Loading { level: u32, frame: i32 },
+    /// The number of peers, including ourselves, that have loaded the level changed.
+    Progress { ready: usize, total: usize },
+    /// Everyone has loaded the level, the simulation continues with `frame`.
+    Entering { level: u32, frame: i32 },
+}
+
+/// Coordinates level transitions in the middle of a session. Insert it as a resource on every peer, using a clone of
+/// the `MultiplexSocket` the session runs on.
+///
+/// 1. A system in the rollback schedule calls `request()`, which schedules the transition a few frames ahead.
+/// 2. All peers simulate up to that frame and stop there. A `LevelTransition::Loading` event asks to load the
+///    assets of the new level, outside of the rollback schedule.
+/// 3. When done, each peer calls `ready()`. Once all peers are ready, the simulation continues.
+/// 4. While simulating the barrier frame, `entering()` returns the new level. Systems in the rollback schedule
+///    despawn the old level and spawn the rollback entities of the new one then, so this happens at the same frame
+///    on every peer and is covered by the snapshots like any other change.
+#[derive(Resource)]
+pub struct LevelBarrier<A> {
+    socket: MultiplexSocket<A>,
+    peers: Vec<A>,
+    delay: i32,
+    /// the frame being simulated
+    frame: i32,
+    /// barrier frame -> (frame of the request, level)
+    scheduled: BTreeMap<i32, (i32, u32)>,
+    /// the barrier frame we are loading for, and whether we are done
+    loading: Option<(i32, bool)>,
+    /// peers that have loaded the level of a barrier frame
+    ready: BTreeMap<i32, Vec<A>>,
+    /// barrier frames everyone is ready for
+    passed: BTreeSet<i32>,
+    events: Vec<LevelTransition>,
+}
+
+impl<A: Clone + PartialEq + Send + Sync + 'static> LevelBarrier<A> {
+    /// Creates the barrier, waiting for the peers at the given addresses.
+    pub fn new(socket: MultiplexSocket<A>, peers: Vec<A>) -> Self {
+        Self {
+            socket,
+            peers,
+            delay: DEFAULT_DELAY,
+            frame: 0,
+            scheduled: BTreeMap::new(),
+            loading: None,
+            ready: BTreeMap::new(),
+            passed: BTreeSet::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Changes the number of frames between requesting a level and entering it. Defaults to 16.
+    pub fn with_delay(mut self, frames: i32) -> Self {
+        self.delay = frames.max(1);
+        self
+    }
+
+    /// Schedules a transition to `level` and returns the frame it happens at. Call this from a system in the
+    /// rollback schedule, so every peer requests the transition at the same frame.
+    pub fn request(&mut self, level: u32) -> i32 {
+        let barrier = self.frame + self.delay;
+        self.scheduled.insert(barrier, (self.frame, level));
+        barrier
+    }
+
+    /// Returns the level to load while the simulation waits at a barrier.
+    pub fn loading(&self) -> Option<u32> {
+        let (barrier, _) = self.loading?;
+        self.scheduled.get(&barrier).map(|(_, level)| *level)
+    }
+
+    /// Returns true while the simulation waits at a barrier.
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Tells the other peers that we have loaded the level of the current barrier.
+    pub fn ready(&mut self) {
+        if let Some((barrier, ready)) = self.loading.as_mut() {
+            if !*ready {
+                *ready = true;
+                let barrier = *barrier;
+                self.send_ready(barrier);
+                self.report_progress(barrier);
+            }
+        }
+    }
+
+    /// Returns the level entered in the frame being simulated. Spawn the new level when this returns `Some`.
+    pub fn entering(&self) -> Option<u32> {
+        if !self.passed.contains(&self.frame) {
+            return None;
+        }
+        self.scheduled.get(&self.frame).map(|(_, level)| *level)
+    }
+
+    /// Returns true if the stage has to wait before simulating `frame`. Requests that have not been confirmed yet
+    /// may still be rolled back, so loading only starts once `confirmed` has reached the request.
+    pub(crate) fn holds(&mut self, frame: i32, confirmed: i32) -> bool {
+        let Some(&(requested, level)) = self.scheduled.get(&frame) else {
+            return false;
+        };
+        if self.passed.contains(&frame) {
+            return false;
+        }
+        if self.loading.is_none() && confirmed >= requested {
+            self.loading = Some((frame, false));
+            self.events.push(LevelTransition::Loading { level, frame });
+        }
+        true
+    }
+
+    fn send_ready(&self, barrier: i32) {
+        let packet = bincode::serialize(&Ready { frame: barrier }).expect("should serialize");
+        for peer in &self.peers {
+            self.socket.send_on(channel::LEVEL, &packet, peer);
+        }
+    }
+
+    fn report_progress(&mut self, barrier: i32) {
+        let remote = self.ready.get(&barrier).map_or(0, |ready| ready.len());
+        let local = matches!(self.loading, Some((b, true)) if b == barrier) as usize;
+        self.events.push(LevelTransition::Progress {
+            ready: remote + local,
+            total: self.peers.len() + 1,
+        });
+    }
+
+    /// Collects and repeats ready messages, and passes the barrier once everyone is ready.
+    pub(crate) fn poll(&mut self) {
+        for (addr, data) in self.socket.receive_on(channel::LEVEL) {
+            if !self.peers.contains(&addr) {
+                continue;
+            }
+            let Ok(Ready { frame }) = bincode::deserialize(&data) else {
+                debug!("received a malformed level barrier packet");
+                continue;
+            };
+            if self.passed.contains(&frame) {
+                // the peer may have missed our message
+                self.send_ready(frame);
+            }
+            let ready = self.ready.entry(frame).or_default();
+            if !ready.contains(&addr) {
+                ready.push(addr);
+                self.report_progress(frame);
+            }
+        }
+
+        let Some((barrier, true)) = self.loading else {
+            return;
+        };
+        let ready = self.ready.get(&barrier).map_or(0, |ready| ready.len());
+        if ready < self.peers.len() {
+            // datagrams may get lost
+            self.send_ready(barrier);
+            return;
+        }
+        self.loading = None;
+        self.passed.insert(barrier);
+        if let Some(&(_, level)) = self.scheduled.get(&barrier) {
+            self.events.push(LevelTransition::Entering {
+                level,
+                frame: barrier,
+            });
+        }
+    }
+
+    pub(crate) fn on_stage_event(&mut self, event: StageEvent) {
+        match event {
+            StageEvent::Advancing { frame } => self.frame = frame,
+            // the resimulated frames will request their transitions again
+            StageEvent::Loaded { frame } => self
+                .scheduled
+                .retain(|_, (requested, _)| *requested < frame),
+            StageEvent::Advanced { frame } => self.frame = frame + 1,
+            StageEvent::Confirmed { frame } => {
+                self.scheduled.retain(|barrier, _| *barrier > frame);
+                self.passed.retain(|barrier| *barrier > frame);
+                self.ready.retain(|barrier, _| *barrier > frame);
+            }
+            StageEvent::Reset => {
+                self.frame = 0;
+                self.scheduled.clear();
+                self.loading = None;
+                self.ready.clear();
+                self.passed.clear();
+            }
+            StageEvent::Saved { .. } => {}
+        }
+    }
+
+    pub(crate) fn take_events(&mut self) -> Vec<LevelTransition> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+pub(crate) fn poll_level_barrier_system<A: Clone + PartialEq + Send + Sync + 'static>(
+    barrier: Option<ResMut<LevelBarrier<A>>>,
+    mut transitions: EventWriter<LevelTransition>,
+) {
+    if let Some(mut barrier) = barrier {
+        barrier.poll();
+        transitions.send_batch(barrier.take_events());
+    }
+}
@@ -2,21 +2,132 @@
 #![forbid(unsafe_code)] // let us try
 
 use bevy::{
+    input::InputSystem,
     prelude::*,
-    reflect::{FromType, GetTypeRegistration, TypeRegistry, TypeRegistryInternal},
+    reflect::{FromReflect, FromType, GetTypeRegistration, TypeRegistry, TypeRegistryInternal},
 };
 use ggrs::{Config, InputStatus, P2PSession, PlayerHandle, SpectatorSession, SyncTestSession};
-use ggrs_stage::GGRSStage;
+use ggrs_stage::{GGRSStage, StageEvent, StageHold, StageHook};
 use parking_lot::RwLock;
-use std::sync::Arc;
+use prefab::PrefabRegistry;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{path::PathBuf, sync::Arc};
+use world_snapshot::WorldSnapshot;
 
 pub use ggrs;
 
+pub use async_gateway::{AsyncGateway, AsyncResults};
+pub use catch_up::CatchUp;
+#[cfg(feature = "test-utils")]
+pub use conformance::{
+    Actor, ConformanceConfig, ConformanceReport, ConformanceScenario, Health, ScriptedAction,
+};
+pub use debug_commands::DebugCommands;
+#[cfg(feature = "debug-lines")]
+pub use debug_shapes::DebugLines;
+pub use debug_shapes::{DebugShape, DebugShapes, Shape, ShapeInstance};
+pub use desync_check::{AdaptiveDesyncCheck, DesyncCheckFailed};
+pub use device_assignment::{AssignedDevice, DeviceAssignment, DisconnectPolicy};
+pub use diagnostics::{SessionDiagnostics, SnapshotMetadata};
+pub use floating_origin::{FloatingOrigin, OriginFocus};
+pub use frame_timer::FrameTimer;
+pub use ggrs_stage::DesyncBisection;
+pub use input_injection::InjectedInputs;
+pub use input_packing::{
+    pack_input, pack_input_system, unpack_input, BitReader, BitWriter, PackedInput,
+};
+pub use interpolation::{FrameAlpha, Interpolated, Lerp, Snap, SnapComponent};
+pub use late_join::LateJoin;
+pub use level_barrier::{LevelBarrier, LevelTransition};
+pub use log_plugin::{GgrsLogPlugin, LogVerbosity, RollbackStats};
+pub use match_setup::{ControlScheme, ControlSchemes, InputDevice, MatchSetup, MatchSetupExchange};
+pub use memory_budget::{MemoryBudget, MemoryBudgetExceeded, MemoryDegradation, MemoryUsage};
+pub use migration::PeerAddressChanged;
+pub use netcode_hud::{NetcodeHud, PlayerConnection};
+pub use network_profile::NetworkProfile;
+pub use peer_messages::{PeerMessage, PeerMessages};
+pub use playback::PlaybackSpeed;
+pub use pool::{PoolQuery, PoolSlot, RollbackPool, SpawnPool};
+pub use prefab::{Prefab, PrefabLog, PrefabSpawner, SpawnPrefab};
+pub use presentation::{
+    Attachment, OrphanPolicy, PresentationEvent, PresentationEvents, PresentationOf,
+};
+pub use prewarm::{PrewarmRollback, SnapshotCapacity};
+pub use probe::{ConnectionProbe, ProbeReport};
+pub use replay_branch::ReplayBranch;
+pub use resync::DesyncRecovery;
+pub use rewind::Rewind;
+pub use rollback_events::RollbackEvents;
+pub use rollback_input::RollbackInput;
+pub use rollback_toggles::RollbackToggles;
+pub use schedule_lint::ScheduleLint;
+pub use shutdown::PeerLeft;
+pub use snapshot_stats::{SnapshotStats, TypeSnapshotStats};
+pub use socket::{DatagramSocket, MultiplexSocket};
+pub use spectator_hud::SpectatorHud;
+pub use spectator_links::{SpectatorLink, SpectatorLinks};
+pub use stage_placement::StagePlacement;
+pub use standby::StandbySession;
+pub use state_transfer::{ReceivedState, StateTransfer, StateTransferEvent, TransferDirection};
+
+pub(crate) mod async_gateway;
+pub(crate) mod catch_up;
+#[cfg(feature = "test-utils")]
+pub(crate) mod conformance;
+pub(crate) mod debug_commands;
+pub(crate) mod debug_shapes;
+pub(crate) mod desync_check;
+pub(crate) mod device_assignment;
+pub(crate) mod diagnostics;
+pub(crate) mod floating_origin;
+pub(crate) mod frame_timer;
 pub(crate) mod ggrs_stage;
+pub(crate) mod initial_scene;
+pub(crate) mod input_injection;
+pub(crate) mod input_packing;
+pub(crate) mod interpolation;
+pub(crate) mod late_join;
+pub(crate) mod level_barrier;
+pub(crate) mod log_plugin;
+pub(crate) mod match_setup;
+pub(crate) mod memory_budget;
+pub(crate) mod migration;
+pub(crate) mod netcode_hud;
+pub(crate) mod network_profile;
+pub(crate) mod panic_dump;
+pub(crate) mod peer_messages;
+pub(crate) mod playback;
+pub(crate) mod pool;
+pub(crate) mod prefab;
+pub(crate) mod presentation;
+pub(crate) mod prewarm;
+pub(crate) mod probe;
+pub(crate) mod replay_branch;
+pub(crate) mod request_trace;
+pub(crate) mod resync;
+pub(crate) mod rewind;
+pub(crate) mod rollback_events;
+pub(crate) mod rollback_input;
+pub(crate) mod rollback_toggles;
+pub(crate) mod schedule_lint;
+pub(crate) mod shutdown;
+pub(crate) mod snapshot_stats;
+pub(crate) mod socket;
+pub(crate) mod spectator_hud;
+pub(crate) mod spectator_links;
+pub(crate) mod stage_placement;
+pub(crate) mod standby;
+pub(crate) mod state_transfer;
 pub(crate) mod world_snapshot;
 
 /// Stage label for the Custom GGRS Stage.
 pub const GGRS_UPDATE: &str = "ggrs_update";
+/// Stage label for the stage right after the GGRS Stage, which keeps presentation entities in sync with the rollback world.
+pub const GGRS_PRESENTATION: &str = "ggrs_presentation";
+/// Stage label of the floating origin shift, appended to the rollback schedule.
+pub const GGRS_FLOATING_ORIGIN: &str = "ggrs_floating_origin";
+/// Stage label of the recording of debug shapes, appended to the rollback schedule.
+pub const GGRS_DEBUG_SHAPES: &str = "ggrs_debug_shapes";
 const DEFAULT_FPS: usize = 60;
 
 /// Defines the Session that the GGRS Plugin should expect as a resource.
@@ -31,25 +142,96 @@ pub enum Session<T: Config> {
 #[derive(Resource, Deref, DerefMut)]
 pub struct PlayerInputs<T: Config>(Vec<(T::Input, InputStatus)>);
 
+impl<T: Config> PlayerInputs<T>
+where
+    T::Input: AsRef<[u8]>,
+{
+    /// Unpacks the input of the given player, if the inputs were packed with `pack_input()`.
+    pub fn unpack<I: PackedInput>(&self, handle: PlayerHandle) -> (I, InputStatus) {
+        let (input, status) = &self.0[handle];
+        (unpack_input(input.as_ref()), *status)
+    }
+}
+
+/// The frame the rollback schedule is simulating. The inputs in `PlayerInputs` belong to this frame.
+/// Inserted by the GGRS stage before every frame advance and left in place afterwards, so outside of the rollback
+/// schedule it holds the last simulated frame.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deref)]
+pub struct RollbackFrame(pub(crate) i32);
+
 /// Add this component to all entities you want to be loaded/saved on rollback.
 /// The `id` has to be unique. Consider using the `RollbackIdProvider` resource.
-#[derive(Component)]
+///
+/// The id identifies an entity across rollbacks and across peers, so it has to be the same on every peer: either
+/// hand out ids in the same order everywhere with `RollbackIdProvider`, or derive them from something all peers
+/// agree on with `from_name()` or `derived()`. Rollback components are ordered by id, so sorting entities by their
+/// `Rollback` gives the same order on every peer, unlike the iteration order of queries.
+///
+/// Derived ids have 31 bits, so among many thousands of them a collision becomes likely. Saving a world with two
+/// entities of the same id panics, derive one of them from another name or index then.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[reflect(Component, Hash, PartialEq)]
 pub struct Rollback {
     id: u32,
 }
 
 impl Rollback {
+    /// Ids derived by `from_name()` and `derived()` have this bit set, so they don't collide with the ids of a
+    /// `RollbackIdProvider`, which counts up from 0.
+    pub const DERIVED_BIT: u32 = 1 << 31;
+
     /// Creates a new rollback tag with the given id.
     pub fn new(id: u32) -> Self {
         Self { id }
     }
 
+    /// Creates a rollback tag whose id is a stable hash of `name`, for entities that exist on every peer from the
+    /// start, such as the entities of a level. The hash is the same on every platform and version.
+    pub fn from_name(name: &str) -> Self {
+        Self::from_hash(name.as_bytes())
+    }
+
+    /// Creates a rollback tag for the `index`th entity spawned by the entity tagged with `parent`, so entities
+    /// spawned during the simulation get the same id on every peer without sharing a provider.
+    pub fn derived(parent: &Rollback, index: u32) -> Self {
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&parent.id.to_le_bytes());
+        bytes[4..].copy_from_slice(&index.to_le_bytes());
+        Self::from_hash(&bytes)
+    }
+
+    /// 32 bit FNV-1a, which is simple enough to never change.
+    fn from_hash(bytes: &[u8]) -> Self {
+        let hash = bytes.iter().fold(0x811c_9dc5_u32, |hash, byte| {
+            (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+        });
+        Self::new(hash | Self::DERIVED_BIT)
+    }
+
     /// Returns the rollback id.
     pub const fn id(&self) -> u32 {
         self.id
     }
 }
 
+/// Only there for components created by reflection, such as the ones of a scene: bevy creates the component with
+/// `from_world()` and applies the reflected value right after, which overwrites the id. The placeholder id returned
+/// here doesn't take an id from the `RollbackIdProvider` and must not be used for entities; create their tags with
+/// the provider, `from_name()` or `derived()` instead.
+impl FromWorld for Rollback {
+    fn from_world(_: &mut World) -> Self {
+        Self::new(u32::MAX)
+    }
+}
+
+/// A query over entities with a `Rollback` component, for systems of the rollback schedule that should only touch
+/// the simulated state.
+pub type RollbackQuery<'w, 's, Q, F = ()> = Query<'w, 's, Q, (With<Rollback>, F)>;
+
+/// A query over entities without a `Rollback` component, for presentation-only entities (effects, UI, cameras)
+/// that live outside of the simulation.
+pub type NonRollback<'w, 's, Q, F = ()> = Query<'w, 's, Q, (Without<Rollback>, F)>;
+
 /// Provides unique ids for your Rollback components.
 /// When you add the GGRS Plugin, this should be available as a resource.
 #[derive(Resource, Default)]
@@ -60,9 +242,10 @@ pub struct RollbackIdProvider {
 impl RollbackIdProvider {
     /// Returns an unused, unique id.
     pub fn next_id(&mut self) -> u32 {
-        if self.next_id == u32::MAX {
+        // the ids above are used by `Rollback::from_name()` and `Rollback::derived()`
+        if self.next_id == Rollback::DERIVED_BIT {
             // TODO: do something smart?
-            panic!("RollbackIdProvider: u32::MAX has been reached.");
+            panic!("RollbackIdProvider: all ids below Rollback::DERIVED_BIT have been used.");
         }
         let ret = self.next_id;
         self.next_id += 1;
@@ -93,8 +276,25 @@ impl RollbackIdProvider {
 pub struct GGRSPlugin<T: Config + Send + Sync> {
     input_system: Option<Box<dyn System<In = PlayerHandle, Out = T::Input>>>,
     fps: usize,
+    catch_up: CatchUp,
+    bisect_desyncs: bool,
+    lint_schedule: bool,
+    snapshot_stats: bool,
+    trace_path: Option<PathBuf>,
+    panic_dump: Option<PathBuf>,
+    rewind_history: usize,
+    floating_origin: bool,
+    initial_scene: Option<DynamicScene>,
+    debug_shapes: Option<SystemStage>,
+    placement: StagePlacement,
     type_registry: TypeRegistry,
     schedule: Schedule,
+    /// Setup steps for registered user types, applied to the app in `build()`.
+    app_setup: Vec<Box<dyn FnOnce(&mut App)>>,
+    /// Callbacks following what the GGRSStage does.
+    hooks: Vec<StageHook>,
+    /// Callbacks that can stop the GGRSStage before a frame.
+    holds: Vec<StageHold>,
 }
 
 impl<T: Config + Send + Sync> Default for GGRSPlugin<T> {
@@ -102,6 +302,17 @@ impl<T: Config + Send + Sync> Default for GGRSPlugin<T> {
         Self {
             input_system: None,
             fps: DEFAULT_FPS,
+            catch_up: CatchUp::Burst,
+            bisect_desyncs: false,
+            lint_schedule: false,
+            snapshot_stats: false,
+            trace_path: None,
+            panic_dump: None,
+            rewind_history: 0,
+            floating_origin: false,
+            initial_scene: None,
+            debug_shapes: None,
+            placement: StagePlacement::default(),
             type_registry: TypeRegistry {
                 internal: Arc::new(RwLock::new({
                     let mut r = TypeRegistryInternal::empty();
@@ -115,10 +326,17 @@ impl<T: Config + Send + Sync> Default for GGRSPlugin<T> {
                     // The user can still register any custom types with `register_rollback_type()`.
                     r.register::<Parent>();
                     r.register::<Children>();
+                    // used inside of rollback components and resources
+                    r.register::<FrameTimer>();
+                    // toggles the entities of pools
+                    r.register::<PoolSlot>();
                     r
                 })),
             },
             schedule: Default::default(),
+            app_setup: Vec::new(),
+            hooks: Vec::new(),
+            holds: Vec::new(),
         }
     }
 }
@@ -135,11 +353,129 @@ impl<T: Config + Send + Sync> GGRSPlugin<T> {
         self
     }
 
+    /// Changes how the rollback stage catches up after it fell behind, by simulating the missed frames at once or by
+    /// running slightly faster for a while. Defaults to `CatchUp::Burst`.
+    pub fn with_catch_up(mut self, catch_up: CatchUp) -> Self {
+        self.catch_up = catch_up;
+        self
+    }
+
+    /// Changes where the GGRS stage runs in the main schedule, for example `StagePlacement::after("physics_sync")`.
+    /// The stage it is placed relative to has to exist when `build()` is called. Defaults to
+    /// `StagePlacement::BeforeUpdate`.
+    pub fn with_stage_placement(mut self, placement: StagePlacement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    /// When a `SyncTestSession` reports mismatching checksums, resimulate the recent frames stage by stage to find
+    /// the stage of the rollback schedule that first produces diverging state, and log it together with the
+    /// differing components. The findings are kept in the `DesyncBisection` resource. Put systems into separate
+    /// stages for a more precise result.
+    pub fn with_sync_test_bisection(mut self, enabled: bool) -> Self {
+        self.bisect_desyncs = enabled;
+        self
+    }
+
+    /// After the rollback schedule first ran, it is checked for common sources of nondeterminism: conflicting systems
+    /// without a defined order, non-send data, wall-clock time and local device input, random number generators, and
+    /// resources and entities that are modified but not rolled back. Findings are logged as warnings and kept in the
+    /// `ScheduleLint` resource. The lint only knows the entities that exist when it runs, and recognizes random number
+    /// generators by the crate defining them, so it can miss some findings. Disabled by default.
+    pub fn with_schedule_lint(mut self, enabled: bool) -> Self {
+        self.lint_schedule = enabled;
+        self
+    }
+
+    /// Measures how much time saving and loading each registered type takes, how large its values are and how
+    /// often they change. The results are available in the `SnapshotStats` resource. Measuring has a small
+    /// overhead, so this is disabled by default.
+    pub fn with_snapshot_stats(mut self, enabled: bool) -> Self {
+        self.snapshot_stats = enabled;
+        self
+    }
+
+    /// Writes every request the GGRS stage executes (save frame X, load frame Y, advance frame Z with these inputs)
+    /// to a compact text file at `path`. Attach this trace to desync or stall bug reports, since it shows exactly
+    /// what the stage did.
+    pub fn with_request_trace(mut self, path: impl Into<PathBuf>) -> Self {
+        self.trace_path = Some(path.into());
+        self
+    }
+
+    /// When the rollback schedule panics, writes the frame being simulated, its inputs and the latest snapshot to a
+    /// text file at `path` before the panic continues to unwind, so crashes reported from playtests come with the
+    /// state that caused them. Nothing is written if the app is built with `panic = "abort"`.
+    pub fn with_panic_dump(mut self, path: impl Into<PathBuf>) -> Self {
+        self.panic_dump = Some(path.into());
+        self
+    }
+
+    /// Keeps the state of the last `frames` frames so that practice modes can go back in time with the `Rewind`
+    /// resource. Only works in a `SyncTestSession` with a check distance of 0. Every frame is saved an additional
+    /// time, so this is disabled by default.
+    pub fn with_rewind_history(mut self, frames: usize) -> Self {
+        self.rewind_history = frames;
+        self
+    }
+
+    /// Keeps the simulated space centered on the entity marked with `OriginFocus`, moving the `FloatingOrigin` in
+    /// steps of `cell_size`. The shift runs in a stage appended to the rollback schedule, so it is deterministic and
+    /// rolled back like everything else. Root entities outside of the rollback are shifted after the GGRS stage.
+    pub fn with_floating_origin(mut self, cell_size: u32) -> Self {
+        self.floating_origin = true;
+        self.app_setup.push(Box::new(move |app: &mut App| {
+            app.insert_resource(FloatingOrigin::new(cell_size))
+                .add_system_to_stage(
+                    GGRS_PRESENTATION,
+                    floating_origin::shift_presentation_system,
+                );
+        }));
+        self.register_rollback_resource::<FloatingOrigin>()
+            .register_rollback_component::<OriginFocus>()
+    }
+
+    /// Records the shapes of a collision component at the end of every simulated frame into `DebugShapes`. They are
+    /// not drawn unless `with_debug_lines()` is used; otherwise pass their line segments to a line renderer to see
+    /// hitboxes and how rollbacks moved them.
+    pub fn register_debug_shape<Type>(mut self) -> Self
+    where
+        Type: Component + DebugShape,
+    {
+        if self.debug_shapes.is_none() {
+            self.app_setup.push(Box::new(|app: &mut App| {
+                app.init_resource::<DebugShapes>();
+            }));
+        }
+        self.debug_shapes
+            .get_or_insert_with(SystemStage::single_threaded)
+            .add_system(debug_shapes::record_debug_shapes_system::<Type>);
+        self
+    }
+
+    /// Draws the recorded `DebugShapes` as lines after every update, the shapes of earlier frames fading towards
+    /// the color of the oldest one. Configure the colors and the number of frames with the `DebugLines` resource.
+    /// Needs the `debug-lines` feature and bevy's renderer.
+    #[cfg(feature = "debug-lines")]
+    pub fn with_debug_lines(mut self) -> Self {
+        self.app_setup.push(Box::new(|app: &mut App| {
+            app.init_resource::<DebugLines>()
+                .add_system_to_stage(GGRS_PRESENTATION, debug_shapes::draw_debug_lines_system);
+        }));
+        self
+    }
+
     /// Registers a system that takes player handles as input and returns the associated inputs for that player.
+    /// The input type has to be a `RollbackInput`, so mistakes that would break determinism fail to compile.
     pub fn with_input_system<Params>(
         mut self,
         input_fn: impl IntoSystem<PlayerHandle, T::Input, Params>,
-    ) -> Self {
+    ) -> Self
+    where
+        T::Input: RollbackInput,
+    {
+        #[allow(clippy::let_unit_value)]
+        let () = rollback_input::InputAssertions::<T::Input>::VALID;
         self.input_system = Some(Box::new(IntoSystem::into_system(input_fn)));
         self
     }
@@ -172,6 +508,189 @@ impl<T: Config + Send + Sync> GGRSPlugin<T> {
         self
     }
 
+    /// Registers a type of event that systems of the rollback schedule send to each other through the
+    /// `RollbackEvents<Type>` resource. The events are part of the snapshots, so they are rolled back as well.
+    pub fn register_rollback_event<Type>(mut self) -> Self
+    where
+        Type: GetTypeRegistration + FromReflect,
+    {
+        self.hooks.push(Box::new(|world: &mut World, event| {
+            if let Some(mut events) = world.get_resource_mut::<RollbackEvents<Type>>() {
+                events.on_stage_event(event);
+            }
+        }));
+        self.app_setup.push(Box::new(|app: &mut App| {
+            app.init_resource::<RollbackEvents<Type>>();
+        }));
+        self.type_registry.write().register::<Type>();
+        self.register_rollback_resource::<RollbackEvents<Type>>()
+    }
+
+    /// Registers a type of result computed in the background by an `AsyncGateway<Type>`. The results are handed to
+    /// the rollback schedule through the `AsyncResults<Type>` resource, which is part of the snapshots.
+    pub fn register_async_gateway<Type>(mut self) -> Self
+    where
+        Type: GetTypeRegistration + FromReflect + Clone,
+    {
+        self.hooks.push(Box::new(|world: &mut World, event| {
+            if world.contains_resource::<AsyncGateway<Type>>() {
+                world.resource_scope(|world, mut gateway: Mut<AsyncGateway<Type>>| {
+                    gateway.on_stage_event(world, event);
+                });
+            }
+        }));
+        self.app_setup.push(Box::new(|app: &mut App| {
+            app.init_resource::<AsyncGateway<Type>>()
+                .init_resource::<AsyncResults<Type>>();
+        }));
+        self.type_registry.write().register::<Type>();
+        self.register_rollback_resource::<AsyncResults<Type>>()
+    }
+
+    /// Registers a prefab that systems of the rollback schedule spawn with `SpawnPrefab`.
+    pub fn register_prefab<Type: Prefab>(mut self) -> Self {
+        self.app_setup.push(Box::new(|app: &mut App| {
+            app.init_resource::<PrefabLog>()
+                .init_resource::<PrefabRegistry>()
+                .world
+                .resource_mut::<PrefabRegistry>()
+                .register::<Type>();
+        }));
+        self
+    }
+
+    /// Registers a type of component to be smoothed between simulation frames. Every entity with such a component
+    /// gets an `Interpolated<Type>`, which holds the value to present. Add a `Snap` or `SnapComponent<Type>` to
+    /// make it jump instead.
+    pub fn register_interpolated_component<Type>(mut self) -> Self
+    where
+        Type: Component + Lerp + Clone,
+    {
+        self.app_setup.push(Box::new(|app: &mut App| {
+            app.add_system_to_stage(GGRS_PRESENTATION, interpolation::interpolate_system::<Type>);
+        }));
+        self
+    }
+
+    /// Registers a type of match setup data, which is exchanged between peers by a `MatchSetupExchange<Type, _>`
+    /// resource before the session starts. Once the exchange is complete, a `MatchSetup<Type>` resource is inserted.
+    pub fn register_match_setup<Type>(mut self) -> Self
+    where
+        Type: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+        T::Address: Send + Sync + 'static,
+    {
+        self.app_setup.push(Box::new(|app: &mut App| {
+            app.add_system_to_stage(
+                CoreStage::PreUpdate,
+                match_setup::exchange_match_setup_system::<Type, T::Address>,
+            );
+        }));
+        self
+    }
+
+    /// Registers the type of messages players exchange outside of the simulation through a `PeerMessages<Type, _>`
+    /// resource. Messages that arrive are delivered as `PeerMessage<Type>` events, except those of muted players.
+    pub fn register_peer_messages<Type>(mut self) -> Self
+    where
+        Type: Serialize + DeserializeOwned + Send + Sync + 'static,
+        T::Address: Send + Sync + 'static,
+    {
+        self.app_setup.push(Box::new(|app: &mut App| {
+            app.add_event::<PeerMessage<Type>>().add_system_to_stage(
+                CoreStage::PreUpdate,
+                peer_messages::poll_peer_messages_system::<Type, T>,
+            );
+        }));
+        self
+    }
+
+    /// Registers a type of presentation event. Systems in the rollback schedule send them through the
+    /// `PresentationEvents<Type>` resource, the rest of the app reads them as `PresentationEvent<Type>` events, which
+    /// are delivered exactly once, even if their frame is resimulated.
+    pub fn register_presentation_event<Type>(mut self) -> Self
+    where
+        Type: PartialEq + Clone + Send + Sync + 'static,
+    {
+        self.hooks.push(Box::new(|world: &mut World, event| {
+            if let Some(mut events) = world.get_resource_mut::<PresentationEvents<Type>>() {
+                events.on_stage_event(event);
+            }
+        }));
+        self.app_setup.push(Box::new(|app: &mut App| {
+            app.add_event::<PresentationEvent<Type>>()
+                .init_resource::<PresentationEvents<Type>>()
+                .add_system_to_stage(
+                    GGRS_PRESENTATION,
+                    presentation::deliver_presentation_events_system::<Type>,
+                );
+        }));
+        self
+    }
+
+    /// Registers a type of per-frame data that players forward to spectators through a `SpectatorHud<Type, _>`
+    /// resource.
+    pub fn register_spectator_hud<Type>(mut self) -> Self
+    where
+        Type: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+        T::Address: Send + Sync + 'static,
+    {
+        self.hooks.push(Box::new(|world: &mut World, event| {
+            let profile = world.get_resource::<NetworkProfile>().copied();
+            if let Some(mut hud) = world.get_resource_mut::<SpectatorHud<Type, T::Address>>() {
+                hud.set_send_interval(profile.unwrap_or_default().send_interval());
+                hud.on_stage_event(event);
+            }
+        }));
+        self.app_setup.push(Box::new(|app: &mut App| {
+            app.add_system_to_stage(
+                CoreStage::PreUpdate,
+                spectator_hud::receive_spectator_hud_system::<Type, T::Address>,
+            );
+        }));
+        self
+    }
+
+    /// Registers a type of debug console commands, see `DebugCommands`. The resource still has to be inserted on
+    /// all peers.
+    pub fn register_debug_commands<Type>(mut self) -> Self
+    where
+        Type: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+        T::Address: Send + Sync + 'static,
+    {
+        self.hooks.push(Box::new(|world: &mut World, event| {
+            let profile = world.get_resource::<NetworkProfile>().copied();
+            if let Some(mut commands) = world.get_resource_mut::<DebugCommands<Type, T::Address>>()
+            {
+                commands.set_send_interval(profile.unwrap_or_default().send_interval());
+                commands.on_stage_event(event);
+            }
+        }));
+        self.holds.push(Box::new(|world: &World, frame| {
+            world
+                .get_resource::<DebugCommands<Type, T::Address>>()
+                .map_or(false, |commands| commands.holds(frame))
+        }));
+        self.app_setup.push(Box::new(|app: &mut App| {
+            app.add_system_to_stage(
+                CoreStage::PreUpdate,
+                debug_commands::receive_debug_commands_system::<Type, T::Address>,
+            );
+        }));
+        self
+    }
+
+    /// Spawns the entities of `scene` as the initial rollback world when the plugin is built, for example a level
+    /// loaded from a `.scn.ron` file with a `SceneDeserializer`. Every entity becomes a rollback entity with an id
+    /// derived from its entity id in the scene, so all peers get the same ids.
+    ///
+    /// The checksum of the rollback world after loading is part of the config checksum of `SessionDiagnostics`,
+    /// which peers compare during the `MatchSetupExchange`. Peers that authored or loaded a different version of the
+    /// level don't start the match together, instead of silently desyncing from the first frame.
+    pub fn with_initial_scene(mut self, scene: DynamicScene) -> Self {
+        self.initial_scene = Some(scene);
+        self
+    }
+
     /// Adds a schedule into the GGRSStage that holds the game logic systems. This schedule should contain all
     /// systems you want to be executed during frame advances.
     pub fn with_rollback_schedule(mut self, schedule: Schedule) -> Self {
@@ -180,7 +699,10 @@ impl<T: Config + Send + Sync> GGRSPlugin<T> {
     }
 
     /// Consumes the builder and makes changes on the bevy app according to the settings.
-    pub fn build(self, app: &mut App) {
+    pub fn build(self, app: &mut App)
+    where
+        T::Address: Send + Sync + 'static,
+    {
         let mut input_system = self
             .input_system
             .expect("Adding an input system through GGRSBuilder::with_input_system is required");
@@ -188,10 +710,183 @@ impl<T: Config + Send + Sync> GGRSPlugin<T> {
         input_system.initialize(&mut app.world);
         let mut stage = GGRSStage::<T>::new(input_system);
         stage.set_update_frequency(self.fps);
-        stage.set_schedule(self.schedule);
+        stage.set_catch_up(self.catch_up);
+        stage.set_desync_bisection(self.bisect_desyncs);
+        stage.set_schedule_lint(self.lint_schedule);
+        stage.set_snapshot_stats(self.snapshot_stats);
+        stage.set_rewind_history(self.rewind_history);
+        if let Some(path) = self.panic_dump {
+            stage.set_panic_dump(path);
+        }
+        if let Some(path) = &self.trace_path {
+            match request_trace::RequestTrace::create(path) {
+                Ok(trace) => {
+                    app.insert_resource(trace);
+                }
+                Err(e) => warn!(
+                    "could not create the request trace at {}: {e}",
+                    path.display()
+                ),
+            }
+        }
+        let mut schedule = self.schedule;
+        if self.floating_origin {
+            schedule.add_stage(
+                GGRS_FLOATING_ORIGIN,
+                SystemStage::single_threaded().with_system(floating_origin::shift_origin_system),
+            );
+        }
+        if let Some(debug_shapes) = self.debug_shapes {
+            schedule.add_stage(GGRS_DEBUG_SHAPES, debug_shapes);
+        }
+        stage.set_schedule(schedule);
+        let mut type_names: Vec<String> = self
+            .type_registry
+            .read()
+            .iter()
+            .map(|registration| registration.type_name().to_string())
+            .collect();
+        let initial_state = self.initial_scene.as_ref().map(|scene| {
+            initial_scene::spawn_initial_scene(&mut app.world, scene, &self.type_registry)
+        });
+        let config = diagnostics::describe_config::<T>(self.fps, &mut type_names, initial_state);
+        app.insert_resource(SessionDiagnostics::new(config, self.trace_path.clone()))
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                diagnostics::record_diagnostics_system::<T>,
+            );
+        let checksum_registry = self.type_registry.clone();
         stage.set_type_registry(self.type_registry);
-        app.add_stage_before(CoreStage::Update, GGRS_UPDATE, stage);
+        for hook in self.hooks {
+            stage.add_hook(hook);
+        }
+        for hold in self.holds {
+            stage.add_hold(hold);
+        }
+        // batched traffic to spectators is sent in intervals of simulated frames
+        stage.add_hook(Box::new(|world: &mut World, event| {
+            if let StageEvent::Advanced { .. } = event {
+                if let Some(socket) = world.get_resource::<MultiplexSocket<T::Address>>() {
+                    socket.on_frame_advanced();
+                }
+            }
+        }));
+        stage.add_hook(Box::new(|world: &mut World, event| {
+            if let Some(mut barrier) = world.get_resource_mut::<LevelBarrier<T::Address>>() {
+                barrier.on_stage_event(event);
+            }
+        }));
+        stage.add_hook(Box::new(|world: &mut World, event| {
+            if let Some(mut stats) = world.get_resource_mut::<RollbackStats>() {
+                stats.on_stage_event(event);
+            }
+        }));
+        stage.add_hook(Box::new(move |world: &mut World, event| {
+            let profile = world
+                .get_resource::<NetworkProfile>()
+                .copied()
+                .unwrap_or_default();
+            let Some(mut check) = world.get_resource_mut::<AdaptiveDesyncCheck<T::Address>>()
+            else {
+                return;
+            };
+            check.set_interval_floor(profile.desync_check_interval());
+            // with sparse saving, GGRS may not have saved a frame the peers compare
+            if let StageEvent::Advancing { frame } = event {
+                if profile.sparse_saving() && check.needs_checksum(frame) {
+                    let checksum = WorldSnapshot::from_world(world, &checksum_registry).checksum;
+                    world
+                        .resource_mut::<AdaptiveDesyncCheck<T::Address>>()
+                        .on_stage_event(StageEvent::Saved { frame, checksum });
+                }
+            }
+            world
+                .resource_mut::<AdaptiveDesyncCheck<T::Address>>()
+                .on_stage_event(event);
+        }));
+        stage.add_hook(Box::new(|world: &mut World, event| {
+            if world.contains_resource::<PrefabLog>() {
+                world.resource_scope(|world, mut log: Mut<PrefabLog>| {
+                    log.on_stage_event(world, event);
+                });
+            }
+        }));
+        self.placement.add_stage(app, GGRS_UPDATE, stage);
+        // presentation stage
+        app.add_stage_after(GGRS_UPDATE, GGRS_PRESENTATION, SystemStage::parallel());
+        app.add_system_to_stage(GGRS_PRESENTATION, presentation::sync_presentation_system);
+        app.add_system_to_stage(GGRS_PRESENTATION, pool::pool_visibility_system);
+        // local players picking their devices
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            device_assignment::assign_devices_system::<T>.after(InputSystem),
+        );
+        // the session of the next match
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            standby::poll_standby_session_system::<T>,
+        );
+        // connection probing before a session
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            probe::poll_connection_probe_system::<T::Address>,
+        );
+        // leaving the session cleanly
+        app.add_event::<PeerLeft<T::Address>>()
+            .add_system_to_stage(CoreStage::PreUpdate, shutdown::receive_goodbye_system::<T>)
+            .add_system_to_stage(CoreStage::Last, shutdown::shutdown_on_exit_system::<T>);
+        // spectator metrics
+        app.init_resource::<SpectatorLinks<T::Address>>()
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                spectator_links::update_spectator_links_system::<T>,
+            );
+        // values for netplay indicators
+        app.init_resource::<NetcodeHud>().add_system_to_stage(
+            CoreStage::PostUpdate,
+            netcode_hud::update_netcode_hud_system::<T>,
+        );
+        // level transitions
+        app.add_event::<LevelTransition>().add_system_to_stage(
+            CoreStage::PreUpdate,
+            level_barrier::poll_level_barrier_system::<T::Address>,
+        );
+        // large states sent to other peers
+        app.add_event::<StateTransferEvent<T::Address>>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                state_transfer::poll_state_transfer_system::<T::Address>,
+            );
+        // comparing checksums with the peers
+        app.add_event::<DesyncCheckFailed<T::Address>>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                desync_check::poll_desync_check_system::<T::Address>,
+            );
+        // peers moving to new addresses
+        app.add_event::<PeerAddressChanged<T::Address>>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                migration::address_change_system::<T::Address>,
+            );
+        // running out of memory
+        app.add_event::<MemoryBudgetExceeded>();
+        if self.snapshot_stats {
+            app.init_resource::<SnapshotStats>();
+        }
+        if self.rewind_history > 0 {
+            app.insert_resource(Rewind::new(self.rewind_history, self.fps));
+        }
+        // for editors and external integrations, not part of the snapshots
+        app.register_type::<Rollback>();
         // other resources
-        app.insert_resource(RollbackIdProvider::default());
+        app.insert_resource(RollbackIdProvider::default())
+            .init_resource::<InjectedInputs<T>>()
+            .init_resource::<RollbackStats>()
+            .init_resource::<PlaybackSpeed>();
+        // systems for registered user types
+        for setup in self.app_setup {
+            setup(app);
+        }
     }
 }
@@ -0,0 +1,218 @@
+use bevy::prelude::*;
+use ggrs::{Config, GGRSEvent};
+use instant::Duration;
+use std::{
+    fmt::{Arguments, Debug},
+    marker::PhantomData,
+};
+
+use crate::{ggrs_stage::StageEvent, Session};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Counts the rollbacks of the GGRS stage since the app started. Always available as a resource.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RollbackStats {
+    /// Number of times the world was restored to an earlier frame.
+    pub rollbacks: usize,
+    /// Number of frames simulated again after a rollback.
+    pub resimulated_frames: usize,
+    /// The largest number of frames a single rollback went back.
+    pub max_depth: i32,
+    /// the frame after the latest simulated one
+    next_frame: i32,
+}
+
+impl RollbackStats {
+    pub(crate) fn on_stage_event(&mut self, event: StageEvent) {
+        match event {
+            StageEvent::Loaded { frame } if frame < self.next_frame => {
+                self.rollbacks += 1;
+                self.max_depth = self.max_depth.max(self.next_frame - frame);
+            }
+            StageEvent::Advancing { frame } if frame < self.next_frame => {
+                self.resimulated_frames += 1;
+            }
+            StageEvent::Advanced { frame } => self.next_frame = self.next_frame.max(frame + 1),
+            // the counts go on, the frames start over
+            StageEvent::Reset => self.next_frame = 0,
+            _ => {}
+        }
+    }
+}
+
+/// The log level of everything `GgrsLogPlugin` reports, except desyncs, which are always warnings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogVerbosity {
+    /// Reports are visible with the default log filter.
+    #[default]
+    Info,
+    /// Reports only show up once debug logging is enabled.
+    Debug,
+}
+
+/// Logs GGRS events, the network stats of every remote player and the rollback counts in regular intervals, like
+/// the box_game examples do. Add it after building the `GGRSPlugin`.
+///
+/// Logging the events drains them from the session. Disable it with `with_events(false)` if your own systems read
+/// `P2PSession::events()`.
+pub struct GgrsLogPlugin<T: Config> {
+    settings: LogSettings,
+    _marker: PhantomData<fn() -> T>,
+}
+
+#[derive(Resource, Debug, Clone, Copy)]
+struct LogSettings {
+    verbosity: LogVerbosity,
+    events: bool,
+    network_stats: Option<Duration>,
+    rollback_stats: Option<Duration>,
+}
+
+impl<T: Config> Default for GgrsLogPlugin<T> {
+    fn default() -> Self {
+        Self {
+            settings: LogSettings {
+                verbosity: LogVerbosity::default(),
+                events: true,
+                network_stats: Some(DEFAULT_INTERVAL),
+                rollback_stats: Some(DEFAULT_INTERVAL),
+            },
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Config> GgrsLogPlugin<T> {
+    /// Logs everything at info level, the network and rollback stats every 2 seconds.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Changes the log level. Defaults to `LogVerbosity::Info`.
+    pub fn with_verbosity(mut self, verbosity: LogVerbosity) -> Self {
+        self.settings.verbosity = verbosity;
+        self
+    }
+
+    /// Enables or disables logging the events of the session.
+    pub fn with_events(mut self, enabled: bool) -> Self {
+        self.settings.events = enabled;
+        self
+    }
+
+    /// Changes how often the network stats of each remote player are logged. `None` disables them.
+    pub fn with_network_stats_interval(mut self, interval: Option<Duration>) -> Self {
+        self.settings.network_stats = interval;
+        self
+    }
+
+    /// Changes how often the number of rollbacks since the last report is logged. `None` disables them.
+    pub fn with_rollback_stats_interval(mut self, interval: Option<Duration>) -> Self {
+        self.settings.rollback_stats = interval;
+        self
+    }
+}
+
+impl<T: Config + Send + Sync> Plugin for GgrsLogPlugin<T>
+where
+    GGRSEvent<T>: Debug,
+{
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.settings)
+            .add_system_to_stage(CoreStage::PostUpdate, log_system::<T>);
+    }
+}
+
+#[derive(Default)]
+struct LogState {
+    network_stats: Option<Timer>,
+    rollback_stats: Option<Timer>,
+    /// the rollback stats at the last report
+    reported: RollbackStats,
+}
+
+fn log(verbosity: LogVerbosity, message: Arguments) {
+    match verbosity {
+        LogVerbosity::Info => info!("{message}"),
+        LogVerbosity::Debug => debug!("{message}"),
+    }
+}
+
+fn log_system<T: Config>(
+    time: Res<Time>,
+    settings: Res<LogSettings>,
+    rollbacks: Res<RollbackStats>,
+    session: Option<ResMut<Session<T>>>,
+    mut state: Local<LogState>,
+) where
+    GGRSEvent<T>: Debug,
+{
+    let Some(mut session) = session else {
+        return;
+    };
+    let verbosity = settings.verbosity;
+
+    if settings.events {
+        let events: Vec<GGRSEvent<T>> = match session.as_mut() {
+            Session::P2PSession(session) => session.events().collect(),
+            Session::SpectatorSession(session) => session.events().collect(),
+            Session::SyncTestSession(_) => Vec::new(),
+        };
+        for event in events {
+            match event {
+                GGRSEvent::DesyncDetected { .. } => warn!("GGRS event: {event:?}"),
+                _ => log(verbosity, format_args!("GGRS event: {event:?}")),
+            }
+        }
+    }
+
+    let finished = |timer: &mut Option<Timer>, interval: Option<Duration>| {
+        let Some(interval) = interval else {
+            return false;
+        };
+        timer
+            .get_or_insert_with(|| Timer::new(interval, TimerMode::Repeating))
+            .tick(time.delta())
+            .just_finished()
+    };
+
+    if finished(&mut state.network_stats, settings.network_stats) {
+        match session.as_ref() {
+            Session::P2PSession(session) => {
+                for handle in 0..session.num_players() {
+                    // local players have no network stats
+                    if let Ok(stats) = session.network_stats(handle) {
+                        log(
+                            verbosity,
+                            format_args!("network stats of player {handle}: {stats:?}"),
+                        );
+                    }
+                }
+            }
+            Session::SpectatorSession(session) => {
+                if let Ok(stats) = session.network_stats() {
+                    log(
+                        verbosity,
+                        format_args!("network stats of the host: {stats:?}"),
+                    );
+                }
+            }
+            Session::SyncTestSession(_) => {}
+        }
+    }
+
+    if finished(&mut state.rollback_stats, settings.rollback_stats) {
+        let reported = &state.reported;
+        log(
+            verbosity,
+            format_args!(
+                "{} rollbacks resimulating {} frames since the last report, deepest rollback so far: {} frames",
+                rollbacks.rollbacks - reported.rollbacks,
+                rollbacks.resimulated_frames - reported.resimulated_frames,
+                rollbacks.max_depth
+            ),
+        );
+        state.reported = rollbacks.clone();
+    }
+}
@@ -0,0 +1,333 @@
+use bevy::prelude::*;
+use ggrs::PlayerHandle;
+use instant::{Duration, Instant};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    socket::{channel, MultiplexSocket},
+    SessionDiagnostics,
+};
+
+const DEFAULT_RESEND_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Serialize, Deserialize)]
+enum SetupPacket {
+    /// The setup data and control schemes of all players local to the sender, and its config checksum.
+    Data(
+        Vec<(PlayerHandle, Vec<u8>)>,
+        Vec<(PlayerHandle, ControlScheme)>,
+        Option<u64>,
+    ),
+    /// Sent in response to `Data`.
+    Ack,
+}
+
+/// The setup data (characters, stages, loadouts, ...) of all players, indexed by player handle.
+/// Inserted as a resource once the `MatchSetupExchange<T, A>` has completed. It is identical on all peers.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct MatchSetup<T> {
+    entries: BTreeMap<PlayerHandle, T>,
+}
+
+impl<T> MatchSetup<T> {
+    /// Returns the setup data of the given player.
+    pub fn get(&self, handle: PlayerHandle) -> Option<&T> {
+        self.entries.get(&handle)
+    }
+
+    /// Iterates over the setup data of all players, ordered by player handle.
+    pub fn iter(&self) -> impl Iterator<Item = (PlayerHandle, &T)> {
+        self.entries.iter().map(|(handle, data)| (*handle, data))
+    }
+}
+
+/// The kind of device a player controls the game with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InputDevice {
+    Keyboard,
+    Mouse,
+    Gamepad,
+    Touch,
+    Other,
+}
+
+/// How a player controls the game, so input displays of replays and spectators can show the right button icons.
+/// This is metadata only, it has no influence on the simulation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlScheme {
+    /// The device the player uses.
+    pub device: InputDevice,
+    /// The layout or family of the device, for example "xbox", "playstation" or "azerty". Empty if unknown.
+    pub layout: String,
+    /// The button bound to each game action, by action name.
+    pub bindings: BTreeMap<String, String>,
+}
+
+impl ControlScheme {
+    /// Creates a scheme for the given device, without layout and bindings.
+    pub fn new(device: InputDevice) -> Self {
+        Self {
+            device,
+            layout: String::new(),
+            bindings: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the layout of the device.
+    pub fn with_layout(mut self, layout: impl Into<String>) -> Self {
+        self.layout = layout.into();
+        self
+    }
+
+    /// Binds a game action to a button.
+    pub fn with_binding(mut self, action: impl Into<String>, button: impl Into<String>) -> Self {
+        self.bindings.insert(action.into(), button.into());
+        self
+    }
+}
+
+/// The control schemes players shared during the match setup, indexed by player handle. Inserted together with the
+/// `MatchSetup`. Players that didn't share their scheme are missing.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ControlSchemes {
+    entries: BTreeMap<PlayerHandle, ControlScheme>,
+}
+
+impl ControlSchemes {
+    /// Returns the control scheme of the given player.
+    pub fn get(&self, handle: PlayerHandle) -> Option<&ControlScheme> {
+        self.entries.get(&handle)
+    }
+
+    /// Iterates over the control schemes of all players that shared theirs, ordered by player handle.
+    pub fn iter(&self) -> impl Iterator<Item = (PlayerHandle, &ControlScheme)> {
+        self.entries
+            .iter()
+            .map(|(handle, scheme)| (*handle, scheme))
+    }
+}
+
+struct RemotePeer<A> {
+    addr: A,
+    handles: Vec<PlayerHandle>,
+    acked: bool,
+    mismatched: bool,
+}
+
+/// Exchanges setup data between all peers before the session starts, over the same `MultiplexSocket` the session
+/// will use later. Insert it as a resource and register the setup type with
+/// `GGRSPlugin::register_match_setup::<T>()`; a `MatchSetup<T>` resource is inserted once every peer has received
+/// the data of every player. Keep the exchange around after starting the session, so late retransmissions of other
+/// peers still get acknowledged.
+///
+/// The peers also compare the config checksum of their `SessionDiagnostics`, which covers the plugin settings, the
+/// registered rollback types and the initial scene. If a peer runs a different build or level, the exchange never
+/// completes and `config_mismatches()` lists that peer.
+#[derive(Resource)]
+pub struct MatchSetupExchange<T, A> {
+    socket: MultiplexSocket<A>,
+    num_players: usize,
+    local: Vec<(PlayerHandle, Vec<u8>)>,
+    local_schemes: Vec<(PlayerHandle, ControlScheme)>,
+    remotes: Vec<RemotePeer<A>>,
+    received: BTreeMap<PlayerHandle, T>,
+    schemes: BTreeMap<PlayerHandle, ControlScheme>,
+    resend_interval: Duration,
+    last_send: Option<Instant>,
+    config_checksum: Option<u64>,
+}
+
+impl<T, A> MatchSetupExchange<T, A>
+where
+    T: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    A: Clone + PartialEq + Send + Sync + 'static,
+{
+    /// Creates an exchange for a match with `num_players` players.
+    pub fn new(socket: MultiplexSocket<A>, num_players: usize) -> Self {
+        Self {
+            socket,
+            num_players,
+            local: Vec::new(),
+            local_schemes: Vec::new(),
+            remotes: Vec::new(),
+            received: BTreeMap::new(),
+            schemes: BTreeMap::new(),
+            resend_interval: DEFAULT_RESEND_INTERVAL,
+            last_send: None,
+            config_checksum: None,
+        }
+    }
+
+    /// Adds a player on this machine together with its setup data.
+    pub fn add_local_player(mut self, handle: PlayerHandle, data: T) -> Self {
+        let encoded = bincode::serialize(&data).expect("match setup data should serialize");
+        self.local.push((handle, encoded));
+        self.received.insert(handle, data);
+        self
+    }
+
+    /// Shares the control scheme of a local player with all other peers.
+    pub fn with_control_scheme(mut self, handle: PlayerHandle, scheme: ControlScheme) -> Self {
+        self.local_schemes.push((handle, scheme.clone()));
+        self.schemes.insert(handle, scheme);
+        self
+    }
+
+    /// Adds a player on the peer at `addr`. Multiple players may share an address.
+    pub fn add_remote_player(mut self, handle: PlayerHandle, addr: A) -> Self {
+        match self.remotes.iter_mut().find(|peer| peer.addr == addr) {
+            Some(peer) => peer.handles.push(handle),
+            None => self.remotes.push(RemotePeer {
+                addr,
+                handles: vec![handle],
+                acked: false,
+                mismatched: false,
+            }),
+        }
+        self
+    }
+
+    /// Adds a spectator at `addr`, which receives the data of all players but has no players itself. On the
+    /// spectator, create the exchange without local players and add all players as remote players.
+    pub fn add_spectator(mut self, addr: A) -> Self {
+        if !self.remotes.iter().any(|peer| peer.addr == addr) {
+            self.remotes.push(RemotePeer {
+                addr,
+                handles: Vec::new(),
+                acked: false,
+                mismatched: false,
+            });
+        }
+        self
+    }
+
+    /// Changes how often the local data is sent again to peers that have not acknowledged it yet.
+    pub fn with_resend_interval(mut self, interval: Duration) -> Self {
+        self.resend_interval = interval;
+        self
+    }
+
+    /// Sets the config checksum to compare with the other peers. By default, the checksum of the
+    /// `SessionDiagnostics` is used.
+    pub fn with_config_checksum(mut self, checksum: u64) -> Self {
+        self.config_checksum = Some(checksum);
+        self
+    }
+
+    /// Returns true if the data of all players has arrived, all peers have acknowledged our data and no peer
+    /// reported a different config checksum.
+    pub fn is_complete(&self) -> bool {
+        self.received.len() == self.num_players
+            && self
+                .remotes
+                .iter()
+                .all(|peer| peer.acked && !peer.mismatched)
+    }
+
+    /// Iterates over the addresses of the peers whose config checksum differs from ours.
+    pub fn config_mismatches(&self) -> impl Iterator<Item = &A> {
+        self.remotes
+            .iter()
+            .filter(|peer| peer.mismatched)
+            .map(|peer| &peer.addr)
+    }
+
+    /// Sends and receives setup data. Returns the `MatchSetup` once the exchange is complete.
+    pub fn poll(&mut self) -> Option<MatchSetup<T>> {
+        for (addr, data) in self.socket.receive_on(channel::MATCH_SETUP) {
+            let Some(peer) = self.remotes.iter_mut().find(|peer| peer.addr == addr) else {
+                debug!("ignoring match setup data from an unknown address");
+                continue;
+            };
+            match bincode::deserialize(&data) {
+                Ok(SetupPacket::Data(entries, schemes, checksum)) => {
+                    let mismatched = matches!(
+                        (checksum, self.config_checksum),
+                        (Some(theirs), Some(ours)) if theirs != ours
+                    );
+                    if mismatched && !peer.mismatched {
+                        error!("a peer runs with a different config checksum, refusing to start the match");
+                    }
+                    peer.mismatched |= mismatched;
+                    for (handle, scheme) in schemes {
+                        if peer.handles.contains(&handle) {
+                            self.schemes.entry(handle).or_insert(scheme);
+                        }
+                    }
+                    for (handle, encoded) in entries {
+                        if !peer.handles.contains(&handle) {
+                            warn!("peer sent match setup data for player {handle}, which it doesn't own");
+                            continue;
+                        }
+                        match bincode::deserialize(&encoded) {
+                            Ok(data) => {
+                                self.received.entry(handle).or_insert(data);
+                            }
+                            Err(e) => {
+                                warn!("failed to decode match setup data of player {handle}: {e}")
+                            }
+                        }
+                    }
+                    let ack = bincode::serialize(&SetupPacket::Ack).expect("should serialize");
+                    self.socket.send_on(channel::MATCH_SETUP, &ack, &peer.addr);
+                }
+                Ok(SetupPacket::Ack) => peer.acked = true,
+                Err(_) => debug!("received a malformed match setup packet"),
+            }
+        }
+
+        // (re)send our data to everyone who hasn't acknowledged it
+        let resend_due = self
+            .last_send
+            .map_or(true, |last| last.elapsed() >= self.resend_interval);
+        if resend_due && self.remotes.iter().any(|peer| !peer.acked) {
+            let packet = bincode::serialize(&SetupPacket::Data(
+                self.local.clone(),
+                self.local_schemes.clone(),
+                self.config_checksum,
+            ))
+            .expect("should serialize");
+            for peer in self.remotes.iter().filter(|peer| !peer.acked) {
+                self.socket
+                    .send_on(channel::MATCH_SETUP, &packet, &peer.addr);
+            }
+            self.last_send = Some(Instant::now());
+        }
+
+        self.is_complete().then(|| MatchSetup {
+            entries: self.received.clone(),
+        })
+    }
+
+    /// Returns the control schemes shared so far.
+    pub fn control_schemes(&self) -> ControlSchemes {
+        ControlSchemes {
+            entries: self.schemes.clone(),
+        }
+    }
+}
+
+/// Polls the exchange and inserts the `MatchSetup` as soon as it is complete.
+pub(crate) fn exchange_match_setup_system<T, A>(
+    mut commands: Commands,
+    exchange: Option<ResMut<MatchSetupExchange<T, A>>>,
+    setup: Option<Res<MatchSetup<T>>>,
+    diagnostics: Option<Res<SessionDiagnostics>>,
+) where
+    T: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    A: Clone + PartialEq + Send + Sync + 'static,
+{
+    let Some(mut exchange) = exchange else {
+        return;
+    };
+    if exchange.config_checksum.is_none() {
+        exchange.config_checksum = diagnostics.map(|diagnostics| diagnostics.config_checksum());
+    }
+    if let Some(result) = exchange.poll() {
+        if setup.is_none() {
+            commands.insert_resource(result);
+            commands.insert_resource(exchange.control_schemes());
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+
+/// Estimated memory held by the netcode, in bytes. Like `SnapshotStats`, saved values count their own size and the
+/// vectors, strings and maps they own, but not the spare capacity of vectors and maps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The snapshots GGRS can roll back to.
+    pub snapshots: usize,
+    /// The states kept for `Rewind` and the inputs kept for bisecting desyncs and desync recovery.
+    pub history: usize,
+    /// The inputs recorded from the start of the session for late joiners.
+    pub replay: usize,
+    /// The buffered request trace, the samples of `SessionDiagnostics` and the recorded `DebugShapes`.
+    pub diagnostics: usize,
+    /// The states being sent and received by `StateTransfer` and `DesyncRecovery`.
+    pub transfers: usize,
+}
+
+impl MemoryUsage {
+    /// Sum of all kinds of memory.
+    pub fn total(&self) -> usize {
+        self.snapshots + self.history + self.replay + self.diagnostics + self.transfers
+    }
+}
+
+/// How far the GGRS stage has cut back to stay within the `MemoryBudget`. Every level includes the ones before it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MemoryDegradation {
+    /// Everything is kept as configured.
+    #[default]
+    None,
+    /// `Rewind` only reaches back a quarter of the frames set with `GGRSPlugin::with_rewind_history()`, and desync
+    /// recovery only keeps the inputs of the frames that can be rolled back.
+    ReducedHistory,
+    /// Snapshots are dropped as soon as their frame is confirmed, only the frames that can still be rolled back to are
+    /// kept. GGRS still saves every frame; its sparse saving mode, which saves fewer of them, can only be chosen
+    /// when the session is started, for example with `NetworkProfile::LowBandwidth`.
+    NoConfirmedSnapshots,
+    /// Inputs are no longer recorded for late joiners, and the ones recorded so far are dropped. Late joiners then
+    /// can't catch up anymore.
+    NoReplayCapture,
+}
+
+impl MemoryDegradation {
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::None => Some(Self::ReducedHistory),
+            Self::ReducedHistory => Some(Self::NoConfirmedSnapshots),
+            Self::NoConfirmedSnapshots => Some(Self::NoReplayCapture),
+            Self::NoReplayCapture => None,
+        }
+    }
+}
+
+/// Sent when the memory of the GGRS stage exceeded the `MemoryBudget` and the stage cut back further.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBudgetExceeded {
+    /// The memory in use when the budget was exceeded.
+    pub usage: MemoryUsage,
+    /// The budget, in bytes.
+    pub limit: usize,
+    /// The level now in effect.
+    pub degradation: MemoryDegradation,
+}
+
+/// A hard limit on the memory of the netcode, for platforms with little RAM. Insert it as a resource to have the
+/// stage estimate its snapshots, recorded inputs, diagnostics and state transfers after every update, see
+/// `MemoryUsage`. Whenever they exceed the limit, the stage takes the next step of `MemoryDegradation`, one per
+/// update, and sends a `MemoryBudgetExceeded` event. Diagnostics and transfers count towards the limit, but are
+/// never cut back.
+///
+/// The stage never goes back to a lower level on its own, since memory that was freed once would just fill up
+/// again. Call `restore()`, for example when a new session starts, to keep everything again.
+#[derive(Resource, Debug, Clone)]
+pub struct MemoryBudget {
+    limit: usize,
+    usage: MemoryUsage,
+    degradation: MemoryDegradation,
+}
+
+impl MemoryBudget {
+    /// Limits the memory of the netcode to `bytes`.
+    pub fn new(bytes: usize) -> Self {
+        Self {
+            limit: bytes,
+            usage: MemoryUsage::default(),
+            degradation: MemoryDegradation::None,
+        }
+    }
+
+    /// Returns the limit, in bytes.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Changes the limit. Lowering it takes effect with the next update, raising it doesn't undo any degradation.
+    pub fn set_limit(&mut self, bytes: usize) {
+        self.limit = bytes;
+    }
+
+    /// Returns the memory in use after the last update.
+    pub fn usage(&self) -> MemoryUsage {
+        self.usage
+    }
+
+    /// Returns the level of degradation in effect.
+    pub fn degradation(&self) -> MemoryDegradation {
+        self.degradation
+    }
+
+    /// Returns true if the memory in use exceeds the limit, even after all steps of degradation.
+    pub fn is_exhausted(&self) -> bool {
+        self.usage.total() > self.limit && self.degradation == MemoryDegradation::NoReplayCapture
+    }
+
+    /// Keeps everything as configured again.
+    pub fn restore(&mut self) {
+        self.degradation = MemoryDegradation::None;
+    }
+
+    /// Records the memory in use and takes the next step of degradation if it exceeds the limit.
+    pub(crate) fn update(&mut self, usage: MemoryUsage) -> Option<MemoryBudgetExceeded> {
+        self.usage = usage;
+        if usage.total() <= self.limit {
+            return None;
+        }
+        self.degradation = self.degradation.next()?;
+        Some(MemoryBudgetExceeded {
+            usage,
+            limit: self.limit,
+            degradation: self.degradation,
+        })
+    }
+}
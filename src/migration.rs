@@ -0,0 +1,31 @@
+use bevy::prelude::*;
+
+use crate::socket::MultiplexSocket;
+
+/// Sent when a peer started sending from a new address, for example after switching from Wi-Fi to a mobile network.
+/// Only detected by a `MultiplexSocket` set up with `with_peer_tokens()`. The session keeps running: the peer is
+/// still known by `addr`, but all traffic to it now goes to `new`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerAddressChanged<A> {
+    /// The address the session knows the peer by.
+    pub addr: A,
+    /// The address the peer sent from before.
+    pub old: A,
+    /// The address the peer sends from now.
+    pub new: A,
+}
+
+pub(crate) fn address_change_system<A>(
+    socket: Option<Res<MultiplexSocket<A>>>,
+    mut changed: EventWriter<PeerAddressChanged<A>>,
+) where
+    A: Clone + PartialEq + Send + Sync + 'static,
+{
+    let Some(socket) = socket else {
+        return;
+    };
+    for (addr, old, new) in socket.take_address_changes() {
+        info!("a peer of the GGRS session moved to a new address");
+        changed.send(PeerAddressChanged { addr, old, new });
+    }
+}
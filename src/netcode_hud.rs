@@ -0,0 +1,126 @@
+use bevy::prelude::*;
+use ggrs::{Config, PlayerHandle};
+use instant::{Duration, Instant};
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{desync_check::AdaptiveDesyncCheck, log_plugin::RollbackStats, Session};
+
+/// Round trip times in milliseconds below which a connection gets 4, 3, 2 and 1 bars.
+const BAR_THRESHOLDS: [u128; 4] = [60, 120, 200, 350];
+/// Time span of `NetcodeHud::rollback_frames_last_second()`.
+const ROLLBACK_WINDOW: Duration = Duration::from_secs(1);
+
+/// The connection to a remote player, as shown by a netplay indicator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlayerConnection {
+    /// Round trip time in milliseconds. 0 while not connected.
+    pub ping: u128,
+    /// Connection quality from 0 (no connection) to 4 bars.
+    pub bars: u8,
+}
+
+/// Ready-to-render values for the standard netplay indicators, updated every frame: the number of frames resimulated
+/// during the last second, how far this peer is ahead of the others, the cadence of desync checks and connection bars
+/// per remote player. Always available as a resource.
+///
+/// GGRS doesn't report the input delay a session was started with; show the value passed to
+/// `SessionBuilder::with_input_delay()` next to the HUD if players should see it.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetcodeHud {
+    rollback_frames: usize,
+    frames_ahead: i32,
+    predicted_frames: i32,
+    desync_check_interval: Option<u32>,
+    connections: BTreeMap<PlayerHandle, PlayerConnection>,
+}
+
+impl NetcodeHud {
+    /// Returns the number of frames simulated again because of rollbacks during the last second.
+    pub fn rollback_frames_last_second(&self) -> usize {
+        self.rollback_frames
+    }
+
+    /// Returns the number of frames this peer is ahead of the remote players, as reported by the `P2PSession`. The
+    /// GGRS stage runs slower until it is back to 0, so these frames add to the input delay the players feel.
+    pub fn frames_ahead(&self) -> i32 {
+        self.frames_ahead
+    }
+
+    /// Returns the number of frames simulated with predicted inputs that may still be rolled back.
+    pub fn predicted_frames(&self) -> i32 {
+        self.predicted_frames
+    }
+
+    /// Returns the number of frames between the checksums sent by `AdaptiveDesyncCheck`, if there is one.
+    pub fn desync_check_interval(&self) -> Option<u32> {
+        self.desync_check_interval
+    }
+
+    /// Returns the connection to the given remote player. Local players have none.
+    pub fn connection(&self, handle: PlayerHandle) -> Option<PlayerConnection> {
+        self.connections.get(&handle).copied()
+    }
+
+    /// Iterates over the connections to all remote players, ordered by player handle. In a `SpectatorSession`, the
+    /// host is listed as handle 0.
+    pub fn connections(&self) -> impl Iterator<Item = (PlayerHandle, PlayerConnection)> + '_ {
+        self.connections
+            .iter()
+            .map(|(handle, connection)| (*handle, *connection))
+    }
+}
+
+fn bars(ping: u128) -> u8 {
+    BAR_THRESHOLDS.iter().filter(|limit| ping < **limit).count() as u8
+}
+
+pub(crate) fn update_netcode_hud_system<T: Config>(
+    session: Option<Res<Session<T>>>,
+    stats: Res<RollbackStats>,
+    desync_check: Option<Res<AdaptiveDesyncCheck<T::Address>>>,
+    mut hud: ResMut<NetcodeHud>,
+    mut history: Local<VecDeque<(Instant, usize)>>,
+) {
+    // the resimulated frames counted at least a second ago are the baseline
+    let now = Instant::now();
+    history.push_back((now, stats.resimulated_frames));
+    while history.len() > 1 && now.duration_since(history[1].0) >= ROLLBACK_WINDOW {
+        history.pop_front();
+    }
+    let baseline = history.front().map_or(0, |(_, frames)| *frames);
+    hud.rollback_frames = stats.resimulated_frames - baseline;
+    hud.desync_check_interval = desync_check.map(|check| check.interval());
+
+    hud.connections.clear();
+    hud.predicted_frames = 0;
+    hud.frames_ahead = 0;
+    match session.as_deref() {
+        Some(Session::P2PSession(session)) => {
+            hud.predicted_frames = (session.current_frame() - session.confirmed_frame() - 1).max(0);
+            hud.frames_ahead = session.frames_ahead().max(0);
+            let local_handles = session.local_player_handles();
+            for handle in (0..session.num_players()).filter(|h| !local_handles.contains(h)) {
+                // disconnected and not yet synchronized players have no stats
+                let connection = match session.network_stats(handle) {
+                    Ok(stats) => PlayerConnection {
+                        ping: stats.ping,
+                        bars: bars(stats.ping),
+                    },
+                    Err(_) => PlayerConnection::default(),
+                };
+                hud.connections.insert(handle, connection);
+            }
+        }
+        Some(Session::SpectatorSession(session)) => {
+            let connection = match session.network_stats() {
+                Ok(stats) => PlayerConnection {
+                    ping: stats.ping,
+                    bars: bars(stats.ping),
+                },
+                Err(_) => PlayerConnection::default(),
+            };
+            hud.connections.insert(0, connection);
+        }
+        _ => {}
+    }
+}
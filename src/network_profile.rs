@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+use ggrs::{Config, DesyncDetection, SessionBuilder};
+
+/// Frames between desync checks in the low-bandwidth profile.
+const LOW_BANDWIDTH_DESYNC_INTERVAL: u32 = 60;
+/// Frames between sends of the additional bevy_ggrs channels in the low-bandwidth profile.
+const LOW_BANDWIDTH_SEND_INTERVAL: i32 = 6;
+
+/// How much bandwidth a session may use. Pick it before starting the session, pass the `SessionBuilder` through
+/// `configure()` and insert the profile as a resource on every peer.
+///
+/// `LowBandwidth` is meant for players on metered mobile connections. It enables GGRS' sparse saving, checks for
+/// desyncs only once per second, both with GGRS' desync detection and with `AdaptiveDesyncCheck`, and sends the data
+/// of `SpectatorHud` in batches of six frames. `DebugCommands` repeat their packets less often as well, but at least
+/// twice per command delay, so their stalls don't get longer.
+///
+/// With sparse saving, GGRS only saves confirmed frames, and which ones differs between the peers. Desync recovery
+/// and late joins hand over the latest stored state that is final instead of the one right after the confirmed
+/// frame, and `AdaptiveDesyncCheck` only compares frames that are multiples of its interval, taking their checksums
+/// itself where GGRS didn't save them.
+///
+/// The inputs and quality reports of GGRS are sent as usual: it already sends inputs as deltas against the last
+/// acknowledged one, which `pack_input()` helps with by keeping inputs small, and its quality reports go out at a
+/// fixed rate that can't be configured.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NetworkProfile {
+    /// The defaults of GGRS and bevy_ggrs.
+    #[default]
+    Default,
+    /// Trades latency of the additional channels and desync detection for less traffic.
+    LowBandwidth,
+}
+
+impl NetworkProfile {
+    /// Applies the session settings of the profile to the builder.
+    pub fn configure<T: Config>(self, builder: SessionBuilder<T>) -> SessionBuilder<T> {
+        match self {
+            NetworkProfile::Default => builder,
+            NetworkProfile::LowBandwidth => builder
+                .with_sparse_saving_mode(self.sparse_saving())
+                .with_desync_detection_mode(DesyncDetection::On {
+                    interval: self.desync_check_interval(),
+                }),
+        }
+    }
+
+    /// Returns true if the session saves only some of the frames.
+    pub fn sparse_saving(self) -> bool {
+        self == NetworkProfile::LowBandwidth
+    }
+
+    /// Returns the number of frames between sends of the additional bevy_ggrs channels.
+    pub fn send_interval(self) -> i32 {
+        match self {
+            NetworkProfile::Default => 1,
+            NetworkProfile::LowBandwidth => LOW_BANDWIDTH_SEND_INTERVAL,
+        }
+    }
+
+    /// Returns the shortest number of frames between desync checks.
+    pub fn desync_check_interval(self) -> u32 {
+        match self {
+            NetworkProfile::Default => 1,
+            NetworkProfile::LowBandwidth => LOW_BANDWIDTH_DESYNC_INTERVAL,
+        }
+    }
+}
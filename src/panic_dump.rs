@@ -0,0 +1,51 @@
+use bevy::prelude::*;
+use std::{any::Any, fs, path::Path};
+
+const HEADER: &str = "# bevy_ggrs panic dump v1";
+
+/// Everything known about the frame the rollback schedule panicked in.
+pub(crate) struct PanicDump<'a> {
+    /// the frame being simulated
+    pub(crate) frame: i32,
+    /// the inputs of that frame, formatted like in the request trace
+    pub(crate) inputs: String,
+    /// the frame and checksum of the latest saved snapshot and its contents
+    pub(crate) snapshot: Option<(i32, u64, String)>,
+    pub(crate) payload: &'a (dyn Any + Send),
+}
+
+impl PanicDump<'_> {
+    /// Writes the dump to `path`, replacing an older dump. Failing to write is only logged, so the original panic
+    /// is what gets reported.
+    pub(crate) fn write(&self, path: &Path) {
+        let message = match (
+            self.payload.downcast_ref::<&str>(),
+            self.payload.downcast_ref::<String>(),
+        ) {
+            (Some(message), _) => message.to_string(),
+            (_, Some(message)) => message.clone(),
+            _ => "unknown".to_string(),
+        };
+
+        let mut dump = format!(
+            "{HEADER}\npanic: {message}\nframe: {}\ninputs: {}\n",
+            self.frame, self.inputs
+        );
+        match &self.snapshot {
+            Some((frame, checksum, contents)) => {
+                dump.push_str(&format!("snapshot: {frame} {checksum:016x}\n{contents}"));
+            }
+            None => dump.push_str("snapshot: none\n"),
+        }
+        match fs::write(path, dump) {
+            Ok(()) => error!(
+                "the rollback schedule panicked, wrote a dump to {}",
+                path.display()
+            ),
+            Err(e) => error!(
+                "the rollback schedule panicked, failed to write a dump to {}: {e}",
+                path.display()
+            ),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use bevy::prelude::*;
+use ggrs::{Config, PlayerHandle};
+use instant::{Duration, Instant};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    marker::PhantomData,
+};
+
+use crate::{
+    socket::{channel, MultiplexSocket},
+    Session,
+};
+
+const DEFAULT_RESEND_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Serialize, Deserialize)]
+enum MessagePacket {
+    /// A serialized message, numbered by its sender.
+    Message { id: u32, message: Vec<u8> },
+    /// The message with the id has arrived.
+    Ack { id: u32 },
+}
+
+/// Delivered for every message another player sent through `PeerMessages<M, _>`, unless that player is muted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerMessage<M> {
+    pub sender: PlayerHandle,
+    pub message: M,
+}
+
+struct Outgoing<A> {
+    id: u32,
+    message: Vec<u8>,
+    /// peers that haven't acknowledged the message yet
+    waiting: Vec<A>,
+    last_send: Instant,
+}
+
+/// The ids of the messages received from one player.
+#[derive(Default)]
+struct Seen {
+    /// all ids below this have been received
+    below: u32,
+    /// ids above `below` that have been received
+    above: BTreeSet<u32>,
+}
+
+impl Seen {
+    /// Returns false if the id has been received before.
+    fn insert(&mut self, id: u32) -> bool {
+        if id < self.below || !self.above.insert(id) {
+            return false;
+        }
+        while self.above.remove(&self.below) {
+            self.below += 1;
+        }
+        true
+    }
+}
+
+/// Messages between players outside of the simulation, such as chat or emotes. They are not part of the rollback
+/// and arrive in no particular frame. Register the message type with `GGRSPlugin::register_peer_messages::<M>()`
+/// and insert the resource on all peers; arriving messages are delivered as `PeerMessage<M>` events. Use a single
+/// message type per app, for example an enum of chat lines and emotes.
+///
+/// Messages are resent until every peer has acknowledged them. The sender of a message is the player the `P2PSession`
+/// has at the address it came from, so messages only arrive while a session runs. Messages of muted players are
+/// dropped on arrival, before they reach any system.
+#[derive(Resource)]
+pub struct PeerMessages<M, A> {
+    socket: MultiplexSocket<A>,
+    local: PlayerHandle,
+    peers: Vec<A>,
+    muted: Vec<PlayerHandle>,
+    resend_interval: Duration,
+    next_id: u32,
+    outgoing: Vec<Outgoing<A>>,
+    /// the messages received from each player so far, so repeated sends are delivered once
+    seen: BTreeMap<PlayerHandle, Seen>,
+    received: Vec<PeerMessage<M>>,
+    _marker: PhantomData<fn() -> M>,
+}
+
+impl<M, A> PeerMessages<M, A>
+where
+    M: Serialize + DeserializeOwned + Send + Sync + 'static,
+    A: Clone + PartialEq + Send + Sync + 'static,
+{
+    /// Creates the resource for the local player with the given handle, exchanging messages with the given peers.
+    pub fn new(socket: MultiplexSocket<A>, local: PlayerHandle, peers: Vec<A>) -> Self {
+        Self {
+            socket,
+            local,
+            peers,
+            muted: Vec::new(),
+            resend_interval: DEFAULT_RESEND_INTERVAL,
+            next_id: 0,
+            outgoing: Vec::new(),
+            seen: BTreeMap::new(),
+            received: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Changes how often unacknowledged messages are sent again. Defaults to 200 ms.
+    pub fn with_resend_interval(mut self, interval: Duration) -> Self {
+        self.resend_interval = interval;
+        self
+    }
+
+    /// Sends a message to all peers.
+    pub fn send(&mut self, message: &M) {
+        let message = bincode::serialize(message).expect("peer messages should serialize");
+        let outgoing = Outgoing {
+            id: self.next_id,
+            message,
+            waiting: self.peers.clone(),
+            last_send: Instant::now(),
+        };
+        self.next_id += 1;
+        self.send_message(&outgoing);
+        self.outgoing.push(outgoing);
+    }
+
+    /// Drops all messages of the given player from now on, including ones that arrived but haven't been delivered
+    /// yet.
+    pub fn mute(&mut self, handle: PlayerHandle) {
+        if !self.muted.contains(&handle) {
+            self.muted.push(handle);
+        }
+        self.received.retain(|message| message.sender != handle);
+    }
+
+    /// Delivers the messages of the given player again. Messages sent while it was muted stay dropped.
+    pub fn unmute(&mut self, handle: PlayerHandle) {
+        self.muted.retain(|muted| *muted != handle);
+    }
+
+    /// Returns true if the messages of the given player are dropped.
+    pub fn is_muted(&self, handle: PlayerHandle) -> bool {
+        self.muted.contains(&handle)
+    }
+
+    /// Returns all muted players.
+    pub fn muted(&self) -> &[PlayerHandle] {
+        &self.muted
+    }
+
+    /// Returns the handle of the local player, the sender of the messages sent from here.
+    pub fn local(&self) -> PlayerHandle {
+        self.local
+    }
+
+    fn send_message(&self, outgoing: &Outgoing<A>) {
+        let packet = MessagePacket::Message {
+            id: outgoing.id,
+            message: outgoing.message.clone(),
+        };
+        let packet = bincode::serialize(&packet).expect("should serialize");
+        for peer in &outgoing.waiting {
+            self.socket.send_on(channel::PEER_MESSAGES, &packet, peer);
+        }
+    }
+
+    /// Collects and acknowledges arriving messages and sends unacknowledged ones again. `sender` returns the player
+    /// at an address.
+    pub(crate) fn poll(&mut self, sender: impl Fn(&A) -> Option<PlayerHandle>) {
+        for (addr, data) in self.socket.receive_on(channel::PEER_MESSAGES) {
+            if !self.peers.contains(&addr) {
+                continue;
+            }
+            match bincode::deserialize(&data) {
+                Ok(MessagePacket::Message { id, message }) => {
+                    let Some(sender) = sender(&addr) else {
+                        debug!("received a peer message from an address without a player");
+                        continue;
+                    };
+                    // acknowledged even if muted, so the sender stops repeating it
+                    let ack =
+                        bincode::serialize(&MessagePacket::Ack { id }).expect("should serialize");
+                    self.socket.send_on(channel::PEER_MESSAGES, &ack, &addr);
+                    if !self.seen.entry(sender).or_default().insert(id) || self.is_muted(sender) {
+                        continue;
+                    }
+                    match bincode::deserialize(&message) {
+                        Ok(message) => self.received.push(PeerMessage { sender, message }),
+                        Err(_) => debug!("received a malformed peer message"),
+                    }
+                }
+                Ok(MessagePacket::Ack { id }) => {
+                    if let Some(outgoing) = self.outgoing.iter_mut().find(|o| o.id == id) {
+                        outgoing.waiting.retain(|peer| *peer != addr);
+                    }
+                }
+                Err(_) => debug!("received a malformed peer message packet"),
+            }
+        }
+
+        self.outgoing
+            .retain(|outgoing| !outgoing.waiting.is_empty());
+        let now = Instant::now();
+        let due: Vec<usize> = (0..self.outgoing.len())
+            .filter(|i| now.duration_since(self.outgoing[*i].last_send) >= self.resend_interval)
+            .collect();
+        for i in due {
+            self.send_message(&self.outgoing[i]);
+            self.outgoing[i].last_send = now;
+        }
+    }
+
+    pub(crate) fn take_received(&mut self) -> Vec<PeerMessage<M>> {
+        std::mem::take(&mut self.received)
+    }
+}
+
+pub(crate) fn poll_peer_messages_system<M, T: Config>(
+    messages: Option<ResMut<PeerMessages<M, T::Address>>>,
+    session: Option<Res<Session<T>>>,
+    mut events: EventWriter<PeerMessage<M>>,
+) where
+    M: Serialize + DeserializeOwned + Send + Sync + 'static,
+    T::Address: Send + Sync + 'static,
+{
+    let Some(mut messages) = messages else {
+        return;
+    };
+    let session = match session.as_deref() {
+        Some(Session::P2PSession(session)) => Some(session),
+        _ => None,
+    };
+    messages.poll(|addr| {
+        let session = session?;
+        session.handles_by_address(addr.clone()).first().copied()
+    });
+    events.send_batch(messages.take_received());
+}
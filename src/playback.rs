@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+use instant::Duration;
+
+/// Controls how fast spectator and sync test sessions (used for replays) advance, for slow motion analysis or to
+/// skip through downtime. Live `P2PSession`s always run in real time, since the other peers would not wait.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct PlaybackSpeed {
+    rate: f32,
+    paused: bool,
+}
+
+impl Default for PlaybackSpeed {
+    fn default() -> Self {
+        Self {
+            rate: 1.,
+            paused: false,
+        }
+    }
+}
+
+impl PlaybackSpeed {
+    /// The slowest supported rate.
+    pub const MIN_RATE: f32 = 0.25;
+    /// The fastest supported rate.
+    pub const MAX_RATE: f32 = 4.;
+
+    /// Creates a playback speed with the given rate, clamped to `MIN_RATE..=MAX_RATE`.
+    pub fn new(rate: f32) -> Self {
+        let mut speed = Self::default();
+        speed.set_rate(rate);
+        speed
+    }
+
+    /// Changes the rate, clamped to `MIN_RATE..=MAX_RATE`. 1 is real time.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate.clamp(Self::MIN_RATE, Self::MAX_RATE);
+    }
+
+    /// Returns the current rate.
+    pub fn rate(&self) -> f32 {
+        self.rate
+    }
+
+    /// Stops advancing frames until `resume()` is called.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Continues advancing frames with the current rate.
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Returns true while paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Scales the time that passed in the real world to the time that passes in the session.
+    pub(crate) fn scale(&self, delta: Duration) -> Duration {
+        if self.paused {
+            Duration::ZERO
+        } else {
+            delta.mul_f32(self.rate)
+        }
+    }
+}
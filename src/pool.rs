@@ -0,0 +1,148 @@
+use bevy::{
+    ecs::{query::ReadOnlyWorldQuery, system::Command},
+    prelude::*,
+    utils::HashSet,
+};
+
+use crate::{prewarm::SnapshotCapacity, Rollback};
+
+/// Marks an entity of a pool spawned with `SpawnPool`. Pooled entities are never despawned, acquiring and releasing
+/// them only flips `is_active()`. The slot is part of the snapshots, so a rollback hands out the same entities again.
+///
+/// Systems that simulate pooled entities should skip the inactive ones. Entities with a `Visibility` are hidden
+/// while inactive.
+#[derive(Component, Reflect, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[reflect(Component, Hash)]
+pub struct PoolSlot {
+    index: u32,
+    active: bool,
+}
+
+impl PoolSlot {
+    /// Returns the position of this entity in its pool. Free entities are acquired in order of their index.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Returns true while the entity has been acquired and not released yet.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+/// A query over the entities of a pool. Use the filter to tell pools apart, usually with a marker component of
+/// the pooled bundle, and `RollbackPool` to acquire and release entities.
+pub type PoolQuery<'w, 's, F = ()> =
+    Query<'w, 's, (Entity, &'static Rollback, &'static mut PoolSlot), F>;
+
+/// Acquiring and releasing the entities of a `PoolQuery` deterministically, for systems of the rollback schedule.
+pub trait RollbackPool {
+    /// Activates the free entity with the lowest index and returns it, or `None` if all entities are in use.
+    /// The components of the entity are left as they were when it was released, set them up before use.
+    fn acquire(&mut self) -> Option<Entity>;
+
+    /// Deactivates the entity, so it can be acquired again. Returns false if it is not an active entity of the pool.
+    fn release(&mut self, entity: Entity) -> bool;
+
+    /// Returns the number of entities that can still be acquired.
+    fn available(&self) -> usize;
+}
+
+impl<'w, 's, F: ReadOnlyWorldQuery> RollbackPool for PoolQuery<'w, 's, F> {
+    fn acquire(&mut self) -> Option<Entity> {
+        // the query order differs between peers, the index (and the rollback id for equal indices) doesn't
+        let (entity, _, _) = self
+            .iter()
+            .filter(|(_, _, slot)| !slot.active)
+            .min_by_key(|(_, rollback, slot)| (slot.index, rollback.id))?;
+        let (_, _, mut slot) = self.get_mut(entity).expect("entity should be in the pool");
+        slot.active = true;
+        Some(entity)
+    }
+
+    fn release(&mut self, entity: Entity) -> bool {
+        match self.get_mut(entity) {
+            Ok((_, _, mut slot)) if slot.active => {
+                slot.active = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn available(&self) -> usize {
+        self.iter().filter(|(_, _, slot)| !slot.active).count()
+    }
+}
+
+/// Spawns `count` inactive entities of `bundle` for a pool, so games with many short-lived entities (bullets,
+/// particles with gameplay effects, ...) don't spawn and despawn them during the simulation. Add it at match start
+/// on every peer with `commands.add(SpawnPool::new("bullets", bundle, count))`.
+///
+/// The rollback ids are derived from `name`, so every peer gets the same ids without a `RollbackIdProvider`. Use a
+/// different name for every pool. Derived ids can collide, so spawning a pool panics if one of its ids is taken
+/// already; another name fixes that. Snapshots reserve space for the pooled entities through `SnapshotCapacity`.
+pub struct SpawnPool<B> {
+    name: String,
+    bundle: B,
+    count: u32,
+}
+
+impl<B: Bundle + Clone> SpawnPool<B> {
+    /// Spawns `count` entities of `bundle`. The bundle should not contain a `Rollback` or `PoolSlot`, they are added
+    /// for you.
+    pub fn new(name: impl Into<String>, bundle: B, count: u32) -> Self {
+        Self {
+            name: name.into(),
+            bundle,
+            count,
+        }
+    }
+}
+
+impl<B: Bundle + Clone> Command for SpawnPool<B> {
+    fn write(self, world: &mut World) {
+        let pool = Rollback::from_name(&self.name);
+        let mut taken: HashSet<u32> = world
+            .query::<&Rollback>()
+            .iter(world)
+            .map(Rollback::id)
+            .collect();
+        for index in 0..self.count {
+            let id = Rollback::derived(&pool, index).id();
+            assert!(
+                taken.insert(id),
+                "SpawnPool: the rollback id {id:#x} of entity {index} of the pool {} is taken already, pick another \
+                 name",
+                self.name
+            );
+        }
+
+        let bundle = self.bundle;
+        for index in 0..self.count {
+            world.spawn((
+                bundle.clone(),
+                Rollback::derived(&pool, index),
+                PoolSlot {
+                    index,
+                    active: false,
+                },
+            ));
+        }
+
+        world
+            .get_resource_or_insert_with(SnapshotCapacity::default)
+            .entities += self.count as usize;
+    }
+}
+
+/// Hides pooled entities while they are inactive.
+pub(crate) fn pool_visibility_system(
+    mut query: Query<(&PoolSlot, &mut Visibility), Changed<PoolSlot>>,
+) {
+    for (slot, mut visibility) in query.iter_mut() {
+        if visibility.is_visible != slot.active {
+            visibility.is_visible = slot.active;
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use bevy::{ecs::system::Command, prelude::*, utils::HashMap};
+use std::any::Any;
+
+use crate::{ggrs_stage::StageEvent, Rollback, RollbackFrame};
+
+/// A multi-entity hierarchy that systems of the rollback schedule spawn with `SpawnPrefab`. Register it with
+/// `GGRSPlugin::register_prefab()`.
+///
+/// Snapshots only restore the registered components of rollback entities, which leaves meshes, scene instances and
+/// other presentation children of a prefab behind after a rollback. Prefabs are spawned again instead: every spawn
+/// is logged with its frame and parameters, undone when a rollback goes back to before it, and executed again when
+/// a rollback brings back a prefab that has been despawned since. Afterwards, the snapshot overwrites the
+/// registered components as usual, and presentation children are attached to their parents again.
+pub trait Prefab: Send + Sync + 'static {
+    /// Identifies the prefab in the log, and is part of the rollback ids of its entities. Must be unique.
+    const NAME: &'static str;
+
+    /// What a spawn of the prefab depends on, such as a position or a team.
+    type Params: Clone + Send + Sync + 'static;
+
+    /// Spawns the entities of the prefab. The same parameters must always result in the same entities.
+    fn spawn(params: &Self::Params, spawner: &mut PrefabSpawner);
+}
+
+/// Spawns the entities of a prefab and assigns their rollback ids. The ids only depend on the prefab, the frame of
+/// the spawn and the number of prefabs spawned before in that frame, so they are the same on every peer and in every
+/// resimulation.
+pub struct PrefabSpawner<'w> {
+    world: &'w mut World,
+    root: Rollback,
+    rollback_entities: u32,
+    entities: Vec<Entity>,
+}
+
+impl<'w> PrefabSpawner<'w> {
+    /// Spawns a rollback entity. The first one is the root of the prefab, which is despawned to despawn the prefab.
+    pub fn spawn(&mut self, bundle: impl Bundle) -> Entity {
+        let rollback = match self.rollback_entities {
+            0 => self.root,
+            index => Rollback::derived(&self.root, index),
+        };
+        let entity = self.world.spawn((bundle, rollback)).id();
+        match self.rollback_entities {
+            0 => self.entities.insert(0, entity),
+            _ => self.entities.push(entity),
+        }
+        self.rollback_entities += 1;
+        entity
+    }
+
+    /// Spawns an entity that is not part of the snapshots, such as a mesh or a light. It is despawned together
+    /// with the prefab when a rollback undoes the spawn.
+    pub fn spawn_presentation(&mut self, bundle: impl Bundle) -> Entity {
+        let entity = self.world.spawn(bundle).id();
+        self.entities.push(entity);
+        entity
+    }
+
+    /// Returns the rollback id of the root entity.
+    pub fn root(&self) -> Rollback {
+        self.root
+    }
+
+    /// Gives access to the world, for example to set up parents or look up assets.
+    pub fn world(&mut self) -> &mut World {
+        self.world
+    }
+}
+
+/// Spawns a prefab from a system of the rollback schedule, with `commands.add(SpawnPrefab::<Tree>::new(params))`.
+pub struct SpawnPrefab<P: Prefab> {
+    params: P::Params,
+}
+
+impl<P: Prefab> SpawnPrefab<P> {
+    /// Spawns the prefab with the given parameters.
+    pub fn new(params: P::Params) -> Self {
+        Self { params }
+    }
+}
+
+impl<P: Prefab> Command for SpawnPrefab<P> {
+    fn write(self, world: &mut World) {
+        assert!(
+            world
+                .get_resource::<PrefabRegistry>()
+                .map_or(false, |registry| registry.spawners.contains_key(P::NAME)),
+            "prefab {} is not registered, register it with GGRSPlugin::register_prefab()",
+            P::NAME
+        );
+        let frame = world
+            .get_resource::<RollbackFrame>()
+            .map_or(0, |frame| **frame);
+        let index = world.resource::<PrefabLog>().spawned_in(frame);
+        let root = Rollback::derived(
+            &Rollback::derived(&Rollback::from_name(P::NAME), frame as u32),
+            index as u32,
+        );
+        let entities = spawn_prefab::<P>(world, root, &self.params);
+        world.resource_mut::<PrefabLog>().entries.push(PrefabEntry {
+            frame,
+            name: P::NAME,
+            params: Box::new(self.params),
+            root,
+            entities,
+            despawned: None,
+        });
+    }
+}
+
+fn spawn_prefab<P: Prefab>(world: &mut World, root: Rollback, params: &P::Params) -> Vec<Entity> {
+    let mut spawner = PrefabSpawner {
+        world,
+        root,
+        rollback_entities: 0,
+        entities: Vec::new(),
+    };
+    P::spawn(params, &mut spawner);
+    spawner.entities
+}
+
+fn respawn_prefab<P: Prefab>(
+    world: &mut World,
+    root: Rollback,
+    params: &(dyn Any + Send + Sync),
+) -> Vec<Entity> {
+    let params = params
+        .downcast_ref::<P::Params>()
+        .expect("logged parameters should match the prefab");
+    spawn_prefab::<P>(world, root, params)
+}
+
+type Respawn = fn(&mut World, Rollback, &(dyn Any + Send + Sync)) -> Vec<Entity>;
+
+/// The prefabs registered with `GGRSPlugin::register_prefab()`, by name.
+#[derive(Resource, Default)]
+pub(crate) struct PrefabRegistry {
+    spawners: HashMap<&'static str, Respawn>,
+}
+
+impl PrefabRegistry {
+    pub(crate) fn register<P: Prefab>(&mut self) {
+        let previous = self.spawners.insert(P::NAME, respawn_prefab::<P>);
+        assert!(
+            previous.is_none(),
+            "a prefab named {} is already registered",
+            P::NAME
+        );
+    }
+}
+
+struct PrefabEntry {
+    frame: i32,
+    name: &'static str,
+    params: Box<dyn Any + Send + Sync>,
+    root: Rollback,
+    /// everything spawned for the prefab, the root first
+    entities: Vec<Entity>,
+    /// the frame during which the root was despawned
+    despawned: Option<i32>,
+}
+
+/// The prefabs spawned in the frames that can still be rolled back, and the ones still alive. Entries are dropped
+/// once the prefab has been despawned in a confirmed frame.
+#[derive(Resource, Default)]
+pub struct PrefabLog {
+    entries: Vec<PrefabEntry>,
+}
+
+impl PrefabLog {
+    /// Returns the number of logged spawns.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no spawns are logged.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterates over the frame, prefab name and root entity of the logged prefabs that are alive.
+    pub fn iter(&self) -> impl Iterator<Item = (i32, &'static str, Entity)> + '_ {
+        self.entries
+            .iter()
+            .filter(|entry| entry.despawned.is_none())
+            .filter_map(|entry| Some((entry.frame, entry.name, *entry.entities.first()?)))
+    }
+
+    fn spawned_in(&self, frame: i32) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.frame == frame)
+            .count()
+    }
+
+    pub(crate) fn on_stage_event(&mut self, world: &mut World, event: StageEvent) {
+        match event {
+            StageEvent::Loaded { .. } => {
+                for entry in self.entries.iter() {
+                    repair_hierarchy(world, &entry.entities);
+                }
+            }
+            StageEvent::Advanced { frame } => {
+                for entry in self.entries.iter_mut() {
+                    let alive = entry
+                        .entities
+                        .first()
+                        .map_or(false, |root| world.get_entity(*root).is_some());
+                    if entry.despawned.is_none() && !alive {
+                        entry.despawned = Some(frame);
+                    }
+                }
+            }
+            StageEvent::Confirmed { frame } => self
+                .entries
+                .retain(|entry| entry.despawned.map_or(true, |despawned| despawned > frame)),
+            // prefabs despawned in the previous match can't come back anymore
+            StageEvent::Reset => self.entries.retain(|entry| entry.despawned.is_none()),
+            _ => {}
+        }
+    }
+}
+
+/// Prepares the prefabs for loading the snapshot of `frame`: the spawns of that frame and later are undone, the
+/// prefabs despawned since are spawned again.
+pub(crate) fn restore_prefabs(world: &mut World, frame: i32) {
+    let Some(mut log) = world.remove_resource::<PrefabLog>() else {
+        return;
+    };
+    let registry = world
+        .remove_resource::<PrefabRegistry>()
+        .unwrap_or_default();
+
+    let (undone, kept): (Vec<_>, Vec<_>) = log
+        .entries
+        .drain(..)
+        .partition(|entry| entry.frame >= frame);
+    for entry in undone {
+        despawn_all(world, &entry.entities);
+    }
+    log.entries = kept;
+
+    for entry in log.entries.iter_mut() {
+        if entry.despawned.map_or(true, |despawned| despawned < frame) {
+            continue;
+        }
+        despawn_all(world, &entry.entities);
+        let respawn = registry.spawners[entry.name];
+        entry.entities = respawn(world, entry.root, &*entry.params);
+        entry.despawned = None;
+    }
+
+    world.insert_resource(registry);
+    world.insert_resource(log);
+}
+
+fn despawn_all(world: &mut World, entities: &[Entity]) {
+    for entity in entities {
+        if world.get_entity(*entity).is_some() {
+            world.despawn(*entity);
+        }
+    }
+}
+
+/// The snapshots restore the `Children` of rollback entities as they were saved, pointing to presentation entities
+/// that may have been spawned again since. Lists the live children of every prefab entity again.
+fn repair_hierarchy(world: &mut World, entities: &[Entity]) {
+    for &entity in entities {
+        if world.get_entity(entity).is_none() {
+            continue;
+        }
+        let is_child = |world: &World, child: Entity| {
+            world.get::<Parent>(child).map(|parent| parent.get()) == Some(entity)
+        };
+        let mut children: Vec<Entity> = world
+            .get::<Children>(entity)
+            .map(|children| children.iter().copied().collect())
+            .unwrap_or_default();
+        children.retain(|child| world.get_entity(*child).is_some() && is_child(world, *child));
+        for &candidate in entities {
+            if !children.contains(&candidate) && is_child(world, candidate) {
+                children.push(candidate);
+            }
+        }
+        let unchanged = world
+            .get::<Children>(entity)
+            .map_or(children.is_empty(), |current| **current == children[..]);
+        if !unchanged {
+            let mut entity = world.entity_mut(entity);
+            entity.remove::<Children>();
+            if !children.is_empty() {
+                entity.push_children(&children);
+            }
+        }
+    }
+}
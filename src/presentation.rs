@@ -0,0 +1,231 @@
+use bevy::{prelude::*, utils::HashMap};
+use std::collections::BTreeMap;
+
+use crate::{ggrs_stage::StageEvent, Rollback};
+
+/// How a presentation entity follows its rollback owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Attachment {
+    /// The presentation entity is kept as a child of the current owner entity.
+    #[default]
+    Child,
+    /// The presentation entity is left where it is, only `PresentationOf::target()` is updated.
+    /// Use this for UI nodes or world-space elements that position themselves.
+    Target,
+}
+
+/// What to do with a presentation entity while its rollback owner does not exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrphanPolicy {
+    /// Hide the presentation entity until the owner is respawned by a rollback.
+    #[default]
+    Hide,
+    /// Leave the presentation entity untouched.
+    Keep,
+    /// Despawn the presentation entity and its children.
+    Despawn,
+}
+
+/// Add this component to non-rollback presentation entities (health bars, name tags, ...) that belong to a
+/// rollback entity. Since loading a snapshot may despawn and respawn rollback entities, the owner is tracked by
+/// its rollback id instead of its `Entity`. After every GGRS update, the presentation entity is re-attached to
+/// whatever entity currently carries that rollback id.
+#[derive(Component, Debug, Clone)]
+pub struct PresentationOf {
+    owner: u32,
+    attachment: Attachment,
+    orphans: OrphanPolicy,
+    target: Option<Entity>,
+    hidden: bool,
+}
+
+impl PresentationOf {
+    /// Keeps the presentation entity as a child of the rollback entity tagged with `owner`.
+    pub fn child_of(owner: &Rollback) -> Self {
+        Self::new(owner.id(), Attachment::Child)
+    }
+
+    /// Keeps `target()` pointing to the rollback entity tagged with `owner`, without changing the hierarchy.
+    pub fn targeting(owner: &Rollback) -> Self {
+        Self::new(owner.id(), Attachment::Target)
+    }
+
+    fn new(owner: u32, attachment: Attachment) -> Self {
+        Self {
+            owner,
+            attachment,
+            orphans: OrphanPolicy::default(),
+            target: None,
+            hidden: false,
+        }
+    }
+
+    /// Changes what happens to the presentation entity while its owner doesn't exist.
+    pub fn with_orphan_policy(mut self, orphans: OrphanPolicy) -> Self {
+        self.orphans = orphans;
+        self
+    }
+
+    /// Returns the rollback id of the owner.
+    pub const fn owner(&self) -> u32 {
+        self.owner
+    }
+
+    /// Returns the entity currently carrying the owner's rollback id, if there is one.
+    pub const fn target(&self) -> Option<Entity> {
+        self.target
+    }
+}
+
+/// Re-attaches presentation entities to their rollback owners. Runs after the GGRS stage.
+pub(crate) fn sync_presentation_system(
+    mut commands: Commands,
+    rollback_query: Query<(Entity, &Rollback)>,
+    children_query: Query<&Children>,
+    mut presentation_query: Query<(
+        Entity,
+        &mut PresentationOf,
+        Option<&Parent>,
+        Option<&mut Visibility>,
+    )>,
+) {
+    let owners: HashMap<u32, Entity> = rollback_query
+        .iter()
+        .map(|(entity, rollback)| (rollback.id(), entity))
+        .collect();
+
+    for (entity, mut presentation, parent, visibility) in presentation_query.iter_mut() {
+        match owners.get(&presentation.owner) {
+            Some(&owner) => {
+                if presentation.target != Some(owner) {
+                    presentation.target = Some(owner);
+                }
+                // a snapshot saved before the attachment restores the owner's children without us
+                let listed = children_query
+                    .get(owner)
+                    .map_or(false, |children| children.contains(&entity));
+                if presentation.attachment == Attachment::Child
+                    && (parent.map(|p| p.get()) != Some(owner) || !listed)
+                {
+                    commands.entity(owner).add_child(entity);
+                }
+                // the owner is back, undo our hiding
+                if presentation.hidden {
+                    presentation.hidden = false;
+                    if let Some(mut visibility) = visibility {
+                        visibility.is_visible = true;
+                    }
+                }
+            }
+            None => {
+                if presentation.target.is_some() {
+                    presentation.target = None;
+                }
+                match presentation.orphans {
+                    OrphanPolicy::Hide => {
+                        if let Some(mut visibility) = visibility {
+                            if visibility.is_visible {
+                                visibility.is_visible = false;
+                                presentation.hidden = true;
+                            }
+                        }
+                    }
+                    OrphanPolicy::Keep => {}
+                    OrphanPolicy::Despawn => commands.entity(entity).despawn_recursive(),
+                }
+            }
+        }
+    }
+}
+
+/// Delivered to the rest of the app for every event sent to `PresentationEvents<E>` from within the rollback
+/// schedule. Read them with an `EventReader<PresentationEvent<E>>` to trigger screen shake, flashes, sounds, ...
+#[derive(Debug, Clone, PartialEq)]
+pub enum PresentationEvent<E> {
+    /// The event happened in the simulation. Resimulating its frame does not deliver it again.
+    Triggered { frame: i32, event: E },
+    /// A previously triggered event did not happen after all, because a rollback changed the outcome of its frame.
+    Cancelled { frame: i32, event: E },
+}
+
+/// Send events from within the rollback schedule that should reach the presentation exactly once. Register the event
+/// type with `GGRSPlugin::register_presentation_event::<E>()`.
+///
+/// When a frame is resimulated after a rollback, events equal to the ones sent during the first simulation of that
+/// frame are not delivered again. Events that are not sent again during resimulation are delivered as
+/// `PresentationEvent::Cancelled`.
+#[derive(Resource)]
+pub struct PresentationEvents<E> {
+    frame: i32,
+    /// events delivered for frames that may still be rolled back
+    delivered: BTreeMap<i32, Vec<E>>,
+    /// events delivered for frames that are being resimulated, waiting to be sent again
+    unverified: BTreeMap<i32, Vec<E>>,
+    outgoing: Vec<PresentationEvent<E>>,
+}
+
+impl<E> Default for PresentationEvents<E> {
+    fn default() -> Self {
+        Self {
+            frame: 0,
+            delivered: BTreeMap::new(),
+            unverified: BTreeMap::new(),
+            outgoing: Vec::new(),
+        }
+    }
+}
+
+impl<E: PartialEq + Clone + Send + Sync + 'static> PresentationEvents<E> {
+    /// Sends an event for the frame that is currently simulated.
+    pub fn send(&mut self, event: E) {
+        let frame = self.frame;
+        if let Some(unverified) = self.unverified.get_mut(&frame) {
+            if let Some(pos) = unverified.iter().position(|e| *e == event) {
+                // this event has been delivered before the rollback already
+                unverified.remove(pos);
+                self.delivered.entry(frame).or_default().push(event);
+                return;
+            }
+        }
+        self.delivered.entry(frame).or_default().push(event.clone());
+        self.outgoing
+            .push(PresentationEvent::Triggered { frame, event });
+    }
+
+    pub(crate) fn on_stage_event(&mut self, event: StageEvent) {
+        match event {
+            StageEvent::Loaded { frame } => {
+                // everything delivered from this frame on has to happen again
+                for (frame, events) in self.delivered.split_off(&frame) {
+                    self.unverified.entry(frame).or_default().extend(events);
+                }
+            }
+            StageEvent::Advancing { frame } => self.frame = frame,
+            StageEvent::Advanced { frame } => {
+                for event in self.unverified.remove(&frame).unwrap_or_default() {
+                    self.outgoing
+                        .push(PresentationEvent::Cancelled { frame, event });
+                }
+            }
+            StageEvent::Confirmed { frame } => {
+                self.delivered = self.delivered.split_off(&(frame + 1));
+            }
+            StageEvent::Reset => {
+                self.frame = 0;
+                self.delivered.clear();
+                self.unverified.clear();
+            }
+            StageEvent::Saved { .. } => {}
+        }
+    }
+}
+
+/// Forwards the events of the current update to the rest of the app.
+pub(crate) fn deliver_presentation_events_system<E: PartialEq + Clone + Send + Sync + 'static>(
+    mut events: ResMut<PresentationEvents<E>>,
+    mut writer: EventWriter<PresentationEvent<E>>,
+) {
+    for event in events.outgoing.drain(..) {
+        writer.send(event);
+    }
+}
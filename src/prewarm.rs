@@ -0,0 +1,54 @@
+use bevy::{ecs::system::Command, prelude::*};
+
+use crate::Rollback;
+
+/// The number of rollback entities and resources snapshots reserve space for upfront. Raised by `PrewarmRollback`,
+/// you can also insert or change it yourself. Snapshots reserve at least as much space as the previous snapshot
+/// needed either way.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotCapacity {
+    pub entities: usize,
+    pub resources: usize,
+}
+
+/// Marks the entities spawned by `PrewarmRollback`, which snapshots leave out. Stored in a sparse set, so the
+/// entities are stored in the same table as the real ones.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub(crate) struct Prewarmed;
+
+/// Prepares the world for `count` rollback entities with the given bundle, so the first wave of spawns and the
+/// first deep rollback don't allocate in the middle of a match. Add it at match start with
+/// `commands.add(PrewarmRollback::new(bundle, count))`, once for each kind of entity you expect.
+///
+/// The entities are spawned and despawned right away. This creates their table and grows its storage, which bevy
+/// keeps around for later spawns. They are tagged with `Prewarmed`, so their placeholder rollback id never shows up
+/// in a snapshot, even if a real entity has the same id. Snapshots reserve space for the prewarmed entities through
+/// `SnapshotCapacity`.
+pub struct PrewarmRollback<B> {
+    bundle: B,
+    count: usize,
+}
+
+impl<B: Bundle + Clone> PrewarmRollback<B> {
+    /// Prewarms `count` entities of `bundle`. The bundle should not contain a `Rollback`, it is added for you.
+    pub fn new(bundle: B, count: usize) -> Self {
+        Self { bundle, count }
+    }
+}
+
+impl<B: Bundle + Clone> Command for PrewarmRollback<B> {
+    fn write(self, world: &mut World) {
+        let bundle = self.bundle;
+        let entities: Vec<Entity> = world
+            .spawn_batch((0..self.count).map(|_| (bundle.clone(), Rollback::new(0), Prewarmed)))
+            .collect();
+        for entity in entities {
+            world.despawn(entity);
+        }
+
+        world
+            .get_resource_or_insert_with(SnapshotCapacity::default)
+            .entities += self.count;
+    }
+}
@@ -0,0 +1,325 @@
+use bevy::prelude::*;
+use ggrs::{
+    Config, Message, NonBlockingSocket, P2PSession, PlayerType, SessionBuilder, SessionState,
+};
+use instant::{Duration, Instant};
+use parking_lot::Mutex;
+use std::{collections::VecDeque, hash::Hash, marker::PhantomData, sync::Arc};
+
+use crate::socket::{channel, MultiplexSocket};
+
+/// GGRS measures the round trip with a quality report about this often.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+/// Number of samples used for the estimate, about six seconds.
+const SAMPLE_WINDOW: usize = 32;
+/// Peers probing us are forgotten after this long without a datagram.
+const RESPONDER_TIMEOUT: Duration = Duration::from_secs(5);
+/// At most this many peers probing us are answered at once.
+const MAX_RESPONDERS: usize = 16;
+
+/// The config of the sessions a probe runs. They never advance a frame, so inputs and states are never used.
+struct ProbeConfig<A>(PhantomData<A>);
+
+impl<A> Config for ProbeConfig<A>
+where
+    A: Clone + PartialEq + Eq + Hash + Send + Sync + 'static,
+{
+    type Input = u8;
+    type State = ();
+    type Address = A;
+}
+
+/// The probe channel of a `MultiplexSocket`, as seen by the session towards one peer. Counts the datagrams in both
+/// directions, since both ends of a probe send the same traffic.
+struct ProbeSocket<A> {
+    socket: MultiplexSocket<A>,
+    peer: A,
+    /// the messages of `peer`, handed over by the probe
+    inbox: Arc<Mutex<Vec<Message>>>,
+    counts: Arc<Mutex<Counts>>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Counts {
+    sent: usize,
+    received: usize,
+}
+
+impl<A> NonBlockingSocket<A> for ProbeSocket<A>
+where
+    A: Clone + PartialEq + Eq + Hash + Send + Sync + 'static,
+{
+    fn send_to(&mut self, msg: &Message, addr: &A) {
+        let data = bincode::serialize(msg).expect("GGRS messages should always serialize");
+        self.socket.send_on(channel::PROBE, &data, addr);
+        self.counts.lock().sent += 1;
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(A, Message)> {
+        std::mem::take(&mut *self.inbox.lock())
+            .into_iter()
+            .map(|msg| (self.peer.clone(), msg))
+            .collect()
+    }
+}
+
+/// Connection quality towards a candidate peer, as measured by a `ConnectionProbe`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProbeReport {
+    /// Average round trip time in milliseconds.
+    pub ping_ms: f32,
+    /// Average deviation between consecutive round trip times in milliseconds.
+    pub jitter_ms: f32,
+    /// Fraction of datagrams that were lost, between 0 and 1.
+    pub packet_loss: f32,
+    /// Number of round trips the estimate is based on.
+    pub samples: usize,
+}
+
+impl ProbeReport {
+    /// Number of frames an input needs to reach the peer, including jitter.
+    pub fn one_way_frames(&self, fps: usize) -> u32 {
+        let frame_ms = 1000. / fps as f32;
+        ((self.ping_ms / 2. + self.jitter_ms) / frame_ms).ceil() as u32
+    }
+
+    /// Number of frames that will typically be rolled back each frame with the given input delay.
+    pub fn expected_rollback_frames(&self, fps: usize, input_delay: u32) -> u32 {
+        self.one_way_frames(fps).saturating_sub(input_delay)
+    }
+
+    /// The input delay that avoids most rollbacks towards this peer.
+    pub fn recommended_input_delay(&self, fps: usize) -> u32 {
+        self.one_way_frames(fps)
+    }
+}
+
+/// A round trip reported by GGRS, and the datagrams sent and received since the previous sample.
+struct Sample {
+    ping_ms: f32,
+    sent: usize,
+    received: usize,
+}
+
+/// The GGRS session towards one peer, either probed by us or probing us.
+struct ProbePeer<A>
+where
+    A: Clone + PartialEq + Eq + Hash + Send + Sync + 'static,
+{
+    addr: A,
+    session: P2PSession<ProbeConfig<A>>,
+    inbox: Arc<Mutex<Vec<Message>>>,
+    counts: Arc<Mutex<Counts>>,
+    /// false for peers that only probe us
+    probed: bool,
+    last_received: Instant,
+    last_sample: Option<Instant>,
+    samples: VecDeque<Sample>,
+}
+
+impl<A> ProbePeer<A>
+where
+    A: Clone + PartialEq + Eq + Hash + Send + Sync + 'static,
+{
+    fn new(socket: &MultiplexSocket<A>, addr: A, probed: bool) -> Option<Self> {
+        let inbox = Arc::new(Mutex::new(Vec::new()));
+        let counts = Arc::new(Mutex::new(Counts::default()));
+        let probe_socket = ProbeSocket {
+            socket: socket.clone(),
+            peer: addr.clone(),
+            inbox: inbox.clone(),
+            counts: counts.clone(),
+        };
+        let session = SessionBuilder::<ProbeConfig<A>>::new()
+            .with_num_players(2)
+            .add_player(PlayerType::Local, 0)
+            .and_then(|builder| builder.add_player(PlayerType::Remote(addr.clone()), 1))
+            .and_then(|builder| builder.start_p2p_session(probe_socket));
+        match session {
+            Ok(session) => Some(Self {
+                addr,
+                session,
+                inbox,
+                counts,
+                probed,
+                last_received: Instant::now(),
+                last_sample: None,
+                samples: VecDeque::new(),
+            }),
+            Err(e) => {
+                warn!("connection probe: could not start a session: {e}");
+                None
+            }
+        }
+    }
+
+    fn poll(&mut self, now: Instant) {
+        self.session.poll_remote_clients();
+        self.session.events().for_each(drop);
+        if !self.probed || self.session.current_state() != SessionState::Running {
+            return;
+        }
+        let due = self
+            .last_sample
+            .map_or(true, |last| now.duration_since(last) >= SAMPLE_INTERVAL);
+        if !due {
+            return;
+        }
+        // available once the session has been running for a second
+        let Ok(stats) = self.session.network_stats(1) else {
+            return;
+        };
+        let counts = std::mem::take(&mut *self.counts.lock());
+        self.samples.push_back(Sample {
+            ping_ms: stats.ping as f32,
+            sent: counts.sent,
+            received: counts.received,
+        });
+        if self.samples.len() > SAMPLE_WINDOW {
+            self.samples.pop_front();
+        }
+        self.last_sample = Some(now);
+    }
+
+    fn report(&self) -> Option<ProbeReport> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let samples = self.samples.len();
+        let ping_ms = self.samples.iter().map(|s| s.ping_ms).sum::<f32>() / samples as f32;
+        let jitter_ms = if samples > 1 {
+            self.samples
+                .iter()
+                .zip(self.samples.iter().skip(1))
+                .map(|(a, b)| (a.ping_ms - b.ping_ms).abs())
+                .sum::<f32>()
+                / (samples - 1) as f32
+        } else {
+            0.
+        };
+        // the first sample also counts the datagrams of the handshake
+        let (sent, received) = self
+            .samples
+            .iter()
+            .skip(1)
+            .fold((0, 0), |(sent, received), s| {
+                (sent + s.sent, received + s.received)
+            });
+        let packet_loss = if sent > 0 {
+            (1. - received as f32 / sent as f32).clamp(0., 1.)
+        } else {
+            0.
+        };
+        Some(ProbeReport {
+            ping_ms,
+            jitter_ms,
+            packet_loss,
+            samples,
+        })
+    }
+}
+
+/// Measures ping, jitter and packet loss towards candidate peers without starting a match, so matchmaking UIs
+/// can show the expected input delay and rollback amount before players commit to a match.
+///
+/// The probe runs a GGRS session towards every candidate on the probe channel of a `MultiplexSocket`, which
+/// synchronizes like the session of a match would and never advances a frame. The ping is the round trip GGRS
+/// itself measures with its quality reports, sampled five times per second. Jitter and packet loss are computed over
+/// the same window of the last 32 samples; the loss compares the datagrams sent and received, since both ends send
+/// the same traffic. The first report is available about a second after the handshake.
+///
+/// The candidate peer needs a `ConnectionProbe` resource as well to answer, even if it doesn't probe anyone itself.
+/// The probe is polled automatically when inserted as a resource.
+#[derive(Resource)]
+pub struct ConnectionProbe<A>
+where
+    A: Clone + PartialEq + Eq + Hash + Send + Sync + 'static,
+{
+    socket: MultiplexSocket<A>,
+    peers: Vec<ProbePeer<A>>,
+}
+
+impl<A> ConnectionProbe<A>
+where
+    A: Clone + PartialEq + Eq + Hash + Send + Sync + 'static,
+{
+    /// Creates a probe using the given socket.
+    pub fn new(socket: MultiplexSocket<A>) -> Self {
+        Self {
+            socket,
+            peers: Vec::new(),
+        }
+    }
+
+    /// Starts probing the peer at `addr`.
+    pub fn probe(&mut self, addr: A) {
+        match self.peers.iter_mut().find(|peer| peer.addr == addr) {
+            Some(peer) => peer.probed = true,
+            None => {
+                if let Some(peer) = ProbePeer::new(&self.socket, addr, true) {
+                    self.peers.push(peer);
+                }
+            }
+        }
+    }
+
+    /// Stops probing the peer at `addr`.
+    pub fn stop(&mut self, addr: &A) {
+        self.peers.retain(|peer| peer.addr != *addr);
+    }
+
+    /// Returns the current estimate for the peer at `addr`, once GGRS has measured a round trip.
+    pub fn report(&self, addr: &A) -> Option<ProbeReport> {
+        self.peers
+            .iter()
+            .find(|peer| peer.addr == *addr && peer.probed)
+            .and_then(|peer| peer.report())
+    }
+
+    /// Hands the received datagrams to the sessions and polls them, answering the peers probing us.
+    pub fn poll(&mut self) {
+        let now = Instant::now();
+
+        for (addr, data) in self.socket.receive_on(channel::PROBE) {
+            let Ok(msg) = bincode::deserialize::<Message>(&data) else {
+                debug!("received a malformed probe packet");
+                continue;
+            };
+            let index = match self.peers.iter().position(|peer| peer.addr == addr) {
+                Some(index) => index,
+                None => {
+                    let responders = self.peers.iter().filter(|peer| !peer.probed).count();
+                    if responders >= MAX_RESPONDERS {
+                        debug!("connection probe: too many peers probing us, ignoring another one");
+                        continue;
+                    }
+                    let Some(peer) = ProbePeer::new(&self.socket, addr, false) else {
+                        continue;
+                    };
+                    self.peers.push(peer);
+                    self.peers.len() - 1
+                }
+            };
+            let peer = &mut self.peers[index];
+            peer.inbox.lock().push(msg);
+            peer.counts.lock().received += 1;
+            peer.last_received = now;
+        }
+
+        self.peers.retain(|peer| {
+            peer.probed || now.duration_since(peer.last_received) < RESPONDER_TIMEOUT
+        });
+        for peer in self.peers.iter_mut() {
+            peer.poll(now);
+        }
+    }
+}
+
+pub(crate) fn poll_connection_probe_system<A>(probe: Option<ResMut<ConnectionProbe<A>>>)
+where
+    A: Clone + PartialEq + Eq + Hash + Send + Sync + 'static,
+{
+    if let Some(mut probe) = probe {
+        probe.poll();
+    }
+}
@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+use ggrs::{Config, PlayerHandle};
+
+/// Plays back the recorded inputs of a replay in a `SyncTestSession` with all players local, and lets one player
+/// take over at any frame ("what if I had blocked here?"). From the branch frame on, the input system provides the
+/// inputs of that player, while the others continue with their recorded inputs. Past the end of the recording,
+/// players without live input idle with zeroed inputs.
+///
+/// To branch at an earlier frame, pause with `PlaybackSpeed`, go back with `Rewind` and call `branch()`; rewinding
+/// also moves the replay back. Injected inputs still take precedence over the replay.
+#[derive(Resource)]
+pub struct ReplayBranch<T: Config> {
+    /// the inputs of all players, one entry per frame
+    inputs: Vec<Vec<T::Input>>,
+    /// the replay frame whose inputs are used next
+    frame: usize,
+    /// the player controlled live, and the frame it takes over at
+    branch: Option<(PlayerHandle, usize)>,
+}
+
+impl<T: Config> ReplayBranch<T> {
+    /// Plays back the given inputs, one entry with the inputs of all players per frame.
+    pub fn new(inputs: Vec<Vec<T::Input>>) -> Self {
+        Self {
+            inputs,
+            frame: 0,
+            branch: None,
+        }
+    }
+
+    /// Lets the input system control `handle` from the replay frame about to be simulated on.
+    pub fn branch(&mut self, handle: PlayerHandle) {
+        self.branch = Some((handle, self.frame));
+    }
+
+    /// Lets the input system control `handle` once the replay reaches `frame`.
+    pub fn branch_at(&mut self, frame: usize, handle: PlayerHandle) {
+        self.branch = Some((handle, frame));
+    }
+
+    /// Goes back to plain playback of the recorded inputs.
+    pub fn unbranch(&mut self) {
+        self.branch = None;
+    }
+
+    /// Returns the live player, once the branch frame has been reached.
+    pub fn live_player(&self) -> Option<PlayerHandle> {
+        match self.branch {
+            Some((handle, frame)) if self.frame >= frame => Some(handle),
+            _ => None,
+        }
+    }
+
+    /// Returns the frame the branch starts at.
+    pub fn branch_frame(&self) -> Option<usize> {
+        self.branch.map(|(_, frame)| frame)
+    }
+
+    /// Returns the replay frame about to be simulated.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Returns the number of recorded frames.
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Returns true if nothing has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Returns the input of `handle` for the current frame, or `None` if the player is controlled live.
+    pub(crate) fn input(&self, handle: PlayerHandle) -> Option<T::Input> {
+        if self.live_player() == Some(handle) {
+            return None;
+        }
+        let recorded = self
+            .inputs
+            .get(self.frame)
+            .and_then(|inputs| inputs.get(handle));
+        Some(recorded.copied().unwrap_or_else(bytemuck::Zeroable::zeroed))
+    }
+
+    /// Moves on to the next frame, after the inputs of all players have been taken.
+    pub(crate) fn advance(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Moves back along with a rewind of the world.
+    pub(crate) fn rewind(&mut self, frames: usize) {
+        self.frame = self.frame.saturating_sub(frames);
+    }
+}
@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+use ggrs::InputStatus;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+const HEADER: &str = "# bevy_ggrs request trace v1";
+
+/// Writes every request the GGRS stage executes to a file, one line per request:
+/// - `S <frame> <checksum>` for saving the state at the start of a frame,
+/// - `L <frame>` for loading the state at the start of a frame,
+/// - `A <frame> <input>:<status> ...` for advancing a frame, with the input bytes of each player in hex and the
+///   input status as `C` (confirmed), `P` (predicted) or `D` (disconnected).
+///
+/// Inserted as a resource by `GGRSPlugin::with_request_trace()`. When the app exits, it is flushed and removed,
+/// which closes the file.
+#[derive(Resource)]
+pub(crate) struct RequestTrace {
+    writer: BufWriter<File>,
+}
+
+impl RequestTrace {
+    pub(crate) fn create(path: &Path) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{HEADER}")?;
+        Ok(Self { writer })
+    }
+
+    pub(crate) fn save(&mut self, frame: i32, checksum: u64) {
+        self.write(format_args!("S {frame} {checksum:016x}"));
+    }
+
+    pub(crate) fn load(&mut self, frame: i32) {
+        self.write(format_args!("L {frame}"));
+    }
+
+    pub(crate) fn advance<I: bytemuck::Pod>(&mut self, frame: i32, inputs: &[(I, InputStatus)]) {
+        let inputs = format_inputs(inputs);
+        if inputs.is_empty() {
+            self.write(format_args!("A {frame}"))
+        } else {
+            self.write(format_args!("A {frame} {inputs}"))
+        }
+    }
+
+    /// Returns the memory held by the buffer of lines not written to the file yet, in bytes.
+    pub(crate) fn memory_size(&self) -> usize {
+        self.writer.capacity()
+    }
+
+    /// Makes sure everything written so far ends up in the file, even if the app crashes later.
+    pub(crate) fn flush(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            warn!("failed to write the request trace: {e}");
+        }
+    }
+
+    fn write(&mut self, line: std::fmt::Arguments) {
+        if let Err(e) = writeln!(self.writer, "{line}") {
+            warn!("failed to write the request trace: {e}");
+        }
+    }
+}
+
+/// Formats the input bytes of each player in hex, followed by the input status.
+pub(crate) fn format_inputs<I: bytemuck::Pod>(inputs: &[(I, InputStatus)]) -> String {
+    let mut line = String::new();
+    for (input, status) in inputs {
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        for byte in bytemuck::bytes_of(input) {
+            line.push_str(&format!("{byte:02x}"));
+        }
+        line.push(':');
+        line.push(match status {
+            InputStatus::Confirmed => 'C',
+            InputStatus::Predicted => 'P',
+            InputStatus::Disconnected => 'D',
+        });
+    }
+    line
+}
@@ -0,0 +1,222 @@
+use bevy::prelude::*;
+use instant::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    socket::{channel, MultiplexSocket},
+    state_transfer::{StateTransfer, StateTransferEvent},
+};
+
+const DEFAULT_RESEND_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Serialize, Deserialize)]
+enum ResyncPacket {
+    /// Asks the authority for its confirmed state.
+    Request,
+}
+
+enum Role<A> {
+    Authority {
+        peers: Vec<A>,
+        /// peers waiting for our confirmed state
+        requesters: Vec<A>,
+    },
+    Follower {
+        authority: A,
+        /// when we last asked for the state, if we are waiting for it
+        requested: Option<Option<Instant>>,
+        /// a complete state that has not been adopted yet
+        received: Option<(i32, Vec<u8>)>,
+    },
+}
+
+/// Opt-in recovery from desyncs in a `P2PSession`. Instead of aborting the match, one peer acts as the authority:
+/// on request, it serializes the world state at the start of its earliest unconfirmed frame and sends it to the
+/// other peers. Followers load that state as soon as they have confirmed the same frame themselves, then
+/// resimulate up to their current frame with the inputs they already have.
+///
+/// Insert it as a resource on every peer, using a clone of the `MultiplexSocket` the session runs on. Call
+/// `request_resync()` when GGRS reports a desync (`GGRSEvent::DesyncDetected`). All rollback components and
+/// resources need to be serializable through reflection.
+///
+/// The state is sent like with a `StateTransfer`, so states of several megabytes resume after packet loss and are
+/// checked for corruption. Its progress is reported as `StateTransferEvent`s.
+#[derive(Resource)]
+pub struct DesyncRecovery<A> {
+    socket: MultiplexSocket<A>,
+    transfer: StateTransfer<A>,
+    role: Role<A>,
+    resend_interval: Duration,
+}
+
+impl<A: Clone + PartialEq + Send + Sync + 'static> DesyncRecovery<A> {
+    /// Creates the recovery for the authority, whose state the peers at `peers` adopt.
+    pub fn authority(socket: MultiplexSocket<A>, peers: Vec<A>) -> Self {
+        Self {
+            transfer: StateTransfer::on_channel(socket.clone(), channel::RESYNC_STATE),
+            socket,
+            role: Role::Authority {
+                peers,
+                requesters: Vec::new(),
+            },
+            resend_interval: DEFAULT_RESEND_INTERVAL,
+        }
+    }
+
+    /// Creates the recovery for a peer that adopts the state of the authority at `authority`.
+    pub fn follower(socket: MultiplexSocket<A>, authority: A) -> Self {
+        Self {
+            transfer: StateTransfer::on_channel(socket.clone(), channel::RESYNC_STATE),
+            socket,
+            role: Role::Follower {
+                authority,
+                requested: None,
+                received: None,
+            },
+            resend_interval: DEFAULT_RESEND_INTERVAL,
+        }
+    }
+
+    /// Changes how often a follower repeats its request until the state has started arriving.
+    pub fn with_resend_interval(mut self, interval: Duration) -> Self {
+        self.resend_interval = interval;
+        self
+    }
+
+    /// Changes how many chunks of the state may be on their way to a follower at once. Defaults to 32.
+    pub fn with_transfer_window(mut self, chunks: usize) -> Self {
+        self.transfer = self.transfer.with_window(chunks);
+        self
+    }
+
+    /// Starts a resync. On a follower, the state of the authority is requested. On the authority, the state is
+    /// sent to all peers.
+    pub fn request_resync(&mut self) {
+        match &mut self.role {
+            Role::Authority { peers, requesters } => *requesters = peers.clone(),
+            Role::Follower { requested, .. } => {
+                if requested.is_none() {
+                    *requested = Some(None);
+                }
+            }
+        }
+    }
+
+    /// Returns true while a follower is waiting for the state of the authority.
+    pub fn is_resyncing(&self) -> bool {
+        match &self.role {
+            Role::Authority { .. } => false,
+            Role::Follower { requested, .. } => requested.is_some(),
+        }
+    }
+
+    /// Answers and sends requests, and collects arriving state.
+    pub(crate) fn poll(&mut self) {
+        let now = Instant::now();
+        self.transfer.poll();
+
+        for (addr, data) in self.socket.receive_on(channel::RESYNC) {
+            let Ok(packet) = bincode::deserialize(&data) else {
+                debug!("received a malformed resync packet");
+                continue;
+            };
+            match (&mut self.role, packet) {
+                (Role::Authority { peers, requesters }, ResyncPacket::Request) => {
+                    // an unfinished transfer resumes on its own
+                    if peers.contains(&addr)
+                        && !requesters.contains(&addr)
+                        && !self.transfer.is_sending(&addr)
+                    {
+                        requesters.push(addr);
+                    }
+                }
+                _ => debug!("ignoring an unexpected resync packet"),
+            }
+        }
+
+        if let Role::Follower {
+            authority,
+            received,
+            ..
+        } = &mut self.role
+        {
+            for state in self.transfer.take_received() {
+                // a newer state replaces one that has not been adopted yet
+                if state.peer == *authority
+                    && received
+                        .as_ref()
+                        .map_or(true, |(frame, _)| state.frame > *frame)
+                {
+                    *received = Some((state.frame, state.data));
+                }
+            }
+        }
+
+        if let Role::Follower {
+            authority,
+            requested: Some(last_request),
+            ..
+        } = &mut self.role
+        {
+            let due = !self.transfer.is_receiving(authority)
+                && last_request.map_or(true, |last| {
+                    now.duration_since(last) >= self.resend_interval
+                });
+            if due {
+                let request = bincode::serialize(&ResyncPacket::Request).expect("should serialize");
+                self.socket.send_on(channel::RESYNC, &request, authority);
+                *last_request = Some(now);
+            }
+        }
+    }
+
+    /// Returns the peers the authority should send its state to.
+    pub(crate) fn take_requesters(&mut self) -> Vec<A> {
+        match &mut self.role {
+            Role::Authority { requesters, .. } => std::mem::take(requesters),
+            Role::Follower { .. } => Vec::new(),
+        }
+    }
+
+    /// Sends the serialized state at the start of `frame` to the given peers.
+    pub(crate) fn send_state(&mut self, addrs: &[A], frame: i32, state: &[u8]) {
+        for addr in addrs {
+            self.transfer.send(addr.clone(), frame, state);
+        }
+    }
+
+    /// Returns the received state if it belongs to a frame up to `max_frame`. Later states stay around until the
+    /// caller has caught up.
+    pub(crate) fn take_state(&mut self, max_frame: i32) -> Option<(i32, Vec<u8>)> {
+        let Role::Follower { received, .. } = &mut self.role else {
+            return None;
+        };
+        match received {
+            Some((frame, _)) if *frame <= max_frame => received.take(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn take_transfer_events(&mut self) -> Vec<StateTransferEvent<A>> {
+        self.transfer.take_events()
+    }
+
+    /// Returns the memory held by the states being sent and received, in bytes.
+    pub(crate) fn memory_size(&self) -> usize {
+        let received = match &self.role {
+            Role::Follower {
+                received: Some((_, state)),
+                ..
+            } => state.capacity(),
+            _ => 0,
+        };
+        self.transfer.memory_size() + received
+    }
+
+    /// Marks the resync as done after the state has been adopted.
+    pub(crate) fn finish(&mut self) {
+        if let Role::Follower { requested, .. } = &mut self.role {
+            *requested = None;
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use bevy::prelude::*;
+
+/// Rewinds the game by a number of frames, for practice and training modes. Inserted by `GGRSPlugin` when a history
+/// length is set with `GGRSPlugin::with_rewind_history()`; the GGRS stage then keeps a snapshot of every frame in
+/// that window and restores the requested one before the next frame is simulated.
+///
+/// Rewinding only works in a `SyncTestSession` with a check distance of 0, which is how single-player sessions are
+/// usually run. With a check distance, the sync test would resimulate frames from before the rewind.
+#[derive(Resource, Debug)]
+pub struct Rewind {
+    requested: Option<usize>,
+    available: usize,
+    capacity: usize,
+    fps: usize,
+}
+
+impl Rewind {
+    pub(crate) fn new(capacity: usize, fps: usize) -> Self {
+        Self {
+            requested: None,
+            available: 0,
+            capacity,
+            fps,
+        }
+    }
+
+    /// Goes back the given number of frames, or as far as the history reaches. Requests replace each other, so
+    /// only the last request before the next update takes effect.
+    pub fn rewind_frames(&mut self, frames: usize) {
+        self.requested = Some(frames);
+    }
+
+    /// Goes back the given number of seconds, rounded to whole frames.
+    pub fn rewind_seconds(&mut self, seconds: f32) {
+        self.rewind_frames((seconds.max(0.) * self.fps as f32).round() as usize);
+    }
+
+    /// Returns true if a rewind has been requested, but not executed yet.
+    pub fn is_pending(&self) -> bool {
+        self.requested.is_some()
+    }
+
+    /// Number of frames the game can currently be rewound by.
+    pub fn available_frames(&self) -> usize {
+        self.available
+    }
+
+    /// Number of seconds the game can currently be rewound by.
+    pub fn available_seconds(&self) -> f32 {
+        self.available as f32 / self.fps as f32
+    }
+
+    /// Maximum number of frames kept in the history.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn take_request(&mut self) -> Option<usize> {
+        self.requested.take()
+    }
+
+    pub(crate) fn set_available(&mut self, frames: usize) {
+        self.available = frames;
+    }
+}
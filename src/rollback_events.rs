@@ -0,0 +1,57 @@
+use bevy::{prelude::*, reflect::FromReflect};
+
+use crate::ggrs_stage::StageEvent;
+
+/// Events between systems of the rollback schedule, saved and restored with the snapshots. Unlike bevy's `Events`,
+/// nothing about the delivery is kept outside of the world state, so rollbacks and resimulated frames deliver the
+/// same events in the same order. Register the event type with `GGRSPlugin::register_rollback_event::<E>()`.
+///
+/// Events are delivered in the order they were sent. For that order to be the same on every peer, the systems
+/// sending them must run in a fixed order, for example in a single threaded stage.
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct RollbackEvents<E: FromReflect> {
+    /// events sent during the previous frame
+    previous: Vec<E>,
+    /// events sent during the current frame so far
+    current: Vec<E>,
+}
+
+impl<E: FromReflect> Default for RollbackEvents<E> {
+    fn default() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+        }
+    }
+}
+
+impl<E: FromReflect> RollbackEvents<E> {
+    /// Sends an event during the current frame.
+    pub fn send(&mut self, event: E) {
+        self.current.push(event);
+    }
+
+    /// Returns the events sent during the previous frame. A system reading them every frame sees every event exactly
+    /// once, no matter where it runs in the schedule.
+    pub fn read(&self) -> impl Iterator<Item = &E> + '_ {
+        self.previous.iter()
+    }
+
+    /// Returns the events sent during the current frame so far, for systems that run after the senders.
+    pub fn read_current(&self) -> impl Iterator<Item = &E> + '_ {
+        self.current.iter()
+    }
+
+    pub(crate) fn on_stage_event(&mut self, event: StageEvent) {
+        match event {
+            // the events of the frame before are gone, the ones of the last frame become readable
+            StageEvent::Advancing { .. } => self.previous = std::mem::take(&mut self.current),
+            StageEvent::Reset => {
+                self.previous.clear();
+                self.current.clear();
+            }
+            _ => {}
+        }
+    }
+}
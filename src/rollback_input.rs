@@ -0,0 +1,36 @@
+use bytemuck::Pod;
+use std::{hash::Hash, marker::PhantomData};
+
+/// The requirements on the input type of a GGRS config, checked when the input system is added:
+/// - `Pod`: fixed size, no padding and no pointers, so the bytes sent to other peers mean the same on every machine,
+/// - `Eq` and `Hash`: inputs compare exactly, which rules out floats. Quantize analog values or pack them with
+///   `pack_input()` instead.
+///
+/// Implemented for every type with these properties, derive them for your input struct:
+///
+/// ```
+/// use bevy_ggrs::RollbackInput;
+/// use bytemuck::{Pod, Zeroable};
+///
+/// #[repr(C)]
+/// #[derive(Copy, Clone, PartialEq, Eq, Hash, Pod, Zeroable)]
+/// struct BoxInput {
+///     buttons: u8,
+/// }
+///
+/// fn assert_input<I: RollbackInput>() {}
+/// assert_input::<BoxInput>();
+/// ```
+pub trait RollbackInput: Pod + Eq + Hash + Send + Sync + 'static {}
+
+impl<I: Pod + Eq + Hash + Send + Sync + 'static> RollbackInput for I {}
+
+/// Properties of the input type trait bounds can't express. Using `VALID` fails to compile if one doesn't hold.
+pub(crate) struct InputAssertions<I>(PhantomData<I>);
+
+impl<I> InputAssertions<I> {
+    pub(crate) const VALID: () = assert!(
+        std::mem::size_of::<I>() > 0,
+        "the input type of the GGRS config is zero-sized, so no input would ever reach the other peers"
+    );
+}
@@ -0,0 +1,157 @@
+use bevy::{
+    prelude::*,
+    reflect::{TypeRegistration, TypeRegistry, TypeRegistryInternal},
+};
+use parking_lot::RwLock;
+use std::{
+    any::TypeId,
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+/// Temporarily leaves registered rollback types out of the snapshots and checksums, to find the type responsible
+/// for a desync or a slow snapshot without recompiling. Insert it as a resource and exclude types by name, for
+/// example from a developer console. Excluded types behave as if they weren't registered: they are neither saved
+/// nor restored, so their values carry over rollbacks.
+///
+/// Names are resolved to the registered rollback types, so excluding `Velocity` by name and including
+/// `my_game::Velocity` afterwards refer to the same type. Names excluded before the GGRS stage first saw the
+/// resource are resolved with the next update, until then `is_excluded()` doesn't know about them.
+///
+/// Changes take effect with the next update. Rolling back to a snapshot saved before a type was included again
+/// leaves that type untouched. Frames saved before the change report the checksums they were first saved with when
+/// they are saved again, so a `SyncTestSession` doesn't mistake the change for a desync. Every peer of a
+/// `P2PSession` has to exclude the same types, otherwise the checksums differ.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RollbackToggles {
+    /// the full and short names of the registered rollback types, once known
+    registered: BTreeMap<TypeId, (&'static str, String)>,
+    /// the excluded types and their full names
+    excluded: BTreeMap<TypeId, &'static str>,
+    /// names excluded before the registered types were known
+    unresolved: BTreeSet<String>,
+}
+
+impl RollbackToggles {
+    /// Excludes the registered type `T`.
+    pub fn exclude<T: 'static>(&mut self) {
+        let id = TypeId::of::<T>();
+        let name = std::any::type_name::<T>();
+        if !self.registered.is_empty() && !self.registered.contains_key(&id) {
+            warn!("rollback toggles: {name} is not a registered rollback type");
+        }
+        self.excluded.insert(id, name);
+    }
+
+    /// Excludes a registered type by its full or short type name, such as `my_game::Velocity` or `Velocity`.
+    pub fn exclude_by_name(&mut self, type_name: impl Into<String>) {
+        let type_name = type_name.into();
+        if self.registered.is_empty() {
+            self.unresolved.insert(type_name);
+            return;
+        }
+        match self.resolve(&type_name) {
+            Some((id, name)) => {
+                self.excluded.insert(id, name);
+            }
+            None => warn!("rollback toggles: {type_name} is not a registered rollback type"),
+        }
+    }
+
+    /// Includes the type `T` again, no matter how it was excluded.
+    pub fn include<T: 'static>(&mut self) {
+        self.excluded.remove(&TypeId::of::<T>());
+    }
+
+    /// Includes a type excluded by name or type again, by its full or short type name.
+    pub fn include_by_name(&mut self, type_name: &str) {
+        self.unresolved.remove(type_name);
+        if let Some((id, _)) = self.resolve(type_name) {
+            self.excluded.remove(&id);
+        }
+    }
+
+    /// Includes all types again.
+    pub fn include_all(&mut self) {
+        self.excluded.clear();
+        self.unresolved.clear();
+    }
+
+    /// Returns true if the type `T` is excluded.
+    pub fn is_excluded<T: 'static>(&self) -> bool {
+        self.excluded.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Iterates over the full names of the excluded types, followed by the names that aren't resolved yet.
+    pub fn excluded(&self) -> impl Iterator<Item = &str> {
+        self.excluded
+            .values()
+            .copied()
+            .chain(self.unresolved.iter().map(String::as_str))
+    }
+
+    fn resolve(&self, type_name: &str) -> Option<(TypeId, &'static str)> {
+        self.registered
+            .iter()
+            .find(|(_, (name, short_name))| *name == type_name || short_name == type_name)
+            .map(|(id, (name, _))| (*id, *name))
+    }
+
+    /// Returns true until the registered rollback types are known.
+    pub(crate) fn needs_registered(&self) -> bool {
+        self.registered.is_empty()
+    }
+
+    /// Remembers the registered rollback types and resolves the names excluded before. Warns about names and types
+    /// that match no registered type.
+    pub(crate) fn set_registered(&mut self, registered: &TypeRegistry) {
+        self.registered = registered
+            .read()
+            .iter()
+            .map(|registration| {
+                let names = (
+                    registration.type_name(),
+                    registration.short_name().to_string(),
+                );
+                (registration.type_id(), names)
+            })
+            .collect();
+        for (id, name) in self.excluded.iter() {
+            if !self.registered.contains_key(id) {
+                warn!("rollback toggles: {name} is not a registered rollback type");
+            }
+        }
+        for type_name in std::mem::take(&mut self.unresolved) {
+            self.exclude_by_name(type_name);
+        }
+    }
+
+    /// Returns the registered types that aren't excluded.
+    pub(crate) fn apply(&self, registered: &TypeRegistry) -> TypeRegistry {
+        filter(registered, |registration| {
+            !self.excluded.contains_key(&registration.type_id())
+        })
+    }
+}
+
+/// Returns the types of `registry` that `other` contains as well.
+pub(crate) fn intersect(registry: &TypeRegistry, other: &TypeRegistry) -> TypeRegistry {
+    let other = other.read();
+    filter(registry, |registration| {
+        other.get(registration.type_id()).is_some()
+    })
+}
+
+fn filter(registry: &TypeRegistry, keep: impl Fn(&TypeRegistration) -> bool) -> TypeRegistry {
+    let mut filtered = TypeRegistryInternal::empty();
+    for registration in registry
+        .read()
+        .iter()
+        .filter(|registration| keep(registration))
+    {
+        filtered.add_registration(registration.clone());
+    }
+    TypeRegistry {
+        internal: Arc::new(RwLock::new(filtered)),
+    }
+}
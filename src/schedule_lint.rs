@@ -0,0 +1,280 @@
+use bevy::{
+    ecs::{
+        archetype::ArchetypeComponentId,
+        component::{ComponentId, ComponentInfo},
+        query::Access,
+        schedule::{StageLabelId, SystemContainer},
+    },
+    prelude::*,
+    reflect::TypeRegistry,
+    utils::{HashMap, HashSet},
+};
+use std::collections::BTreeSet;
+
+use crate::Rollback;
+
+/// Resources that differ between peers or between runs, with advice on what to use instead.
+const NONDETERMINISTIC_RESOURCES: &[(&str, &str)] = &[
+    (
+        "bevy_time::time::Time",
+        "it follows the wall clock, rely on the fixed update frequency instead",
+    ),
+    (
+        "bevy_input::input::Input<",
+        "it holds the state of local devices, read `PlayerInputs` instead",
+    ),
+    (
+        "bevy_input::axis::Axis<",
+        "it holds the state of local devices, read `PlayerInputs` instead",
+    ),
+    (
+        "bevy_input::touch::Touches",
+        "it holds the state of local devices, read `PlayerInputs` instead",
+    ),
+];
+
+/// Crates whose types are random number generators, or wrap one.
+const RNG_CRATES: &[&str] = &[
+    "rand::",
+    "rand_chacha::",
+    "rand_core::",
+    "rand_pcg::",
+    "rand_xorshift::",
+    "rand_xoshiro::",
+    "bevy_rand::",
+    "bevy_turborand::",
+    "turborand::",
+    "fastrand::",
+    "oorandom::",
+];
+
+/// The findings of the schedule lint enabled with `GGRSPlugin::with_schedule_lint()`. Inserted as a resource once
+/// the rollback schedule first ran.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ScheduleLint {
+    pub(crate) findings: Vec<String>,
+}
+
+impl ScheduleLint {
+    /// Iterates over the descriptions of everything the lint found, as logged.
+    pub fn findings(&self) -> impl Iterator<Item = &str> {
+        self.findings.iter().map(String::as_str)
+    }
+}
+
+/// Inspects the rollback schedule for common sources of nondeterminism and returns a description of each finding.
+/// The schedule must have run at least once, so its systems are initialized and ordered.
+pub(crate) fn lint_schedule(
+    schedule: &Schedule,
+    world: &World,
+    type_registry: &TypeRegistry,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let labels: Vec<StageLabelId> = schedule
+        .iter_stages()
+        .map(|(label, _)| label.as_label())
+        .collect();
+
+    for label in labels {
+        let Some(stage) = schedule.get_stage::<SystemStage>(label) else {
+            continue;
+        };
+        let systems = stage.parallel_systems();
+
+        for system in systems {
+            if !system.system().is_send() {
+                warnings.push(format!(
+                    "system {} in stage {:?} accesses non-send data, it always runs on the main thread",
+                    system.name(),
+                    label
+                ));
+            }
+            let written = non_rollback_writes(system.system().archetype_component_access(), world);
+            if !written.is_empty() {
+                warnings.push(format!(
+                    "system {} in stage {:?} modifies [{}] of entities without a Rollback component. Changes to them will not be undone by rollbacks, use `RollbackQuery` to only access rollback entities",
+                    system.name(),
+                    label,
+                    written.into_iter().collect::<Vec<_>>().join(", ")
+                ));
+            }
+            for info in world.components().iter() {
+                if let Some(warning) = lint_access(
+                    info,
+                    system.system().component_access(),
+                    world,
+                    type_registry,
+                ) {
+                    warnings.push(format!(
+                        "system {} in stage {:?} {warning}",
+                        system.name(),
+                        label
+                    ));
+                }
+            }
+        }
+
+        // systems that conflict and are not ordered may run in different orders on different machines. Conflicts
+        // are looked up per archetype, so queries kept apart by `With` and `Without` filters don't conflict.
+        let names = archetype_component_names(world);
+        let ordered_before: Vec<HashSet<usize>> =
+            (0..systems.len()).map(|i| ancestors(systems, i)).collect();
+        for (i, a) in systems.iter().enumerate() {
+            for (j, b) in systems.iter().enumerate().skip(i + 1) {
+                if ordered_before[i].contains(&j) || ordered_before[j].contains(&i) {
+                    continue;
+                }
+                let (a_access, b_access) = (
+                    a.system().archetype_component_access(),
+                    b.system().archetype_component_access(),
+                );
+                if a_access.is_compatible(b_access) {
+                    continue;
+                }
+                let conflicts: BTreeSet<_> = a_access
+                    .get_conflicts(b_access)
+                    .iter()
+                    .filter_map(|id| names.get(id).copied())
+                    // our own resources are not part of the simulation
+                    .filter(|name| !name.starts_with("bevy_ggrs::"))
+                    .collect();
+                if conflicts.is_empty() {
+                    continue;
+                }
+                warnings.push(format!(
+                    "systems {} and {} in stage {:?} have no defined order, but access [{}] in conflicting ways",
+                    a.name(),
+                    b.name(),
+                    label,
+                    conflicts.into_iter().collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+    }
+
+    warnings
+}
+
+/// Checks how a system accesses a single component or resource type.
+fn lint_access(
+    info: &ComponentInfo,
+    access: &Access<ComponentId>,
+    world: &World,
+    type_registry: &TypeRegistry,
+) -> Option<String> {
+    let id = info.id();
+    if !access.has_read(id) && !access.has_write(id) {
+        return None;
+    }
+    let name = info.name();
+    let verb = if access.has_write(id) {
+        "modifies"
+    } else {
+        "reads"
+    };
+
+    if let Some((_, advice)) = NONDETERMINISTIC_RESOURCES
+        .iter()
+        .find(|(resource, _)| name.starts_with(resource))
+    {
+        return Some(format!(
+            "{verb} {name}, which is not deterministic: {advice}"
+        ));
+    }
+
+    let is_resource = world
+        .storages()
+        .resources
+        .iter()
+        .any(|(resource_id, _)| resource_id == id);
+    let registered = info
+        .type_id()
+        .and_then(|type_id| {
+            type_registry.read().get(type_id).map(|registration| {
+                registration.data::<ReflectResource>().is_some()
+                    || registration.data::<ReflectComponent>().is_some()
+            })
+        })
+        .unwrap_or(false);
+
+    if !registered && is_rng(name) {
+        return Some(format!(
+            "{verb} the random number generator {name}, which is not registered for rollback. Its state has to be rolled back for rollbacks to be deterministic"
+        ));
+    }
+    // our own resources are meant to live outside of the snapshots
+    if is_resource && !registered && access.has_write(id) && !name.starts_with("bevy_ggrs::") {
+        return Some(format!(
+            "modifies the resource {name}, which is not registered for rollback. Changes to it will not be undone by rollbacks"
+        ));
+    }
+    None
+}
+
+/// Returns true if `name` is the type name of a random number generator, or of a type with one as a generic
+/// argument, judging by the crate it is defined in.
+fn is_rng(name: &str) -> bool {
+    name.split(|c: char| c == '<' || c == '>' || c == ',' || c == ' ')
+        .any(|path| RNG_CRATES.iter().any(|prefix| path.starts_with(prefix)))
+}
+
+/// Returns the names of the components and resources behind the archetype component ids.
+fn archetype_component_names(world: &World) -> HashMap<ArchetypeComponentId, &str> {
+    let mut names = HashMap::default();
+    for archetype in world.archetypes().iter() {
+        for component in archetype.components() {
+            let (Some(id), Some(info)) = (
+                archetype.get_archetype_component_id(component),
+                world.components().get_info(component),
+            ) else {
+                continue;
+            };
+            names.insert(id, info.name());
+        }
+    }
+    for (component, resource) in world.storages().resources.iter() {
+        if let Some(info) = world.components().get_info(component) {
+            names.insert(resource.id(), info.name());
+        }
+    }
+    names
+}
+
+/// Returns the components a system modified on entities that are not rolled back.
+fn non_rollback_writes<'w>(
+    access: &Access<ArchetypeComponentId>,
+    world: &'w World,
+) -> BTreeSet<&'w str> {
+    let mut written = BTreeSet::new();
+    let Some(rollback) = world.components().component_id::<Rollback>() else {
+        return written;
+    };
+    for archetype in world.archetypes().iter() {
+        if archetype.is_empty() || archetype.contains(rollback) {
+            continue;
+        }
+        for component in archetype.components() {
+            let Some(id) = archetype.get_archetype_component_id(component) else {
+                continue;
+            };
+            if access.has_write(id) {
+                if let Some(info) = world.components().get_info(component) {
+                    written.insert(info.name());
+                }
+            }
+        }
+    }
+    written
+}
+
+/// Returns all systems that run before the given one, following the dependencies of the stage.
+fn ancestors<S: SystemContainer>(systems: &[S], index: usize) -> HashSet<usize> {
+    let mut found = HashSet::default();
+    let mut open: Vec<usize> = systems[index].dependencies().to_vec();
+    while let Some(dependency) = open.pop() {
+        if found.insert(dependency) {
+            open.extend_from_slice(systems[dependency].dependencies());
+        }
+    }
+    found
+}
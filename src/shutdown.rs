@@ -0,0 +1,103 @@
+use bevy::{app::AppExit, prelude::*};
+use ggrs::Config;
+
+use crate::{
+    late_join::LateJoin,
+    request_trace::RequestTrace,
+    socket::{channel, MultiplexSocket},
+    Session,
+};
+
+/// The goodbye is sent a few times, since datagrams may get lost.
+const GOODBYE_REPEATS: usize = 3;
+const GOODBYE: &[u8] = b"bye";
+
+/// Sent when a peer of the `P2PSession` announced that it left, before GGRS would notice the missing packets.
+/// The players of that peer have been disconnected from the session already.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerLeft<A> {
+    pub addr: A,
+}
+
+/// When the app exits, tells all peers that we leave, sends the packets GGRS still has queued and drops the session
+/// together with its socket. Joiners still waiting for our inputs are refused, and the request trace is flushed and
+/// closed.
+pub(crate) fn shutdown_on_exit_system<T: Config>(
+    mut commands: Commands,
+    mut exit: EventReader<AppExit>,
+    session: Option<ResMut<Session<T>>>,
+    socket: Option<Res<MultiplexSocket<T::Address>>>,
+    late_join: Option<ResMut<LateJoin<T::Address>>>,
+    trace: Option<ResMut<RequestTrace>>,
+) where
+    T::Address: Send + Sync + 'static,
+{
+    if exit.iter().next().is_none() {
+        return;
+    }
+
+    // they would keep asking a host that is gone
+    if let Some(mut late_join) = late_join {
+        late_join.refuse_waiting();
+    }
+    if let Some(mut session) = session {
+        if let Some(socket) = &socket {
+            for peer in socket.peers() {
+                for _ in 0..GOODBYE_REPEATS {
+                    socket.send_on(channel::GOODBYE, GOODBYE, &peer);
+                }
+            }
+        }
+        // polling sends everything that is still queued
+        match &mut *session {
+            Session::P2PSession(session) => session.poll_remote_clients(),
+            Session::SpectatorSession(session) => session.poll_remote_clients(),
+            Session::SyncTestSession(_) => {}
+        }
+        commands.remove_resource::<Session<T>>();
+        info!("left the GGRS session");
+    }
+    if socket.is_some() {
+        commands.remove_resource::<MultiplexSocket<T::Address>>();
+    }
+    if let Some(mut trace) = trace {
+        trace.flush();
+        commands.remove_resource::<RequestTrace>();
+    }
+}
+
+/// Disconnects the players of peers that said goodbye. Goodbyes from addresses without players in the current
+/// session are ignored.
+pub(crate) fn receive_goodbye_system<T: Config>(
+    mut session: Option<ResMut<Session<T>>>,
+    socket: Option<Res<MultiplexSocket<T::Address>>>,
+    mut peer_left: EventWriter<PeerLeft<T::Address>>,
+) where
+    T::Address: Send + Sync + 'static,
+{
+    let Some(socket) = socket else {
+        return;
+    };
+    let goodbyes = socket.receive_on(channel::GOODBYE);
+    let Some(Session::P2PSession(session)) = session.as_deref_mut() else {
+        return;
+    };
+    for (addr, data) in goodbyes {
+        if data != GOODBYE {
+            continue;
+        }
+        // the goodbye arrives multiple times, only the first one disconnects the players. Players of the same address
+        // in a later session are connected again, so they can leave that one as well.
+        let mut disconnected = false;
+        for handle in session.handles_by_address(addr.clone()) {
+            match session.disconnect_player(handle) {
+                Ok(()) => disconnected = true,
+                Err(e) => debug!("could not disconnect player {handle}: {e}"),
+            }
+        }
+        if disconnected {
+            info!("a peer left the GGRS session");
+            peer_left.send(PeerLeft { addr });
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use bevy::{prelude::*, utils::HashMap};
+use instant::Duration;
+
+use crate::world_snapshot::{Measurements, WorldSnapshot};
+
+/// Snapshot statistics of a single registered type.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeSnapshotStats {
+    /// Number of values of this type in the latest snapshot.
+    pub count: usize,
+    /// Estimated size of all values of this type in the latest snapshot, including the elements of vectors,
+    /// strings and maps they own.
+    pub bytes: usize,
+    /// Total time spent saving values of this type.
+    pub save_time: Duration,
+    /// Total time spent restoring values of this type.
+    pub load_time: Duration,
+    /// Number of snapshots that contained this type.
+    pub saves: usize,
+    /// Number of snapshots in which the values of this type differed from the snapshot of the frame before.
+    pub changes: usize,
+}
+
+impl TypeSnapshotStats {
+    /// Fraction of snapshots in which this type changed, between 0 and 1. Types that rarely change are candidates
+    /// for being excluded from the snapshots or being stored differently.
+    pub fn change_frequency(&self) -> f32 {
+        match self.saves {
+            0 => 0.,
+            saves => self.changes as f32 / saves as f32,
+        }
+    }
+}
+
+/// Per-type metrics of saving and loading snapshots, collected while enabled with
+/// `GGRSPlugin::with_snapshot_stats(true)`. Use it to find the types to optimize, exclude or quantize when
+/// snapshots become expensive.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SnapshotStats {
+    types: HashMap<&'static str, TypeSnapshotStats>,
+    saves: usize,
+    loads: usize,
+}
+
+impl SnapshotStats {
+    /// Returns the statistics of the type with the given name, as returned by `std::any::type_name()`.
+    pub fn get(&self, type_name: &str) -> Option<&TypeSnapshotStats> {
+        self.types.get(type_name)
+    }
+
+    /// Iterates over the statistics of all types that have been saved or loaded so far.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &TypeSnapshotStats)> {
+        self.types.iter().map(|(name, stats)| (*name, stats))
+    }
+
+    /// Returns all types, starting with the one that took the most time to save and load.
+    pub fn most_expensive(&self) -> Vec<(&str, &TypeSnapshotStats)> {
+        let mut types: Vec<_> = self.iter().collect();
+        types.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.save_time + stats.load_time));
+        types
+    }
+
+    /// Number of snapshots saved so far.
+    pub fn saves(&self) -> usize {
+        self.saves
+    }
+
+    /// Number of snapshots loaded so far.
+    pub fn loads(&self) -> usize {
+        self.loads
+    }
+
+    /// Fraction of the total save time spent on the given type, between 0 and 1.
+    pub fn save_share(&self, type_name: &str) -> f32 {
+        let total: Duration = self.types.values().map(|stats| stats.save_time).sum();
+        self.get(type_name)
+            .map_or(0., |stats| share(stats.save_time, total))
+    }
+
+    /// Fraction of the total load time spent on the given type, between 0 and 1.
+    pub fn load_share(&self, type_name: &str) -> f32 {
+        let total: Duration = self.types.values().map(|stats| stats.load_time).sum();
+        self.get(type_name)
+            .map_or(0., |stats| share(stats.load_time, total))
+    }
+
+    /// Forgets everything collected so far.
+    pub fn reset(&mut self) {
+        *self = Default::default();
+    }
+
+    pub(crate) fn record_save(
+        &mut self,
+        snapshot: &WorldSnapshot,
+        previous: Option<&WorldSnapshot>,
+        measurements: Measurements,
+    ) {
+        self.saves += 1;
+        for (name, time) in measurements.time {
+            self.types.entry(name).or_default().save_time += time;
+        }
+
+        let changed = previous.map(|previous| snapshot.changed_types(previous));
+        for (name, stats) in self.types.iter_mut() {
+            let Some(&(count, bytes)) = measurements.sizes.get(name) else {
+                stats.count = 0;
+                stats.bytes = 0;
+                continue;
+            };
+            stats.count = count;
+            stats.bytes = bytes;
+            stats.saves += 1;
+            // without a previous snapshot, everything counts as changed
+            if changed
+                .as_ref()
+                .map_or(true, |changed| changed.contains(name))
+            {
+                stats.changes += 1;
+            }
+        }
+    }
+
+    pub(crate) fn record_load(&mut self, measurements: Measurements) {
+        self.loads += 1;
+        for (name, time) in measurements.time {
+            self.types.entry(name).or_default().load_time += time;
+        }
+    }
+}
+
+fn share(time: Duration, total: Duration) -> f32 {
+    if total.is_zero() {
+        0.
+    } else {
+        time.as_secs_f32() / total.as_secs_f32()
+    }
+}
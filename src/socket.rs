@@ -0,0 +1,554 @@
+use bevy::{prelude::*, utils::HashMap};
+use ggrs::{Message, NonBlockingSocket};
+use instant::{Duration, Instant};
+use parking_lot::Mutex;
+use std::{
+    hash::Hash,
+    io::ErrorKind,
+    net::{SocketAddr, UdpSocket},
+    sync::Arc,
+};
+
+/// Large enough for any GGRS message; bigger datagrams should not be sent over UDP anyway.
+const RECV_BUFFER_SIZE: usize = 4096;
+/// Batches are sent early instead of growing beyond this size, to stay below the usual MTU.
+const MAX_BATCH_SIZE: usize = 1200;
+/// Size of the truncated MAC authenticating a datagram between peers with tokens.
+const MAC_SIZE: usize = 16;
+/// The channel id, and the peer token, packet counter and MAC in front of every datagram.
+const HEADER_SIZE: usize = 1 + 8 + 8 + MAC_SIZE;
+/// The largest payload that fits into a batch. Larger payloads are sent on their own.
+pub(crate) const MAX_PAYLOAD_SIZE: usize = MAX_BATCH_SIZE - 3;
+// a batch must never be cut off by the receive buffer
+const _: () = assert!(MAX_BATCH_SIZE + HEADER_SIZE <= RECV_BUFFER_SIZE);
+/// Datagrams kept per channel until they are read. The oldest ones are dropped beyond that, so channels nobody
+/// reads, such as the one of a replaced session, don't pile up.
+const MAX_QUEUED_DATAGRAMS: usize = 256;
+/// Batches are sent after this time at the latest, while no frames are simulated (during synchronization, while
+/// held by a barrier, ...).
+const MAX_BATCH_DELAY: Duration = Duration::from_millis(100);
+
+/// Logical channels sharing one `MultiplexSocket`. The channel id is the first byte of every datagram.
+pub(crate) mod channel {
+    pub(crate) const GGRS: u8 = 0;
+    pub(crate) const MATCH_SETUP: u8 = 1;
+    pub(crate) const PROBE: u8 = 2;
+    pub(crate) const RESYNC: u8 = 3;
+    pub(crate) const HUD: u8 = 4;
+    pub(crate) const GOODBYE: u8 = 5;
+    pub(crate) const DEBUG_COMMANDS: u8 = 6;
+    pub(crate) const BATCH: u8 = 7;
+    pub(crate) const LEVEL: u8 = 8;
+    pub(crate) const LATE_JOIN: u8 = 9;
+    pub(crate) const STATE_TRANSFER: u8 = 10;
+    pub(crate) const RESYNC_STATE: u8 = 11;
+    pub(crate) const GGRS_STANDBY: u8 = 12;
+    pub(crate) const PEER_MESSAGES: u8 = 13;
+    pub(crate) const DESYNC_CHECK: u8 = 14;
+    pub(crate) const DEVICE_PAUSE: u8 = 15;
+}
+
+/// A transport able to send and receive raw datagrams. Implement this for your own transport (for example a
+/// WebRTC data channel) to use it with a `MultiplexSocket`.
+pub trait DatagramSocket<A>: Send + 'static {
+    /// Sends a single datagram to the given address. Sending is unreliable, errors may be ignored.
+    fn send_datagram(&mut self, data: &[u8], addr: &A);
+    /// Returns all datagrams received since the last call. Must not block.
+    fn receive_datagrams(&mut self) -> Vec<(A, Vec<u8>)>;
+}
+
+impl DatagramSocket<SocketAddr> for UdpSocket {
+    fn send_datagram(&mut self, data: &[u8], addr: &SocketAddr) {
+        if let Err(e) = self.send_to(data, addr) {
+            debug!("failed to send datagram to {addr}: {e}");
+        }
+    }
+
+    fn receive_datagrams(&mut self) -> Vec<(SocketAddr, Vec<u8>)> {
+        let mut received = Vec::new();
+        let mut buffer = [0; RECV_BUFFER_SIZE];
+        loop {
+            match self.recv_from(&mut buffer) {
+                Ok((len, addr)) => received.push((addr, buffer[..len].to_vec())),
+                // no more datagrams to read
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                // on windows, a previous send to an unreachable address shows up here
+                Err(ref e) if e.kind() == ErrorKind::ConnectionReset => continue,
+                Err(e) => {
+                    warn!("failed to receive datagrams: {e}");
+                    break;
+                }
+            }
+        }
+        received
+    }
+}
+
+/// A peer identified by its token instead of its address.
+struct KnownPeer<A> {
+    /// the address the session knows the peer by
+    addr: A,
+    /// the address the peer currently sends from
+    current: A,
+    token: u64,
+    /// the highest packet counter received from the peer
+    counter: Option<u64>,
+}
+
+/// Tokens identifying the peers, so traffic from a new address can be attributed to the right peer.
+struct Identities<A> {
+    /// the secret all peers authenticate their datagrams with
+    key: [u8; 32],
+    local: u64,
+    /// the counter of the next datagram we send
+    counter: u64,
+    peers: Vec<KnownPeer<A>>,
+    /// (peer, old address, new address)
+    changes: Vec<(A, A, A)>,
+}
+
+/// Datagrams to a spectator, collected to be sent together.
+struct SpectatorBatch<A> {
+    addr: A,
+    /// entries of a length, a channel id and the data
+    queue: Vec<u8>,
+    /// when the first entry of the queue was added
+    since: Option<Instant>,
+    stats: BatchStats,
+}
+
+/// Traffic sent to a spectator through a batching `MultiplexSocket`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct BatchStats {
+    pub(crate) datagrams: usize,
+    pub(crate) batches: usize,
+    pub(crate) bytes: usize,
+}
+
+struct Batching<A> {
+    interval: usize,
+    frames: usize,
+    spectators: Vec<SpectatorBatch<A>>,
+}
+
+struct SocketState<A> {
+    transport: Box<dyn DatagramSocket<A>>,
+    inbox: HashMap<u8, Vec<(A, Vec<u8>)>>,
+    peers: Vec<A>,
+    identities: Option<Identities<A>>,
+    batching: Option<Batching<A>>,
+}
+
+impl<A: Clone + PartialEq> SocketState<A> {
+    fn send(&mut self, channel: u8, data: &[u8], addr: &A) {
+        let Some(batching) = self.batching.as_mut() else {
+            return self.send_now(channel, data, addr);
+        };
+        let Some(index) = batching
+            .spectators
+            .iter()
+            .position(|batch| batch.addr == *addr)
+        else {
+            return self.send_now(channel, data, addr);
+        };
+        if data.len() > MAX_PAYLOAD_SIZE {
+            return self.send_now(channel, data, addr);
+        }
+        let batch = &mut batching.spectators[index];
+        if batch.queue.len() + data.len() + 3 > MAX_BATCH_SIZE {
+            self.flush_batch(index);
+        }
+        let batch = &mut self
+            .batching
+            .as_mut()
+            .expect("batching is enabled")
+            .spectators[index];
+        batch
+            .queue
+            .extend_from_slice(&(data.len() as u16).to_le_bytes());
+        batch.queue.push(channel);
+        batch.queue.extend_from_slice(data);
+        batch.since.get_or_insert_with(Instant::now);
+        batch.stats.datagrams += 1;
+    }
+
+    /// Sends everything queued for the spectator with the given index as a single datagram.
+    fn flush_batch(&mut self, index: usize) {
+        let Some(batch) = self
+            .batching
+            .as_mut()
+            .and_then(|batching| batching.spectators.get_mut(index))
+        else {
+            return;
+        };
+        if batch.queue.is_empty() {
+            return;
+        }
+        let queue = std::mem::take(&mut batch.queue);
+        batch.since = None;
+        let addr = batch.addr.clone();
+        batch.stats.batches += 1;
+        batch.stats.bytes += queue.len() + 1;
+        self.send_now(channel::BATCH, &queue, &addr);
+    }
+
+    fn send_now(&mut self, channel: u8, data: &[u8], addr: &A) {
+        if data.len() + HEADER_SIZE > RECV_BUFFER_SIZE {
+            warn!(
+                "sending a datagram of {} bytes, receivers cut it off after {RECV_BUFFER_SIZE} bytes",
+                data.len() + HEADER_SIZE
+            );
+        }
+        let mut datagram = Vec::with_capacity(data.len() + HEADER_SIZE);
+        datagram.push(channel);
+        let mut target = addr;
+        if let Some(identities) = &mut self.identities {
+            let counter = identities.counter;
+            identities.counter += 1;
+            datagram.extend_from_slice(&identities.local.to_le_bytes());
+            datagram.extend_from_slice(&counter.to_le_bytes());
+            let mac = mac(&identities.key, channel, identities.local, counter, data);
+            datagram.extend_from_slice(&mac);
+            if let Some(peer) = identities.peers.iter().find(|peer| peer.addr == *addr) {
+                target = &peer.current;
+            }
+        }
+        datagram.extend_from_slice(data);
+        self.transport.send_datagram(&datagram, target);
+    }
+
+    /// Sends the batches that have been waiting for too long.
+    fn flush_overdue_batches(&mut self) {
+        let overdue: Vec<usize> = self
+            .batching
+            .iter()
+            .flat_map(|batching| batching.spectators.iter().enumerate())
+            .filter(|(_, batch)| {
+                batch
+                    .since
+                    .map_or(false, |since| since.elapsed() >= MAX_BATCH_DELAY)
+            })
+            .map(|(index, _)| index)
+            .collect();
+        for index in overdue {
+            self.flush_batch(index);
+        }
+    }
+
+    fn receive(&mut self, channel: u8) -> Vec<(A, Vec<u8>)> {
+        self.flush_overdue_batches();
+        for (addr, datagram) in self.transport.receive_datagrams() {
+            let Some((&tag, payload)) = datagram.split_first() else {
+                continue;
+            };
+            let (addr, mut payload) = match &mut self.identities {
+                None => (addr, payload),
+                Some(identities) => {
+                    let Some(identified) = identities.identify(addr, tag, payload) else {
+                        continue;
+                    };
+                    identified
+                }
+            };
+            if tag != channel::BATCH {
+                self.queue(tag, addr, payload.to_vec());
+                continue;
+            }
+            // unpack the datagrams of a batch
+            while payload.len() >= 3 {
+                let len = u16::from_le_bytes([payload[0], payload[1]]) as usize;
+                let Some(data) = payload.get(3..3 + len) else {
+                    debug!("received a truncated batch");
+                    break;
+                };
+                self.queue(payload[2], addr.clone(), data.to_vec());
+                payload = &payload[3 + len..];
+            }
+        }
+        self.inbox.remove(&channel).unwrap_or_default()
+    }
+
+    /// Keeps a datagram until its channel is read.
+    fn queue(&mut self, channel: u8, addr: A, data: Vec<u8>) {
+        let queue = self.inbox.entry(channel).or_default();
+        queue.push((addr, data));
+        if queue.len() > MAX_QUEUED_DATAGRAMS {
+            queue.remove(0);
+        }
+    }
+}
+
+impl<A: Clone + PartialEq> Identities<A> {
+    /// Returns the address the session knows the sender of a datagram by and its payload, following the sender to
+    /// its new address if it changed. Datagrams without a known token or a valid MAC are dropped, and so are
+    /// datagrams from another address that are not newer than everything received from the peer so far, so replayed
+    /// or reordered datagrams can't move a peer.
+    fn identify<'a>(&mut self, from: A, channel: u8, datagram: &'a [u8]) -> Option<(A, &'a [u8])> {
+        if datagram.len() < HEADER_SIZE - 1 {
+            return None;
+        }
+        let (header, payload) = datagram.split_at(HEADER_SIZE - 1);
+        let token = u64::from_le_bytes(header[..8].try_into().ok()?);
+        let counter = u64::from_le_bytes(header[8..16].try_into().ok()?);
+        let Some(peer) = self.peers.iter_mut().find(|peer| peer.token == token) else {
+            debug!("dropping a datagram with an unknown peer token");
+            return None;
+        };
+        if !verify(
+            &mac(&self.key, channel, token, counter, payload),
+            &header[16..],
+        ) {
+            debug!("dropping a datagram with an invalid MAC");
+            return None;
+        }
+        let newer = peer.counter.map_or(true, |highest| counter > highest);
+        if peer.current != from {
+            if !newer {
+                debug!("dropping an old datagram from another address of a peer");
+                return None;
+            }
+            let old = std::mem::replace(&mut peer.current, from.clone());
+            self.changes.push((peer.addr.clone(), old, from));
+        }
+        if newer {
+            peer.counter = Some(counter);
+        }
+        Some((peer.addr.clone(), payload))
+    }
+}
+
+/// Returns the MAC of a datagram.
+fn mac(key: &[u8; 32], channel: u8, token: u64, counter: u64, payload: &[u8]) -> [u8; MAC_SIZE] {
+    let mut hasher = blake3::Hasher::new_keyed(key);
+    hasher.update(&[channel]);
+    hasher.update(&token.to_le_bytes());
+    hasher.update(&counter.to_le_bytes());
+    hasher.update(payload);
+    let mut mac = [0; MAC_SIZE];
+    mac.copy_from_slice(&hasher.finalize().as_bytes()[..MAC_SIZE]);
+    mac
+}
+
+/// Compares MACs in constant time.
+fn verify(expected: &[u8], received: &[u8]) -> bool {
+    expected.len() == received.len()
+        && expected
+            .iter()
+            .zip(received)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// A socket that carries GGRS traffic alongside the additional channels bevy_ggrs uses to talk to other peers
+/// (match setup, ...). Hand a clone of it to `SessionBuilder::start_p2p_session()` and keep another clone as a
+/// resource, so bevy_ggrs can use the same connection that GGRS uses.
+///
+/// All peers of a session have to use a `MultiplexSocket`, since every datagram is prefixed with a channel id.
+#[derive(Resource)]
+pub struct MultiplexSocket<A> {
+    state: Arc<Mutex<SocketState<A>>>,
+    /// the channel of the GGRS traffic handed to the session
+    ggrs: u8,
+}
+
+impl<A> Clone for MultiplexSocket<A> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            ggrs: self.ggrs,
+        }
+    }
+}
+
+impl MultiplexSocket<SocketAddr> {
+    /// Binds a non-blocking UDP socket to the given port on all interfaces.
+    pub fn bind_to_port(port: u16) -> Result<Self, std::io::Error> {
+        let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], port)))?;
+        socket.set_nonblocking(true)?;
+        Ok(Self::new(socket))
+    }
+}
+
+impl<A: Clone + PartialEq + Send + 'static> MultiplexSocket<A> {
+    /// Wraps a transport. The transport must not block when receiving.
+    pub fn new(transport: impl DatagramSocket<A>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SocketState {
+                transport: Box::new(transport),
+                inbox: HashMap::default(),
+                peers: Vec::new(),
+                identities: None,
+                batching: None,
+            })),
+            ggrs: channel::GGRS,
+        }
+    }
+
+    /// Returns a clone that carries the GGRS traffic on a separate channel, for a `StandbySession` that
+    /// synchronizes while the session on this socket is still running. Calling it on the returned socket switches
+    /// back, so the sessions of consecutive rematches alternate between two channels. All peers have to switch
+    /// alike.
+    pub fn standby(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            ggrs: match self.ggrs {
+                channel::GGRS => channel::GGRS_STANDBY,
+                _ => channel::GGRS,
+            },
+        }
+    }
+
+    /// Returns the channel the GGRS traffic of this socket is carried on.
+    pub(crate) fn ggrs_channel(&self) -> u8 {
+        self.ggrs
+    }
+
+    /// Collects everything sent to the given spectators and sends it as one datagram every `frames` simulated
+    /// frames, instead of one datagram per message. This reduces the upstream bandwidth of hosts with many
+    /// spectators, at the cost of up to `frames` frames of additional delay for the spectators. While no frames are
+    /// simulated, for example while the spectators are still synchronizing, batches are sent when the socket is
+    /// polled after 100 ms at the latest. The spectators need no configuration, every `MultiplexSocket` understands
+    /// batches.
+    pub fn with_spectator_batching(self, spectators: Vec<A>, frames: usize) -> Self {
+        let spectators = spectators
+            .into_iter()
+            .map(|addr| SpectatorBatch {
+                addr,
+                queue: Vec::new(),
+                since: None,
+                stats: BatchStats::default(),
+            })
+            .collect();
+        self.state.lock().batching = Some(Batching {
+            interval: frames.max(1),
+            frames: 0,
+            spectators,
+        });
+        self
+    }
+
+    /// Called after every simulated frame, sends the batches once the interval is over.
+    pub(crate) fn on_frame_advanced(&self) {
+        let mut state = self.state.lock();
+        let Some(batching) = state.batching.as_mut() else {
+            return;
+        };
+        batching.frames += 1;
+        if batching.frames < batching.interval {
+            return;
+        }
+        batching.frames = 0;
+        let spectators = batching.spectators.len();
+        for index in 0..spectators {
+            state.flush_batch(index);
+        }
+    }
+
+    /// Returns the traffic sent to each batched spectator so far.
+    pub(crate) fn batch_stats(&self) -> Vec<(A, BatchStats)> {
+        self.state
+            .lock()
+            .batching
+            .as_ref()
+            .map(|batching| {
+                batching
+                    .spectators
+                    .iter()
+                    .map(|batch| (batch.addr.clone(), batch.stats))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Tags every datagram with the `local` token and identifies other peers by their tokens instead of their
+    /// addresses. A peer whose address changes mid-session (switching networks, NAT rebinding) keeps talking to the
+    /// session from its new address, which is reported with a `PeerAddressChanged` event. Given as `(address, token)`,
+    /// the peers are still known by their original address to GGRS and everything else.
+    ///
+    /// Every datagram carries a counter and a MAC computed with `key`, a random secret only shared with the other
+    /// participants of the session, for example by the matchmaking server. Datagrams without a known token or a
+    /// valid MAC are dropped, and a peer only moves to another address with a datagram newer than all datagrams
+    /// received from it before, so neither sniffed tokens nor replayed datagrams can redirect its traffic. All peers
+    /// must enable this with the same key.
+    pub fn with_peer_tokens(
+        self,
+        key: [u8; 32],
+        local: u64,
+        peers: impl IntoIterator<Item = (A, u64)>,
+    ) -> Self {
+        let peers = peers
+            .into_iter()
+            .map(|(addr, token)| KnownPeer {
+                current: addr.clone(),
+                addr,
+                token,
+                counter: None,
+            })
+            .collect();
+        self.state.lock().identities = Some(Identities {
+            key,
+            local,
+            counter: 0,
+            peers,
+            changes: Vec::new(),
+        });
+        self
+    }
+
+    /// Returns the number of received datagrams that haven't been read on their channel yet.
+    pub fn queued_datagrams(&self) -> usize {
+        self.state.lock().inbox.values().map(Vec::len).sum()
+    }
+
+    /// Returns all addresses GGRS has sent messages to so far.
+    pub fn peers(&self) -> Vec<A> {
+        self.state.lock().peers.clone()
+    }
+
+    /// Sends `data` on the given channel.
+    pub(crate) fn send_on(&self, channel: u8, data: &[u8], addr: &A) {
+        self.state.lock().send(channel, data, addr);
+    }
+
+    /// Returns the address changes of peers since the last call, as (peer, old address, new address).
+    pub(crate) fn take_address_changes(&self) -> Vec<(A, A, A)> {
+        self.state
+            .lock()
+            .identities
+            .as_mut()
+            .map(|identities| std::mem::take(&mut identities.changes))
+            .unwrap_or_default()
+    }
+
+    /// Returns all datagrams received on the given channel since the last call.
+    pub(crate) fn receive_on(&self, channel: u8) -> Vec<(A, Vec<u8>)> {
+        self.state.lock().receive(channel)
+    }
+}
+
+impl<A> NonBlockingSocket<A> for MultiplexSocket<A>
+where
+    A: Clone + PartialEq + Eq + Hash + Send + Sync + 'static,
+{
+    fn send_to(&mut self, msg: &Message, addr: &A) {
+        let data = bincode::serialize(msg).expect("GGRS messages should always serialize");
+        let mut state = self.state.lock();
+        if !state.peers.contains(addr) {
+            state.peers.push(addr.clone());
+        }
+        state.send(self.ggrs, &data, addr);
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(A, Message)> {
+        self.receive_on(self.ggrs)
+            .into_iter()
+            .filter_map(|(addr, data)| match bincode::deserialize(&data) {
+                Ok(msg) => Some((addr, msg)),
+                Err(_) => {
+                    debug!("received a malformed GGRS message");
+                    None
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,203 @@
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    ggrs_stage::StageEvent,
+    socket::{channel, MultiplexSocket, MAX_PAYLOAD_SIZE},
+};
+
+/// Size of the length of the entry list in a HUD packet, and of the frame and the data length of an entry.
+const PACKET_OVERHEAD: usize = 8;
+const ENTRY_OVERHEAD: usize = 12;
+
+enum HudRole<A> {
+    Broadcaster { spectators: Vec<A> },
+    Receiver { host: A },
+}
+
+/// Per-frame metadata (combo counters, meters, ...) that players forward to their spectators, so broadcast overlays
+/// don't have to recompute it from the game state. Register the data type with
+/// `GGRSPlugin::register_spectator_hud::<H>()` and insert the resource on the hosting player and on all spectators.
+///
+/// Systems in the rollback schedule of the host call `set()` each frame. Once a frame is confirmed, its data is sent
+/// to the spectators, where `get()` returns the data of the frame the spectator simulated last. Delivery is best
+/// effort: if a packet is lost, spectators keep showing the data of an earlier frame. The data of many frames is
+/// split into several packets, data of a single frame that doesn't fit into one packet (about 1 KB) is dropped.
+#[derive(Resource)]
+pub struct SpectatorHud<H, A> {
+    socket: MultiplexSocket<A>,
+    role: HudRole<A>,
+    /// the frame being simulated
+    frame: i32,
+    /// host: data of unconfirmed frames, receiver: data of frames that have not been simulated yet
+    pending: BTreeMap<i32, H>,
+    current: Option<(i32, H)>,
+    /// host: frames between sends, the last frame sent
+    send_interval: i32,
+    last_sent: i32,
+}
+
+impl<H, A> SpectatorHud<H, A>
+where
+    H: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    A: Clone + PartialEq + Send + Sync + 'static,
+{
+    /// Creates the resource for a player that sends its data to the given spectators.
+    pub fn broadcaster(socket: MultiplexSocket<A>, spectators: Vec<A>) -> Self {
+        Self::new(socket, HudRole::Broadcaster { spectators })
+    }
+
+    /// Creates the resource for a spectator watching the player at `host`.
+    pub fn receiver(socket: MultiplexSocket<A>, host: A) -> Self {
+        Self::new(socket, HudRole::Receiver { host })
+    }
+
+    fn new(socket: MultiplexSocket<A>, role: HudRole<A>) -> Self {
+        Self {
+            socket,
+            role,
+            frame: 0,
+            pending: BTreeMap::new(),
+            current: None,
+            send_interval: 1,
+            last_sent: -1,
+        }
+    }
+
+    /// Sets the data of the frame that is currently simulated. Does nothing on spectators, which receive the data
+    /// from the host instead.
+    pub fn set(&mut self, data: H) {
+        if let HudRole::Broadcaster { .. } = self.role {
+            self.pending.insert(self.frame, data);
+        }
+    }
+
+    /// Returns the data of the last simulated frame that has data.
+    pub fn get(&self) -> Option<&H> {
+        self.current.as_ref().map(|(_, data)| data)
+    }
+
+    /// Returns the frame the data returned by `get()` belongs to.
+    pub fn frame(&self) -> Option<i32> {
+        self.current.as_ref().map(|(frame, _)| *frame)
+    }
+
+    pub(crate) fn set_send_interval(&mut self, frames: i32) {
+        self.send_interval = frames.max(1);
+    }
+
+    pub(crate) fn on_stage_event(&mut self, event: StageEvent) {
+        match event {
+            StageEvent::Advancing { frame } => self.frame = frame,
+            StageEvent::Loaded { frame } => {
+                // the resimulated frames will set their data again
+                if let HudRole::Broadcaster { .. } = self.role {
+                    self.pending.retain(|f, _| *f < frame);
+                }
+            }
+            StageEvent::Advanced { frame } => match self.role {
+                HudRole::Broadcaster { .. } => {
+                    if let Some(data) = self.pending.get(&frame) {
+                        self.current = Some((frame, data.clone()));
+                    }
+                }
+                HudRole::Receiver { .. } => {
+                    let later = self.pending.split_off(&(frame + 1));
+                    if let Some(data) = self.pending.remove(&frame) {
+                        self.current = Some((frame, data));
+                    }
+                    self.pending = later;
+                }
+            },
+            StageEvent::Confirmed { frame } => {
+                let HudRole::Broadcaster { spectators } = &self.role else {
+                    return;
+                };
+                if frame - self.last_sent < self.send_interval {
+                    return;
+                }
+                self.last_sent = frame;
+                let later = self.pending.split_off(&(frame + 1));
+                let confirmed = std::mem::replace(&mut self.pending, later);
+                if confirmed.is_empty() {
+                    return;
+                }
+                let mut packets = vec![Vec::new()];
+                let mut size = PACKET_OVERHEAD;
+                for (frame, data) in confirmed.iter() {
+                    let data = bincode::serialize(data).expect("HUD data should serialize");
+                    let entry_size = ENTRY_OVERHEAD + data.len();
+                    if PACKET_OVERHEAD + entry_size > MAX_PAYLOAD_SIZE {
+                        warn!(
+                            "spectator HUD: the data of frame {frame} is too large to send ({} bytes)",
+                            data.len()
+                        );
+                        continue;
+                    }
+                    if size + entry_size > MAX_PAYLOAD_SIZE {
+                        packets.push(Vec::new());
+                        size = PACKET_OVERHEAD;
+                    }
+                    size += entry_size;
+                    packets
+                        .last_mut()
+                        .expect("there is a packet")
+                        .push((*frame, data));
+                }
+                for packet in packets.iter().filter(|packet| !packet.is_empty()) {
+                    let packet = bincode::serialize(packet).expect("should serialize");
+                    debug_assert!(packet.len() <= MAX_PAYLOAD_SIZE);
+                    for spectator in spectators {
+                        self.socket.send_on(channel::HUD, &packet, spectator);
+                    }
+                }
+            }
+            StageEvent::Reset => {
+                self.frame = 0;
+                self.pending.clear();
+                self.current = None;
+                self.last_sent = -1;
+            }
+            StageEvent::Saved { .. } => {}
+        }
+    }
+
+    /// Collects data sent by the host.
+    pub(crate) fn receive(&mut self) {
+        let HudRole::Receiver { host } = &self.role else {
+            return;
+        };
+        for (addr, packet) in self.socket.receive_on(channel::HUD) {
+            if addr != *host {
+                continue;
+            }
+            let Ok(entries) = bincode::deserialize::<Vec<(i32, Vec<u8>)>>(&packet) else {
+                debug!("received a malformed HUD packet");
+                continue;
+            };
+            for (frame, data) in entries {
+                let Ok(data) = bincode::deserialize(&data) else {
+                    debug!("received malformed HUD data");
+                    continue;
+                };
+                if frame > self.frame {
+                    self.pending.insert(frame, data);
+                } else if self.frame().map_or(true, |current| frame > current) {
+                    // arrived after the frame has been simulated, but is still newer than what we show
+                    self.current = Some((frame, data));
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn receive_spectator_hud_system<H, A>(hud: Option<ResMut<SpectatorHud<H, A>>>)
+where
+    H: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    A: Clone + PartialEq + Send + Sync + 'static,
+{
+    if let Some(mut hud) = hud {
+        hud.receive();
+    }
+}
@@ -0,0 +1,88 @@
+use bevy::prelude::*;
+use ggrs::Config;
+
+use crate::{socket::MultiplexSocket, Session};
+
+/// Connection metrics of a spectator served by this peer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpectatorLink {
+    /// Number of confirmed frames whose inputs the spectator has not acknowledged yet, roughly how far it lags
+    /// behind.
+    pub frames_behind: usize,
+    /// Round trip time to the spectator in milliseconds.
+    pub ping: u128,
+    /// Upstream bandwidth used for the spectator in kilobits per second.
+    pub kbps_sent: usize,
+    /// Number of datagrams queued for the spectator so far.
+    pub datagrams: usize,
+    /// Number of batches sent to the spectator so far, each holding several datagrams.
+    pub batches: usize,
+    /// Number of bytes sent to the spectator in batches so far.
+    pub bytes: usize,
+}
+
+/// Metrics of the spectators configured with `MultiplexSocket::with_spectator_batching()`, updated every frame
+/// while a `P2PSession` is running.
+#[derive(Resource)]
+pub struct SpectatorLinks<A> {
+    links: Vec<(A, SpectatorLink)>,
+}
+
+impl<A> Default for SpectatorLinks<A> {
+    fn default() -> Self {
+        Self { links: Vec::new() }
+    }
+}
+
+impl<A: PartialEq> SpectatorLinks<A> {
+    /// Returns the metrics of the spectator at the given address.
+    pub fn get(&self, addr: &A) -> Option<&SpectatorLink> {
+        self.links
+            .iter()
+            .find(|(a, _)| a == addr)
+            .map(|(_, link)| link)
+    }
+
+    /// Iterates over the metrics of all spectators.
+    pub fn iter(&self) -> impl Iterator<Item = (&A, &SpectatorLink)> {
+        self.links.iter().map(|(addr, link)| (addr, link))
+    }
+}
+
+pub(crate) fn update_spectator_links_system<T: Config>(
+    session: Option<Res<Session<T>>>,
+    socket: Option<Res<MultiplexSocket<T::Address>>>,
+    mut links: ResMut<SpectatorLinks<T::Address>>,
+) where
+    T::Address: Send + Sync + 'static,
+{
+    let Some(socket) = socket else {
+        return;
+    };
+    let session = match session.as_deref() {
+        Some(Session::P2PSession(session)) => Some(session),
+        _ => None,
+    };
+    links.links = socket
+        .batch_stats()
+        .into_iter()
+        .map(|(addr, stats)| {
+            let mut link = SpectatorLink {
+                datagrams: stats.datagrams,
+                batches: stats.batches,
+                bytes: stats.bytes,
+                ..Default::default()
+            };
+            let network_stats = session.and_then(|session| {
+                let handle = *session.handles_by_address(addr.clone()).first()?;
+                session.network_stats(handle).ok()
+            });
+            if let Some(network_stats) = network_stats {
+                link.frames_behind = network_stats.send_queue_len;
+                link.ping = network_stats.ping;
+                link.kbps_sent = network_stats.kbps_sent;
+            }
+            (addr, link)
+        })
+        .collect();
+}
@@ -0,0 +1,35 @@
+use bevy::{ecs::schedule::StageLabelId, prelude::*};
+
+/// Where the GGRS stage runs in the main schedule of the app. The presentation stage always follows right after
+/// it. Plugins syncing physics or capturing input can then be ordered strictly before or after the netcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StagePlacement {
+    /// Right before `CoreStage::Update`, after everything in `CoreStage::PreUpdate`.
+    #[default]
+    BeforeUpdate,
+    /// Right before the given stage.
+    Before(StageLabelId),
+    /// Right after the given stage.
+    After(StageLabelId),
+}
+
+impl StagePlacement {
+    /// Runs the GGRS stage right before the stage with the given label.
+    pub fn before(label: impl StageLabel) -> Self {
+        Self::Before(label.as_label())
+    }
+
+    /// Runs the GGRS stage right after the stage with the given label.
+    pub fn after(label: impl StageLabel) -> Self {
+        Self::After(label.as_label())
+    }
+
+    /// Adds the stage to the app at this place.
+    pub(crate) fn add_stage(self, app: &mut App, label: impl StageLabel, stage: impl Stage) {
+        match self {
+            StagePlacement::BeforeUpdate => app.add_stage_before(CoreStage::Update, label, stage),
+            StagePlacement::Before(target) => app.add_stage_before(target, label, stage),
+            StagePlacement::After(target) => app.add_stage_after(target, label, stage),
+        };
+    }
+}
@@ -0,0 +1,74 @@
+use bevy::prelude::*;
+use ggrs::{Config, P2PSession, SessionState};
+
+use crate::socket::MultiplexSocket;
+
+/// The session of the next match, synchronizing in the background while the post-match screen is shown, so a
+/// rematch starts without waiting for the handshake. Start the session on `MultiplexSocket::standby()` of the
+/// socket the current session runs on and insert it as a resource. It is polled every update until it is needed.
+///
+/// Once `is_ready()`, call `rematch()`: at the start of the next GGRS stage run, it replaces the current `Session`
+/// and the stage starts over at frame 0, saving the world as it is then. Reset the world to the state the match
+/// starts from before that.
+#[derive(Resource)]
+pub struct StandbySession<T: Config> {
+    session: P2PSession<T>,
+    /// the GGRS channel of the session this one replaces
+    previous_channel: u8,
+    rematch: bool,
+}
+
+impl<T: Config> StandbySession<T> {
+    /// Wraps a session started on `socket`, which was returned by `MultiplexSocket::standby()`.
+    pub fn new(session: P2PSession<T>, socket: &MultiplexSocket<T::Address>) -> Self
+    where
+        T::Address: Clone + PartialEq + Send + 'static,
+    {
+        Self {
+            session,
+            previous_channel: socket.standby().ggrs_channel(),
+            rematch: false,
+        }
+    }
+
+    /// Returns true once all players are synchronized.
+    pub fn is_ready(&self) -> bool {
+        self.session.current_state() == SessionState::Running
+    }
+
+    /// Switches to the session as soon as it is ready.
+    pub fn rematch(&mut self) {
+        self.rematch = true;
+    }
+
+    /// Returns the session, for example to show its events or network stats.
+    pub fn session(&self) -> &P2PSession<T> {
+        &self.session
+    }
+
+    /// Returns the session, for example to drain its events.
+    pub fn session_mut(&mut self) -> &mut P2PSession<T> {
+        &mut self.session
+    }
+
+    /// Returns true if the session should replace the current one.
+    pub(crate) fn is_due(&self) -> bool {
+        self.rematch && self.is_ready()
+    }
+
+    /// Returns the GGRS channel the replaced session ran on.
+    pub(crate) fn previous_channel(&self) -> u8 {
+        self.previous_channel
+    }
+
+    pub(crate) fn into_session(self) -> P2PSession<T> {
+        self.session
+    }
+}
+
+/// Keeps the handshake and the keep-alives of the standby session going.
+pub(crate) fn poll_standby_session_system<T: Config>(standby: Option<ResMut<StandbySession<T>>>) {
+    if let Some(mut standby) = standby {
+        standby.session.poll_remote_clients();
+    }
+}
@@ -0,0 +1,567 @@
+use bevy::prelude::*;
+use instant::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    resync::DesyncRecovery,
+    socket::{channel, MultiplexSocket},
+};
+
+const DEFAULT_RESEND_INTERVAL: Duration = Duration::from_millis(250);
+/// Chunks sent without an acknowledgement yet. 32 KiB in flight per peer.
+const DEFAULT_WINDOW: usize = 32;
+const DEFAULT_MAX_SIZE: usize = 32 * 1024 * 1024;
+/// Keeps every chunk datagram well below the receive buffer of the socket.
+const CHUNK_SIZE: usize = 1024;
+/// Keeps every acknowledgement datagram below the receive buffer of the socket as well.
+const MAX_ACK_RANGES: usize = 128;
+/// Transfers without any progress for this long are given up on both ends.
+const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize)]
+enum TransferPacket {
+    /// One piece of the state tagged with `frame`. The state is identified by its frame and checksum.
+    Chunk {
+        frame: i32,
+        checksum: u64,
+        size: u64,
+        index: u32,
+        chunk_checksum: u64,
+        data: Vec<u8>,
+    },
+    /// The chunks in the half-open index ranges have arrived.
+    Ack {
+        frame: i32,
+        checksum: u64,
+        ranges: Vec<(u32, u32)>,
+    },
+}
+
+/// Whether a `StateTransferEvent` is about a state we send or receive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferDirection {
+    Sending,
+    Receiving,
+}
+
+/// Reports the progress of the transfers of a `StateTransfer` or `DesyncRecovery`, at most once per update and
+/// transfer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateTransferEvent<A> {
+    /// `bytes` of the `total` bytes of the state tagged with `frame` have been received, or acknowledged by `peer`.
+    Progress {
+        peer: A,
+        direction: TransferDirection,
+        frame: i32,
+        bytes: usize,
+        total: usize,
+    },
+    /// The state has arrived completely and matches its checksum.
+    Completed {
+        peer: A,
+        direction: TransferDirection,
+        frame: i32,
+    },
+    /// The state didn't match its checksum after arriving, or the other end stopped responding.
+    Failed {
+        peer: A,
+        direction: TransferDirection,
+        frame: i32,
+    },
+}
+
+/// A state that arrived through a `StateTransfer`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedState<A> {
+    pub peer: A,
+    pub frame: i32,
+    pub data: Vec<u8>,
+}
+
+struct Outgoing<A> {
+    peer: A,
+    frame: i32,
+    checksum: u64,
+    state: Vec<u8>,
+    acked: Vec<bool>,
+    /// when each chunk was last sent
+    sent: Vec<Option<Instant>>,
+    acked_bytes: usize,
+    reported_bytes: usize,
+    last_progress: Instant,
+}
+
+struct Incoming<A> {
+    peer: A,
+    frame: i32,
+    checksum: u64,
+    size: usize,
+    chunks: Vec<Option<Vec<u8>>>,
+    received_bytes: usize,
+    reported_bytes: usize,
+    /// chunks to acknowledge with the next poll, including ones that arrived again
+    unacked: Vec<u32>,
+    last_progress: Instant,
+}
+
+/// Sends states of any size to other peers, in chunks that are acknowledged and resent until they have arrived.
+/// Only the chunks that are still missing are sent again, so a transfer resumes where it stopped after packet loss or
+/// a stalled connection. Every chunk and the complete state carry a checksum; corrupted chunks are dropped and sent
+/// again. Meant for serialized world states of several megabytes, as sent for joining or resyncing a running match.
+///
+/// Insert it as a resource using a clone of the `MultiplexSocket` the session runs on, on the sending and the
+/// receiving peer. Progress is reported as `StateTransferEvent`s. `DesyncRecovery` transfers its states this way
+/// on a channel of its own.
+#[derive(Resource)]
+pub struct StateTransfer<A> {
+    socket: MultiplexSocket<A>,
+    channel: u8,
+    window: usize,
+    resend_interval: Duration,
+    max_size: usize,
+    outgoing: Vec<Outgoing<A>>,
+    incoming: Vec<Incoming<A>>,
+    /// the latest complete state from each peer, so chunks arriving again are only acknowledged
+    completed: Vec<(A, i32, u64)>,
+    received: Vec<ReceivedState<A>>,
+    events: Vec<StateTransferEvent<A>>,
+}
+
+impl<A: Clone + PartialEq + Send + Sync + 'static> StateTransfer<A> {
+    /// Creates a transfer sending and receiving on the given socket.
+    pub fn new(socket: MultiplexSocket<A>) -> Self {
+        Self::on_channel(socket, channel::STATE_TRANSFER)
+    }
+
+    pub(crate) fn on_channel(socket: MultiplexSocket<A>, channel: u8) -> Self {
+        Self {
+            socket,
+            channel,
+            window: DEFAULT_WINDOW,
+            resend_interval: DEFAULT_RESEND_INTERVAL,
+            max_size: DEFAULT_MAX_SIZE,
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+            completed: Vec::new(),
+            received: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Changes how many chunks of 1 KiB may be on their way to a peer without being acknowledged. Defaults to 32.
+    pub fn with_window(mut self, chunks: usize) -> Self {
+        self.window = chunks.max(1);
+        self
+    }
+
+    /// Changes how long a chunk may stay unacknowledged before it is sent again. Defaults to 250 ms.
+    pub fn with_resend_interval(mut self, interval: Duration) -> Self {
+        self.resend_interval = interval;
+        self
+    }
+
+    /// Changes the size of the largest state accepted from other peers. Defaults to 32 MiB.
+    pub fn with_max_size(mut self, bytes: usize) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Sends the state tagged with `frame` to `peer`, replacing an unfinished transfer to the same peer.
+    pub fn send(&mut self, peer: A, frame: i32, state: &[u8]) {
+        self.outgoing.retain(|transfer| transfer.peer != peer);
+        let count = chunk_count(state.len());
+        self.outgoing.push(Outgoing {
+            peer,
+            frame,
+            checksum: checksum(state),
+            state: state.to_vec(),
+            acked: vec![false; count],
+            sent: vec![None; count],
+            acked_bytes: 0,
+            reported_bytes: 0,
+            last_progress: Instant::now(),
+        });
+    }
+
+    /// Returns true while a state is being sent to `peer`.
+    pub fn is_sending(&self, peer: &A) -> bool {
+        self.outgoing.iter().any(|transfer| transfer.peer == *peer)
+    }
+
+    /// Returns true while chunks from `peer` keep arriving.
+    pub fn is_receiving(&self, peer: &A) -> bool {
+        self.incoming.iter().any(|transfer| {
+            transfer.peer == *peer && transfer.last_progress.elapsed() < self.resend_interval
+        })
+    }
+
+    /// Returns the states that have arrived completely since the last call.
+    pub fn take_received(&mut self) -> Vec<ReceivedState<A>> {
+        std::mem::take(&mut self.received)
+    }
+
+    /// Collects arriving chunks, acknowledges them and sends the chunks that are due.
+    pub(crate) fn poll(&mut self) {
+        let now = Instant::now();
+
+        for (addr, data) in self.socket.receive_on(self.channel) {
+            match bincode::deserialize(&data) {
+                Ok(TransferPacket::Chunk {
+                    frame,
+                    checksum,
+                    size,
+                    index,
+                    chunk_checksum,
+                    data,
+                }) => {
+                    if self::checksum(&data) != chunk_checksum {
+                        debug!("dropping a corrupted state chunk");
+                        continue;
+                    }
+                    self.receive_chunk(addr, frame, checksum, size, index, data, now);
+                }
+                Ok(TransferPacket::Ack {
+                    frame,
+                    checksum,
+                    ranges,
+                }) => {
+                    let Some(transfer) = self.outgoing.iter_mut().find(|transfer| {
+                        transfer.peer == addr
+                            && transfer.frame == frame
+                            && transfer.checksum == checksum
+                    }) else {
+                        continue;
+                    };
+                    let count = transfer.acked.len();
+                    if ranges
+                        .iter()
+                        .any(|(start, end)| start >= end || *end as usize > count)
+                    {
+                        debug!("ignoring an invalid state transfer acknowledgement");
+                        continue;
+                    }
+                    for (start, end) in ranges {
+                        for index in start as usize..end as usize {
+                            if !transfer.acked[index] {
+                                transfer.acked[index] = true;
+                                transfer.acked_bytes += chunk_len(transfer.state.len(), index);
+                                transfer.last_progress = now;
+                            }
+                        }
+                    }
+                }
+                Err(_) => debug!("received a malformed state transfer packet"),
+            }
+        }
+
+        self.send_acks();
+        self.send_chunks(now);
+        self.finish_transfers(now);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn receive_chunk(
+        &mut self,
+        addr: A,
+        frame: i32,
+        checksum: u64,
+        size: u64,
+        index: u32,
+        data: Vec<u8>,
+        now: Instant,
+    ) {
+        let size = size as usize;
+        let count = chunk_count(size);
+        if size > self.max_size
+            || index as usize >= count
+            || data.len() != chunk_len(size, index as usize)
+        {
+            debug!("ignoring an invalid state chunk");
+            return;
+        }
+        let done = self
+            .completed
+            .iter()
+            .any(|(peer, f, c)| *peer == addr && *f == frame && *c == checksum);
+        if done {
+            // the acknowledgement got lost
+            let ack = TransferPacket::Ack {
+                frame,
+                checksum,
+                ranges: vec![(index, index + 1)],
+            };
+            let ack = bincode::serialize(&ack).expect("should serialize");
+            self.socket.send_on(self.channel, &ack, &addr);
+            return;
+        }
+
+        let position = self
+            .incoming
+            .iter()
+            .position(|transfer| transfer.peer == addr);
+        let position = match position {
+            Some(position) => {
+                let transfer = &self.incoming[position];
+                if transfer.frame == frame && transfer.checksum == checksum {
+                    Some(position)
+                } else if frame >= transfer.frame {
+                    // a newer state replaces a partially received older one
+                    self.incoming.remove(position);
+                    None
+                } else {
+                    return;
+                }
+            }
+            None => None,
+        };
+        let position = position.unwrap_or_else(|| {
+            self.incoming.push(Incoming {
+                peer: addr,
+                frame,
+                checksum,
+                size,
+                chunks: vec![None; count],
+                received_bytes: 0,
+                reported_bytes: 0,
+                unacked: Vec::new(),
+                last_progress: now,
+            });
+            self.incoming.len() - 1
+        });
+
+        let transfer = &mut self.incoming[position];
+        transfer.unacked.push(index);
+        let chunk = &mut transfer.chunks[index as usize];
+        if chunk.is_none() {
+            transfer.received_bytes += data.len();
+            transfer.last_progress = now;
+            *chunk = Some(data);
+        }
+    }
+
+    fn send_acks(&mut self) {
+        for transfer in self.incoming.iter_mut() {
+            if transfer.unacked.is_empty() {
+                continue;
+            }
+            let mut indices = std::mem::take(&mut transfer.unacked);
+            indices.sort_unstable();
+            indices.dedup();
+            let ranges = ranges(&indices);
+            for ranges in ranges.chunks(MAX_ACK_RANGES) {
+                let ack = TransferPacket::Ack {
+                    frame: transfer.frame,
+                    checksum: transfer.checksum,
+                    ranges: ranges.to_vec(),
+                };
+                let ack = bincode::serialize(&ack).expect("should serialize");
+                self.socket.send_on(self.channel, &ack, &transfer.peer);
+            }
+        }
+    }
+
+    fn send_chunks(&mut self, now: Instant) {
+        let resend_interval = self.resend_interval;
+        let in_flight = |sent: &Option<Instant>| {
+            sent.map_or(false, |sent| now.duration_since(sent) < resend_interval)
+        };
+        for transfer in self.outgoing.iter_mut() {
+            let mut window = self.window.saturating_sub(
+                transfer
+                    .sent
+                    .iter()
+                    .zip(transfer.acked.iter())
+                    .filter(|(sent, acked)| !**acked && in_flight(sent))
+                    .count(),
+            );
+            for index in 0..transfer.acked.len() {
+                if window == 0 {
+                    break;
+                }
+                if transfer.acked[index] || in_flight(&transfer.sent[index]) {
+                    continue;
+                }
+                let start = index * CHUNK_SIZE;
+                let data = &transfer.state[start..start + chunk_len(transfer.state.len(), index)];
+                let packet = TransferPacket::Chunk {
+                    frame: transfer.frame,
+                    checksum: transfer.checksum,
+                    size: transfer.state.len() as u64,
+                    index: index as u32,
+                    chunk_checksum: checksum(data),
+                    data: data.to_vec(),
+                };
+                let packet = bincode::serialize(&packet).expect("should serialize");
+                self.socket.send_on(self.channel, &packet, &transfer.peer);
+                transfer.sent[index] = Some(now);
+                window -= 1;
+            }
+        }
+    }
+
+    /// Reports progress, assembles complete states and gives up on stalled transfers.
+    fn finish_transfers(&mut self, now: Instant) {
+        let events = &mut self.events;
+
+        self.outgoing.retain_mut(|transfer| {
+            let (peer, frame) = (transfer.peer.clone(), transfer.frame);
+            let direction = TransferDirection::Sending;
+            if transfer.acked_bytes != transfer.reported_bytes {
+                transfer.reported_bytes = transfer.acked_bytes;
+                events.push(StateTransferEvent::Progress {
+                    peer: peer.clone(),
+                    direction,
+                    frame,
+                    bytes: transfer.acked_bytes,
+                    total: transfer.state.len(),
+                });
+            }
+            if transfer.acked.iter().all(|acked| *acked) {
+                events.push(StateTransferEvent::Completed {
+                    peer,
+                    direction,
+                    frame,
+                });
+                false
+            } else if now.duration_since(transfer.last_progress) >= STALL_TIMEOUT {
+                warn!("state transfer: gave up sending the state of frame {frame}, the peer stopped responding");
+                events.push(StateTransferEvent::Failed {
+                    peer,
+                    direction,
+                    frame,
+                });
+                false
+            } else {
+                true
+            }
+        });
+
+        let mut finished = Vec::new();
+        self.incoming.retain_mut(|transfer| {
+            let (peer, frame) = (transfer.peer.clone(), transfer.frame);
+            let direction = TransferDirection::Receiving;
+            if transfer.received_bytes != transfer.reported_bytes {
+                transfer.reported_bytes = transfer.received_bytes;
+                events.push(StateTransferEvent::Progress {
+                    peer: peer.clone(),
+                    direction,
+                    frame,
+                    bytes: transfer.received_bytes,
+                    total: transfer.size,
+                });
+            }
+            if transfer.chunks.iter().all(|chunk| chunk.is_some()) {
+                let data: Vec<u8> = transfer.chunks.drain(..).flatten().flatten().collect();
+                if checksum(&data) == transfer.checksum {
+                    events.push(StateTransferEvent::Completed {
+                        peer: peer.clone(),
+                        direction,
+                        frame,
+                    });
+                    finished.push(ReceivedState { peer, frame, data });
+                } else {
+                    warn!("state transfer: the state of frame {frame} doesn't match its checksum");
+                    events.push(StateTransferEvent::Failed {
+                        peer,
+                        direction,
+                        frame,
+                    });
+                }
+                false
+            } else if now.duration_since(transfer.last_progress) >= STALL_TIMEOUT {
+                warn!("state transfer: gave up receiving the state of frame {frame}, the peer stopped sending");
+                events.push(StateTransferEvent::Failed {
+                    peer,
+                    direction,
+                    frame,
+                });
+                false
+            } else {
+                true
+            }
+        });
+
+        for state in finished {
+            let checksum = checksum(&state.data);
+            self.completed.retain(|(peer, _, _)| *peer != state.peer);
+            self.completed
+                .push((state.peer.clone(), state.frame, checksum));
+            self.received.push(state);
+        }
+    }
+
+    pub(crate) fn take_events(&mut self) -> Vec<StateTransferEvent<A>> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Returns the memory held by the states being sent and received, in bytes.
+    pub(crate) fn memory_size(&self) -> usize {
+        let outgoing: usize = self
+            .outgoing
+            .iter()
+            .map(|transfer| {
+                transfer.state.capacity()
+                    + transfer.acked.capacity()
+                    + transfer.sent.capacity() * std::mem::size_of::<Option<Instant>>()
+            })
+            .sum();
+        let incoming: usize = self
+            .incoming
+            .iter()
+            .map(|transfer| {
+                let chunks: usize = transfer.chunks.iter().flatten().map(Vec::capacity).sum();
+                chunks
+                    + transfer.chunks.capacity() * std::mem::size_of::<Option<Vec<u8>>>()
+                    + transfer.unacked.capacity() * std::mem::size_of::<u32>()
+            })
+            .sum();
+        let received: usize = self
+            .received
+            .iter()
+            .map(|state| state.data.capacity())
+            .sum();
+        outgoing + incoming + received
+    }
+}
+
+fn chunk_count(size: usize) -> usize {
+    ((size + CHUNK_SIZE - 1) / CHUNK_SIZE).max(1)
+}
+
+fn chunk_len(size: usize, index: usize) -> usize {
+    size.saturating_sub(index * CHUNK_SIZE).min(CHUNK_SIZE)
+}
+
+/// Merges sorted, distinct indices into half-open ranges.
+fn ranges(indices: &[u32]) -> Vec<(u32, u32)> {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for index in indices {
+        match ranges.last_mut() {
+            Some((_, end)) if *end == *index => *end += 1,
+            _ => ranges.push((*index, *index + 1)),
+        }
+    }
+    ranges
+}
+
+/// 64 bit FNV-1a, identical on every platform.
+pub(crate) fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub(crate) fn poll_state_transfer_system<A: Clone + PartialEq + Send + Sync + 'static>(
+    transfer: Option<ResMut<StateTransfer<A>>>,
+    recovery: Option<ResMut<DesyncRecovery<A>>>,
+    mut events: EventWriter<StateTransferEvent<A>>,
+) {
+    if let Some(mut transfer) = transfer {
+        transfer.poll();
+        events.send_batch(transfer.take_events());
+    }
+    // the recovery polls its own transfer from within the GGRS stage
+    if let Some(mut recovery) = recovery {
+        events.send_batch(recovery.take_transfer_events());
+    }
+}
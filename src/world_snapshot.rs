@@ -1,12 +1,27 @@
 use bevy::{
     ecs::{entity::EntityMap, reflect::ReflectMapEntities},
     prelude::*,
-    reflect::{Reflect, TypeRegistry},
-    utils::HashMap,
+    reflect::{
+        serde::{ReflectSerializer, UntypedReflectDeserializer},
+        Reflect, ReflectRef, TypeRegistry, TypeRegistryInternal,
+    },
+    utils::{HashMap, HashSet},
 };
+use instant::{Duration, Instant};
+use serde::{de::DeserializeSeed, Deserialize, Serialize};
 use std::{fmt::Debug, num::Wrapping};
 
-use crate::Rollback;
+use crate::{
+    prewarm::{Prewarmed, SnapshotCapacity},
+    state_transfer, Rollback,
+};
+
+/// Time spent saving or restoring each registered type, and the number and size of its saved values, by type name.
+#[derive(Default)]
+pub(crate) struct Measurements {
+    pub time: HashMap<&'static str, Duration>,
+    pub sizes: HashMap<&'static str, (usize, usize)>,
+}
 
 /// Maps rollback_ids to entity id+generation. Necessary to track entities over time.
 fn rollback_id_map(world: &mut World) -> HashMap<u32, Entity> {
@@ -45,6 +60,15 @@ impl Debug for RollbackEntity {
     }
 }
 
+/// The serialized form of a `WorldSnapshot`. Every reflected value is stored as its own RON string, since the
+/// reflection deserializer needs a self-describing format.
+#[derive(Serialize, Deserialize)]
+struct SerializedSnapshot {
+    entities: Vec<(u64, u32, Vec<String>)>,
+    resources: Vec<String>,
+    checksum: u64,
+}
+
 /// Holds registered components of `Rollback` tagged entities, as well as registered resources to save and load from/to the real bevy world.
 /// The `checksum` is the sum of hash-values from all hashable objects. It is a sum for the checksum to be order insensitive. This of course
 /// is not the best checksum to ever exist, but it is a starting point.
@@ -53,35 +77,74 @@ pub(crate) struct WorldSnapshot {
     entities: Vec<RollbackEntity>,
     pub resources: Vec<Box<dyn Reflect>>,
     pub checksum: u64,
+    /// estimated size of the saved values, including what they own on the heap
+    values_size: usize,
 }
 
 impl WorldSnapshot {
     pub(crate) fn from_world(world: &World, type_registry: &TypeRegistry) -> Self {
-        let mut snapshot = WorldSnapshot::default();
+        Self::from_world_timed(
+            world,
+            type_registry,
+            SnapshotCapacity::default(),
+            false,
+            None,
+        )
+    }
+
+    /// Like `from_world()`, but reserves space for the entities and resources of `capacity` upfront and measures
+    /// the time spent on each type. The size of the saved values is only estimated with `measure_sizes`, since that
+    /// walks every saved value once more; `size()` leaves them out otherwise.
+    pub(crate) fn from_world_timed(
+        world: &World,
+        type_registry: &TypeRegistry,
+        capacity: SnapshotCapacity,
+        measure_sizes: bool,
+        mut measurements: Option<&mut Measurements>,
+    ) -> Self {
+        let mut snapshot = WorldSnapshot {
+            entities: Vec::with_capacity(capacity.entities),
+            resources: Vec::with_capacity(capacity.resources),
+            ..Default::default()
+        };
         let type_registry = type_registry.read();
+        let mut ids = HashMap::with_capacity(capacity.entities);
+        let prewarmed = world.components().component_id::<Prewarmed>();
 
         // create a `RollbackEntity` for every entity tagged with rollback
         for archetype in world.archetypes().iter() {
+            if prewarmed.map_or(false, |prewarmed| archetype.contains(prewarmed)) {
+                continue;
+            }
             let entities_offset = snapshot.entities.len();
             for entity in archetype.entities() {
                 let entity = entity.entity();
                 if let Some(rollback) = world.get::<Rollback>(entity) {
+                    if let Some(other) = ids.insert(rollback.id, entity) {
+                        panic!(
+                            "the entities {other:?} and {entity:?} have the same rollback id {:#x}, derive one of them \
+                             from another name or index",
+                            rollback.id
+                        );
+                    }
                     snapshot.entities.push(RollbackEntity {
                         entity,
                         rollback_id: rollback.id,
-                        components: Vec::new(),
+                        components: Vec::with_capacity(archetype.components().len()),
                     });
                 }
             }
 
             // fill the component vectors of rollback entities
             for component_id in archetype.components() {
-                let reflect_component = world
+                let registration = world
                     .components()
                     .get_info(component_id)
-                    .and_then(|info| type_registry.get(info.type_id().unwrap()))
-                    .and_then(|registration| registration.data::<ReflectComponent>());
+                    .and_then(|info| type_registry.get(info.type_id().unwrap()));
+                let reflect_component =
+                    registration.and_then(|registration| registration.data::<ReflectComponent>());
                 if let Some(reflect_component) = reflect_component {
+                    let start = measurements.is_some().then(Instant::now);
                     for (i, entity) in archetype
                         .entities()
                         .iter()
@@ -97,39 +160,350 @@ impl WorldSnapshot {
                                 snapshot.checksum =
                                     (Wrapping(snapshot.checksum) + Wrapping(hash)).0;
                             }
+                            let value_size = if measure_sizes {
+                                value_size(component)
+                            } else {
+                                0
+                            };
+                            if let (Some(measurements), Some(registration)) =
+                                (measurements.as_deref_mut(), registration)
+                            {
+                                let size = measurements
+                                    .sizes
+                                    .entry(registration.type_name())
+                                    .or_default();
+                                size.0 += 1;
+                                size.1 += value_size;
+                            }
                             // add the component to the shapshot
+                            snapshot.values_size += value_size;
                             snapshot.entities[entities_offset + i]
                                 .components
                                 .push(component.clone_value());
                         }
                     }
+                    if let (Some(measurements), Some(start), Some(registration)) =
+                        (measurements.as_deref_mut(), start, registration)
+                    {
+                        *measurements
+                            .time
+                            .entry(registration.type_name())
+                            .or_default() += start.elapsed();
+                    }
                 }
             }
         }
 
         // go through all resources and clone those that are registered
         for (component_id, _) in world.storages().resources.iter() {
-            let reflect_component = world
+            let registration = world
                 .components()
                 .get_info(component_id)
-                .and_then(|info| type_registry.get(info.type_id().unwrap()))
-                .and_then(|registration| registration.data::<ReflectResource>());
+                .and_then(|info| type_registry.get(info.type_id().unwrap()));
+            let reflect_component =
+                registration.and_then(|registration| registration.data::<ReflectResource>());
             if let Some(reflect_resource) = reflect_component {
+                let start = measurements.is_some().then(Instant::now);
                 if let Some(resource) = reflect_resource.reflect(world) {
                     // add the hash value of that resource to the shapshot checksum, if that resource supports hashing
                     if let Some(hash) = resource.reflect_hash() {
                         snapshot.checksum = (Wrapping(snapshot.checksum) + Wrapping(hash)).0;
                     }
+                    let value_size = if measure_sizes {
+                        value_size(resource)
+                    } else {
+                        0
+                    };
+                    if let (Some(measurements), Some(registration)) =
+                        (measurements.as_deref_mut(), registration)
+                    {
+                        let size = measurements
+                            .sizes
+                            .entry(registration.type_name())
+                            .or_default();
+                        size.0 += 1;
+                        size.1 += value_size;
+                    }
                     // add the resource to the shapshot
+                    snapshot.values_size += value_size;
                     snapshot.resources.push(resource.clone_value());
                 }
+                if let (Some(measurements), Some(start), Some(registration)) =
+                    (measurements.as_deref_mut(), start, registration)
+                {
+                    *measurements
+                        .time
+                        .entry(registration.type_name())
+                        .or_default() += start.elapsed();
+                }
             }
         }
 
         snapshot
     }
 
-    pub(crate) fn write_to_world(&self, world: &mut World, type_registry: &TypeRegistry) {
+    /// Describes how this snapshot differs from `other`, one line per difference.
+    pub(crate) fn diff(&self, other: &WorldSnapshot, type_registry: &TypeRegistry) -> Vec<String> {
+        let type_registry = type_registry.read();
+        let (ids, other_ids) = (self.rollback_ids(), other.rollback_ids());
+        // values that can't be compared through reflection are compared by their description
+        let equal = |value: &dyn Reflect, other: &dyn Reflect| {
+            value.reflect_partial_eq(other).unwrap_or_else(|| {
+                describe_value(value, &ids, &type_registry)
+                    == describe_value(other, &other_ids, &type_registry)
+            })
+        };
+        let mut differences = Vec::new();
+
+        for entity in self.entities.iter() {
+            let Some(other_entity) = other
+                .entities
+                .iter()
+                .find(|e| e.rollback_id == entity.rollback_id)
+            else {
+                differences.push(format!("rollback entity {} is missing", entity.rollback_id));
+                continue;
+            };
+            let prefix = format!("rollback entity {}", entity.rollback_id);
+            diff_values(
+                &prefix,
+                &entity.components,
+                &other_entity.components,
+                &equal,
+                &mut differences,
+            );
+        }
+        for entity in other.entities.iter() {
+            if !self
+                .entities
+                .iter()
+                .any(|e| e.rollback_id == entity.rollback_id)
+            {
+                differences.push(format!("rollback entity {} is new", entity.rollback_id));
+            }
+        }
+
+        diff_values(
+            "resources",
+            &self.resources,
+            &other.resources,
+            &equal,
+            &mut differences,
+        );
+        differences
+    }
+
+    /// Returns the names of all types whose values differ between this snapshot and `previous`.
+    pub(crate) fn changed_types<'a>(&'a self, previous: &'a WorldSnapshot) -> HashSet<&'a str> {
+        let mut changed = HashSet::default();
+        let mut compare = |values: &'a [Box<dyn Reflect>],
+                           previous_values: &'a [Box<dyn Reflect>]| {
+            for value in values.iter() {
+                let previous_value = previous_values
+                    .iter()
+                    .find(|previous| previous.type_name() == value.type_name());
+                let unchanged = previous_value
+                    .and_then(|previous| value.reflect_partial_eq(&**previous))
+                    .unwrap_or(false);
+                if !unchanged {
+                    changed.insert(value.type_name());
+                }
+            }
+            // removed values count as a change as well
+            for previous_value in previous_values.iter() {
+                if !values
+                    .iter()
+                    .any(|v| v.type_name() == previous_value.type_name())
+                {
+                    changed.insert(previous_value.type_name());
+                }
+            }
+        };
+
+        for entity in self.entities.iter() {
+            match previous
+                .entities
+                .iter()
+                .find(|e| e.rollback_id == entity.rollback_id)
+            {
+                Some(previous_entity) => compare(&entity.components, &previous_entity.components),
+                None => compare(&entity.components, &[]),
+            }
+        }
+        for previous_entity in previous.entities.iter() {
+            if !self
+                .entities
+                .iter()
+                .any(|e| e.rollback_id == previous_entity.rollback_id)
+            {
+                compare(&[], &previous_entity.components);
+            }
+        }
+        compare(&self.resources, &previous.resources);
+        changed
+    }
+
+    /// Serializes the snapshot, so it can be sent to other peers. Fails if a registered type can't be serialized.
+    pub(crate) fn to_bytes(&self, type_registry: &TypeRegistry) -> Result<Vec<u8>, String> {
+        let type_registry = type_registry.read();
+
+        let mut serialized = SerializedSnapshot {
+            entities: Vec::new(),
+            resources: Vec::new(),
+            checksum: self.checksum,
+        };
+        for entity in self.entities.iter() {
+            let components = entity
+                .components
+                .iter()
+                .map(|value| serialize_value(&**value, &type_registry))
+                .collect::<Result<_, _>>()?;
+            serialized
+                .entities
+                .push((entity.entity.to_bits(), entity.rollback_id, components));
+        }
+        serialized.resources = self
+            .resources
+            .iter()
+            .map(|value| serialize_value(&**value, &type_registry))
+            .collect::<Result<_, _>>()?;
+
+        bincode::serialize(&serialized).map_err(|e| e.to_string())
+    }
+
+    /// Describes the snapshot in human readable form, one line per resource and entity. Values that can't be
+    /// serialized are described with their debug representation instead.
+    pub(crate) fn to_text(&self, type_registry: &TypeRegistry) -> String {
+        let type_registry = type_registry.read();
+        let describe = |value: &dyn Reflect| {
+            serialize_value(value, &type_registry).unwrap_or_else(|_| format!("{value:?}"))
+        };
+
+        let mut text = String::new();
+        for resource in self.resources.iter() {
+            text.push_str(&format!("resource {}\n", describe(&**resource)));
+        }
+        for entity in self.entities.iter() {
+            text.push_str(&format!("entity {}\n", entity.rollback_id));
+            for component in entity.components.iter() {
+                text.push_str(&format!("    {}\n", describe(&**component)));
+            }
+        }
+        text
+    }
+
+    /// Maps the entities of the snapshot to their rollback ids.
+    fn rollback_ids(&self) -> HashMap<Entity, u32> {
+        self.entities
+            .iter()
+            .map(|entity| (entity.entity, entity.rollback_id))
+            .collect()
+    }
+
+    /// Checksums the contents of the snapshot regardless of the order of entities and components. Unlike `checksum`,
+    /// it also covers values that can't be hashed. Entities are identified by their rollback id, also where
+    /// components refer to them, like `Parent` and `Children` do.
+    pub(crate) fn content_checksum(&self, type_registry: &TypeRegistry) -> u64 {
+        let type_registry = type_registry.read();
+        let ids = self.rollback_ids();
+        let describe = |value: &dyn Reflect| describe_value(value, &ids, &type_registry);
+
+        let mut lines: Vec<String> = self
+            .resources
+            .iter()
+            .map(|resource| format!("resource {}", describe(&**resource)))
+            .collect();
+        for entity in self.entities.iter() {
+            let mut components: Vec<String> = entity
+                .components
+                .iter()
+                .map(|component| describe(&**component))
+                .collect();
+            components.sort();
+            lines.push(format!(
+                "entity {} {}",
+                entity.rollback_id,
+                components.join(" ")
+            ));
+        }
+        lines.sort();
+        state_transfer::checksum(lines.join("\n").as_bytes())
+    }
+
+    /// Restores a snapshot serialized with `to_bytes()`. The values are dynamic representations of the registered
+    /// types. They can be written to the world, but don't provide hashes for the checksum.
+    pub(crate) fn from_bytes(bytes: &[u8], type_registry: &TypeRegistry) -> Result<Self, String> {
+        let type_registry = type_registry.read();
+        let serialized: SerializedSnapshot =
+            bincode::deserialize(bytes).map_err(|e| e.to_string())?;
+
+        let mut snapshot = WorldSnapshot {
+            checksum: serialized.checksum,
+            ..Default::default()
+        };
+        for (entity, rollback_id, components) in serialized.entities {
+            let components: Vec<Box<dyn Reflect>> = components
+                .iter()
+                .map(|value| deserialize_value(value, &type_registry))
+                .collect::<Result<_, _>>()?;
+            snapshot.values_size += components
+                .iter()
+                .map(|component| value_size(&**component))
+                .sum::<usize>();
+            snapshot.entities.push(RollbackEntity {
+                entity: Entity::from_bits(entity),
+                rollback_id,
+                components,
+            });
+        }
+        snapshot.resources = serialized
+            .resources
+            .iter()
+            .map(|value| deserialize_value(value, &type_registry))
+            .collect::<Result<_, _>>()?;
+        snapshot.values_size += snapshot
+            .resources
+            .iter()
+            .map(|resource| value_size(&**resource))
+            .sum::<usize>();
+        Ok(snapshot)
+    }
+
+    /// Returns the number of rollback entities in this snapshot.
+    pub(crate) fn entity_count(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Returns the space this snapshot needed, so the next one can reserve it upfront.
+    pub(crate) fn capacity(&self) -> SnapshotCapacity {
+        SnapshotCapacity {
+            entities: self.entities.len(),
+            resources: self.resources.len(),
+        }
+    }
+
+    /// Returns an estimate of the memory held by this snapshot, in bytes, see `value_size()`. The saved values are
+    /// only included if their sizes were measured when the snapshot was taken.
+    pub(crate) fn size(&self) -> usize {
+        let entities: usize = self
+            .entities
+            .iter()
+            .map(|entity| entity.components.capacity() * std::mem::size_of::<Box<dyn Reflect>>())
+            .sum();
+        std::mem::size_of::<Self>()
+            + self.entities.capacity() * std::mem::size_of::<RollbackEntity>()
+            + entities
+            + self.resources.capacity() * std::mem::size_of::<Box<dyn Reflect>>()
+            + self.values_size
+    }
+
+    /// Writes the snapshot to the world, measuring the time spent on each type if `measurements` is given.
+    pub(crate) fn write_to_world(
+        &self,
+        world: &mut World,
+        type_registry: &TypeRegistry,
+        mut measurements: Option<&mut Measurements>,
+    ) {
         let type_registry = type_registry.read();
         let mut rid_map = rollback_id_map(world);
 
@@ -158,6 +532,7 @@ impl WorldSnapshot {
                 let Some(reflect_component) = registration.data::<ReflectComponent>() else {
                     continue;
                 };
+                let start = measurements.is_some().then(Instant::now);
 
                 if world.entity(entity).contains_type_id(type_id) {
                     // the entity in the world has such a component
@@ -192,6 +567,12 @@ impl WorldSnapshot {
                     }
                     // if both the snapshot and the world does not have the registered component, we don't need to to anything
                 }
+                if let (Some(measurements), Some(start)) = (measurements.as_deref_mut(), start) {
+                    *measurements
+                        .time
+                        .entry(registration.type_name())
+                        .or_default() += start.elapsed();
+                }
             }
 
             // afterwards, remove the pair from the map (leftover entities will need to be despawned)
@@ -211,6 +592,7 @@ impl WorldSnapshot {
                     continue;
                 }
             };
+            let start = measurements.is_some().then(Instant::now);
 
             match reflect_resource.reflect(world) {
                 // the world has such a resource
@@ -242,6 +624,12 @@ impl WorldSnapshot {
                     // if both the world and the snapshot does not have this resource, do nothing
                 }
             }
+            if let (Some(measurements), Some(start)) = (measurements.as_deref_mut(), start) {
+                *measurements
+                    .time
+                    .entry(registration.type_name())
+                    .or_default() += start.elapsed();
+            }
         }
 
         // For every type that reflects `MapEntities`, map the entities so that they reference the
@@ -257,3 +645,135 @@ impl WorldSnapshot {
         }
     }
 }
+
+fn serialize_value(
+    value: &dyn Reflect,
+    type_registry: &TypeRegistryInternal,
+) -> Result<String, String> {
+    ron::to_string(&ReflectSerializer::new(value, type_registry))
+        .map_err(|e| format!("failed to serialize {}: {e}", value.type_name()))
+}
+
+/// Describes a value field by field, so concrete values and their dynamic representations restored by
+/// `from_bytes()` read the same. Entities are written as their rollback id, and map entries are sorted. Only plain
+/// values are serialized, or formatted with `Debug` if their type isn't registered.
+fn describe_value(
+    value: &dyn Reflect,
+    ids: &HashMap<Entity, u32>,
+    type_registry: &TypeRegistryInternal,
+) -> String {
+    let describe = |value: &dyn Reflect| describe_value(value, ids, type_registry);
+    let fields: Vec<String> = match value.reflect_ref() {
+        ReflectRef::Struct(value) => (0..value.field_len())
+            .map(|i| {
+                let name = value.name_at(i).unwrap_or_default();
+                format!("{name}: {}", describe(value.field_at(i).unwrap()))
+            })
+            .collect(),
+        ReflectRef::TupleStruct(value) => (0..value.field_len())
+            .map(|i| describe(value.field(i).unwrap()))
+            .collect(),
+        ReflectRef::Tuple(value) => (0..value.field_len())
+            .map(|i| describe(value.field(i).unwrap()))
+            .collect(),
+        ReflectRef::List(value) => value.iter().map(describe).collect(),
+        ReflectRef::Array(value) => value.iter().map(describe).collect(),
+        ReflectRef::Map(value) => {
+            let mut entries: Vec<String> = value
+                .iter()
+                .map(|(key, value)| format!("{}: {}", describe(key), describe(value)))
+                .collect();
+            entries.sort();
+            entries
+        }
+        ReflectRef::Enum(value) => {
+            let fields = value.iter_fields().map(|field| match field.name() {
+                Some(name) => format!("{name}: {}", describe(field.value())),
+                None => describe(field.value()),
+            });
+            std::iter::once(value.variant_name().to_string())
+                .chain(fields)
+                .collect()
+        }
+        ReflectRef::Value(value) => {
+            if let Some(entity) = value.downcast_ref::<Entity>() {
+                return match ids.get(entity) {
+                    Some(id) => format!("rollback entity {id}"),
+                    None => "entity outside the rollback world".to_string(),
+                };
+            }
+            return serialize_value(value, type_registry).unwrap_or_else(|_| format!("{value:?}"));
+        }
+    };
+    format!("{}({})", value.type_name(), fields.join(", "))
+}
+
+/// Estimates the memory held by a value, in bytes: its own size and, field by field, what it owns on the heap.
+/// Lists, maps and strings count their elements, but not the spare capacity of lists and maps, and other values
+/// are counted without the heap allocations they might hide, such as the contents of a `Box`.
+pub(crate) fn value_size(value: &dyn Reflect) -> usize {
+    std::mem::size_of_val(value) + owned_size(value)
+}
+
+/// The part of `value_size()` that lives outside of the value itself.
+fn owned_size(value: &dyn Reflect) -> usize {
+    match value.reflect_ref() {
+        ReflectRef::Struct(value) => value.iter_fields().map(owned_size).sum(),
+        ReflectRef::TupleStruct(value) => value.iter_fields().map(owned_size).sum(),
+        ReflectRef::Tuple(value) => value.iter_fields().map(owned_size).sum(),
+        ReflectRef::List(value) => value.iter().map(value_size).sum(),
+        ReflectRef::Array(value) => value.iter().map(owned_size).sum(),
+        ReflectRef::Map(value) => value
+            .iter()
+            .map(|(key, value)| value_size(key) + value_size(value))
+            .sum(),
+        ReflectRef::Enum(value) => value
+            .iter_fields()
+            .map(|field| owned_size(field.value()))
+            .sum(),
+        ReflectRef::Value(value) => value.downcast_ref::<String>().map_or(0, String::capacity),
+    }
+}
+
+fn deserialize_value(
+    value: &str,
+    type_registry: &TypeRegistryInternal,
+) -> Result<Box<dyn Reflect>, String> {
+    let mut deserializer = ron::Deserializer::from_str(value).map_err(|e| e.to_string())?;
+    UntypedReflectDeserializer::new(type_registry)
+        .deserialize(&mut deserializer)
+        .map_err(|e| e.to_string())
+}
+
+/// Compares two lists of reflected values by type name and records the differences.
+fn diff_values(
+    prefix: &str,
+    values: &[Box<dyn Reflect>],
+    other_values: &[Box<dyn Reflect>],
+    equal: impl Fn(&dyn Reflect, &dyn Reflect) -> bool,
+    differences: &mut Vec<String>,
+) {
+    for value in values.iter() {
+        match other_values
+            .iter()
+            .find(|other| other.type_name() == value.type_name())
+        {
+            Some(other) => {
+                if !equal(&**value, &**other) {
+                    differences.push(format!(
+                        "{prefix}: {} changed from {:?} to {:?}",
+                        value.type_name(),
+                        value,
+                        other
+                    ));
+                }
+            }
+            None => differences.push(format!("{prefix}: {} was removed", value.type_name())),
+        }
+    }
+    for other in other_values.iter() {
+        if !values.iter().any(|v| v.type_name() == other.type_name()) {
+            differences.push(format!("{prefix}: {} was added", other.type_name()));
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// Frames to spawn the task in and deliver it at.
+#[derive(Resource, Clone, Copy)]
+struct Timing(i32, i32);
+
+/// Not rolled back: everything seen by any simulation of a frame.
+#[derive(Resource, Default)]
+struct Observed {
+    spawned: usize,
+    /// the frame and results of every simulated frame
+    polls: Vec<(i32, Vec<(u64, u32)>)>,
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn gateway_system(
+    frame: Res<RollbackFrame>,
+    timing: Res<Timing>,
+    mut gateway: ResMut<AsyncGateway<u32>>,
+    results: Res<AsyncResults<u32>>,
+    mut observed: ResMut<Observed>,
+) {
+    let Timing(spawn_at, deliver_at) = *timing;
+    if **frame == spawn_at && gateway.spawn(7, deliver_at, async { 42 }) {
+        observed.spawned += 1;
+    }
+    let results = results.iter().map(|(id, result)| (id, *result)).collect();
+    observed.polls.push((**frame, results));
+}
+
+fn run(timing: Timing) -> Observed {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(timing)
+        .init_resource::<Observed>()
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_async_gateway::<u32>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(gateway_system),
+        ))
+        .build(&mut app);
+
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+    assert_eq!(app.world.resource::<AsyncGateway<u32>>().pending(), 0);
+    app.world.remove_resource::<Observed>().unwrap()
+}
+
+/// This test makes sure that resimulating the frames of the spawn and the delivery neither starts the task again
+/// nor delivers the result more than once per simulation of the delivery frame.
+#[test]
+fn resimulated_tasks_are_delivered_once_per_frame() {
+    let observed = run(Timing(2, 6));
+
+    assert_eq!(observed.spawned, 1);
+    let delivering: Vec<_> = observed
+        .polls
+        .iter()
+        .filter(|(_, results)| !results.is_empty())
+        .collect();
+    // the sync test simulates the delivery frame several times, always with the same single result
+    assert!(delivering.len() > 1);
+    for (frame, results) in delivering {
+        assert_eq!(*frame, 6);
+        assert_eq!(results, &vec![(7, 42)]);
+    }
+}
+
+/// This test makes sure that a task spawned in its own delivery frame, which has already been handed its results,
+/// is dropped instead of being delivered at a later frame.
+#[test]
+fn late_tasks_are_dropped() {
+    let observed = run(Timing(4, 4));
+
+    // every simulation of frame 4 spawns it again, since it is gone once dropped
+    assert!(observed.spawned > 0);
+    assert!(observed.polls.iter().all(|(_, results)| results.is_empty()));
+}
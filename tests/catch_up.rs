@@ -0,0 +1,88 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::{Duration, Instant};
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn app(catch_up: CatchUp) -> App {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_catch_up(catch_up)
+        .with_input_system(input_system)
+        .build(&mut app);
+
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+    app
+}
+
+fn frame(app: &App) -> i32 {
+    **app.world.resource::<RollbackFrame>()
+}
+
+/// Falls behind by about 12 frames and returns the number of frames simulated in the next update.
+fn hitch(app: &mut App) -> i32 {
+    let before = frame(app);
+    std::thread::sleep(Duration::from_millis(200));
+    app.update();
+    frame(app) - before
+}
+
+#[test]
+fn burst_simulates_missed_frames_at_once() {
+    let mut app = app(CatchUp::Burst);
+    assert!(hitch(&mut app) >= 10);
+}
+
+#[test]
+fn dilation_spreads_missed_frames_over_the_next_updates() {
+    let mut app = app(CatchUp::Dilate {
+        rate: 2.,
+        max_backlog: 30,
+    });
+    assert!(hitch(&mut app) <= 3);
+
+    // at twice the speed, every update makes up for one of the 10 frames behind
+    let start = Instant::now();
+    let before = frame(&app);
+    for _ in 0..30 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+    let real_time = (start.elapsed().as_secs_f64() * 60.) as i32;
+    assert!(frame(&app) - before >= real_time + 6);
+}
+
+#[test]
+fn dilation_bursts_beyond_the_max_backlog() {
+    let mut app = app(CatchUp::Dilate {
+        rate: 62. / 60.,
+        max_backlog: 2,
+    });
+    assert!(hitch(&mut app) >= 8);
+}
@@ -0,0 +1,118 @@
+//! The in-memory network the tests connect their peers with. Every test file only uses some of it.
+#![allow(dead_code)]
+
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+use bevy_ggrs::*;
+
+pub type Queue = Arc<Mutex<Vec<(usize, Vec<u8>)>>>;
+
+/// The datagrams one end of a `Link` sent so far.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Traffic {
+    /// Number of datagrams, including the dropped ones.
+    pub sent: usize,
+    /// Size of the largest datagram in bytes.
+    pub largest: usize,
+}
+
+/// The peer at `addr` of an in-memory network, where every address has an inbox. Datagrams to addresses outside
+/// the network are dropped.
+pub struct Link {
+    addr: usize,
+    inboxes: Vec<Queue>,
+    traffic: Arc<Mutex<Traffic>>,
+    drop_every: usize,
+    corrupt_every: usize,
+}
+
+impl Link {
+    /// Returns the links of the addresses 0 to `count - 1`.
+    pub fn network(count: usize) -> Vec<Link> {
+        let inboxes: Vec<Queue> = (0..count).map(|_| Queue::default()).collect();
+        (0..count)
+            .map(|addr| Link {
+                addr,
+                inboxes: inboxes.clone(),
+                traffic: Arc::default(),
+                drop_every: 0,
+                corrupt_every: 0,
+            })
+            .collect()
+    }
+
+    /// Drops every `n`th datagram this end sends.
+    pub fn dropping_every(mut self, n: usize) -> Self {
+        self.drop_every = n;
+        self
+    }
+
+    /// Flips the last byte of every `n`th datagram this end sends.
+    pub fn corrupting_every(mut self, n: usize) -> Self {
+        self.corrupt_every = n;
+        self
+    }
+
+    /// Returns the traffic this end sent, which keeps counting once the link is handed to a socket.
+    pub fn traffic(&self) -> Arc<Mutex<Traffic>> {
+        self.traffic.clone()
+    }
+
+    /// Returns the datagrams on their way to this end, to inspect them or to add some from an address of our
+    /// choice.
+    pub fn inbox(&self) -> Queue {
+        self.inboxes[self.addr].clone()
+    }
+}
+
+impl DatagramSocket<usize> for Link {
+    fn send_datagram(&mut self, data: &[u8], addr: &usize) {
+        let sent = {
+            let mut traffic = self.traffic.lock();
+            traffic.sent += 1;
+            traffic.largest = traffic.largest.max(data.len());
+            traffic.sent
+        };
+        if self.drop_every > 0 && sent % self.drop_every == 0 {
+            return;
+        }
+        let mut data = data.to_vec();
+        if self.corrupt_every > 0 && sent % self.corrupt_every == 0 {
+            if let Some(last) = data.last_mut() {
+                *last ^= 0xff;
+            }
+        }
+        if let Some(inbox) = self.inboxes.get(*addr) {
+            inbox.lock().push((self.addr, data));
+        }
+    }
+    fn receive_datagrams(&mut self) -> Vec<(usize, Vec<u8>)> {
+        std::mem::take(&mut *self.inboxes[self.addr].lock())
+    }
+}
+
+/// Returns the sockets of the addresses 0 to `count - 1`.
+pub fn network(count: usize) -> Vec<MultiplexSocket<usize>> {
+    Link::network(count)
+        .into_iter()
+        .map(MultiplexSocket::new)
+        .collect()
+}
+
+/// Returns the sockets of peer 0 and 1.
+pub fn sockets() -> (MultiplexSocket<usize>, MultiplexSocket<usize>) {
+    let mut sockets = network(2);
+    let b = sockets.pop().unwrap();
+    let a = sockets.pop().unwrap();
+    (a, b)
+}
+
+/// A transport without any other peers.
+pub struct NoTransport;
+impl DatagramSocket<usize> for NoTransport {
+    fn send_datagram(&mut self, _: &[u8], _: &usize) {}
+    fn receive_datagrams(&mut self) -> Vec<(usize, Vec<u8>)> {
+        Vec::new()
+    }
+}
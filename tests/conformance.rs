@@ -0,0 +1,61 @@
+use bevy::{prelude::*, scene::DynamicEntity};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use bevy_ggrs::*;
+
+/// A value that can't be hashed, so it is left out of the checksums GGRS compares.
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+struct Label(f32);
+
+/// This test makes sure that two peers agree on every confirmed frame while they spawn, damage and despawn actors
+/// across deep rollbacks.
+#[test]
+fn standard_scenario_conforms() {
+    let report = ConformanceScenario::standard().with_latency(6).run();
+
+    assert_eq!(report.frames_compared, 240);
+    assert!(report.rollbacks > 0);
+    assert!(report.max_rollback_depth >= 2);
+}
+
+/// This test makes sure that actors spawned and killed within a single rollback window come back correctly.
+#[test]
+fn spawn_and_kill_within_a_rollback() {
+    let report = ConformanceScenario::new(60)
+        .with_latency(7)
+        .with_action(10, 0, ScriptedAction::Spawn)
+        .with_action(11, 1, ScriptedAction::Damage)
+        .with_action(12, 1, ScriptedAction::Damage)
+        .with_action(13, 1, ScriptedAction::Damage)
+        .with_action(14, 1, ScriptedAction::Damage)
+        .with_action(15, 0, ScriptedAction::Spawn)
+        .with_action(16, 0, ScriptedAction::Despawn)
+        .run();
+
+    assert_eq!(report.frames_compared, 60);
+}
+
+/// This test makes sure that the peers are compared on everything registered for rollback, not just the actors of
+/// the scenario.
+#[test]
+#[should_panic(expected = "the peers disagree about the state at the end of frame 0")]
+fn differing_state_is_reported() {
+    // every peer calls the setup once, so each one starts with a different label
+    static PEERS: AtomicU32 = AtomicU32::new(0);
+    ConformanceScenario::new(30)
+        .with_plugin_setup(|plugin| {
+            let label = PEERS.fetch_add(1, Ordering::Relaxed) as f32;
+            plugin
+                .register_rollback_component::<Actor>()
+                .register_rollback_component::<Health>()
+                .register_rollback_component::<Label>()
+                .with_initial_scene(DynamicScene {
+                    entities: vec![DynamicEntity {
+                        entity: 0,
+                        components: vec![Box::new(Label(label))],
+                    }],
+                })
+        })
+        .run();
+}
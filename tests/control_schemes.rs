@@ -0,0 +1,32 @@
+use bevy_ggrs::*;
+
+mod common;
+use common::sockets;
+
+/// This test makes sure that control schemes shared by one peer show up on the other one.
+#[test]
+fn control_schemes_are_exchanged() {
+    let (socket_0, socket_1) = sockets();
+    let keyboard = ControlScheme::new(InputDevice::Keyboard)
+        .with_layout("azerty")
+        .with_binding("jump", "Space");
+
+    let mut exchange_0 = MatchSetupExchange::<u8, usize>::new(socket_0, 2)
+        .add_local_player(0, 7)
+        .with_control_scheme(0, keyboard.clone())
+        .add_remote_player(1, 1);
+    let mut exchange_1 = MatchSetupExchange::<u8, usize>::new(socket_1, 2)
+        .add_local_player(1, 9)
+        .add_remote_player(0, 0);
+
+    for _ in 0..5 {
+        exchange_0.poll();
+        exchange_1.poll();
+    }
+
+    assert!(exchange_0.is_complete());
+    assert!(exchange_1.is_complete());
+    let schemes = exchange_1.control_schemes();
+    assert_eq!(schemes.get(0), Some(&keyboard));
+    assert_eq!(schemes.get(1), None);
+}
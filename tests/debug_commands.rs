@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+mod common;
+use common::NoTransport;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Resource, Default, Debug)]
+#[reflect(Resource)]
+struct Gold(u32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn give_gold_system(commands: Res<DebugCommands<u32, usize>>, mut gold: ResMut<Gold>) {
+    for (_, amount) in commands.current() {
+        gold.0 += amount;
+    }
+}
+
+/// Issues a command in frame 2, like a console command that is itself triggered by an input.
+fn issue_system(frame: Res<RollbackFrame>, mut commands: ResMut<DebugCommands<u32, usize>>) {
+    if **frame == 2 {
+        assert_eq!(commands.issue(10), Some(5));
+    }
+}
+
+fn app(schedule: Schedule) -> App {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .insert_resource(
+            DebugCommands::<u32, usize>::new(MultiplexSocket::new(NoTransport), 0, Vec::new())
+                .with_delay(3),
+        )
+        .init_resource::<Gold>();
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_rollback_resource::<Gold>()
+        .register_debug_commands::<u32>()
+        .with_rollback_schedule(schedule)
+        .build(&mut app);
+    app
+}
+
+fn run(app: &mut App) {
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+}
+
+/// This test makes sure that commands are applied exactly once, at the frame they were scheduled for, even though
+/// the sync test keeps rolling these frames back.
+#[test]
+fn commands_are_executed_at_their_frame() {
+    let mut app = app(Schedule::default().with_stage(
+        "default",
+        SystemStage::single_threaded().with_system(give_gold_system),
+    ));
+
+    let frame = app
+        .world
+        .resource_mut::<DebugCommands<u32, usize>>()
+        .issue(100)
+        .expect("everyone may issue commands by default");
+    assert_eq!(frame, 3);
+
+    run(&mut app);
+    assert_eq!(app.world.resource::<Gold>().0, 100);
+}
+
+/// This test makes sure that a command issued in the rollback schedule is scheduled once, although the sync test
+/// simulates the frame it was issued in several times.
+#[test]
+fn resimulated_commands_are_not_duplicated() {
+    let mut app = app(Schedule::default().with_stage(
+        "default",
+        SystemStage::single_threaded()
+            .with_system(issue_system)
+            .with_system(give_gold_system.after(issue_system)),
+    ));
+
+    run(&mut app);
+    assert_eq!(app.world.resource::<Gold>().0, 10);
+}
+
+#[test]
+fn only_authorities_issue_commands() {
+    let mut commands =
+        DebugCommands::<u32, usize>::new(MultiplexSocket::new(NoTransport), 1, vec![0])
+            .with_authorities(vec![0]);
+    assert!(commands.is_authority(0));
+    assert!(!commands.is_authority(1));
+    assert_eq!(commands.issue(5), None);
+}
@@ -0,0 +1,102 @@
+use bevy::{asset::AssetPlugin, prelude::*, render::mesh::VertexAttributeValues};
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+struct Hitbox;
+
+impl DebugShape for Hitbox {
+    fn debug_shape(&self) -> Shape {
+        Shape::Box {
+            center: Vec3::ZERO,
+            half_extents: Vec3::splat(0.5),
+        }
+    }
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn move_system(mut query: Query<&mut Transform, With<Hitbox>>) {
+    for mut transform in query.iter_mut() {
+        transform.translation.x += 1.;
+    }
+}
+
+/// This test makes sure that the shapes of the drawn frames end up in a single line mesh, colored by their age.
+#[test]
+fn shapes_are_drawn_as_lines() {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin::default())
+        .add_asset::<Mesh>()
+        .add_asset::<StandardMaterial>()
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_rollback_component::<Transform>()
+        .register_rollback_component::<Hitbox>()
+        .register_debug_shape::<Hitbox>()
+        .with_debug_lines()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(move_system),
+        ))
+        .build(&mut app);
+
+    app.world
+        .spawn((Rollback::new(0), Hitbox, TransformBundle::default()));
+
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let handles: Vec<Handle<Mesh>> = app
+        .world
+        .query::<&Handle<Mesh>>()
+        .iter(&app.world)
+        .cloned()
+        .collect();
+    assert_eq!(handles.len(), 1);
+    let mesh = app
+        .world
+        .resource::<Assets<Mesh>>()
+        .get(&handles[0])
+        .unwrap();
+    let frames = app.world.resource::<DebugLines>().frames;
+    assert_eq!(mesh.count_vertices(), frames * 12 * 2);
+
+    let Some(VertexAttributeValues::Float32x4(colors)) = mesh.attribute(Mesh::ATTRIBUTE_COLOR)
+    else {
+        panic!("the lines should have vertex colors");
+    };
+    let lines = app.world.resource::<DebugLines>();
+    assert_eq!(colors[0], lines.current.as_linear_rgba_f32());
+    let oldest = lines.oldest.as_linear_rgba_f32();
+    for (channel, expected) in colors[colors.len() - 1].iter().zip(oldest) {
+        assert!((channel - expected).abs() < 1e-6, "{colors:?}");
+    }
+}
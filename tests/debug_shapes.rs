@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+struct Hitbox;
+
+impl DebugShape for Hitbox {
+    fn debug_shape(&self) -> Shape {
+        Shape::Box {
+            center: Vec3::ZERO,
+            half_extents: Vec3::splat(0.5),
+        }
+    }
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn move_system(mut query: Query<&mut Transform, With<Hitbox>>) {
+    for mut transform in query.iter_mut() {
+        transform.translation.x += 1.;
+    }
+}
+
+/// This test makes sure that the shapes of every frame are recorded with the state of that frame.
+#[test]
+fn shapes_are_recorded_per_frame() {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_rollback_component::<Transform>()
+        .register_rollback_component::<Hitbox>()
+        .register_debug_shape::<Hitbox>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(move_system),
+        ))
+        .build(&mut app);
+
+    app.world
+        .spawn((Rollback::new(0), Hitbox, TransformBundle::default()));
+
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let shapes = app.world.resource::<DebugShapes>();
+    let current = &shapes.current()[0];
+    let earlier = &shapes.frames_ago(3).expect("history should be kept")[0];
+    assert_eq!(current.rollback_id, 0);
+    assert_eq!(
+        current.transform.translation.x - earlier.transform.translation.x,
+        3.
+    );
+    assert_eq!(current.lines().len(), 12);
+}
@@ -0,0 +1,133 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// Can't be compared through reflection.
+#[derive(Component, Reflect, Clone, Default, Debug)]
+#[reflect_value(Component)]
+struct Opaque(u32);
+
+/// Part of the checksums, so the sync test notices the desync.
+#[derive(Component, Reflect, Default, Debug, Hash)]
+#[reflect(Component, Hash)]
+struct Mirror(u32);
+
+/// Not rolled back, so every simulation sees a different value.
+#[derive(Resource, Default)]
+struct Calls(u32);
+
+#[derive(Resource, Default)]
+struct Received(Vec<PresentationEvent<i32>>);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn wobble_system(mut calls: ResMut<Calls>, mut query: Query<(&mut Opaque, &mut Mirror)>) {
+    calls.0 += 1;
+    for (mut opaque, mut mirror) in query.iter_mut() {
+        opaque.0 = calls.0;
+        mirror.0 = calls.0;
+    }
+}
+
+fn announce_system(frame: Res<RollbackFrame>, mut events: ResMut<PresentationEvents<i32>>) {
+    events.send(**frame);
+}
+
+fn receive_system(mut reader: EventReader<PresentationEvent<i32>>, mut received: ResMut<Received>) {
+    received.0.extend(reader.iter().cloned());
+}
+
+fn build_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<Calls>()
+        .init_resource::<Received>()
+        .add_system(receive_system)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_sync_test_bisection(true)
+        .register_rollback_component::<Opaque>()
+        .register_rollback_component::<Mirror>()
+        .register_presentation_event::<i32>()
+        .with_rollback_schedule(
+            Schedule::default().with_stage(
+                "default",
+                SystemStage::single_threaded()
+                    .with_system(wobble_system)
+                    .with_system(announce_system.after(wobble_system)),
+            ),
+        )
+        .build(&mut app);
+    app.world.spawn((Rollback::new(0), Opaque(0), Mirror(0)));
+
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+    app
+}
+
+/// This test makes sure that the bisection of a desync also reports values that can't be compared through
+/// reflection.
+#[test]
+fn bisection_reports_opaque_values() {
+    let app = build_app();
+
+    let bisection = app
+        .world
+        .get_resource::<DesyncBisection>()
+        .expect("the sync test should have reported a mismatch");
+    let findings: Vec<&str> = bisection.findings().collect();
+    assert!(
+        findings
+            .iter()
+            .any(|finding| finding.contains("Opaque changed")),
+        "{findings:?}"
+    );
+}
+
+/// This test makes sure that the hooks see the frames simulated by the bisection like any other resimulation, so
+/// the events of those frames reach the presentation once and for the frame they were sent in.
+#[test]
+fn bisection_resimulates_presentation_events_like_a_rollback() {
+    let app = build_app();
+
+    assert!(app.world.contains_resource::<DesyncBisection>());
+    let received = &app.world.resource::<Received>().0;
+    let mut frames = Vec::new();
+    for event in received.iter() {
+        match event {
+            PresentationEvent::Triggered { frame, event } => {
+                assert_eq!(frame, event, "{received:?}");
+                frames.push(*frame);
+            }
+            PresentationEvent::Cancelled { .. } => panic!("{received:?}"),
+        }
+    }
+    let mut unique = frames.clone();
+    unique.dedup();
+    assert_eq!(frames, unique, "{received:?}");
+    assert!(!frames.is_empty());
+}
@@ -0,0 +1,131 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+mod common;
+use common::sockets;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Resource, Default, Debug, Hash)]
+#[reflect(Resource, Hash)]
+struct Counter(u32);
+
+/// Added to the counter once frame 60 is reached. Not part of the snapshots, so peers can disagree on it.
+#[derive(Resource)]
+struct Drift(u32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn count_system(frame: Res<RollbackFrame>, drift: Res<Drift>, mut counter: ResMut<Counter>) {
+    counter.0 += 1;
+    if **frame == 60 {
+        counter.0 += drift.0;
+    }
+}
+
+fn app(socket: MultiplexSocket<usize>, local: usize, drift: u32) -> App {
+    let session = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(2)
+        .add_player(PlayerType::Local, local)
+        .unwrap()
+        .add_player(PlayerType::Remote(1 - local), 1 - local)
+        .unwrap()
+        .start_p2p_session(socket.clone())
+        .unwrap();
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::P2PSession(session))
+        .insert_resource(
+            AdaptiveDesyncCheck::new(socket, vec![1 - local])
+                .with_interval_range(2, 16)
+                .with_stable_checks(2),
+        )
+        .insert_resource(Drift(drift))
+        .init_resource::<Counter>();
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_rollback_resource::<Counter>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(count_system),
+        ))
+        .build(&mut app);
+    app
+}
+
+fn update(apps: &mut [&mut App]) {
+    std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+    for app in apps.iter_mut() {
+        app.update();
+    }
+}
+
+fn check(app: &App) -> &AdaptiveDesyncCheck<usize> {
+    app.world.resource::<AdaptiveDesyncCheck<usize>>()
+}
+
+fn failures(app: &App) -> Vec<DesyncCheckFailed<usize>> {
+    let events = app.world.resource::<Events<DesyncCheckFailed<usize>>>();
+    let mut reader = events.get_reader();
+    reader.iter(events).cloned().collect()
+}
+
+/// This test makes sure that matching checksums relax the cadence up to the longest interval, that it is shown by
+/// the `NetcodeHud` and that `tighten()` goes back to the shortest one.
+#[test]
+fn stable_session_relaxes_cadence() {
+    let (a, b) = sockets();
+    let (mut a, mut b) = (app(a, 0, 0), app(b, 1, 0));
+
+    for _ in 0..300 {
+        update(&mut [&mut a, &mut b]);
+        assert!(failures(&a).is_empty() && failures(&b).is_empty());
+        if check(&a).interval() == 16 && check(&b).interval() == 16 {
+            break;
+        }
+    }
+    assert_eq!(check(&a).interval(), 16);
+    assert!(check(&a).checks() >= 6);
+    assert_eq!(
+        a.world.resource::<NetcodeHud>().desync_check_interval(),
+        Some(16)
+    );
+
+    a.world
+        .resource_mut::<AdaptiveDesyncCheck<usize>>()
+        .tighten();
+    assert_eq!(check(&a).interval(), 2);
+}
+
+/// This test makes sure that differing states are reported and go back to the shortest interval.
+#[test]
+fn mismatch_is_reported() {
+    let (a, b) = sockets();
+    let (mut a, mut b) = (app(a, 0, 0), app(b, 1, 1));
+
+    let mut reported = Vec::new();
+    for _ in 0..300 {
+        update(&mut [&mut a, &mut b]);
+        reported.extend(failures(&a));
+        if !reported.is_empty() {
+            break;
+        }
+    }
+    let failure = reported.first().expect("a mismatch should be reported");
+    assert_eq!(failure.peer, 1);
+    assert!(failure.frame > 60);
+    assert_ne!(failure.local, failure.remote);
+    assert_eq!(check(&a).interval(), 2);
+}
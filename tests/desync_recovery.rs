@@ -0,0 +1,233 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+mod common;
+use common::sockets;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// Counts the simulated frames.
+#[derive(Reflect, Resource, Default, Debug)]
+#[reflect(Resource)]
+struct Sum(u32);
+
+/// A prefab spawned every frame from `FIRST_TOWER` on, which remembers the frame it was spawned in.
+#[derive(Component, Reflect, Default, Debug, Clone, Copy, Hash)]
+#[reflect(Component, Hash)]
+struct Tower {
+    frame: i32,
+}
+
+/// Stands in for a mesh, which is not part of the snapshots.
+#[derive(Component)]
+struct TowerMesh;
+
+const FIRST_TOWER: i32 = 25;
+
+struct TowerPrefab;
+
+impl Prefab for TowerPrefab {
+    const NAME: &'static str = "tower";
+    type Params = i32;
+
+    fn spawn(frame: &i32, spawner: &mut PrefabSpawner) {
+        let root = spawner.spawn(Tower { frame: *frame });
+        let mesh = spawner.spawn_presentation(TowerMesh);
+        spawner.world().entity_mut(root).push_children(&[mesh]);
+    }
+}
+
+/// Not rolled back: the frame in which the state of this peer goes astray.
+#[derive(Resource)]
+struct CorruptAt(i32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn sum_system(mut sum: ResMut<Sum>) {
+    sum.0 += 1;
+}
+
+fn corrupt_system(
+    mut commands: Commands,
+    frame: Res<RollbackFrame>,
+    corrupt_at: Option<Res<CorruptAt>>,
+    mut sum: ResMut<Sum>,
+) {
+    if matches!(corrupt_at, Some(at) if at.0 == **frame) {
+        sum.0 += 1000;
+        commands.remove_resource::<CorruptAt>();
+    }
+}
+
+fn build_towers_system(mut commands: Commands, frame: Res<RollbackFrame>) {
+    if **frame >= FIRST_TOWER {
+        commands.add(SpawnPrefab::<TowerPrefab>::new(**frame));
+    }
+}
+
+fn app(
+    socket: MultiplexSocket<usize>,
+    local: usize,
+    recovery: DesyncRecovery<usize>,
+    profile: NetworkProfile,
+) -> App {
+    let builder = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(2)
+        .add_player(PlayerType::Local, local)
+        .unwrap()
+        .add_player(PlayerType::Remote(1 - local), 1 - local)
+        .unwrap();
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(recovery)
+        .insert_resource(profile)
+        .init_resource::<Sum>()
+        .insert_resource(Session::P2PSession(
+            profile
+                .configure(builder)
+                .start_p2p_session(socket)
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_rollback_resource::<Sum>()
+        .register_rollback_component::<Tower>()
+        .register_prefab::<TowerPrefab>()
+        .with_rollback_schedule(
+            Schedule::default().with_stage(
+                "default",
+                SystemStage::single_threaded()
+                    .with_system(sum_system)
+                    .with_system(corrupt_system.after(sum_system))
+                    .with_system(build_towers_system),
+            ),
+        )
+        .build(&mut app);
+    app
+}
+
+fn update(apps: &mut [&mut App], times: usize) {
+    for _ in 0..times {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        for app in apps.iter_mut() {
+            app.update();
+        }
+    }
+}
+
+/// The frame last simulated, or -1 while the session is synchronizing.
+fn frame(app: &App) -> i32 {
+    app.world
+        .get_resource::<RollbackFrame>()
+        .map_or(-1, |frame| **frame)
+}
+
+/// The difference between the simulated frames counted and the current frame, the same on all peers in sync.
+fn drift(app: &App) -> i64 {
+    app.world.resource::<Sum>().0 as i64 - frame(app) as i64
+}
+
+/// Lets the follower go astray, resynchronizes it with the authority and returns both apps afterwards.
+fn resync(profile: NetworkProfile) -> (App, App) {
+    let (socket_0, socket_1) = sockets();
+    let mut authority = app(
+        socket_0.clone(),
+        0,
+        DesyncRecovery::authority(socket_0, vec![1]),
+        profile,
+    );
+    let mut follower = app(
+        socket_1.clone(),
+        1,
+        DesyncRecovery::follower(socket_1, 0),
+        profile,
+    );
+    follower.insert_resource(CorruptAt(20));
+
+    for _ in 0..300 {
+        update(&mut [&mut authority, &mut follower], 1);
+        if frame(&authority) > 30 && frame(&follower) > 30 {
+            break;
+        }
+    }
+    assert!(!follower.world.contains_resource::<CorruptAt>());
+    assert_ne!(drift(&follower), drift(&authority));
+
+    follower
+        .world
+        .resource_mut::<DesyncRecovery<usize>>()
+        .request_resync();
+    for _ in 0..300 {
+        update(&mut [&mut authority, &mut follower], 1);
+        if !follower
+            .world
+            .resource::<DesyncRecovery<usize>>()
+            .is_resyncing()
+        {
+            break;
+        }
+    }
+    assert!(!follower
+        .world
+        .resource::<DesyncRecovery<usize>>()
+        .is_resyncing());
+
+    update(&mut [&mut authority, &mut follower], 10);
+    (authority, follower)
+}
+
+/// This test makes sure that a follower whose state went astray adopts the state of the authority, and stays in
+/// sync afterwards.
+#[test]
+fn follower_adopts_the_state_of_the_authority() {
+    let (authority, follower) = resync(NetworkProfile::Default);
+    assert_eq!(drift(&follower), drift(&authority));
+}
+
+/// This test makes sure that the follower still adopts the state of the authority when GGRS only saves some of the
+/// frames.
+#[test]
+fn follower_adopts_the_state_of_the_authority_with_sparse_saving() {
+    let (authority, follower) = resync(NetworkProfile::LowBandwidth);
+    assert_eq!(drift(&follower), drift(&authority));
+}
+
+/// This test makes sure that the prefabs spawned after the adopted frame are undone before the follower simulates
+/// those frames again, so the prefabs spawned again get the same rollback ids and leave no meshes behind.
+#[test]
+fn prefabs_spawned_after_the_adopted_frame_are_undone() {
+    let (_, mut follower) = resync(NetworkProfile::Default);
+
+    let mut towers = follower.world.query::<(&Tower, &Rollback)>();
+    let towers: Vec<_> = towers
+        .iter(&follower.world)
+        .map(|(tower, rollback)| (*tower, *rollback))
+        .collect();
+    assert!(!towers.is_empty());
+    for (tower, rollback) in towers.iter() {
+        // the first prefab spawned in its frame
+        let expected = Rollback::derived(
+            &Rollback::derived(&Rollback::from_name(TowerPrefab::NAME), tower.frame as u32),
+            0,
+        );
+        assert_eq!(*rollback, expected, "tower of frame {}", tower.frame);
+    }
+    let meshes = follower
+        .world
+        .query::<&TowerMesh>()
+        .iter(&follower.world)
+        .count();
+    assert_eq!(meshes, towers.len());
+}
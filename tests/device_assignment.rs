@@ -0,0 +1,225 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+mod common;
+use common::sockets;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// The inputs of player 0 for every simulated frame.
+#[derive(Resource, Default)]
+struct Simulated(Vec<u8>);
+
+/// Not rolled back: the latest frame simulated.
+#[derive(Resource, Default)]
+struct Latest(i32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    5
+}
+
+fn record_system(inputs: Res<PlayerInputs<GGRSConfig>>, mut simulated: ResMut<Simulated>) {
+    simulated.0.push(inputs[0].0);
+}
+
+fn latest_system(frame: Res<RollbackFrame>, mut latest: ResMut<Latest>) {
+    latest.0 = latest.0.max(**frame);
+}
+
+fn app(assignment: DeviceAssignment<GGRSConfig>) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(0)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .insert_resource(assignment)
+        .init_resource::<Input<KeyCode>>()
+        .init_resource::<Gamepads>()
+        .init_resource::<Simulated>();
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(record_system),
+        ))
+        .build(&mut app);
+    app
+}
+
+/// A peer of a `P2PSession` whose local player uses the keyboard.
+fn p2p(socket: MultiplexSocket<usize>, local: usize) -> App {
+    let session = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(2)
+        .add_player(PlayerType::Local, local)
+        .unwrap()
+        .add_player(PlayerType::Remote(1 - local), 1 - local)
+        .unwrap()
+        .start_p2p_session(socket.clone())
+        .unwrap();
+    let mut assignment = DeviceAssignment::new(vec![local]).with_peers(socket, vec![1 - local]);
+    assignment.assign(local, AssignedDevice::Keyboard);
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::P2PSession(session))
+        .insert_resource(assignment)
+        .init_resource::<Input<KeyCode>>()
+        .init_resource::<Gamepads>()
+        .init_resource::<Latest>();
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(latest_system),
+        ))
+        .build(&mut app);
+    app
+}
+
+fn assignment(app: &mut App) -> Mut<DeviceAssignment<GGRSConfig>> {
+    app.world.resource_mut::<DeviceAssignment<GGRSConfig>>()
+}
+
+fn update_both(a: &mut App, b: &mut App, times: usize) {
+    for _ in 0..times {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        a.update();
+        b.update();
+    }
+}
+
+fn update(app: &mut App, times: usize) {
+    for _ in 0..times {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+}
+
+/// This test makes sure that pressing the join key assigns the keyboard to the first free local player.
+#[test]
+fn keyboard_joins_by_pressing_the_join_key() {
+    let mut app = app(DeviceAssignment::new(vec![0]));
+    update(&mut app, 1);
+    assert_eq!(
+        app.world
+            .resource::<DeviceAssignment<GGRSConfig>>()
+            .device(0),
+        None
+    );
+
+    app.world
+        .resource_mut::<Input<KeyCode>>()
+        .press(KeyCode::Return);
+    update(&mut app, 1);
+
+    let assignment = app.world.resource::<DeviceAssignment<GGRSConfig>>();
+    assert_eq!(assignment.device(0), Some(AssignedDevice::Keyboard));
+    assert!(assignment.is_complete());
+}
+
+/// This test makes sure that a player whose gamepad is missing continues with the substitute input.
+#[test]
+fn disconnected_players_use_the_substitute_input() {
+    let mut assignment = DeviceAssignment::new(vec![0])
+        .with_disconnect_policy(DisconnectPolicy::Substitute)
+        .with_substitute_input(9);
+    assignment.assign(0, AssignedDevice::Gamepad(Gamepad::new(0)));
+    let mut app = app(assignment);
+    update(&mut app, 10);
+
+    let assignment = app.world.resource::<DeviceAssignment<GGRSConfig>>();
+    assert!(assignment.is_disconnected(0));
+    assert!(!assignment.is_paused());
+    // the first frame may have been simulated before the disconnect was noticed
+    let simulated = &app.world.resource::<Simulated>().0;
+    assert!(simulated.len() > 1);
+    assert!(simulated[1..].iter().all(|input| *input == 9));
+}
+
+/// This test makes sure that the simulation stops while a gamepad is disconnected with the pause policy.
+#[test]
+fn disconnected_gamepads_pause_the_simulation() {
+    let mut assignment = DeviceAssignment::new(vec![0]);
+    assignment.assign(0, AssignedDevice::Gamepad(Gamepad::new(0)));
+    let mut app = app(assignment);
+    update(&mut app, 10);
+
+    assert!(app
+        .world
+        .resource::<DeviceAssignment<GGRSConfig>>()
+        .is_paused());
+    assert!(app.world.resource::<Simulated>().0.len() <= 1);
+
+    // taking over with the keyboard continues the simulation
+    app.world
+        .resource_mut::<Input<KeyCode>>()
+        .press(KeyCode::Return);
+    update(&mut app, 10);
+    assert!(app.world.resource::<Simulated>().0.len() > 1);
+    assert!(app
+        .world
+        .resource::<Simulated>()
+        .0
+        .iter()
+        .all(|input| *input == 5));
+}
+
+/// This test makes sure that all peers of a `P2PSession` stop at the same frame when a gamepad of one of them is
+/// disconnected, and continue once it is replaced.
+#[test]
+fn peers_pause_at_the_same_frame() {
+    let (a, b) = sockets();
+    let (mut a, mut b) = (p2p(a, 0), p2p(b, 1));
+    update_both(&mut a, &mut b, 60);
+    assert!(a.world.resource::<Latest>().0 > 10);
+
+    // the gamepad isn't connected
+    assignment(&mut a).assign(0, AssignedDevice::Gamepad(Gamepad::new(0)));
+    update_both(&mut a, &mut b, 30);
+    assert!(assignment(&mut a).is_paused());
+    assert!(assignment(&mut b).is_paused());
+    let stopped = a.world.resource::<Latest>().0;
+    assert_eq!(b.world.resource::<Latest>().0, stopped);
+    update_both(&mut a, &mut b, 30);
+    assert_eq!(a.world.resource::<Latest>().0, stopped);
+    assert_eq!(b.world.resource::<Latest>().0, stopped);
+
+    assignment(&mut a).assign(0, AssignedDevice::Keyboard);
+    update_both(&mut a, &mut b, 30);
+    assert!(!assignment(&mut a).is_paused());
+    assert!(!assignment(&mut b).is_paused());
+    assert!(a.world.resource::<Latest>().0 > stopped);
+    assert!(b.world.resource::<Latest>().0 > stopped);
+}
+
+/// This test makes sure that gamepads count as connected without gamepad support, so a pause can't get stuck.
+#[test]
+fn gamepads_without_gamepad_support_are_connected() {
+    let mut assignment = DeviceAssignment::new(vec![0]);
+    assignment.assign(0, AssignedDevice::Gamepad(Gamepad::new(0)));
+    let mut app = app(assignment);
+    app.world.remove_resource::<Gamepads>();
+    update(&mut app, 10);
+
+    let assignment = app.world.resource::<DeviceAssignment<GGRSConfig>>();
+    assert!(!assignment.is_disconnected(0));
+    assert!(!assignment.is_paused());
+    assert!(app.world.resource::<Simulated>().0.len() > 1);
+}
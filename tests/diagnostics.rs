@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use std::io::{Cursor, Read};
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Resource, Default, Debug)]
+#[reflect(Resource)]
+struct Score(u32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0x2a
+}
+
+fn plugin() -> GGRSPlugin<GGRSConfig> {
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+}
+
+fn config_checksum(plugin: GGRSPlugin<GGRSConfig>) -> u64 {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins);
+    plugin.build(&mut app);
+    app.world.resource::<SessionDiagnostics>().config_checksum()
+}
+
+/// Returns the names and contents of the entries of a zip archive, checking their CRCs.
+fn zip_entries(zip: Vec<u8>) -> Vec<(String, Vec<u8>)> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(zip)).expect("bundle should be a zip archive");
+    (0..archive.len())
+        .map(|i| {
+            let mut entry = archive.by_index(i).unwrap();
+            let mut contents = Vec::new();
+            entry
+                .read_to_end(&mut contents)
+                .expect("entry should be intact");
+            (entry.name().to_string(), contents)
+        })
+        .collect()
+}
+
+/// This test makes sure that the config checksum only changes with the settings and registered types.
+#[test]
+fn config_checksum_covers_registered_types() {
+    let checksum = config_checksum(plugin());
+    assert_eq!(config_checksum(plugin()), checksum);
+    assert_ne!(
+        config_checksum(plugin().register_rollback_resource::<Score>()),
+        checksum
+    );
+    assert_ne!(
+        config_checksum(plugin().with_update_frequency(30)),
+        checksum
+    );
+}
+
+/// This test makes sure that the bundle contains the request trace, the rollback metrics and the snapshot metadata.
+#[test]
+fn bundle_contains_all_diagnostics() {
+    let trace_path = std::env::temp_dir().join("bevy_ggrs_diagnostics_trace_test.txt");
+    let bundle_path = std::env::temp_dir().join("bevy_ggrs_diagnostics_test.zip");
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .init_resource::<Score>();
+    plugin()
+        .with_request_trace(&trace_path)
+        .register_rollback_resource::<Score>()
+        .build(&mut app);
+
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let diagnostics = app.world.resource::<SessionDiagnostics>();
+    assert!(diagnostics.snapshots().count() > 0);
+    diagnostics
+        .export_diagnostics_bundle(&bundle_path)
+        .expect("bundle should be written");
+
+    let zip = std::fs::read(&bundle_path).expect("bundle should exist");
+    let entries = zip_entries(zip);
+    let entry = |name: &str| {
+        let (_, contents) = entries
+            .iter()
+            .find(|(entry, _)| entry == name)
+            .unwrap_or_else(|| panic!("{name} should be in the bundle"));
+        String::from_utf8(contents.clone()).unwrap()
+    };
+    let checksum = format!("checksum: {:016x}", diagnostics.config_checksum());
+    assert!(entry("config.txt").contains(&checksum));
+    assert!(entry("config.txt").contains("Score"));
+    assert!(entry("requests.trace").contains("A 0 2a:C"));
+    assert!(entry("rollbacks.txt").contains("rollbacks: "));
+    assert!(entry("snapshots.txt").lines().count() > 0);
+    assert!(entries.iter().any(|(name, _)| name == "network_stats.txt"));
+
+    let _ = std::fs::remove_file(trace_path);
+    let _ = std::fs::remove_file(bundle_path);
+}
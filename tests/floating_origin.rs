@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Component, Default, Debug)]
+#[reflect(Component)]
+struct Distance(u32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn move_system(mut query: Query<(&mut Transform, &mut Distance)>) {
+    for (mut transform, mut distance) in query.iter_mut() {
+        transform.translation.x += 10.;
+        distance.0 += 10;
+    }
+}
+
+/// This test makes sure that the focus stays close to the origin while its position relative to the true origin
+/// keeps growing, and that entities outside of the rollback follow the shifts.
+#[test]
+fn origin_follows_focus() {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_floating_origin(100)
+        .register_rollback_component::<Transform>()
+        .register_rollback_component::<Distance>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(move_system),
+        ))
+        .build(&mut app);
+
+    let focus = app
+        .world
+        .spawn((
+            Rollback::new(0),
+            OriginFocus,
+            Distance(0),
+            TransformBundle::default(),
+        ))
+        .id();
+    let marker = app.world.spawn(TransformBundle::default()).id();
+
+    for _ in 0..30 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let origin = app.world.resource::<FloatingOrigin>().clone();
+    let translation = app.world.get::<Transform>(focus).unwrap().translation;
+    let distance = app.world.get::<Distance>(focus).unwrap().0;
+    assert!(distance > 100);
+    assert!(origin.cell()[0] > 0);
+    assert!(translation.x.abs() <= 50.);
+    assert_eq!(origin.to_world(translation)[0], distance as f64);
+
+    // the marker stayed at the true origin
+    let marker = app.world.get::<Transform>(marker).unwrap().translation;
+    assert_eq!(origin.to_world(marker)[0], 0.);
+}
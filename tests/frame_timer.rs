@@ -0,0 +1,55 @@
+use bevy::prelude::*;
+use bevy_ggrs::FrameTimer;
+
+#[test]
+fn once_timer_finishes_after_duration() {
+    let mut timer = FrameTimer::new(3, TimerMode::Once);
+    timer.tick().tick();
+    assert!(!timer.finished());
+    assert_eq!(timer.remaining(), 1);
+
+    timer.tick();
+    assert!(timer.finished());
+    assert!(timer.just_finished());
+
+    timer.tick();
+    assert!(timer.finished());
+    assert!(!timer.just_finished());
+    assert_eq!(timer.elapsed(), 3);
+}
+
+#[test]
+fn repeating_timer_wraps_around() {
+    let mut timer = FrameTimer::new(2, TimerMode::Repeating);
+    timer.tick_frames(5);
+    assert!(timer.just_finished());
+    assert_eq!(timer.times_finished_this_tick(), 2);
+    assert_eq!(timer.elapsed(), 1);
+
+    timer.tick_frames(0);
+    assert!(!timer.finished());
+}
+
+#[test]
+fn paused_timer_does_not_advance() {
+    let mut timer = FrameTimer::from_seconds(0.5, 60, TimerMode::Once);
+    assert_eq!(timer.duration(), 30);
+    timer.pause();
+    timer.tick();
+    assert_eq!(timer.elapsed(), 0);
+    timer.unpause();
+    timer.tick();
+    assert_eq!(timer.elapsed(), 1);
+}
+
+/// Timers in rollback state must hash the same on all peers.
+#[test]
+fn timer_state_is_hashable() {
+    let mut a = FrameTimer::new(10, TimerMode::Once);
+    let mut b = a.clone();
+    a.tick();
+    b.tick();
+    assert_eq!(a.reflect_hash(), b.reflect_hash());
+    b.tick();
+    assert_ne!(a.reflect_hash(), b.reflect_hash());
+}
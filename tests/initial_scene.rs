@@ -0,0 +1,170 @@
+use bevy::{prelude::*, reflect::DynamicTupleStruct, scene::DynamicEntity};
+
+use bevy_ggrs::*;
+use ggrs::*;
+
+mod common;
+use common::sockets;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Component, Reflect, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[reflect(Component, Hash)]
+struct Health(u32);
+
+/// Can't be hashed, so it is only covered by the checksum of the initial state.
+#[derive(Component, Reflect, Default, Debug, Clone, Copy, PartialEq)]
+#[reflect(Component)]
+struct Speed(f32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn level(health: &[u32]) -> DynamicScene {
+    DynamicScene {
+        entities: health
+            .iter()
+            .enumerate()
+            .map(|(i, health)| DynamicEntity {
+                entity: i as u32,
+                components: vec![Box::new(Health(*health))],
+            })
+            .collect(),
+    }
+}
+
+/// Two entities with a speed, where the second one is a child of the first one.
+fn family(speed: f32) -> DynamicScene {
+    let mut parent = DynamicTupleStruct::default();
+    parent.set_name(std::any::type_name::<Parent>().to_string());
+    parent.insert(Entity::from_raw(0));
+    DynamicScene {
+        entities: vec![
+            DynamicEntity {
+                entity: 0,
+                components: vec![Box::new(Speed(speed))],
+            },
+            DynamicEntity {
+                entity: 1,
+                components: vec![Box::new(Speed(speed)), Box::new(parent)],
+            },
+        ],
+    }
+}
+
+fn app(scene: Option<DynamicScene>) -> App {
+    app_with(scene, 0)
+}
+
+/// Spawns `unrelated` entities before building the plugin, which moves the entities of the scene.
+fn app_with(scene: Option<DynamicScene>, unrelated: usize) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins);
+    for _ in 0..unrelated {
+        app.world.spawn_empty();
+    }
+    let mut plugin = GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_rollback_component::<Health>()
+        .register_rollback_component::<Speed>()
+        .register_match_setup::<u8>();
+    if let Some(scene) = scene {
+        plugin = plugin.with_initial_scene(scene);
+    }
+    plugin.build(&mut app);
+    app
+}
+
+fn config_checksum(app: &App) -> u64 {
+    app.world.resource::<SessionDiagnostics>().config_checksum()
+}
+
+/// This test makes sure that the entities of the initial scene are spawned as rollback entities.
+#[test]
+fn initial_scene_spawns_rollback_entities() {
+    let mut app = app(Some(level(&[3, 5])));
+
+    let mut query = app.world.query::<(&Rollback, &Health)>();
+    let mut health: Vec<u32> = query.iter(&app.world).map(|(_, health)| health.0).collect();
+    health.sort();
+    assert_eq!(health, vec![3, 5]);
+
+    // the ids only depend on the scene
+    let mut ids: Vec<Rollback> = query.iter(&app.world).map(|(id, _)| *id).collect();
+    let mut other = self::app(Some(level(&[3, 5])));
+    let mut other_ids: Vec<Rollback> = other
+        .world
+        .query::<&Rollback>()
+        .iter(&other.world)
+        .copied()
+        .collect();
+    ids.sort();
+    other_ids.sort();
+    assert_eq!(ids, other_ids);
+}
+
+/// This test makes sure that the config checksum covers the state loaded from the initial scene.
+#[test]
+fn initial_state_is_part_of_the_config_checksum() {
+    let base = config_checksum(&app(Some(level(&[3, 5]))));
+    assert_eq!(base, config_checksum(&app(Some(level(&[3, 5])))));
+    assert_ne!(base, config_checksum(&app(Some(level(&[3, 6])))));
+    assert_ne!(base, config_checksum(&app(Some(level(&[3])))));
+    assert_ne!(base, config_checksum(&app(None)));
+}
+
+/// This test makes sure that the checksum of the initial state only depends on the scene, not on the entities the
+/// scene was spawned as or the values that can't be hashed.
+#[test]
+fn initial_state_checksum_ignores_entity_ids() {
+    let base = config_checksum(&app(Some(family(1.5))));
+    let mut moved = app_with(Some(family(1.5)), 10);
+    let mut query = moved.world.query_filtered::<&Parent, With<Speed>>();
+    assert_eq!(query.iter(&moved.world).count(), 1);
+    assert_eq!(base, config_checksum(&moved));
+    assert_ne!(base, config_checksum(&app(Some(family(2.5)))));
+}
+
+/// Runs two peers with the given scenes through a match setup exchange and returns both apps.
+fn exchange(scene_0: DynamicScene, scene_1: DynamicScene) -> (App, App) {
+    let (socket_0, socket_1) = sockets();
+    let mut app_0 = app(Some(scene_0));
+    app_0.insert_resource(
+        MatchSetupExchange::<u8, usize>::new(socket_0, 2)
+            .add_local_player(0, 7)
+            .add_remote_player(1, 1),
+    );
+    let mut app_1 = app(Some(scene_1));
+    app_1.insert_resource(
+        MatchSetupExchange::<u8, usize>::new(socket_1, 2)
+            .add_local_player(1, 9)
+            .add_remote_player(0, 0),
+    );
+    for _ in 0..5 {
+        app_0.update();
+        app_1.update();
+    }
+    (app_0, app_1)
+}
+
+/// This test makes sure that peers exchange their config checksums and don't start a match with a peer that loaded
+/// a different initial scene.
+#[test]
+fn mismatched_initial_scenes_stop_the_match_setup() {
+    let (app_0, app_1) = exchange(level(&[3, 5]), level(&[3, 5]));
+    assert!(app_0.world.contains_resource::<MatchSetup<u8>>());
+    assert!(app_1.world.contains_resource::<MatchSetup<u8>>());
+
+    let (app_0, app_1) = exchange(level(&[3, 5]), level(&[3, 6]));
+    assert!(!app_0.world.contains_resource::<MatchSetup<u8>>());
+    assert!(!app_1.world.contains_resource::<MatchSetup<u8>>());
+    let exchange = app_0.world.resource::<MatchSetupExchange<u8, usize>>();
+    assert_eq!(exchange.config_mismatches().collect::<Vec<_>>(), vec![&1]);
+}
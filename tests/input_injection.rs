@@ -0,0 +1,74 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// The input simulated for every frame. Not rolled back, resimulated frames overwrite their entry.
+#[derive(Resource, Default)]
+struct SimulatedInputs(HashMap<i32, u8>);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    1
+}
+
+fn record_system(
+    frame: Res<RollbackFrame>,
+    inputs: Res<PlayerInputs<GGRSConfig>>,
+    mut simulated: ResMut<SimulatedInputs>,
+) {
+    simulated.0.insert(**frame, inputs[0].0);
+}
+
+/// This test makes sure that injected inputs replace the input system for their frames only.
+#[test]
+fn injected_inputs_replace_the_input_system() {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<SimulatedInputs>()
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(record_system),
+        ))
+        .build(&mut app);
+
+    app.world
+        .resource_mut::<InjectedInputs<GGRSConfig>>()
+        .inject_sequence(0, 3, [7, 8]);
+
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let simulated = &app.world.resource::<SimulatedInputs>().0;
+    assert_eq!(simulated.get(&2), Some(&1));
+    assert_eq!(simulated.get(&3), Some(&7));
+    assert_eq!(simulated.get(&4), Some(&8));
+    assert_eq!(simulated.get(&5), Some(&1));
+    assert!(!app
+        .world
+        .resource::<InjectedInputs<GGRSConfig>>()
+        .is_pending(0));
+}
@@ -0,0 +1,73 @@
+use bevy_ggrs::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TestInput {
+    buttons: [bool; 5],
+    stick: (f32, f32),
+    wheel: i32,
+}
+
+impl PackedInput for TestInput {
+    fn pack(&self, writer: &mut BitWriter) {
+        for pressed in self.buttons {
+            writer.write_bool(pressed);
+        }
+        writer.write_quantized(self.stick.0, -1., 1., 6);
+        writer.write_quantized(self.stick.1, -1., 1., 6);
+        writer.write_signed(self.wheel, 4);
+    }
+
+    fn unpack(reader: &mut BitReader) -> Self {
+        let mut buttons = [false; 5];
+        for pressed in buttons.iter_mut() {
+            *pressed = reader.read_bool();
+        }
+        Self {
+            buttons,
+            stick: (
+                reader.read_quantized(-1., 1., 6),
+                reader.read_quantized(-1., 1., 6),
+            ),
+            wheel: reader.read_signed(4),
+        }
+    }
+}
+
+#[test]
+fn packed_input_round_trips() {
+    let input = TestInput {
+        buttons: [true, false, false, true, true],
+        stick: (-1., 1.),
+        wheel: -3,
+    };
+
+    let packed: [u8; 3] = pack_input(&input);
+    let unpacked: TestInput = unpack_input(&packed);
+
+    assert_eq!(unpacked, input);
+}
+
+#[test]
+fn quantized_values_stay_close() {
+    let mut writer = BitWriter::new();
+    writer.write_quantized(0.3, -1., 1., 6);
+    writer.write_quantized(5., -1., 1., 6);
+    assert_eq!(writer.bit_len(), 12);
+
+    let bytes = writer.into_bytes();
+    let mut reader = BitReader::new(&bytes);
+    assert!((reader.read_quantized(-1., 1., 6) - 0.3).abs() <= 1. / 63.);
+    // out of range values are clamped
+    assert_eq!(reader.read_quantized(-1., 1., 6), 1.);
+}
+
+#[test]
+#[should_panic]
+fn packing_into_a_too_small_array_panics() {
+    let input = TestInput {
+        buttons: [false; 5],
+        stick: (0., 0.),
+        wheel: 0,
+    };
+    let _: [u8; 2] = pack_input(&input);
+}
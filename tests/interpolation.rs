@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Component, Debug, Clone, PartialEq)]
+struct HealthBarFill(f32);
+
+impl Lerp for HealthBarFill {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self(Lerp::lerp(&self.0, &other.0, t))
+    }
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+#[test]
+fn lerp_interpolates_between_values() {
+    assert_eq!(
+        HealthBarFill(0.).lerp(&HealthBarFill(1.), 0.25),
+        HealthBarFill(0.25)
+    );
+    assert_eq!(
+        Color::rgba(0., 0., 0., 0.).lerp(&Color::rgba(1., 1., 1., 1.), 0.5),
+        Color::rgba(0.5, 0.5, 0.5, 0.5)
+    );
+}
+
+/// This test makes sure that registered components get an interpolated copy.
+#[test]
+fn interpolated_copy_is_added() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins);
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_input_system(input_system)
+        .register_interpolated_component::<HealthBarFill>()
+        .build(&mut app);
+
+    let entity = app.world.spawn((Rollback::new(0), HealthBarFill(0.5))).id();
+    app.update();
+    app.update();
+
+    let interpolated = app
+        .world
+        .get::<Interpolated<HealthBarFill>>(entity)
+        .expect("interpolated copy should be added");
+    assert_eq!(interpolated.value(), &HealthBarFill(0.5));
+}
+
+#[derive(Component)]
+struct Teleported;
+
+fn teleport_system(
+    mut commands: Commands,
+    frame: Res<RollbackFrame>,
+    mut query: Query<(Entity, &mut HealthBarFill), With<Teleported>>,
+) {
+    if **frame != 5 {
+        return;
+    }
+    for (entity, mut fill) in query.iter_mut() {
+        fill.0 = 1.;
+        commands.entity(entity).insert(Snap::on(&frame));
+    }
+}
+
+/// This test makes sure that an entity flagged with `Snap` jumps to the state of the frame instead of blending
+/// into it.
+#[test]
+fn snapped_entity_jumps() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_interpolated_component::<HealthBarFill>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(teleport_system),
+        ))
+        .build(&mut app);
+
+    let entity = app.world.spawn((HealthBarFill(0.), Teleported)).id();
+    for _ in 0..30 {
+        std::thread::sleep(instant::Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+        if app
+            .world
+            .get_resource::<RollbackFrame>()
+            .map_or(false, |frame| **frame >= 5)
+        {
+            break;
+        }
+    }
+
+    let interpolated = app
+        .world
+        .get::<Interpolated<HealthBarFill>>(entity)
+        .expect("interpolated copy should be added");
+    assert_eq!(interpolated.latest(), &HealthBarFill(1.));
+    assert_eq!(interpolated.value(), &HealthBarFill(1.));
+}
@@ -0,0 +1,221 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+mod common;
+use common::network;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Resource, Default, Debug)]
+#[reflect(Resource)]
+struct Sum(u32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    3
+}
+
+fn sum_system(
+    frame: Res<RollbackFrame>,
+    inputs: Res<PlayerInputs<GGRSConfig>>,
+    mut sum: ResMut<Sum>,
+) {
+    sum.0 = sum
+        .0
+        .wrapping_mul(31)
+        .wrapping_add(**frame as u32 + inputs[0].0 as u32);
+}
+
+fn app(session: Option<Session<GGRSConfig>>, late_join: LateJoin<usize>) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(late_join)
+        .init_resource::<Sum>();
+    if let Some(session) = session {
+        app.insert_resource(session);
+    }
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_rollback_resource::<Sum>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(sum_system),
+        ))
+        .build(&mut app);
+    app
+}
+
+fn synctest() -> Session<GGRSConfig> {
+    Session::SyncTestSession(
+        SessionBuilder::<GGRSConfig>::new()
+            .with_num_players(1)
+            .with_check_distance(2)
+            .add_player(PlayerType::Local, 0)
+            .unwrap()
+            .start_synctest_session()
+            .unwrap(),
+    )
+}
+
+/// Player `local` of a two player match with the player at the other address.
+fn p2p(
+    socket: &MultiplexSocket<usize>,
+    local: usize,
+    profile: NetworkProfile,
+) -> Session<GGRSConfig> {
+    let builder = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(2)
+        .add_player(PlayerType::Local, local)
+        .unwrap()
+        .add_player(PlayerType::Remote(1 - local), 1 - local)
+        .unwrap();
+    Session::P2PSession(
+        profile
+            .configure(builder)
+            .start_p2p_session(socket.clone())
+            .unwrap(),
+    )
+}
+
+fn ready_frame(app: &App) -> Option<i32> {
+    app.world.resource::<LateJoin<usize>>().ready_frame()
+}
+
+fn update(apps: &mut [&mut App], times: usize) {
+    for _ in 0..times {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        for app in apps.iter_mut() {
+            app.update();
+        }
+    }
+}
+
+/// This test makes sure that a late joiner replays the inputs of the host and ends up with the state the host
+/// stopped at.
+#[test]
+fn joiner_catches_up_with_the_host() {
+    let [host_socket, joiner_socket]: [_; 2] = network(2).try_into().ok().unwrap();
+    let mut host = app(
+        Some(synctest()),
+        LateJoin::host(host_socket, Vec::new(), vec![1]),
+    );
+    update(&mut [&mut host], 20);
+
+    let mut joiner = app(None, LateJoin::joiner(joiner_socket, 0));
+    update(&mut [&mut joiner, &mut host], 5);
+
+    let frame = host.world.resource::<LateJoin<usize>>().ready_frame();
+    assert!(matches!(frame, Some(frame) if frame > 10));
+    assert_eq!(
+        joiner.world.resource::<LateJoin<usize>>().ready_frame(),
+        frame
+    );
+    assert_eq!(
+        joiner.world.resource::<Sum>().0,
+        host.world.resource::<Sum>().0
+    );
+
+    // the host doesn't simulate any further until the new session starts
+    let sum = host.world.resource::<Sum>().0;
+    update(&mut [&mut host], 5);
+    assert_eq!(host.world.resource::<Sum>().0, sum);
+}
+
+/// This test makes sure that joining is refused once the match is longer than the limit.
+#[test]
+fn joining_long_matches_is_refused() {
+    let [host_socket, joiner_socket]: [_; 2] = network(2).try_into().ok().unwrap();
+    let mut host = app(
+        Some(synctest()),
+        LateJoin::host(host_socket, Vec::new(), vec![1]).with_max_frames(5),
+    );
+    update(&mut [&mut host], 20);
+
+    let mut joiner = app(None, LateJoin::joiner(joiner_socket, 0).with_max_frames(5));
+    update(&mut [&mut joiner, &mut host], 5);
+
+    let late_join = joiner.world.resource::<LateJoin<usize>>();
+    assert!(late_join.is_refused());
+    assert_eq!(late_join.ready_frame(), None);
+    assert_eq!(host.world.resource::<LateJoin<usize>>().ready_frame(), None);
+}
+
+/// This test makes sure that the host ignores joiners it wasn't told about.
+#[test]
+fn unknown_joiners_are_ignored() {
+    let [host_socket, joiner_socket]: [_; 2] = network(2).try_into().ok().unwrap();
+    let mut host = app(
+        Some(synctest()),
+        LateJoin::host(host_socket, Vec::new(), vec![5]),
+    );
+    update(&mut [&mut host], 20);
+
+    let mut joiner = app(None, LateJoin::joiner(joiner_socket, 0));
+    update(&mut [&mut joiner, &mut host], 5);
+
+    assert_eq!(ready_frame(&joiner), None);
+    assert_eq!(ready_frame(&host), None);
+    assert!(**host.world.resource::<RollbackFrame>() > 20);
+}
+
+/// Lets a joiner join a `P2PSession` of two players and checks that everyone stops at the same state.
+fn join_p2p(profile: NetworkProfile) {
+    let [host_socket, peer_socket, joiner_socket]: [_; 3] = network(3).try_into().ok().unwrap();
+    let mut host = app(
+        Some(p2p(&host_socket, 0, profile)),
+        LateJoin::host(host_socket, vec![1], vec![2]),
+    );
+    let mut peer = app(
+        Some(p2p(&peer_socket, 1, profile)),
+        LateJoin::peer(peer_socket, 0),
+    );
+    for _ in 0..300 {
+        update(&mut [&mut host, &mut peer], 1);
+        let frame = host.world.get_resource::<RollbackFrame>();
+        if frame.map_or(false, |frame| **frame > 20) {
+            break;
+        }
+    }
+
+    let mut joiner = app(None, LateJoin::joiner(joiner_socket, 0));
+    for _ in 0..300 {
+        update(&mut [&mut host, &mut peer, &mut joiner], 1);
+        if [&host, &peer, &joiner]
+            .iter()
+            .all(|app| ready_frame(app).is_some())
+        {
+            break;
+        }
+    }
+
+    let frame = ready_frame(&host);
+    assert!(matches!(frame, Some(frame) if frame > 20));
+    assert_eq!(ready_frame(&peer), frame);
+    assert_eq!(ready_frame(&joiner), frame);
+    let sum = host.world.resource::<Sum>().0;
+    assert_eq!(peer.world.resource::<Sum>().0, sum);
+    assert_eq!(joiner.world.resource::<Sum>().0, sum);
+}
+
+/// This test makes sure that the players of a `P2PSession` agree on the handoff frame, return to the state of that
+/// frame after having predicted beyond it, and that the joiner catches up to the same state.
+#[test]
+fn p2p_players_stop_at_the_handoff() {
+    join_p2p(NetworkProfile::Default);
+}
+
+/// This test makes sure that the players also reach the state of the handoff frame when GGRS didn't save that
+/// frame.
+#[test]
+fn p2p_players_stop_at_the_handoff_with_sparse_saving() {
+    join_p2p(NetworkProfile::LowBandwidth);
+}
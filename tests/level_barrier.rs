@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+mod common;
+use common::NoTransport;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Resource, Default, Debug)]
+#[reflect(Resource)]
+struct CurrentLevel(u32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn level_system(
+    frame: Res<RollbackFrame>,
+    mut barrier: ResMut<LevelBarrier<usize>>,
+    mut level: ResMut<CurrentLevel>,
+) {
+    if **frame == 5 {
+        barrier.request(1);
+    }
+    if let Some(next) = barrier.entering() {
+        level.0 = next;
+    }
+}
+
+fn update(app: &mut App, times: usize) {
+    for _ in 0..times {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+}
+
+/// This test makes sure that the simulation stops at the barrier until the level is loaded, and that the level is
+/// entered at the barrier frame.
+#[test]
+fn simulation_waits_for_level() {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .insert_resource(
+            LevelBarrier::<usize>::new(MultiplexSocket::new(NoTransport), Vec::new()).with_delay(4),
+        )
+        .init_resource::<CurrentLevel>();
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_rollback_resource::<CurrentLevel>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(level_system),
+        ))
+        .build(&mut app);
+
+    update(&mut app, 30);
+
+    // the last frame before the barrier has been simulated
+    assert_eq!(**app.world.resource::<RollbackFrame>(), 8);
+    assert_eq!(
+        app.world.resource::<LevelBarrier<usize>>().loading(),
+        Some(1)
+    );
+    assert_eq!(app.world.resource::<CurrentLevel>().0, 0);
+
+    app.world.resource_mut::<LevelBarrier<usize>>().ready();
+    update(&mut app, 10);
+
+    assert!(**app.world.resource::<RollbackFrame>() > 9);
+    assert!(!app.world.resource::<LevelBarrier<usize>>().is_loading());
+    assert_eq!(app.world.resource::<CurrentLevel>().0, 1);
+}
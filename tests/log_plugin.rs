@@ -0,0 +1,55 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+#[derive(Debug)]
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+/// This test makes sure that the rollbacks of a sync test are counted while the log plugin is running.
+#[test]
+fn rollbacks_are_counted() {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .build(&mut app);
+    app.add_plugin(
+        GgrsLogPlugin::<GGRSConfig>::new()
+            .with_verbosity(LogVerbosity::Debug)
+            .with_rollback_stats_interval(Some(Duration::from_millis(50))),
+    );
+
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let stats = app.world.resource::<RollbackStats>();
+    assert!(stats.rollbacks > 0);
+    // every rollback of the sync test goes back as far as the check distance
+    assert_eq!(stats.max_depth, 2);
+    assert_eq!(stats.resimulated_frames, 2 * stats.rollbacks);
+}
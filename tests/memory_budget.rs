@@ -0,0 +1,142 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+mod common;
+use common::NoTransport;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Resource, Default, Debug)]
+#[reflect(Resource)]
+struct FramesSimulated(i32);
+
+/// Owns most of its memory on the heap.
+#[derive(Reflect, Resource, Default, Debug)]
+#[reflect(Resource)]
+struct Terrain {
+    heights: Vec<u8>,
+    name: String,
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn count_system(mut count: ResMut<FramesSimulated>) {
+    count.0 += 1;
+}
+
+fn build_app(budget: usize) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(0)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .insert_resource(MemoryBudget::new(budget))
+        .init_resource::<FramesSimulated>();
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_rewind_history(60)
+        .register_rollback_resource::<FramesSimulated>()
+        .register_rollback_resource::<Terrain>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(count_system),
+        ))
+        .build(&mut app);
+    app
+}
+
+/// This test makes sure that nothing is cut back while the memory stays within the budget.
+#[test]
+fn usage_within_budget_keeps_everything() {
+    let mut app = build_app(usize::MAX);
+    for _ in 0..30 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let budget = app.world.resource::<MemoryBudget>();
+    assert_eq!(budget.degradation(), MemoryDegradation::None);
+    assert!(budget.usage().history > 0);
+    assert!(!budget.is_exhausted());
+    let events = app.world.resource::<Events<MemoryBudgetExceeded>>();
+    assert!(events.is_empty());
+}
+
+/// This test makes sure that exceeding the budget degrades step by step, starting with the rewind history, and
+/// that every step is reported.
+#[test]
+fn exceeding_budget_degrades_step_by_step() {
+    let mut app = build_app(1);
+    let mut degradations = Vec::new();
+    for _ in 0..30 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+        let events = app.world.resource::<Events<MemoryBudgetExceeded>>();
+        let mut reader = events.get_reader();
+        for event in reader.iter(events) {
+            if !degradations.contains(&event.degradation) {
+                degradations.push(event.degradation);
+            }
+        }
+    }
+
+    assert_eq!(
+        degradations,
+        vec![
+            MemoryDegradation::ReducedHistory,
+            MemoryDegradation::NoConfirmedSnapshots,
+            MemoryDegradation::NoReplayCapture,
+        ]
+    );
+    let budget = app.world.resource::<MemoryBudget>();
+    assert!(budget.is_exhausted());
+    assert!(app.world.resource::<Rewind>().available_frames() <= 15);
+
+    // the simulation keeps going
+    let frame = **app.world.resource::<RollbackFrame>();
+    assert_eq!(app.world.resource::<FramesSimulated>().0, frame + 1);
+}
+
+/// This test makes sure that the usage includes what saved values own on the heap, and the states of transfers.
+#[test]
+fn usage_counts_heap_contents_and_transfers() {
+    let mut app = build_app(usize::MAX);
+    app.insert_resource(Terrain {
+        heights: vec![1; 10_000],
+        name: "level 1".to_string(),
+    });
+    let mut transfer = StateTransfer::new(MultiplexSocket::new(NoTransport));
+    transfer.send(1, 0, &vec![2; 50_000]);
+    app.insert_resource(transfer);
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let usage = app.world.resource::<MemoryBudget>().usage();
+    assert!(usage.snapshots >= 10_000, "{usage:?}");
+    assert!(usage.history >= 10_000, "{usage:?}");
+    assert!(usage.transfers >= 50_000, "{usage:?}");
+    assert_eq!(
+        usage.total(),
+        usage.snapshots + usage.history + usage.replay + usage.diagnostics + usage.transfers
+    );
+}
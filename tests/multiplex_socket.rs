@@ -0,0 +1,86 @@
+use instant::Duration;
+
+use bevy_ggrs::*;
+use ggrs::*;
+
+mod common;
+use common::{Link, Queue};
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// Returns the sockets of peer 0 and 1, and the queue of datagrams on their way to peer 1.
+fn sockets() -> (MultiplexSocket<usize>, MultiplexSocket<usize>, Queue) {
+    let [a, b]: [_; 2] = Link::network(2).try_into().ok().unwrap();
+    let to_b = b.inbox();
+    (MultiplexSocket::new(a), MultiplexSocket::new(b), to_b)
+}
+
+/// Makes a session on `socket` send its sync requests to peer 1.
+fn send_ggrs_messages(socket: MultiplexSocket<usize>) -> P2PSession<GGRSConfig> {
+    let mut session = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(2)
+        .add_player(PlayerType::Local, 0)
+        .unwrap()
+        .add_player(PlayerType::Remote(1), 1)
+        .unwrap()
+        .start_p2p_session(socket)
+        .unwrap();
+    session.poll_remote_clients();
+    session
+}
+
+/// This test makes sure that GGRS traffic only reaches the socket of the channel it was sent on.
+#[test]
+fn ggrs_channels_are_separate() {
+    let (a, b, _) = sockets();
+    let _session = send_ggrs_messages(a.standby());
+
+    let mut main = b.clone();
+    let mut standby = b.standby();
+    assert!(main.receive_all_messages().is_empty());
+    let received = standby.receive_all_messages();
+    assert!(!received.is_empty());
+    assert!(received.iter().all(|(addr, _)| *addr == 0));
+}
+
+/// This test makes sure that datagrams sent as a batch arrive as the datagrams they were made of.
+#[test]
+fn batches_are_unpacked() {
+    let (a, b, to_b) = sockets();
+    let a = a.with_spectator_batching(vec![1], 1000);
+    let _session = send_ggrs_messages(a.clone());
+
+    // no frames are simulated, so the batch is sent once the socket is polled after a while
+    let mut polled = a;
+    polled.receive_all_messages();
+    assert!(to_b.lock().is_empty());
+    std::thread::sleep(Duration::from_millis(150));
+    polled.receive_all_messages();
+    let datagrams = to_b.lock().len();
+    assert_eq!(datagrams, 1);
+
+    let mut b = b;
+    assert!(!b.receive_all_messages().is_empty());
+}
+
+/// This test makes sure that datagrams of a channel nobody reads don't pile up.
+#[test]
+fn unread_channels_are_capped() {
+    let (_, b, to_b) = sockets();
+    for i in 0..10_000_u32 {
+        let mut datagram = vec![200];
+        datagram.extend_from_slice(&i.to_le_bytes());
+        to_b.lock().push((0, datagram));
+    }
+
+    let mut b = b;
+    assert!(b.receive_all_messages().is_empty());
+    let queued = b.queued_datagrams();
+    assert!(queued > 0);
+    assert!(queued < 1000);
+}
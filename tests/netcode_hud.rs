@@ -0,0 +1,53 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+/// This test makes sure that the frames resimulated by a sync test show up in the HUD, and that a session without
+/// remote players has no connections to show.
+#[test]
+fn rollback_frames_are_shown() {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .build(&mut app);
+
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let hud = app.world.resource::<NetcodeHud>();
+    assert_eq!(
+        hud.rollback_frames_last_second(),
+        app.world.resource::<RollbackStats>().resimulated_frames
+    );
+    assert!(hud.rollback_frames_last_second() > 0);
+    assert_eq!(hud.frames_ahead(), 0);
+    assert_eq!(hud.connections().count(), 0);
+}
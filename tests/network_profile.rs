@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+mod common;
+use common::{Link, Traffic};
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+type Hud = SpectatorHud<u32, usize>;
+
+/// Returns the sockets of peer 0 and 1, and the traffic peer 0 sent.
+fn sockets() -> (
+    MultiplexSocket<usize>,
+    MultiplexSocket<usize>,
+    Arc<Mutex<Traffic>>,
+) {
+    let [a, b]: [_; 2] = Link::network(2).try_into().ok().unwrap();
+    let traffic = a.traffic();
+    (MultiplexSocket::new(a), MultiplexSocket::new(b), traffic)
+}
+
+#[derive(Reflect, Resource, Default, Debug, Hash)]
+#[reflect(Resource, Hash)]
+struct Counter(u32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn count_system(mut counter: ResMut<Counter>, hud: Option<ResMut<Hud>>) {
+    counter.0 += 1;
+    if let Some(mut hud) = hud {
+        hud.set(counter.0);
+    }
+}
+
+fn app(session: Session<GGRSConfig>, profile: NetworkProfile) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(session)
+        .insert_resource(profile)
+        .init_resource::<Counter>();
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_rollback_resource::<Counter>()
+        .register_spectator_hud::<u32>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(count_system),
+        ))
+        .build(&mut app);
+    app
+}
+
+fn p2p(socket: MultiplexSocket<usize>, local: usize, profile: NetworkProfile) -> App {
+    let builder = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(2)
+        .add_player(PlayerType::Local, local)
+        .unwrap()
+        .add_player(PlayerType::Remote(1 - local), 1 - local)
+        .unwrap();
+    let session = profile
+        .configure(builder)
+        .start_p2p_session(socket.clone())
+        .unwrap();
+    let mut app = app(Session::P2PSession(session), profile);
+    app.insert_resource(AdaptiveDesyncCheck::new(socket, vec![1 - local]));
+    app
+}
+
+fn sync_test(profile: NetworkProfile) -> Session<GGRSConfig> {
+    let builder = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(1)
+        .with_check_distance(2)
+        .add_player(PlayerType::Local, 0)
+        .unwrap();
+    Session::SyncTestSession(profile.configure(builder).start_synctest_session().unwrap())
+}
+
+fn update(apps: &mut [&mut App]) {
+    std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+    for app in apps.iter_mut() {
+        app.update();
+    }
+}
+
+/// Runs two peers comparing checksums for a few seconds and returns the number of checks and the interval of
+/// peer 0.
+fn desync_checks(profile: NetworkProfile) -> (usize, u32) {
+    let (a, b, _) = sockets();
+    let (mut a, mut b) = (p2p(a, 0, profile), p2p(b, 1, profile));
+    for _ in 0..240 {
+        update(&mut [&mut a, &mut b]);
+    }
+    let check = a.world.resource::<AdaptiveDesyncCheck<usize>>();
+    (check.checks(), check.interval())
+}
+
+/// Runs a host and a spectator and returns the number of datagrams the host sent, and the last value shown by
+/// the spectator.
+fn spectator_traffic(profile: NetworkProfile) -> (usize, Option<u32>) {
+    let (a, b, sent) = sockets();
+    let mut host = app(sync_test(profile), profile);
+    host.insert_resource(Hud::broadcaster(a, vec![1]));
+    let mut spectator = app(sync_test(profile), profile);
+    spectator.insert_resource(Hud::receiver(b, 0));
+    for _ in 0..60 {
+        update(&mut [&mut host, &mut spectator]);
+    }
+    let shown = spectator.world.resource::<Hud>().get().copied();
+    let sent = sent.lock().sent;
+    (sent, shown)
+}
+
+/// This test makes sure that the low-bandwidth profile sends fewer checksums, without turning the checks off.
+#[test]
+fn low_bandwidth_checks_for_desyncs_less_often() {
+    let (default_checks, default_interval) = desync_checks(NetworkProfile::Default);
+    let (low_checks, low_interval) = desync_checks(NetworkProfile::LowBandwidth);
+
+    assert!(default_interval < 60);
+    assert!(low_interval >= 60);
+    assert!(low_checks > 0);
+    assert!(
+        low_checks < default_checks,
+        "{low_checks} checks with the low-bandwidth profile, {default_checks} without"
+    );
+}
+
+/// This test makes sure that the low-bandwidth profile sends the spectator data in fewer datagrams, which still
+/// arrive.
+#[test]
+fn low_bandwidth_batches_spectator_data() {
+    let (default_sent, default_shown) = spectator_traffic(NetworkProfile::Default);
+    let (low_sent, low_shown) = spectator_traffic(NetworkProfile::LowBandwidth);
+
+    assert!(default_shown.is_some());
+    assert!(low_shown.is_some());
+    assert!(
+        low_sent * 3 < default_sent,
+        "{low_sent} datagrams with the low-bandwidth profile, {default_sent} without"
+    );
+}
+
+/// This test makes sure that the profile only changes settings GGRS accepts.
+#[test]
+fn profile_configures_session() {
+    let builder = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(1)
+        .add_player(PlayerType::Local, 0)
+        .unwrap();
+    let session = NetworkProfile::LowBandwidth
+        .configure(builder)
+        .start_synctest_session();
+    assert!(session.is_ok());
+}
@@ -0,0 +1,79 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Resource, Default, Debug)]
+#[reflect(Resource)]
+struct Counter(u32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0x2a
+}
+
+fn count_system(frame: Res<RollbackFrame>, mut counter: ResMut<Counter>) {
+    if **frame == 5 {
+        panic!("counter broke");
+    }
+    counter.0 += 1;
+}
+
+/// This test makes sure that a panic in the rollback schedule writes the frame, its inputs and the latest snapshot
+/// to the dump, and still reaches the caller.
+#[test]
+fn panics_are_dumped() {
+    let path = std::env::temp_dir().join("bevy_ggrs_panic_dump_test.txt");
+    let _ = std::fs::remove_file(&path);
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .init_resource::<Counter>();
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_panic_dump(&path)
+        .register_rollback_resource::<Counter>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(count_system),
+        ))
+        .build(&mut app);
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        for _ in 0..20 {
+            std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+            app.update();
+        }
+    }));
+    assert!(result.is_err());
+
+    let dump = std::fs::read_to_string(&path).expect("dump should have been written");
+    let lines: Vec<&str> = dump.lines().collect();
+    assert!(lines[0].starts_with('#'));
+    assert!(lines.contains(&"panic: counter broke"));
+    assert!(lines.contains(&"frame: 5"));
+    assert!(lines.contains(&"inputs: 2a:C"));
+    assert!(lines.iter().any(|line| line.starts_with("snapshot: 5 ")));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("resource ") && line.contains("Counter")));
+    let _ = std::fs::remove_file(path);
+}
@@ -0,0 +1,110 @@
+use bevy::{app::AppExit, prelude::*};
+
+use bevy_ggrs::*;
+use ggrs::*;
+
+mod common;
+use common::Link;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// Not rolled back: every `PeerLeft` event so far.
+#[derive(Resource, Default)]
+struct Left(Vec<usize>);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn record_system(mut events: EventReader<PeerLeft<usize>>, mut left: ResMut<Left>) {
+    left.0.extend(events.iter().map(|event| event.addr));
+}
+
+/// A session of the local player 0 and the remote player 1 at address 1.
+fn session(socket: &MultiplexSocket<usize>) -> Session<GGRSConfig> {
+    Session::P2PSession(
+        SessionBuilder::<GGRSConfig>::new()
+            .with_num_players(2)
+            .add_player(PlayerType::Local, 0)
+            .unwrap()
+            .add_player(PlayerType::Remote(1), 1)
+            .unwrap()
+            .start_p2p_session(socket.clone())
+            .unwrap(),
+    )
+}
+
+fn goodbye() -> Vec<u8> {
+    // the goodbye channel, then the goodbye
+    let mut datagram = vec![5];
+    datagram.extend_from_slice(b"bye");
+    datagram
+}
+
+/// This test makes sure that only peers of the session can leave it, each once per session, and again after a
+/// rematch.
+#[test]
+fn peers_leave_each_session_once() {
+    // nobody else is connected, the test delivers the datagrams itself
+    let [link]: [_; 1] = Link::network(1).try_into().ok().unwrap();
+    let queue = link.inbox();
+    let socket = MultiplexSocket::new(link);
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<Left>()
+        .insert_resource(session(&socket))
+        .insert_resource(socket.clone())
+        .add_system(record_system);
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_input_system(input_system)
+        .build(&mut app);
+
+    // address 2 has no player in the session
+    queue
+        .lock()
+        .extend([(2, goodbye()), (1, goodbye()), (1, goodbye())]);
+    app.update();
+    queue.lock().push((1, goodbye()));
+    app.update();
+    assert_eq!(app.world.resource::<Left>().0, vec![1]);
+
+    // a rematch with the same peer
+    app.insert_resource(session(&socket));
+    queue.lock().push((1, goodbye()));
+    app.update();
+    assert_eq!(app.world.resource::<Left>().0, vec![1, 1]);
+}
+
+/// This test makes sure that exiting the app says goodbye to the peer and drops the session and the socket.
+#[test]
+fn exit_leaves_the_session_and_drops_the_socket() {
+    let [link, peer]: [_; 2] = Link::network(2).try_into().ok().unwrap();
+    let peer_inbox = peer.inbox();
+    let socket = MultiplexSocket::new(link);
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(session(&socket))
+        .insert_resource(socket);
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_input_system(input_system)
+        .build(&mut app);
+    app.update();
+
+    app.world.send_event(AppExit);
+    app.update();
+    assert!(!app.world.contains_resource::<Session<GGRSConfig>>());
+    assert!(!app.world.contains_resource::<MultiplexSocket<usize>>());
+    let goodbyes = peer_inbox
+        .lock()
+        .iter()
+        .filter(|(addr, data)| *addr == 0 && *data == goodbye())
+        .count();
+    assert!(goodbyes > 0);
+}
@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use bevy_ggrs::*;
+use ggrs::*;
+
+mod common;
+use common::{Link, Queue};
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+enum Chat {
+    Line(String),
+    Emote(u8),
+}
+
+/// The wire format of a message, to send one from an address of our choice.
+#[derive(Serialize)]
+enum MessagePacket {
+    Message { id: u32, message: Vec<u8> },
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+/// The app of the player at address `local`, which accepts messages from the other player and from address 2,
+/// where the session has no player.
+fn app(socket: MultiplexSocket<usize>, local: PlayerHandle) -> App {
+    let session = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(2)
+        .add_player(PlayerType::Local, local)
+        .unwrap()
+        .add_player(PlayerType::Remote(1 - local), 1 - local)
+        .unwrap()
+        .start_p2p_session(socket.clone())
+        .unwrap();
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::P2PSession(session))
+        .insert_resource(PeerMessages::<Chat, usize>::new(
+            socket,
+            local,
+            vec![1 - local, 2],
+        ));
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_input_system(input_system)
+        .register_peer_messages::<Chat>()
+        .build(&mut app);
+    app
+}
+
+fn received(app: &App) -> Vec<PeerMessage<Chat>> {
+    let events = app.world.resource::<Events<PeerMessage<Chat>>>();
+    events.get_reader().iter(events).cloned().collect()
+}
+
+/// Returns the apps of player 0 and 1, and the queue of datagrams on their way to player 1.
+fn apps() -> ([App; 2], Queue) {
+    let [a, b]: [_; 2] = Link::network(2).try_into().ok().unwrap();
+    let to_b = b.inbox();
+    let apps = [
+        app(MultiplexSocket::new(a), 0),
+        app(MultiplexSocket::new(b), 1),
+    ];
+    (apps, to_b)
+}
+
+/// This test makes sure that messages are delivered once and that the messages of muted players are dropped.
+#[test]
+fn muted_players_are_not_delivered() {
+    let (mut apps, _) = apps();
+    let update = |apps: &mut [App; 2]| {
+        for app in apps.iter_mut() {
+            app.update();
+        }
+    };
+
+    apps[0]
+        .world
+        .resource_mut::<PeerMessages<Chat, usize>>()
+        .send(&Chat::Line("gg".to_string()));
+    update(&mut apps);
+    assert_eq!(
+        received(&apps[1]),
+        vec![PeerMessage {
+            sender: 0,
+            message: Chat::Line("gg".to_string())
+        }]
+    );
+
+    apps[1]
+        .world
+        .resource_mut::<PeerMessages<Chat, usize>>()
+        .mute(0);
+    apps[0]
+        .world
+        .resource_mut::<PeerMessages<Chat, usize>>()
+        .send(&Chat::Emote(3));
+    // the events of the first message are gone after two updates
+    update(&mut apps);
+    update(&mut apps);
+    assert!(received(&apps[1]).is_empty());
+    assert!(apps[1]
+        .world
+        .resource::<PeerMessages<Chat, usize>>()
+        .is_muted(0));
+}
+
+/// This test makes sure that the sender of a message is the player at the address it came from, so messages from
+/// addresses without a player are dropped, and repeated ones are delivered once.
+#[test]
+fn senders_are_taken_from_the_session() {
+    let (mut apps, to_1) = apps();
+    let message = bincode::serialize(&Chat::Emote(1)).unwrap();
+    let packet = |id| {
+        // the channel of peer messages, then the packet
+        let mut datagram = vec![13];
+        datagram.extend(
+            bincode::serialize(&MessagePacket::Message {
+                id,
+                message: message.clone(),
+            })
+            .unwrap(),
+        );
+        datagram
+    };
+    to_1.lock()
+        .extend([(2, packet(0)), (0, packet(5)), (0, packet(5))]);
+
+    apps[1].update();
+    assert_eq!(
+        received(&apps[1]),
+        vec![PeerMessage {
+            sender: 0,
+            message: Chat::Emote(1)
+        }]
+    );
+}
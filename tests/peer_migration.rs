@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+use bevy_ggrs::*;
+use ggrs::*;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+type Queue = Arc<Mutex<Vec<(usize, Vec<u8>)>>>;
+
+const KEY: [u8; 32] = [7; 32];
+
+/// A transport that delivers whatever the test puts into its queue, and collects what is sent through it.
+struct QueueTransport {
+    inbox: Queue,
+    sent: Queue,
+}
+impl DatagramSocket<usize> for QueueTransport {
+    fn send_datagram(&mut self, data: &[u8], addr: &usize) {
+        self.sent.lock().push((*addr, data.to_vec()));
+    }
+    fn receive_datagrams(&mut self) -> Vec<(usize, Vec<u8>)> {
+        std::mem::take(&mut *self.inbox.lock())
+    }
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+/// Returns `count` authenticated datagrams with increasing counters, sent by the peer with the given token and key.
+fn datagrams(key: [u8; 32], token: u64, count: usize) -> Vec<Vec<u8>> {
+    let sent = Queue::default();
+    let socket = MultiplexSocket::new(QueueTransport {
+        inbox: Queue::default(),
+        sent: sent.clone(),
+    })
+    .with_peer_tokens(key, token, vec![(0, 1)]);
+    // every new session sends a sync request once polled
+    for _ in 0..count {
+        let mut session = SessionBuilder::<GGRSConfig>::new()
+            .with_num_players(2)
+            .add_player(PlayerType::Local, 0)
+            .unwrap()
+            .add_player(PlayerType::Remote(0), 1)
+            .unwrap()
+            .start_p2p_session(socket.clone())
+            .unwrap();
+        session.poll_remote_clients();
+    }
+    let datagrams: Vec<_> = sent.lock().drain(..).map(|(_, data)| data).collect();
+    assert!(datagrams.len() >= count);
+    datagrams
+}
+
+/// Returns the address changes the app reported.
+fn changes(app: &App) -> Vec<PeerAddressChanged<usize>> {
+    let events = app.world.resource::<Events<PeerAddressChanged<usize>>>();
+    events.get_reader().iter(events).cloned().collect()
+}
+
+fn app(inbox: Queue) -> App {
+    let socket = MultiplexSocket::new(QueueTransport {
+        inbox,
+        sent: Queue::default(),
+    })
+    .with_peer_tokens(KEY, 1, vec![(10, 2), (20, 3)]);
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins).insert_resource(socket);
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_input_system(input_system)
+        .build(&mut app);
+    app
+}
+
+/// This test makes sure that traffic with a known token from a new address is reported as an address change, while
+/// traffic with unknown tokens or without the right key is ignored.
+#[test]
+fn address_changes_are_detected_by_token() {
+    let queue = Queue::default();
+    let mut app = app(queue.clone());
+
+    let mut forged = datagrams(KEY, 2, 1).remove(0);
+    *forged.last_mut().unwrap() ^= 1;
+    queue.lock().extend([
+        (10, datagrams(KEY, 2, 1).remove(0)),
+        (11, datagrams(KEY, 3, 1).remove(0)),
+        (12, datagrams(KEY, 99, 1).remove(0)),
+        (13, datagrams([8; 32], 2, 1).remove(0)),
+        (14, forged),
+    ]);
+    app.update();
+    app.update();
+
+    assert_eq!(
+        changes(&app),
+        vec![PeerAddressChanged {
+            addr: 20,
+            old: 20,
+            new: 11
+        }]
+    );
+}
+
+/// This test makes sure that a peer only moves to an address with a datagram newer than the ones received before,
+/// so reordered or replayed datagrams don't move it back.
+#[test]
+fn old_datagrams_do_not_move_peers() {
+    let queue = Queue::default();
+    let mut app = app(queue.clone());
+
+    let datagrams = datagrams(KEY, 3, 2);
+    let (old, new) = (datagrams[0].clone(), datagrams[datagrams.len() - 1].clone());
+    queue
+        .lock()
+        .extend([(11, new), (20, old.clone()), (12, old)]);
+    app.update();
+    app.update();
+
+    assert_eq!(
+        changes(&app),
+        vec![PeerAddressChanged {
+            addr: 20,
+            old: 20,
+            new: 11
+        }]
+    );
+}
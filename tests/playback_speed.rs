@@ -0,0 +1,57 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+/// This test makes sure that a paused session does not advance until it is resumed.
+#[test]
+fn paused_session_does_not_advance() {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .build(&mut app);
+
+    app.world.resource_mut::<PlaybackSpeed>().pause();
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+    assert!(app.world.get_resource::<RollbackFrame>().is_none());
+
+    app.world.resource_mut::<PlaybackSpeed>().resume();
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+    assert!(app.world.get_resource::<RollbackFrame>().is_some());
+}
+
+#[test]
+fn playback_rate_is_clamped() {
+    assert_eq!(PlaybackSpeed::new(10.).rate(), PlaybackSpeed::MAX_RATE);
+    assert_eq!(PlaybackSpeed::new(0.).rate(), PlaybackSpeed::MIN_RATE);
+}
@@ -0,0 +1,124 @@
+use bevy::{ecs::system::Command, prelude::*};
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Component, Reflect, Default, Debug, Clone, Hash)]
+#[reflect(Component, Hash)]
+struct Bullet {
+    ttl: u32,
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+/// Releases bullets whose time is up.
+fn expire_system(mut pool: PoolQuery<With<Bullet>>, mut bullets: Query<&mut Bullet>) {
+    let active: Vec<Entity> = pool
+        .iter()
+        .filter(|(_, _, slot)| slot.is_active())
+        .map(|(entity, _, _)| entity)
+        .collect();
+    for entity in active {
+        let mut bullet = bullets.get_mut(entity).unwrap();
+        bullet.ttl -= 1;
+        if bullet.ttl == 0 {
+            pool.release(entity);
+        }
+    }
+}
+
+/// Fires one bullet per frame.
+fn fire_system(mut pool: PoolQuery<With<Bullet>>, mut bullets: Query<&mut Bullet>) {
+    if let Some(entity) = pool.acquire() {
+        bullets.get_mut(entity).unwrap().ttl = 3;
+    }
+}
+
+/// This test makes sure that a pool spawns inactive entities with the same rollback ids on every peer.
+#[test]
+fn pool_spawns_inactive_entities() {
+    let mut world = World::new();
+    SpawnPool::new("bullets", Bullet::default(), 4).write(&mut world);
+
+    let mut query = world.query::<(&Rollback, &PoolSlot)>();
+    let mut slots: Vec<_> = query
+        .iter(&world)
+        .map(|(rollback, slot)| (slot.index(), rollback.id(), slot.is_active()))
+        .collect();
+    slots.sort_unstable();
+    let pool = Rollback::from_name("bullets");
+    let expected: Vec<_> = (0..4)
+        .map(|index| (index, Rollback::derived(&pool, index).id(), false))
+        .collect();
+    assert_eq!(slots, expected);
+    assert_eq!(world.resource::<SnapshotCapacity>().entities, 4);
+}
+
+/// This test makes sure that acquiring and releasing pooled entities survives the rollbacks of a sync test, and
+/// that released entities are handed out again.
+#[test]
+fn pool_is_rollback_consistent() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_rollback_component::<Bullet>()
+        .with_rollback_schedule(
+            Schedule::default().with_stage(
+                "default",
+                SystemStage::single_threaded()
+                    .with_system(expire_system)
+                    .with_system(fire_system.after(expire_system)),
+            ),
+        )
+        .build(&mut app);
+
+    SpawnPool::new("bullets", Bullet::default(), 5).write(&mut app.world);
+
+    for _ in 0..30 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+    assert!(**app.world.resource::<RollbackFrame>() > 5);
+
+    // every bullet lives for three frames
+    let mut query = app.world.query::<(&PoolSlot, &Bullet)>();
+    let mut ttls: Vec<_> = query
+        .iter(&app.world)
+        .filter(|(slot, _)| slot.is_active())
+        .map(|(_, bullet)| bullet.ttl)
+        .collect();
+    ttls.sort_unstable();
+    assert_eq!(ttls, vec![1, 2, 3]);
+    assert_eq!(app.world.query::<&PoolSlot>().iter(&app.world).count(), 5);
+}
+
+/// This test makes sure that a pool doesn't reuse the rollback ids of entities spawned before.
+#[test]
+#[should_panic(expected = "is taken already")]
+fn pool_ids_are_unique() {
+    let mut world = World::new();
+    SpawnPool::new("bullets", Bullet::default(), 4).write(&mut world);
+    SpawnPool::new("bullets", Bullet::default(), 4).write(&mut world);
+}
@@ -0,0 +1,150 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Component, Reflect, Default, Debug, Clone, Hash)]
+#[reflect(Component, Hash)]
+struct Tower {
+    height: u32,
+}
+
+/// Stands in for a mesh, which is not part of the snapshots.
+#[derive(Component)]
+struct TowerMesh;
+
+struct TowerPrefab;
+
+impl Prefab for TowerPrefab {
+    const NAME: &'static str = "tower";
+    type Params = u32;
+
+    fn spawn(height: &u32, spawner: &mut PrefabSpawner) {
+        let root = spawner.spawn(Tower { height: *height });
+        let mesh = spawner.spawn_presentation(TowerMesh);
+        spawner.world().entity_mut(root).push_children(&[mesh]);
+    }
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn build_towers_system(mut commands: Commands, frame: Res<RollbackFrame>) {
+    if **frame == 3 {
+        commands.add(SpawnPrefab::<TowerPrefab>::new(5));
+    }
+}
+
+fn demolish_towers_system(
+    mut commands: Commands,
+    frame: Res<RollbackFrame>,
+    towers: Query<Entity, With<Tower>>,
+) {
+    if **frame == 6 {
+        for tower in towers.iter() {
+            commands.entity(tower).despawn_recursive();
+        }
+    }
+}
+
+/// The number of meshes in every simulation of frame 5, resimulations included. Not part of the snapshots.
+#[derive(Resource, Default)]
+struct MeshesAtFrame5(Vec<usize>);
+
+fn count_meshes_system(
+    frame: Res<RollbackFrame>,
+    meshes: Query<&TowerMesh>,
+    mut counts: ResMut<MeshesAtFrame5>,
+) {
+    if **frame == 5 {
+        counts.0.push(meshes.iter().count());
+    }
+}
+
+fn build_app(demolish: bool) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .init_resource::<MeshesAtFrame5>();
+
+    let mut stage = SystemStage::single_threaded()
+        .with_system(build_towers_system)
+        .with_system(count_meshes_system);
+    if demolish {
+        stage = stage.with_system(demolish_towers_system);
+    }
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_rollback_component::<Tower>()
+        .register_prefab::<TowerPrefab>()
+        .with_rollback_schedule(Schedule::default().with_stage("default", stage))
+        .build(&mut app);
+    app
+}
+
+fn run(app: &mut App) {
+    for _ in 0..30 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+    assert!(**app.world.resource::<RollbackFrame>() > 10);
+}
+
+/// This test makes sure that rollbacks past the spawn of a prefab neither duplicate nor leak its entities, and that
+/// the prefab keeps its rollback id.
+#[test]
+fn resimulated_prefab_spawns_once() {
+    let mut app = build_app(false);
+    run(&mut app);
+
+    let mut towers = app.world.query::<(Entity, &Rollback, &Tower, &Children)>();
+    let (root, rollback, tower, children) = towers.single(&app.world);
+    let expected = Rollback::derived(&Rollback::derived(&Rollback::from_name("tower"), 3), 0);
+    assert_eq!(rollback.id(), expected.id());
+    assert_eq!(tower.height, 5);
+
+    let mut meshes = app
+        .world
+        .query_filtered::<(Entity, &Parent), With<TowerMesh>>();
+    let (mesh, parent) = meshes.single(&app.world);
+    assert_eq!(parent.get(), root);
+    assert_eq!(&children[..], &[mesh]);
+
+    let log = app.world.resource::<PrefabLog>();
+    assert_eq!(log.iter().collect::<Vec<_>>(), vec![(3, "tower", root)]);
+}
+
+/// This test makes sure that a prefab despawned during the simulation is spawned again, presentation entities
+/// included, when a rollback goes back to before the despawn; and that nothing is left once the despawn is final.
+#[test]
+fn despawned_prefab_is_respawned_by_rollback() {
+    let mut app = build_app(true);
+    run(&mut app);
+
+    // frame 5 is simulated again after the despawn in frame 6
+    let counts = &app.world.resource::<MeshesAtFrame5>().0;
+    assert!(counts.len() > 1);
+    assert!(counts.iter().all(|count| *count == 1));
+
+    assert_eq!(app.world.query::<&Tower>().iter(&app.world).count(), 0);
+    assert_eq!(app.world.query::<&TowerMesh>().iter(&app.world).count(), 0);
+    assert!(app.world.resource::<PrefabLog>().is_empty());
+}
@@ -0,0 +1,78 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Explosion;
+
+#[derive(Resource, Default)]
+struct Received(Vec<PresentationEvent<Explosion>>);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn explode_system(frame: Res<RollbackFrame>, mut events: ResMut<PresentationEvents<Explosion>>) {
+    if **frame == 3 {
+        events.send(Explosion);
+    }
+}
+
+fn receive_system(
+    mut reader: EventReader<PresentationEvent<Explosion>>,
+    mut received: ResMut<Received>,
+) {
+    received.0.extend(reader.iter().cloned());
+}
+
+/// This test makes sure that an event sent from a frame that is resimulated by the sync test is delivered once.
+#[test]
+fn presentation_events_are_delivered_once() {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .init_resource::<Received>()
+        .add_system(receive_system)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_presentation_event::<Explosion>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(explode_system),
+        ))
+        .build(&mut app);
+
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let received = &app.world.resource::<Received>().0;
+    assert_eq!(
+        received,
+        &vec![PresentationEvent::Triggered {
+            frame: 3,
+            event: Explosion
+        }]
+    );
+}
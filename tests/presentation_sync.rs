@@ -0,0 +1,150 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// Not rolled back, so only the first simulation of a frame sees it unset.
+#[derive(Resource, Default)]
+struct Mispredicted(bool);
+
+#[derive(Resource)]
+struct DespawnAt(i32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+/// Despawns the owner in frame `DespawnAt`, only during the first simulation of that frame if `Mispredicted`
+/// exists, like a despawn caused by a wrongly predicted input.
+fn despawn_system(
+    mut commands: Commands,
+    frame: Res<RollbackFrame>,
+    despawn_at: Res<DespawnAt>,
+    mispredicted: Option<ResMut<Mispredicted>>,
+    query: Query<Entity, With<Rollback>>,
+) {
+    if **frame != despawn_at.0 {
+        return;
+    }
+    if let Some(mut mispredicted) = mispredicted {
+        if mispredicted.0 {
+            return;
+        }
+        mispredicted.0 = true;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn app(mispredicted: bool, despawn_at: i32) -> (App, Entity, Entity) {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(DespawnAt(despawn_at))
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+    if mispredicted {
+        app.init_resource::<Mispredicted>();
+    }
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(despawn_system),
+        ))
+        .build(&mut app);
+
+    let owner = app.world.spawn(Rollback::new(0)).id();
+    let health_bar = app
+        .world
+        .spawn((
+            SpatialBundle::default(),
+            PresentationOf::child_of(&Rollback::new(0)),
+        ))
+        .id();
+    (app, owner, health_bar)
+}
+
+fn run(app: &mut App, updates: usize) {
+    for _ in 0..updates {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+}
+
+/// This test makes sure that presentation entities are hidden while their rollback owner doesn't exist.
+#[test]
+fn presentation_is_hidden_without_owner() {
+    let (mut app, owner, health_bar) = app(false, 5);
+
+    app.update();
+    assert_eq!(app.world.get::<Parent>(health_bar).unwrap().get(), owner);
+    assert_eq!(
+        app.world
+            .get::<PresentationOf>(health_bar)
+            .unwrap()
+            .target(),
+        Some(owner)
+    );
+
+    run(&mut app, 20);
+    assert!(app.world.get_entity(owner).is_none());
+    assert!(!app.world.get::<Visibility>(health_bar).unwrap().is_visible);
+    assert_eq!(
+        app.world
+            .get::<PresentationOf>(health_bar)
+            .unwrap()
+            .target(),
+        None
+    );
+}
+
+/// This test makes sure that presentation entities follow their rollback owner when a rollback undoes its despawn,
+/// which respawns it as a new entity.
+#[test]
+fn presentation_follows_respawned_owner() {
+    let (mut app, owner, health_bar) = app(true, 5);
+
+    run(&mut app, 20);
+    assert!(app.world.resource::<Mispredicted>().0);
+    assert!(app.world.get_entity(owner).is_none());
+
+    let mut query = app.world.query_filtered::<Entity, With<Rollback>>();
+    let respawned = query.single(&app.world);
+    assert_ne!(respawned, owner);
+    assert_eq!(
+        app.world.get::<Parent>(health_bar).unwrap().get(),
+        respawned
+    );
+    assert!(app.world.get::<Visibility>(health_bar).unwrap().is_visible);
+}
+
+/// This test makes sure that presentation entities attached after the first snapshot are listed among the children
+/// of their owner again after a rollback to that snapshot, which restores the children without them.
+#[test]
+fn presentation_stays_a_child_after_rollback() {
+    let (mut app, owner, health_bar) = app(false, i32::MAX);
+
+    run(&mut app, 10);
+    assert!(**app.world.resource::<RollbackFrame>() > 2);
+    assert_eq!(app.world.get::<Parent>(health_bar).unwrap().get(), owner);
+    let children = app.world.get::<Children>(owner).unwrap();
+    assert!(children.contains(&health_bar));
+}
@@ -0,0 +1,24 @@
+use bevy::{ecs::system::Command, prelude::*};
+
+use bevy_ggrs::*;
+
+#[derive(Component, Default, Clone)]
+struct Bullet {
+    _speed: f32,
+}
+
+/// This test makes sure that prewarming leaves no entities behind, leaves real rollback entities alone and reserves
+/// snapshot space.
+#[test]
+fn prewarming_leaves_no_entities() {
+    let mut world = World::new();
+    let archetypes = world.archetypes().len();
+    let bullet = world.spawn((Bullet::default(), Rollback::new(0))).id();
+
+    PrewarmRollback::new(Bullet::default(), 100).write(&mut world);
+
+    let remaining: Vec<_> = world.query::<(Entity, &Rollback)>().iter(&world).collect();
+    assert_eq!(remaining, vec![(bullet, &Rollback::new(0))]);
+    assert!(world.archetypes().len() > archetypes);
+    assert_eq!(world.resource::<SnapshotCapacity>().entities, 100);
+}
@@ -0,0 +1,63 @@
+use instant::{Duration, Instant};
+
+use bevy_ggrs::*;
+
+mod common;
+use common::Link;
+
+/// Returns the sockets of peer 0 and 1, which drop every `drop_every`-th datagram they send.
+fn sockets(drop_every: usize) -> (MultiplexSocket<usize>, MultiplexSocket<usize>) {
+    let [a, b]: [_; 2] = Link::network(2).try_into().ok().unwrap();
+    (
+        MultiplexSocket::new(a.dropping_every(drop_every)),
+        MultiplexSocket::new(b.dropping_every(drop_every)),
+    )
+}
+
+/// Probes peer 1 from peer 0 until the report is based on `samples` round trips, for at most ten seconds.
+fn probe(drop_every: usize, samples: usize) -> ProbeReport {
+    let (socket_0, socket_1) = sockets(drop_every);
+    let mut prober = ConnectionProbe::new(socket_0);
+    // the candidate only answers
+    let mut candidate = ConnectionProbe::new(socket_1);
+    prober.probe(1);
+
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(10) {
+        std::thread::sleep(Duration::from_millis(10));
+        prober.poll();
+        candidate.poll();
+        if matches!(prober.report(&1), Some(report) if report.samples >= samples) {
+            break;
+        }
+    }
+    assert_eq!(candidate.report(&0), None);
+    prober
+        .report(&1)
+        .expect("the probe should have measured a round trip")
+}
+
+/// This test makes sure that the probe measures the round trip of the GGRS session towards a candidate, which
+/// answers without probing itself.
+#[test]
+fn probe_measures_the_round_trip() {
+    let report = probe(0, 3);
+
+    assert!(report.samples >= 3);
+    assert!(report.ping_ms < 100., "{report:?}");
+    assert_eq!(report.packet_loss, 0.);
+    assert_eq!(
+        report.recommended_input_delay(60),
+        report.one_way_frames(60)
+    );
+}
+
+/// This test makes sure that lost datagrams show up as packet loss, measured over the same samples as the ping.
+#[test]
+fn lost_datagrams_count_as_loss() {
+    let report = probe(4, 10);
+
+    assert!(report.samples >= 10);
+    assert!(report.packet_loss > 0.1, "{report:?}");
+    assert!(report.packet_loss < 0.5, "{report:?}");
+}
@@ -0,0 +1,108 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// The inputs of both players for every simulated frame.
+#[derive(Resource, Default)]
+struct Simulated(Vec<[u8; 2]>);
+
+#[derive(Reflect, Resource, Default, Debug)]
+#[reflect(Resource)]
+struct Total(u32);
+
+fn live_input_system(_: In<PlayerHandle>) -> u8 {
+    7
+}
+
+fn record_system(
+    inputs: Res<PlayerInputs<GGRSConfig>>,
+    mut simulated: ResMut<Simulated>,
+    mut total: ResMut<Total>,
+) {
+    simulated.0.push([inputs[0].0, inputs[1].0]);
+    total.0 += inputs[0].0 as u32 + inputs[1].0 as u32;
+}
+
+fn replay_app(rewind_history: usize) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(2)
+                .with_check_distance(0)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .add_player(PlayerType::Local, 1)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .insert_resource(ReplayBranch::<GGRSConfig>::new(vec![vec![1, 2]; 100]))
+        .init_resource::<Simulated>()
+        .init_resource::<Total>();
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(live_input_system)
+        .with_rewind_history(rewind_history)
+        .register_rollback_resource::<Total>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(record_system),
+        ))
+        .build(&mut app);
+    app
+}
+
+fn update(app: &mut App, times: usize) {
+    for _ in 0..times {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+}
+
+/// This test makes sure that after branching, the live player uses the input system while the other player keeps
+/// following the replay.
+#[test]
+fn branched_player_is_controlled_live() {
+    let mut app = replay_app(0);
+    update(&mut app, 10);
+
+    let mut replay = app.world.resource_mut::<ReplayBranch<GGRSConfig>>();
+    let branch = replay.frame();
+    replay.branch(1);
+    assert_eq!(replay.live_player(), Some(1));
+    update(&mut app, 10);
+
+    let simulated = &app.world.resource::<Simulated>().0;
+    assert!(simulated.len() > branch);
+    assert!(simulated[..branch].iter().all(|inputs| *inputs == [1, 2]));
+    assert!(simulated[branch..].iter().all(|inputs| *inputs == [1, 7]));
+}
+
+/// This test makes sure that rewinding the world also moves the replay back, so branching after a rewind starts
+/// from the rewound frame.
+#[test]
+fn rewinding_moves_the_replay_back() {
+    let mut app = replay_app(60);
+    update(&mut app, 20);
+
+    let before = app.world.resource::<ReplayBranch<GGRSConfig>>().frame();
+    app.world.resource_mut::<Rewind>().rewind_frames(5);
+    app.world.resource_mut::<PlaybackSpeed>().pause();
+    update(&mut app, 1);
+
+    let replay = app.world.resource::<ReplayBranch<GGRSConfig>>();
+    assert_eq!(replay.frame(), before - 5);
+    // the rewound state matches the inputs replayed up to that frame
+    assert_eq!(app.world.resource::<Total>().0, 3 * replay.frame() as u32);
+}
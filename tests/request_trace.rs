@@ -0,0 +1,53 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0x2a
+}
+
+/// This test makes sure that the saves, loads and advances of a session end up in the trace file.
+#[test]
+fn requests_are_traced() {
+    let path = std::env::temp_dir().join("bevy_ggrs_request_trace_test.txt");
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_request_trace(&path)
+        .build(&mut app);
+
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let trace = std::fs::read_to_string(&path).expect("trace should have been written");
+    let lines: Vec<&str> = trace.lines().collect();
+    assert!(lines[0].starts_with('#'));
+    assert!(lines.iter().any(|line| line.starts_with("S 0 ")));
+    assert!(lines.iter().any(|line| line.starts_with("L ")));
+    assert!(lines.contains(&"A 0 2a:C"));
+    let _ = std::fs::remove_file(path);
+}
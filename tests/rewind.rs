@@ -0,0 +1,71 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Resource, Default, Debug)]
+#[reflect(Resource)]
+struct FramesSimulated(i32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn count_system(mut count: ResMut<FramesSimulated>) {
+    count.0 += 1;
+}
+
+/// This test makes sure that rewinding restores the state of an earlier frame, while the session keeps going.
+#[test]
+fn rewind_restores_earlier_state() {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(0)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .init_resource::<FramesSimulated>();
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_rewind_history(60)
+        .register_rollback_resource::<FramesSimulated>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(count_system),
+        ))
+        .build(&mut app);
+
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let frame = **app.world.resource::<RollbackFrame>();
+    assert_eq!(app.world.resource::<FramesSimulated>().0, frame + 1);
+    assert!(app.world.resource::<Rewind>().available_frames() >= 5);
+
+    app.world.resource_mut::<Rewind>().rewind_frames(5);
+    std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+    app.update();
+
+    // the frames after the rewind were simulated starting from the state of 5 frames ago
+    let frame = **app.world.resource::<RollbackFrame>();
+    assert!(!app.world.resource::<Rewind>().is_pending());
+    assert_eq!(app.world.resource::<FramesSimulated>().0, frame + 1 - 5);
+}
@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, FromReflect, Debug, Clone, PartialEq)]
+struct Scored(i32);
+
+/// Every event read by the systems, in the order they saw them.
+#[derive(Reflect, Resource, Default, Debug)]
+#[reflect(Resource)]
+struct Received {
+    next_frame: Vec<i32>,
+    same_frame: Vec<i32>,
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn send_system(frame: Res<RollbackFrame>, mut events: ResMut<RollbackEvents<Scored>>) {
+    events.send(Scored(**frame));
+    events.send(Scored(-**frame));
+}
+
+fn read_system(events: Res<RollbackEvents<Scored>>, mut received: ResMut<Received>) {
+    let events: Vec<_> = events.read().map(|event| event.0).collect();
+    received.next_frame.extend(events);
+}
+
+fn read_current_system(events: Res<RollbackEvents<Scored>>, mut received: ResMut<Received>) {
+    let events: Vec<_> = events.read_current().map(|event| event.0).collect();
+    received.same_frame.extend(events);
+}
+
+/// This test makes sure that every event is read exactly once and in order, although a sync test rolls back and
+/// resimulates frames all the time.
+#[test]
+fn events_are_delivered_once_in_order() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .init_resource::<Received>();
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_rollback_event::<Scored>()
+        .register_rollback_resource::<Received>()
+        .with_rollback_schedule(
+            Schedule::default().with_stage(
+                "default",
+                SystemStage::single_threaded()
+                    .with_system(read_system)
+                    .with_system(send_system.after(read_system))
+                    .with_system(read_current_system.after(send_system)),
+            ),
+        )
+        .build(&mut app);
+
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let last_frame = **app.world.resource::<RollbackFrame>();
+    assert!(last_frame > 2);
+    let sent = |frames: std::ops::RangeInclusive<i32>| -> Vec<i32> {
+        frames.flat_map(|frame| [frame, -frame]).collect()
+    };
+    let received = app.world.resource::<Received>();
+    assert_eq!(received.same_frame, sent(0..=last_frame));
+    assert_eq!(received.next_frame, sent(0..=last_frame - 1));
+}
@@ -0,0 +1,101 @@
+use bevy::{prelude::*, reflect::GetTypeRegistration};
+use instant::Duration;
+
+use bevy_ggrs::*;
+use ggrs::*;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+#[test]
+fn named_ids_are_stable() {
+    // FNV-1a of "player" with the derived bit set, this must never change
+    assert_eq!(
+        Rollback::from_name("player").id(),
+        0x2c99_c300 | Rollback::DERIVED_BIT
+    );
+    assert_eq!(Rollback::from_name("player"), Rollback::from_name("player"));
+    assert_ne!(Rollback::from_name("player"), Rollback::from_name("enemy"));
+}
+
+#[test]
+fn derived_ids_differ_by_index() {
+    let parent = Rollback::new(3);
+    let first = Rollback::derived(&parent, 0);
+    let second = Rollback::derived(&parent, 1);
+    assert_ne!(first, second);
+    assert_eq!(first, Rollback::derived(&parent, 0));
+    assert!(first.id() & Rollback::DERIVED_BIT != 0);
+}
+
+#[test]
+fn provider_ids_never_collide_with_derived_ids() {
+    let mut provider = RollbackIdProvider::default();
+    for _ in 0..100 {
+        assert_eq!(provider.next().id() & Rollback::DERIVED_BIT, 0);
+    }
+}
+
+#[test]
+fn rollback_tags_are_ordered_by_id() {
+    let mut tags = vec![Rollback::new(5), Rollback::new(1), Rollback::new(3)];
+    tags.sort();
+    assert_eq!(
+        tags,
+        vec![Rollback::new(1), Rollback::new(3), Rollback::new(5)]
+    );
+}
+
+/// This test makes sure that saving a world with two entities of the same rollback id panics, instead of mixing
+/// up their states on the next load.
+#[test]
+#[should_panic(expected = "have the same rollback id")]
+fn duplicate_ids_panic_when_saving() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .build(&mut app);
+
+    let parent = Rollback::from_name("spawner");
+    app.world.spawn(Rollback::derived(&parent, 0));
+    app.world.spawn(Rollback::derived(&parent, 0));
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+}
+
+/// This test makes sure that inserting a reflected `Rollback`, as scenes do, keeps the reflected id and doesn't
+/// use up an id of the `RollbackIdProvider`.
+#[test]
+fn reflected_rollback_keeps_its_id() {
+    let mut world = World::new();
+    world.init_resource::<RollbackIdProvider>();
+    let registration = Rollback::get_type_registration();
+    let reflect_component = registration
+        .data::<ReflectComponent>()
+        .expect("Rollback should reflect Component");
+
+    let entity = world.spawn_empty().id();
+    reflect_component.insert(&mut world, entity, &Rollback::new(7));
+    assert_eq!(world.get::<Rollback>(entity), Some(&Rollback::new(7)));
+    assert_eq!(world.resource_mut::<RollbackIdProvider>().next_id(), 0);
+}
@@ -0,0 +1,23 @@
+use bevy_ggrs::*;
+use bytemuck::{Pod, Zeroable};
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Pod, Zeroable)]
+struct ButtonInput {
+    buttons: u16,
+    stick: [i8; 2],
+}
+
+fn is_rollback_input<I: RollbackInput>() -> bool {
+    true
+}
+
+/// This test makes sure that the common input types are accepted as rollback inputs.
+#[test]
+fn common_inputs_are_rollback_inputs() {
+    assert!(is_rollback_input::<u8>());
+    assert!(is_rollback_input::<u32>());
+    // inputs packed with `pack_input()`
+    assert!(is_rollback_input::<[u8; 4]>());
+    assert!(is_rollback_input::<ButtonInput>());
+}
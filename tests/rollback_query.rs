@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+
+#[derive(Component, Default)]
+struct Position(i32);
+
+#[derive(Resource, Default)]
+struct Counts {
+    simulated: usize,
+    presentation: usize,
+}
+
+fn move_system(mut query: RollbackQuery<&mut Position>) {
+    for mut position in query.iter_mut() {
+        position.0 += 1;
+    }
+}
+
+fn count_system(
+    simulated: RollbackQuery<Entity, With<Position>>,
+    presentation: NonRollback<Entity, With<Position>>,
+    mut counts: ResMut<Counts>,
+) {
+    counts.simulated = simulated.iter().count();
+    counts.presentation = presentation.iter().count();
+}
+
+/// This test makes sure that the query aliases separate rollback and presentation entities.
+#[test]
+fn queries_split_rollback_and_presentation_entities() {
+    let mut app = App::new();
+    app.init_resource::<Counts>()
+        .add_system(move_system)
+        .add_system(count_system.after(move_system));
+
+    let simulated = app.world.spawn((Rollback::new(0), Position(0))).id();
+    let presentation = app.world.spawn(Position(0)).id();
+    app.update();
+
+    assert_eq!(app.world.get::<Position>(simulated).unwrap().0, 1);
+    assert_eq!(app.world.get::<Position>(presentation).unwrap().0, 0);
+    let counts = app.world.resource::<Counts>();
+    assert_eq!((counts.simulated, counts.presentation), (1, 1));
+}
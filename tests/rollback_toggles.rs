@@ -0,0 +1,218 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Component, Default, Debug)]
+#[reflect(Component)]
+struct Counter(u32);
+
+#[derive(Reflect, Component, Default, Debug)]
+#[reflect(Component)]
+struct Constant(u32);
+
+/// Part of the checksums.
+#[derive(Reflect, Component, Default, Debug, Hash)]
+#[reflect(Component, Hash)]
+struct Ticks(u32);
+
+/// Not rolled back: how often each frame was simulated.
+#[derive(Resource, Default)]
+struct Simulations(HashMap<i32, usize>);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn count_system(mut query: Query<&mut Counter>) {
+    for mut counter in query.iter_mut() {
+        counter.0 += 1;
+    }
+}
+
+fn tick_system(
+    frame: Res<RollbackFrame>,
+    mut query: Query<&mut Ticks>,
+    mut simulations: ResMut<Simulations>,
+) {
+    for mut ticks in query.iter_mut() {
+        ticks.0 += 1;
+    }
+    *simulations.0.entry(**frame).or_default() += 1;
+}
+
+fn app(toggles: RollbackToggles) -> App {
+    app_with(toggles, 0)
+}
+
+/// Builds an app whose rollback entity starts with `ticks`.
+fn app_with(toggles: RollbackToggles, ticks: u32) -> App {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(toggles)
+        .init_resource::<Simulations>()
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_snapshot_stats(true)
+        .with_sync_test_bisection(true)
+        .register_rollback_component::<Counter>()
+        .register_rollback_component::<Constant>()
+        .register_rollback_component::<Ticks>()
+        .with_rollback_schedule(
+            Schedule::default().with_stage(
+                "default",
+                SystemStage::single_threaded()
+                    .with_system(count_system)
+                    .with_system(tick_system),
+            ),
+        )
+        .build(&mut app);
+
+    app.world
+        .spawn((Rollback::new(0), Counter(0), Constant(7), Ticks(ticks)));
+    app
+}
+
+fn run(app: &mut App, updates: usize) {
+    for _ in 0..updates {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+}
+
+/// Counts how many more times the counter was incremented than frames were simulated.
+fn drift(app: &mut App) -> i64 {
+    let frame = **app.world.resource::<RollbackFrame>() as i64;
+    let counter = app.world.query::<&Counter>().single(&app.world).0 as i64;
+    counter - frame
+}
+
+/// This test makes sure that an excluded type is neither saved nor restored.
+#[test]
+fn excluded_types_are_left_out_of_the_snapshots() {
+    let mut toggles = RollbackToggles::default();
+    toggles.exclude::<Counter>();
+    let mut app = app(toggles);
+    run(&mut app, 20);
+
+    let stats = app.world.resource::<SnapshotStats>();
+    assert!(stats.get(std::any::type_name::<Counter>()).is_none());
+    assert!(stats.get(std::any::type_name::<Constant>()).is_some());
+    // every resimulation of the sync test counts again
+    assert!(drift(&mut app) > 5);
+
+    let mut app = self::app(RollbackToggles::default());
+    run(&mut app, 20);
+    assert!(drift(&mut app).abs() <= 1);
+}
+
+/// This test makes sure that types excluded by their short name can be included again at runtime.
+#[test]
+fn excluded_types_can_be_included_again() {
+    let mut toggles = RollbackToggles::default();
+    toggles.exclude_by_name("Counter");
+    let mut app = app(toggles);
+    run(&mut app, 10);
+    assert!(drift(&mut app) > 2);
+    assert!(app
+        .world
+        .resource::<RollbackToggles>()
+        .is_excluded::<Counter>());
+
+    app.world.resource_mut::<RollbackToggles>().include_all();
+    run(&mut app, 5);
+    let included = drift(&mut app);
+    run(&mut app, 10);
+    assert_eq!(drift(&mut app), included);
+}
+
+/// This test makes sure that names and types of registered rollback types refer to the same exclusion.
+#[test]
+fn names_and_types_are_interchangeable() {
+    let mut app = app(RollbackToggles::default());
+    app.update();
+
+    let mut toggles = app.world.resource_mut::<RollbackToggles>();
+    toggles.exclude_by_name("Counter");
+    assert!(toggles.is_excluded::<Counter>());
+    toggles.include::<Counter>();
+    assert!(!toggles.is_excluded::<Counter>());
+
+    toggles.exclude::<Counter>();
+    toggles.include_by_name("Counter");
+    assert!(!toggles.is_excluded::<Counter>());
+
+    toggles.exclude_by_name(std::any::type_name::<Counter>());
+    assert_eq!(
+        toggles.excluded().collect::<Vec<_>>(),
+        vec![std::any::type_name::<Counter>()]
+    );
+}
+
+/// Returns the checksums of the last snapshots saved, by frame.
+fn checksums(app: &App) -> HashMap<i32, u64> {
+    app.world
+        .resource::<SessionDiagnostics>()
+        .snapshots()
+        .map(|snapshot| (snapshot.frame, snapshot.checksum))
+        .collect()
+}
+
+/// This test makes sure that excluded types are left out of the checksums.
+#[test]
+fn excluded_types_are_left_out_of_the_checksums() {
+    let same_frames = |toggles: RollbackToggles| {
+        let mut a = app_with(toggles.clone(), 0);
+        let mut b = app_with(toggles, 100);
+        run(&mut a, 10);
+        run(&mut b, 10);
+        let (a, b) = (checksums(&a), checksums(&b));
+        let frames: Vec<_> = a.keys().filter(|frame| b.contains_key(frame)).collect();
+        assert!(!frames.is_empty());
+        frames.into_iter().all(|frame| a[frame] == b[frame])
+    };
+
+    let mut toggles = RollbackToggles::default();
+    toggles.exclude::<Ticks>();
+    assert!(same_frames(toggles));
+    assert!(!same_frames(RollbackToggles::default()));
+}
+
+/// This test makes sure that toggling a type that is part of the checksums during a sync test isn't mistaken for a
+/// desync.
+#[test]
+fn toggling_hashed_types_is_no_desync() {
+    let mut app = app(RollbackToggles::default());
+    run(&mut app, 10);
+    app.world
+        .resource_mut::<RollbackToggles>()
+        .exclude::<Ticks>();
+    run(&mut app, 10);
+    app.world.resource_mut::<RollbackToggles>().include_all();
+    run(&mut app, 10);
+
+    // bisecting a desync simulates the recorded frames again, more often than the sync test does
+    let simulations = &app.world.resource::<Simulations>().0;
+    assert!(simulations.len() > 20);
+    assert!(simulations.values().all(|count| *count <= 3));
+}
@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+struct Health(u32);
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+struct Player;
+
+#[derive(Resource, Reflect, Default, Debug)]
+#[reflect(Resource)]
+struct Score(u32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn heal_players(mut query: Query<&mut Health, With<Player>>) {
+    for mut health in query.iter_mut() {
+        health.0 += 1;
+    }
+}
+
+fn hurt_others(mut query: Query<&mut Health, Without<Player>>) {
+    for mut health in query.iter_mut() {
+        health.0 = health.0.saturating_sub(1);
+    }
+}
+
+fn score_once(mut score: ResMut<Score>) {
+    score.0 += 1;
+}
+
+fn score_twice(mut score: ResMut<Score>) {
+    score.0 += 2;
+}
+
+fn read_clock(time: Res<Time>) {
+    let _ = time.elapsed();
+}
+
+fn app(lint: bool) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_schedule_lint(lint)
+        .register_rollback_component::<Health>()
+        .register_rollback_component::<Player>()
+        .register_rollback_resource::<Score>()
+        .with_rollback_schedule(
+            Schedule::default().with_stage(
+                "default",
+                SystemStage::single_threaded()
+                    .with_system(heal_players)
+                    .with_system(hurt_others)
+                    .with_system(score_once)
+                    .with_system(score_twice)
+                    .with_system(read_clock),
+            ),
+        )
+        .build(&mut app);
+
+    app.init_resource::<Score>();
+    app.world.spawn((Rollback::new(0), Health(5), Player));
+    app.world.spawn((Rollback::new(1), Health(5)));
+    for _ in 0..5 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+    app
+}
+
+/// This test makes sure that the lint only runs when enabled.
+#[test]
+fn schedule_lint_is_opt_in() {
+    let app = app(false);
+    assert!(!app.world.contains_resource::<ScheduleLint>());
+}
+
+/// This test makes sure that unordered systems are only reported if they access the same data, taking query filters
+/// into account, and that reading and modifying are told apart.
+#[test]
+fn schedule_lint_reports_conflicts() {
+    let app = app(true);
+    let findings: Vec<&str> = app.world.resource::<ScheduleLint>().findings().collect();
+
+    let conflicts: Vec<_> = findings
+        .iter()
+        .filter(|finding| finding.contains("have no defined order"))
+        .collect();
+    assert_eq!(conflicts.len(), 1, "{findings:?}");
+    assert!(conflicts[0].contains("score_once"));
+    assert!(conflicts[0].contains("score_twice"));
+    assert!(conflicts[0].contains("Score"));
+
+    let clock: Vec<_> = findings
+        .iter()
+        .filter(|finding| finding.contains("read_clock"))
+        .collect();
+    assert_eq!(clock.len(), 1, "{findings:?}");
+    assert!(clock[0].contains("reads bevy_time::time::Time"));
+    assert!(!findings.iter().any(|finding| finding.contains("Health")));
+}
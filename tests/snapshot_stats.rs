@@ -0,0 +1,81 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+#[derive(Reflect, Component, Default, Debug)]
+#[reflect(Component)]
+struct Counter(u32);
+
+#[derive(Reflect, Component, Default, Debug)]
+#[reflect(Component)]
+struct Constant(u32);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn count_system(mut query: Query<&mut Counter>) {
+    for mut counter in query.iter_mut() {
+        counter.0 += 1;
+    }
+}
+
+/// This test makes sure that saving and loading is measured for every registered type.
+#[test]
+fn snapshot_stats_are_collected_per_type() {
+    let mut app = App::new();
+
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_snapshot_stats(true)
+        .register_rollback_component::<Counter>()
+        .register_rollback_component::<Constant>()
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(count_system),
+        ))
+        .build(&mut app);
+
+    app.world.spawn((Rollback::new(0), Counter(0), Constant(7)));
+
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let stats = app.world.resource::<SnapshotStats>();
+    assert!(stats.saves() > 0);
+    assert!(stats.loads() > 0);
+
+    let counter = stats
+        .get(std::any::type_name::<Counter>())
+        .expect("Counter should have been saved");
+    assert_eq!(counter.count, 1);
+    assert_eq!(counter.bytes, std::mem::size_of::<Counter>());
+
+    let constant = stats
+        .get(std::any::type_name::<Constant>())
+        .expect("Constant should have been saved");
+    assert!(counter.change_frequency() > constant.change_frequency());
+}
@@ -0,0 +1,81 @@
+use bevy::prelude::*;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// A transport that records everything sent through it.
+struct RecordingTransport(Arc<Mutex<Vec<(usize, Vec<u8>)>>>);
+impl DatagramSocket<usize> for RecordingTransport {
+    fn send_datagram(&mut self, data: &[u8], addr: &usize) {
+        self.0.lock().push((*addr, data.to_vec()));
+    }
+    fn receive_datagrams(&mut self) -> Vec<(usize, Vec<u8>)> {
+        Vec::new()
+    }
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+/// This test makes sure that traffic to a batched spectator is sent in few datagrams, and that the batches show
+/// up in the spectator metrics.
+#[test]
+fn spectator_traffic_is_batched() {
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let socket =
+        MultiplexSocket::new(RecordingTransport(sent.clone())).with_spectator_batching(vec![5], 4);
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        // debug commands are resent every frame until confirmed, which gives us traffic to the spectator
+        .insert_resource(
+            DebugCommands::<u32, usize>::new(socket.clone(), 0, vec![5]).with_delay(30),
+        )
+        .insert_resource(socket);
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_debug_commands::<u32>()
+        .build(&mut app);
+
+    app.world
+        .resource_mut::<DebugCommands<u32, usize>>()
+        .issue(1);
+
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        app.update();
+    }
+
+    let sent = sent.lock();
+    assert!(!sent.is_empty());
+    assert!(sent
+        .iter()
+        .all(|(addr, datagram)| *addr == 5 && datagram[0] == 7));
+
+    let links = app.world.resource::<SpectatorLinks<usize>>();
+    let link = links.get(&5).expect("the spectator should be known");
+    assert_eq!(link.batches, sent.len());
+    assert!(link.datagrams > link.batches);
+}
@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+mod common;
+use common::Link;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+type Hud = SpectatorHud<Vec<u8>, usize>;
+
+/// The size of the HUD data of each frame, and a frame whose data is much larger.
+#[derive(Resource, Clone, Copy)]
+struct DataSize(usize, i32);
+
+/// Not rolled back: the frames the spectator showed data of.
+#[derive(Resource, Default)]
+struct Shown(Vec<i32>);
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn hud_data(frame: i32, size: DataSize) -> Vec<u8> {
+    let DataSize(size, large_frame) = size;
+    let size = if frame == large_frame { 2000 } else { size };
+    vec![frame as u8; size]
+}
+
+fn set_hud_system(frame: Res<RollbackFrame>, size: Res<DataSize>, mut hud: ResMut<Hud>) {
+    hud.set(hud_data(**frame, *size));
+}
+
+fn record_hud_system(hud: Res<Hud>, mut shown: ResMut<Shown>) {
+    if let Some(frame) = hud.frame() {
+        if shown.0.last() != Some(&frame) {
+            shown.0.push(frame);
+        }
+    }
+}
+
+fn app(hud: Hud, size: DataSize) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(hud)
+        .insert_resource(size)
+        .insert_resource(NetworkProfile::LowBandwidth)
+        .init_resource::<Shown>()
+        .add_system(record_hud_system)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .with_check_distance(2)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ));
+
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .with_rollback_schedule(Schedule::default().with_stage(
+            "default",
+            SystemStage::single_threaded().with_system(set_hud_system),
+        ))
+        .register_spectator_hud::<Vec<u8>>()
+        .build(&mut app);
+    app
+}
+
+/// Runs a host and a spectator side by side and returns the frames the spectator showed, and the size of the
+/// largest datagram the host sent.
+fn run(size: DataSize) -> (Vec<i32>, usize) {
+    let [host, spectator]: [_; 2] = Link::network(2).try_into().ok().unwrap();
+    let traffic = host.traffic();
+    let (host, spectator) = (MultiplexSocket::new(host), MultiplexSocket::new(spectator));
+    let mut host = app(Hud::broadcaster(host, vec![1]), size);
+    let mut spectator = app(Hud::receiver(spectator, 0), size);
+
+    for _ in 0..40 {
+        std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+        host.update();
+        spectator.update();
+    }
+
+    let hud = spectator.world.resource::<Hud>();
+    let frame = hud
+        .frame()
+        .expect("the spectator should have received data");
+    assert_eq!(hud.get(), Some(&hud_data(frame, size)));
+    let shown = spectator.world.remove_resource::<Shown>().unwrap().0;
+    let largest = traffic.lock().largest;
+    (shown, largest)
+}
+
+/// This test makes sure that the data of several frames is split into datagrams that fit into the MTU, instead of
+/// one datagram the receiver would cut off.
+#[test]
+fn large_hud_data_is_split() {
+    // six frames between sends, which is more than 3 KB of data each time
+    let (shown, largest) = run(DataSize(500, -1));
+
+    assert!(shown.last().copied().unwrap_or_default() > 10);
+    assert!(largest <= 1300, "sent a datagram of {largest} bytes");
+}
+
+/// This test makes sure that data too large for a datagram is left out, while the data of other frames arrives.
+#[test]
+fn oversized_hud_data_is_dropped() {
+    let (shown, largest) = run(DataSize(100, 12));
+
+    assert!(!shown.contains(&12));
+    assert!(shown.iter().any(|frame| *frame > 12));
+    assert!(largest <= 1300, "sent a datagram of {largest} bytes");
+}
@@ -0,0 +1,67 @@
+use bevy::{ecs::schedule::StageLabelId, prelude::*};
+
+use bevy_ggrs::*;
+use ggrs::*;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn stage_labels(app: &App) -> Vec<StageLabelId> {
+    app.schedule
+        .iter_stages()
+        .map(|(label, _)| label.as_label())
+        .collect()
+}
+
+fn position(labels: &[StageLabelId], label: impl StageLabel) -> usize {
+    let label = label.as_label();
+    labels
+        .iter()
+        .position(|l| *l == label)
+        .expect("stage should exist")
+}
+
+/// This test makes sure that the GGRS stage runs before `CoreStage::Update` by default.
+#[test]
+fn ggrs_stage_runs_before_update_by_default() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins);
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_input_system(input_system)
+        .build(&mut app);
+
+    let labels = stage_labels(&app);
+    let ggrs = position(&labels, GGRS_UPDATE);
+    assert_eq!(position(&labels, CoreStage::PreUpdate) + 1, ggrs);
+    assert_eq!(position(&labels, GGRS_PRESENTATION), ggrs + 1);
+    assert_eq!(position(&labels, CoreStage::Update), ggrs + 2);
+}
+
+/// This test makes sure that the GGRS stage and the presentation stage follow the stage they are placed after.
+#[test]
+fn ggrs_stage_runs_after_the_given_stage() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins).add_stage_after(
+        CoreStage::Update,
+        "physics_sync",
+        SystemStage::parallel(),
+    );
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_input_system(input_system)
+        .with_stage_placement(StagePlacement::after("physics_sync"))
+        .build(&mut app);
+
+    let labels = stage_labels(&app);
+    let ggrs = position(&labels, GGRS_UPDATE);
+    assert_eq!(position(&labels, "physics_sync") + 1, ggrs);
+    assert_eq!(position(&labels, GGRS_PRESENTATION), ggrs + 1);
+    assert!(position(&labels, CoreStage::Update) < ggrs);
+}
@@ -0,0 +1,160 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+use instant::Duration;
+
+mod common;
+use common::sockets;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+const DELAY: i32 = 100;
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+/// Peer `local` of a two player match with the peer at the other address, replacing the session running on
+/// `current`.
+fn standby(current: &MultiplexSocket<usize>, local: usize) -> StandbySession<GGRSConfig> {
+    let socket = current.standby();
+    let session = SessionBuilder::<GGRSConfig>::new()
+        .with_num_players(2)
+        .add_player(PlayerType::Local, local)
+        .unwrap()
+        .add_player(PlayerType::Remote(1 - local), 1 - local)
+        .unwrap()
+        .start_p2p_session(socket.clone())
+        .unwrap();
+    StandbySession::new(session, &socket)
+}
+
+fn app(socket: MultiplexSocket<usize>, local: usize) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Session::SyncTestSession(
+            SessionBuilder::<GGRSConfig>::new()
+                .with_num_players(1)
+                .add_player(PlayerType::Local, 0)
+                .unwrap()
+                .start_synctest_session()
+                .unwrap(),
+        ))
+        .insert_resource(standby(&socket, local))
+        .insert_resource(
+            DebugCommands::<u8, usize>::new(socket.clone(), local, vec![1 - local])
+                .with_delay(DELAY),
+        )
+        .insert_resource(socket);
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_update_frequency(60)
+        .with_input_system(input_system)
+        .register_debug_commands::<u8>()
+        .build(&mut app);
+    app
+}
+
+fn update(apps: &mut [&mut App]) {
+    std::thread::sleep(Duration::from_secs_f32(1.0 / 60.0));
+    for app in apps.iter_mut() {
+        app.update();
+    }
+}
+
+fn frame(app: &App) -> i32 {
+    **app.world.resource::<RollbackFrame>()
+}
+
+/// Updates until the standby sessions are ready and at least 10 frames have been simulated, then switches to them.
+fn rematch(a: &mut App, b: &mut App) {
+    for _ in 0..300 {
+        update(&mut [&mut *a, &mut *b]);
+        let ready = |app: &App| {
+            app.world
+                .resource::<StandbySession<GGRSConfig>>()
+                .is_ready()
+        };
+        if ready(a) && ready(b) && frame(a) > 10 {
+            break;
+        }
+    }
+    assert!(a.world.resource::<StandbySession<GGRSConfig>>().is_ready());
+    for app in [&mut *a, &mut *b] {
+        app.world
+            .resource_mut::<StandbySession<GGRSConfig>>()
+            .rematch();
+    }
+    for _ in 0..3 {
+        update(&mut [&mut *a, &mut *b]);
+    }
+    assert!(!a.world.contains_resource::<StandbySession<GGRSConfig>>());
+}
+
+/// This test makes sure that the standby session synchronizes while the current session keeps running, and
+/// replaces it on rematch with the simulation starting over.
+#[test]
+fn rematch_switches_to_the_synchronized_session() {
+    let (a, b) = sockets();
+    let (mut a, mut b) = (app(a, 0), app(b, 1));
+
+    for _ in 0..300 {
+        update(&mut [&mut a, &mut b]);
+        let ready = |app: &App| {
+            app.world
+                .resource::<StandbySession<GGRSConfig>>()
+                .is_ready()
+        };
+        if ready(&a) && ready(&b) && frame(&a) > 10 {
+            break;
+        }
+    }
+    assert!(matches!(
+        a.world.resource::<Session<GGRSConfig>>(),
+        Session::SyncTestSession(_)
+    ));
+    let frame_before = frame(&a);
+    assert!(frame_before > 10);
+
+    rematch(&mut a, &mut b);
+
+    assert!(matches!(
+        a.world.resource::<Session<GGRSConfig>>(),
+        Session::P2PSession(_)
+    ));
+    assert!(frame(&a) < frame_before);
+    // the frames of the previous match are forgotten
+    let scheduled = a
+        .world
+        .resource_mut::<DebugCommands<u8, usize>>()
+        .issue(1)
+        .unwrap();
+    assert!(scheduled <= frame(&a) + DELAY + 1);
+}
+
+/// This test makes sure that the second rematch, whose session runs on the channel of the first match again, drops
+/// the leftover traffic of the session it replaces and keeps the traffic of its own.
+#[test]
+fn second_rematch_drops_the_traffic_of_the_replaced_session() {
+    let (socket_a, socket_b) = sockets();
+    let (mut a, mut b) = (app(socket_a.clone(), 0), app(socket_b.clone(), 1));
+    rematch(&mut a, &mut b);
+
+    // the first rematch runs on the standby channel, the second one on the channel of the first match
+    a.insert_resource(standby(&socket_a.standby(), 0));
+    b.insert_resource(standby(&socket_b.standby(), 1));
+    rematch(&mut a, &mut b);
+
+    let frame_after = frame(&a);
+    for _ in 0..10 {
+        update(&mut [&mut a, &mut b]);
+    }
+    assert!(frame(&a) > frame_after);
+    let mut replaced = socket_a.standby();
+    assert!(replaced.receive_all_messages().is_empty());
+}
@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+
+use bevy_ggrs::*;
+use ggrs::*;
+
+mod common;
+use common::Link;
+
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = u8;
+    type State = u8;
+    type Address = usize;
+}
+
+/// Drops every 7th datagram in both directions and corrupts every 11th chunk.
+fn sockets() -> (MultiplexSocket<usize>, MultiplexSocket<usize>) {
+    let [a, b]: [_; 2] = Link::network(2).try_into().ok().unwrap();
+    (
+        MultiplexSocket::new(a.dropping_every(7).corrupting_every(11)),
+        MultiplexSocket::new(b.dropping_every(7)),
+    )
+}
+
+fn input_system(_: In<PlayerHandle>) -> u8 {
+    0
+}
+
+fn app(transfer: StateTransfer<usize>) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins).insert_resource(transfer);
+    GGRSPlugin::<GGRSConfig>::new()
+        .with_input_system(input_system)
+        .build(&mut app);
+    app
+}
+
+fn events(app: &App) -> Vec<StateTransferEvent<usize>> {
+    let events = app.world.resource::<Events<StateTransferEvent<usize>>>();
+    events.get_reader().iter(events).cloned().collect()
+}
+
+/// This test makes sure that a state of several megabytes arrives intact over a connection losing and corrupting
+/// datagrams, with progress reported on both ends.
+#[test]
+fn large_state_arrives_intact() {
+    let (sender_socket, receiver_socket) = sockets();
+    let resend_interval = instant::Duration::from_millis(1);
+    let mut sender = app(StateTransfer::new(sender_socket)
+        .with_window(256)
+        .with_resend_interval(resend_interval));
+    let mut receiver =
+        app(StateTransfer::new(receiver_socket).with_resend_interval(resend_interval));
+
+    let state: Vec<u8> = (0..3 * 1024 * 1024 + 17)
+        .map(|i| (i * 7 % 251) as u8)
+        .collect();
+    sender
+        .world
+        .resource_mut::<StateTransfer<usize>>()
+        .send(1, 42, &state);
+
+    let mut received = Vec::new();
+    let (mut sender_events, mut receiver_events) = (Vec::new(), Vec::new());
+    for _ in 0..2000 {
+        std::thread::sleep(resend_interval);
+        sender.update();
+        receiver.update();
+        sender_events.extend(events(&sender));
+        receiver_events.extend(events(&receiver));
+        received.extend(
+            receiver
+                .world
+                .resource_mut::<StateTransfer<usize>>()
+                .take_received(),
+        );
+        if !sender
+            .world
+            .resource::<StateTransfer<usize>>()
+            .is_sending(&1)
+        {
+            break;
+        }
+    }
+
+    assert_eq!(
+        received,
+        vec![ReceivedState {
+            peer: 0,
+            frame: 42,
+            data: state
+        }]
+    );
+    assert!(receiver_events.iter().any(|event| matches!(
+        event,
+        StateTransferEvent::Progress {
+            direction: TransferDirection::Receiving,
+            frame: 42,
+            ..
+        }
+    )));
+    assert!(receiver_events.contains(&StateTransferEvent::Completed {
+        peer: 0,
+        direction: TransferDirection::Receiving,
+        frame: 42
+    }));
+    assert!(sender_events.contains(&StateTransferEvent::Completed {
+        peer: 1,
+        direction: TransferDirection::Sending,
+        frame: 42
+    }));
+}